@@ -0,0 +1,589 @@
+//! Link and address changes behind a swappable backend
+//!
+//! [`VlanPlugin`](crate::plugin::VlanPlugin) makes every link, address,
+//! route, rule and neighbor change through a [`NetBackend`], and reads links
+//! and the listings CHECK compares through it too. [`IpCommand`] shells out
+//! to `ip` through a [`CommandRunner`]; [`Netlink`] talks to the kernel
+//! directly, so iproute2 is only needed when it is selected. Listings come
+//! back in the shape `ip -j` prints them, whichever backend read them.
+//!
+//! Failures carry the errno behind them, read from the kernel's answer or
+//! from `ip`'s message, so callers tell e.g. a missing device apart with
+//! [`errno_of`] instead of matching text.
+//!
+//! The backend is chosen with `SOCNI_BACKEND` (`netlink` or `iproute2`).
+//! Without it netlink is used when a socket can be opened, iproute2
+//! otherwise.
+
+mod netlink;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::runner::CommandRunner;
+
+pub use netlink::Netlink;
+
+/// Changes to links and addresses in the current namespace
+///
+/// Errors carry the reason reported by `ip` or the kernel, so callers can
+/// add what they were doing and still recognise the errno with [`errno_of`].
+pub trait NetBackend: Send + Sync {
+    /// Which backend this is, for logs
+    fn kind(&self) -> BackendKind;
+    
+    /// Create `link`, failing if a link of that name already exists
+    fn add_link(&self, link: &NewLink) -> Result<()>;
+    
+    /// Set `ifname` administratively up
+    fn set_link_up(&self, ifname: &str) -> Result<()>;
+    
+    /// Set `ifname` administratively down
+    fn set_link_down(&self, ifname: &str) -> Result<()>;
+    
+    /// Rename `ifname` to `new_name`
+    fn rename_link(&self, ifname: &str, new_name: &str) -> Result<()>;
+    
+    /// Set the hardware address of `ifname`
+    fn set_link_address(&self, ifname: &str, mac: &str) -> Result<()>;
+    
+    /// Set the MTU of `ifname`
+    fn set_link_mtu(&self, ifname: &str, mtu: u32) -> Result<()>;
+    
+    /// Move `ifname` into the named namespace under /var/run/netns
+    fn move_link(&self, ifname: &str, netns: &str) -> Result<()>;
+    
+    /// Delete `ifname`
+    fn delete_link(&self, ifname: &str) -> Result<()>;
+    
+    /// Add `address` (`addr/prefix`) to `ifname`
+    fn add_address(&self, ifname: &str, address: &str, lifetimes: AddressLifetimes) -> Result<()>;
+    
+    /// Remove every global-scope address from `ifname`
+    fn flush_addresses(&self, ifname: &str) -> Result<()>;
+    
+    /// Create an ifb device named `name`
+    fn add_ifb(&self, name: &str) -> Result<()>;
+    
+    /// Create the veth pair `name` and `peer`, with `peer` in the namespace
+    /// `peer_netns`
+    fn add_veth(&self, name: &str, peer: &str, peer_netns: &str) -> Result<()>;
+    
+    /// Enslave `ifname` to the bridge or bond `master`
+    fn set_link_master(&self, ifname: &str, master: &str) -> Result<()>;
+    
+    /// Create the MACsec device `link`
+    fn add_macsec(&self, link: &NewMacsec) -> Result<()>;
+    
+    /// Install `sas` on the MACsec device `ifname`, creating the receive
+    /// channel of each peer they name
+    fn add_macsec_sas(&self, ifname: &str, sas: &[MacsecSa]) -> Result<()>;
+    
+    /// Install `route`
+    fn add_route(&self, route: &NewRoute) -> Result<()>;
+    
+    /// Add a rule looking up `table` for traffic from the address `source`
+    fn add_source_rule(&self, source: &str, table: u32) -> Result<()>;
+    
+    /// Delete the rule [`add_source_rule`](Self::add_source_rule) added
+    fn delete_source_rule(&self, source: &str, table: u32) -> Result<()>;
+    
+    /// Pin `ip` on `ifname` to `lladdr` as a permanent neighbor, replacing
+    /// any entry it has
+    fn set_neighbor(&self, ifname: &str, ip: &str, lladdr: &str) -> Result<()>;
+    
+    /// Have the kernel resolve `ip` on `ifname` as if traffic were waiting for it
+    fn probe_neighbor(&self, ifname: &str, ip: &str) -> Result<()>;
+    
+    /// Delete the neighbor entry of `ip` on `ifname`
+    fn delete_neighbor(&self, ifname: &str, ip: &str) -> Result<()>;
+    
+    /// `ifname` with its link details (`ip -d -j link show`), or `None` when
+    /// there is no such link
+    fn link(&self, ifname: &str) -> Result<Option<serde_json::Value>>;
+    
+    /// `ifname` with its counters (`ip -s -j link show`)
+    fn link_stats(&self, ifname: &str) -> Result<serde_json::Value>;
+    
+    /// Links enslaved to `master` (`ip -j link show master`)
+    fn link_slaves(&self, master: &str) -> Result<Vec<serde_json::Value>>;
+    
+    /// `ifname` with its details and addresses (`ip -d -j addr show`), or
+    /// `None` when there is no such link
+    fn addresses(&self, ifname: &str) -> Result<Option<serde_json::Value>>;
+    
+    /// Routes of `family` through `ifname` in the main table (`ip -j route show dev`)
+    fn routes(&self, ifname: &str, family: IpFamily) -> Result<Vec<serde_json::Value>>;
+    
+    /// Rules of `family` (`ip -j rule show`)
+    fn rules(&self, family: IpFamily) -> Result<Vec<serde_json::Value>>;
+    
+    /// Neighbor entries on `ifname`, only the one of `ip` when given
+    /// (`ip -j neigh show`)
+    fn neighbors(&self, ifname: &str, ip: Option<&str>) -> Result<Vec<serde_json::Value>>;
+}
+
+/// A link to create on top of `parent`
+#[derive(Debug, Clone)]
+pub struct NewLink<'a> {
+    pub name: &'a str,
+    pub parent: &'a str,
+    pub kind: LinkKind,
+    pub mtu: Option<u32>,
+    /// Create the link directly in this namespace under /var/run/netns
+    pub netns: Option<&'a str>,
+}
+
+/// Type of a link to create
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkKind {
+    /// VLAN tagging with `id`. `protocol` is `802.1Q` or `802.1ad` (the
+    /// kernel default of 802.1Q when unset), and `flags` are iproute2 VLAN
+    /// flag names (`reorder_hdr`, `gvrp`, `mvrp`, `loose_binding`) to turn
+    /// on or off
+    Vlan {
+        id: u16,
+        protocol: Option<String>,
+        flags: Vec<(&'static str, bool)>,
+    },
+    /// Bridge-mode macvlan, which carries the parent's untagged traffic
+    Macvlan,
+}
+
+/// A MACsec device to create on `lower`, on port 1
+#[derive(Debug, Clone)]
+pub struct NewMacsec<'a> {
+    pub name: &'a str,
+    pub lower: &'a str,
+    /// Cipher suite by its iproute2 name, e.g. `gcm-aes-xpn-128`
+    pub cipher: &'a str,
+    /// Encrypt frames; when false they are only integrity protected
+    pub encrypt: bool,
+}
+
+/// Secure association 0, active, of a MACsec device's transmit channel or
+/// of the receive channel of `peer`
+#[derive(Debug, Clone)]
+pub struct MacsecSa<'a> {
+    /// MAC of the peer whose frames it accepts; `None` for transmitting
+    pub peer: Option<&'a str>,
+    /// First extended packet number
+    pub pn: u64,
+    pub key_id: &'a [u8],
+    pub key: &'a [u8],
+    pub salt: &'a [u8],
+}
+
+/// Short secure channel identifier every secure association uses
+pub const MACSEC_SSCI: u32 = 1;
+
+/// A route through `dev`
+#[derive(Debug, Clone, Default)]
+pub struct NewRoute<'a> {
+    /// Destination CIDR or address; `None` is the default route of the
+    /// gateway's family
+    pub dst: Option<&'a str>,
+    pub gateway: Option<&'a str>,
+    pub dev: &'a str,
+    pub metric: Option<u32>,
+    /// Routing table, the main one when unset
+    pub table: Option<u32>,
+    /// Reach the gateway through `dev` even outside its prefixes
+    pub onlink: bool,
+}
+
+impl NewRoute<'_> {
+    /// Family of the destination, or of the gateway for a default route
+    pub fn family(&self) -> IpFamily {
+        IpFamily::of(self.dst.or(self.gateway).unwrap_or_default())
+    }
+}
+
+/// Address family of routes and rules
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFamily {
+    V4,
+    V6,
+}
+
+impl IpFamily {
+    /// Family of `addr`, an address or CIDR
+    pub fn of(addr: &str) -> Self {
+        if addr.contains(':') { Self::V6 } else { Self::V4 }
+    }
+}
+
+/// Valid and preferred lifetimes of an address in seconds, `None` meaning forever
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressLifetimes {
+    pub valid: Option<u32>,
+    /// Defaults to the valid lifetime
+    pub preferred: Option<u32>,
+}
+
+impl AddressLifetimes {
+    /// Whether both lifetimes are forever, the kernel's default
+    pub fn is_forever(&self) -> bool {
+        self.valid.is_none() && self.preferred.is_none()
+    }
+    
+    /// `valid_lft` and `preferred_lft` arguments for `ip addr add`
+    pub fn ip_args(&self) -> Vec<String> {
+        if self.is_forever() {
+            return Vec::new();
+        }
+        let lifetime = |l: Option<u32>| l.map_or_else(|| "forever".to_string(), |l| l.to_string());
+        vec![
+            "valid_lft".to_string(), lifetime(self.valid),
+            "preferred_lft".to_string(), lifetime(self.preferred.or(self.valid)),
+        ]
+    }
+}
+
+/// Available [`NetBackend`] implementations
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// rtnetlink requests from the plugin itself
+    Netlink,
+    /// `ip` commands
+    Iproute2,
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "netlink" => Ok(Self::Netlink),
+            "iproute2" | "ip" => Ok(Self::Iproute2),
+            _ => anyhow::bail!("Unknown network backend {} (expected netlink or iproute2)", s),
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Netlink => write!(f, "netlink"),
+            Self::Iproute2 => write!(f, "iproute2"),
+        }
+    }
+}
+
+/// The configured backend kind, or netlink when it is available and iproute2 otherwise
+pub fn resolve(kind: Option<BackendKind>) -> BackendKind {
+    kind.unwrap_or_else(|| match Netlink::available() {
+        true => BackendKind::Netlink,
+        false => BackendKind::Iproute2,
+    })
+}
+
+/// The backend [`resolve`] picks for `kind`
+pub fn select(kind: Option<BackendKind>, runner: Arc<dyn CommandRunner>) -> Arc<dyn NetBackend> {
+    match resolve(kind) {
+        BackendKind::Netlink => Arc::new(Netlink),
+        BackendKind::Iproute2 => Arc::new(IpCommand::new(runner)),
+    }
+}
+
+/// `ip` failing, with the errno its message names when it is recognised
+#[derive(Debug)]
+pub struct IpError {
+    pub errno: Option<i32>,
+    stderr: String,
+}
+
+/// Messages `ip` prints for the errnos callers tell apart
+const IP_ERRNOS: &[(&str, i32)] = &[
+    ("Operation not permitted", libc::EPERM),
+    ("Permission denied", libc::EACCES),
+    ("Cannot find device", libc::ENODEV),
+    ("No such device", libc::ENODEV),
+    ("does not exist", libc::ENODEV),
+    ("File exists", libc::EEXIST),
+    ("No such file or directory", libc::ENOENT),
+    ("No such process", libc::ESRCH),
+];
+
+impl IpError {
+    /// Failure reported on `stderr`
+    pub fn new(stderr: &str) -> Self {
+        let stderr = stderr.trim().to_string();
+        let errno = IP_ERRNOS.iter()
+            .find(|(message, _)| stderr.contains(message))
+            .map(|(_, errno)| *errno);
+        Self { errno, stderr }
+    }
+}
+
+impl fmt::Display for IpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.stderr)
+    }
+}
+
+impl std::error::Error for IpError {}
+
+/// errno a backend call failed with: the kernel's answer for netlink, the
+/// one `ip` reported for iproute2
+pub fn errno_of(e: &anyhow::Error) -> Option<i32> {
+    e.chain().find_map(|cause| match cause.downcast_ref::<io::Error>() {
+        Some(e) => e.raw_os_error(),
+        None => cause.downcast_ref::<IpError>().and_then(|e| e.errno),
+    })
+}
+
+/// Runs `ip` for every change
+pub struct IpCommand {
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl IpCommand {
+    pub fn new(runner: Arc<dyn CommandRunner>) -> Self {
+        Self { runner }
+    }
+    
+    /// Run `ip` with `args`, failing with an [`IpError`] for its stderr
+    fn ip(&self, args: &[&str]) -> Result<()> {
+        self.ip_output(args, None).map(|_| ())
+    }
+    
+    /// Run `ip` with `args`, and `input` on stdin when given, returning its stdout
+    fn ip_output(&self, args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>> {
+        let output = match input {
+            Some(input) => self.runner.output_with_input("ip", args, input),
+            None => self.runner.output("ip", args),
+        };
+        let output = output.with_context(|| format!("Failed to execute ip {}", command(args)))?;
+        
+        if !output.status.success() {
+            return Err(IpError::new(&String::from_utf8_lossy(&output.stderr)).into());
+        }
+        Ok(output.stdout)
+    }
+    
+    /// Run `ip` with `args` and parse the JSON list it prints
+    fn ip_json(&self, args: &[&str]) -> Result<Vec<serde_json::Value>> {
+        let stdout = self.ip_output(args, None)?;
+        serde_json::from_slice(&stdout)
+            .with_context(|| format!("Failed to parse ip {} output", command(args)))
+    }
+    
+    /// The first entry `ip` lists for `args`, `None` when the device doesn't exist
+    fn ip_json_link(&self, args: &[&str]) -> Result<Option<serde_json::Value>> {
+        match self.ip_json(args) {
+            Ok(links) => Ok(links.into_iter().next()),
+            Err(e) if errno_of(&e) == Some(libc::ENODEV) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// The object and command of `ip` `args`, e.g. `link show`
+fn command(args: &[&str]) -> String {
+    args.iter()
+        .filter(|arg| !arg.starts_with('-'))
+        .take(2)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Lowercase hex of `bytes`, as `ip macsec` takes keys
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl NetBackend for IpCommand {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Iproute2
+    }
+    
+    fn add_link(&self, link: &NewLink) -> Result<()> {
+        let mut args: Vec<String> = ["link", "add", "link", link.parent, "name", link.name]
+            .map(String::from)
+            .to_vec();
+        if let Some(netns) = link.netns {
+            args.extend(["netns".to_string(), netns.to_string()]);
+        }
+        if let Some(mtu) = link.mtu {
+            args.extend(["mtu".to_string(), mtu.to_string()]);
+        }
+        match &link.kind {
+            LinkKind::Vlan { id, protocol, flags } => {
+                args.extend(["type", "vlan", "id"].map(String::from));
+                args.push(id.to_string());
+                for (flag, on) in flags {
+                    args.extend([flag.to_string(), if *on { "on" } else { "off" }.to_string()]);
+                }
+                if let Some(protocol) = protocol {
+                    args.extend(["protocol".to_string(), protocol.clone()]);
+                }
+            }
+            LinkKind::Macvlan => args.extend(["type", "macvlan", "mode", "bridge"].map(String::from)),
+        }
+        self.ip(&args.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+    
+    fn set_link_up(&self, ifname: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "up"])
+    }
+    
+    fn set_link_down(&self, ifname: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "down"])
+    }
+    
+    fn rename_link(&self, ifname: &str, new_name: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "name", new_name])
+    }
+    
+    fn set_link_address(&self, ifname: &str, mac: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "address", mac])
+    }
+    
+    fn set_link_mtu(&self, ifname: &str, mtu: u32) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "mtu", &mtu.to_string()])
+    }
+    
+    fn move_link(&self, ifname: &str, netns: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "netns", netns])
+    }
+    
+    fn delete_link(&self, ifname: &str) -> Result<()> {
+        self.ip(&["link", "delete", ifname])
+    }
+    
+    fn add_address(&self, ifname: &str, address: &str, lifetimes: AddressLifetimes) -> Result<()> {
+        let lifetime_args = lifetimes.ip_args();
+        let mut args = vec!["addr", "add", address, "dev", ifname];
+        args.extend(lifetime_args.iter().map(String::as_str));
+        self.ip(&args)
+    }
+    
+    fn flush_addresses(&self, ifname: &str) -> Result<()> {
+        self.ip(&["addr", "flush", "dev", ifname, "scope", "global"])
+    }
+    
+    fn add_ifb(&self, name: &str) -> Result<()> {
+        self.ip(&["link", "add", name, "type", "ifb"])
+    }
+    
+    fn add_veth(&self, name: &str, peer: &str, peer_netns: &str) -> Result<()> {
+        self.ip(&["link", "add", name, "type", "veth", "peer", "name", peer, "netns", peer_netns])
+    }
+    
+    fn set_link_master(&self, ifname: &str, master: &str) -> Result<()> {
+        self.ip(&["link", "set", "dev", ifname, "master", master])
+    }
+    
+    fn add_macsec(&self, link: &NewMacsec) -> Result<()> {
+        let encrypt = if link.encrypt { "on" } else { "off" };
+        self.ip(&["link", "add", "link", link.lower, "name", link.name, "type", "macsec", "port", "1",
+                  "encrypt", encrypt, "cipher", link.cipher])
+    }
+    
+    /// Keys reach `ip` on stdin, since arguments are readable in /proc
+    fn add_macsec_sas(&self, ifname: &str, sas: &[MacsecSa]) -> Result<()> {
+        let mut batch = String::new();
+        for sa in sas {
+            let config = format!("sa 0 xpn {} on ssci {} salt {} key {} {}",
+                                 sa.pn, MACSEC_SSCI, hex(sa.salt), hex(sa.key_id), hex(sa.key));
+            match sa.peer {
+                Some(peer) => {
+                    batch.push_str(&format!("macsec add {} rx port 1 address {}\n", ifname, peer));
+                    batch.push_str(&format!("macsec add {} rx port 1 address {} {}\n", ifname, peer, config));
+                }
+                None => batch.push_str(&format!("macsec add {} tx {}\n", ifname, config)),
+            }
+        }
+        self.ip_output(&["-batch", "-"], Some(batch.as_bytes())).map(|_| ())
+    }
+    
+    fn add_route(&self, route: &NewRoute) -> Result<()> {
+        let (metric, table) = (route.metric.map(|m| m.to_string()), route.table.map(|t| t.to_string()));
+        // iproute2 takes `default` as IPv4 unless told otherwise
+        let mut args = match (route.dst, route.family()) {
+            (None, IpFamily::V6) => vec!["-6", "route", "add", "default"],
+            (dst, _) => vec!["route", "add", dst.unwrap_or("default")],
+        };
+        if let Some(gateway) = route.gateway {
+            args.extend(["via", gateway]);
+        }
+        args.extend(["dev", route.dev]);
+        if let Some(metric) = &metric {
+            args.extend(["metric", metric.as_str()]);
+        }
+        if let Some(table) = &table {
+            args.extend(["table", table.as_str()]);
+        }
+        if route.onlink {
+            args.push("onlink");
+        }
+        self.ip(&args)
+    }
+    
+    fn add_source_rule(&self, source: &str, table: u32) -> Result<()> {
+        self.ip(&["rule", "add", "from", source, "lookup", &table.to_string()])
+    }
+    
+    fn delete_source_rule(&self, source: &str, table: u32) -> Result<()> {
+        self.ip(&["rule", "del", "from", source, "lookup", &table.to_string()])
+    }
+    
+    fn set_neighbor(&self, ifname: &str, ip: &str, lladdr: &str) -> Result<()> {
+        self.ip(&["neigh", "replace", ip, "lladdr", lladdr, "dev", ifname, "nud", "permanent"])
+    }
+    
+    fn probe_neighbor(&self, ifname: &str, ip: &str) -> Result<()> {
+        self.ip(&["neigh", "replace", ip, "dev", ifname, "use"])
+    }
+    
+    fn delete_neighbor(&self, ifname: &str, ip: &str) -> Result<()> {
+        self.ip(&["neigh", "del", ip, "dev", ifname])
+    }
+    
+    fn link(&self, ifname: &str) -> Result<Option<serde_json::Value>> {
+        self.ip_json_link(&["-d", "-j", "link", "show", "dev", ifname])
+    }
+    
+    fn link_stats(&self, ifname: &str) -> Result<serde_json::Value> {
+        self.ip_json(&["-s", "-j", "link", "show", "dev", ifname])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Link {} not found", ifname))
+    }
+    
+    fn link_slaves(&self, master: &str) -> Result<Vec<serde_json::Value>> {
+        self.ip_json(&["-j", "link", "show", "master", master])
+    }
+    
+    fn addresses(&self, ifname: &str) -> Result<Option<serde_json::Value>> {
+        self.ip_json_link(&["-d", "-j", "addr", "show", "dev", ifname])
+    }
+    
+    fn routes(&self, ifname: &str, family: IpFamily) -> Result<Vec<serde_json::Value>> {
+        match family {
+            IpFamily::V4 => self.ip_json(&["-j", "route", "show", "dev", ifname]),
+            IpFamily::V6 => self.ip_json(&["-6", "-j", "route", "show", "dev", ifname]),
+        }
+    }
+    
+    fn rules(&self, family: IpFamily) -> Result<Vec<serde_json::Value>> {
+        match family {
+            IpFamily::V4 => self.ip_json(&["-4", "-j", "rule", "show"]),
+            IpFamily::V6 => self.ip_json(&["-6", "-j", "rule", "show"]),
+        }
+    }
+    
+    fn neighbors(&self, ifname: &str, ip: Option<&str>) -> Result<Vec<serde_json::Value>> {
+        match ip {
+            Some(ip) => self.ip_json(&["-j", "neigh", "show", ip, "dev", ifname]),
+            None => self.ip_json(&["-j", "neigh", "show", "dev", ifname]),
+        }
+    }
+}
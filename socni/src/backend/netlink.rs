@@ -0,0 +1,1061 @@
+//! Link, address, route, rule and neighbor changes over an rtnetlink socket
+//!
+//! Requests and replies are the message types of `netlink-packet-route`,
+//! sent on a blocking `netlink-sys` socket, one socket per request. A
+//! socket belongs to the namespace of the thread that opened it, so every
+//! change applies to the namespace the caller is in. `rtnetlink` builds on
+//! the same two crates, but only offers futures driven by a spawned
+//! connection, which the synchronous [`NetBackend`] cannot wait on from
+//! inside the runtime. MACsec secure associations go over generic netlink,
+//! which has no message crate, as a generic netlink header followed by
+//! attributes; listings are dumps turned into the JSON `ip -j` prints.
+
+use anyhow::{Context, Result};
+use ipnetwork::IpNetwork;
+use libc::c_int;
+use netlink_packet_core::{
+    NetlinkBuffer, NetlinkDeserializable, NetlinkHeader, NetlinkMessage, NetlinkPayload, NetlinkSerializable,
+    NLM_F_ACK, NLM_F_CREATE, NLM_F_DUMP, NLM_F_EXCL, NLM_F_REPLACE, NLM_F_REQUEST,
+};
+use netlink_packet_route::nlas::address::{CacheInfo, Nla as AddressNla};
+use netlink_packet_route::nlas::link::{
+    Info, InfoData, InfoKind, InfoMacSec, InfoMacVlan, InfoVlan, MacSecCipherId, Nla as LinkNla, Stats64, Stats64Buffer,
+    VethInfo,
+};
+use netlink_packet_route::nlas::neighbour::Nla as NeighbourNla;
+use netlink_packet_route::nlas::route::Nla as RouteNla;
+use netlink_packet_route::nlas::rule::Nla as RuleNla;
+use netlink_packet_route::{
+    AddressMessage, LinkMessage, NeighbourMessage, RouteFlags, RouteMessage, RtnlMessage, RuleMessage, FR_ACT_TO_TBL,
+    IFF_BROADCAST, IFF_LOOPBACK, IFF_LOWER_UP, IFF_MULTICAST, IFF_UP, IFLA_VLAN_FLAGS, MACVLAN_MODE_BRIDGE, NTF_USE,
+    NUD_PERMANENT, RTNH_F_ONLINK, RTN_UNICAST, RTPROT_BOOT, RT_SCOPE_HOST, RT_SCOPE_LINK, RT_SCOPE_UNIVERSE, RT_TABLE_MAIN,
+};
+use netlink_packet_utils::nla::{DefaultNla, Nla, NlasIterator, NLA_F_NESTED, NLA_TYPE_MASK};
+use netlink_packet_utils::{DecodeError, Emitable, Parseable};
+use netlink_sys::protocols::{NETLINK_GENERIC, NETLINK_ROUTE};
+use netlink_sys::{Socket, SocketAddr};
+use std::ffi::{CStr, CString};
+use std::io;
+use std::net::IpAddr;
+
+use super::{errno_of, AddressLifetimes, BackendKind, IpFamily, LinkKind, MacsecSa, NetBackend, NewLink, NewMacsec, NewRoute, MACSEC_SSCI};
+use crate::netns;
+
+// VLAN flags, from linux/if_vlan.h
+const VLAN_FLAG_REORDER_HDR: u32 = 0x1;
+const VLAN_FLAG_GVRP: u32 = 0x2;
+const VLAN_FLAG_LOOSE_BINDING: u32 = 0x4;
+const VLAN_FLAG_MVRP: u32 = 0x8;
+
+// Generic netlink, from linux/genetlink.h
+const GENL_HDRLEN: usize = 4;
+const GENL_ID_CTRL: u16 = 0x10;
+const CTRL_VERSION: u8 = 1;
+const CTRL_CMD_GETFAMILY: u8 = 3;
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+// MACsec configuration, from linux/if_macsec.h
+const MACSEC_GENL_VERSION: u8 = 1;
+const MACSEC_CMD_ADD_RXSC: u8 = 1;
+const MACSEC_CMD_ADD_TXSA: u8 = 4;
+const MACSEC_CMD_ADD_RXSA: u8 = 7;
+const MACSEC_ATTR_IFINDEX: u16 = 1;
+const MACSEC_ATTR_RXSC_CONFIG: u16 = 2;
+const MACSEC_ATTR_SA_CONFIG: u16 = 3;
+const MACSEC_RXSC_ATTR_SCI: u16 = 1;
+const MACSEC_SA_ATTR_AN: u16 = 1;
+const MACSEC_SA_ATTR_ACTIVE: u16 = 2;
+const MACSEC_SA_ATTR_PN: u16 = 3;
+const MACSEC_SA_ATTR_KEY: u16 = 4;
+const MACSEC_SA_ATTR_KEYID: u16 = 5;
+const MACSEC_SA_ATTR_SSCI: u16 = 8;
+const MACSEC_SA_ATTR_SALT: u16 = 9;
+
+/// Sends rtnetlink requests instead of spawning `ip`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Netlink;
+
+impl Netlink {
+    /// Whether an rtnetlink socket can be opened in the current namespace
+    pub fn available() -> bool {
+        Socket::new(NETLINK_ROUTE).is_ok()
+    }
+}
+
+impl NetBackend for Netlink {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Netlink
+    }
+    
+    fn add_link(&self, link: &NewLink) -> Result<()> {
+        let parent = link_index(link.parent)?;
+        let fd = link.netns.map(netns::open_netns).transpose()?;
+        // The kernel resolves the namespace fd when it handles the request,
+        // so it stays open until the request has been answered
+        let sent = add_link_message(link, parent, fd)
+            .and_then(|message| request(RtnlMessage::NewLink(message), NLM_F_CREATE | NLM_F_EXCL));
+        if let Some(fd) = fd {
+            unsafe { libc::close(fd) };
+        }
+        sent
+    }
+    
+    fn set_link_up(&self, ifname: &str) -> Result<()> {
+        let mut message = link_message(link_index(ifname)?, Vec::new());
+        message.header.flags = IFF_UP;
+        message.header.change_mask = IFF_UP;
+        request(RtnlMessage::SetLink(message), 0)
+    }
+    
+    fn set_link_down(&self, ifname: &str) -> Result<()> {
+        let mut message = link_message(link_index(ifname)?, Vec::new());
+        message.header.change_mask = IFF_UP;
+        request(RtnlMessage::SetLink(message), 0)
+    }
+    
+    fn rename_link(&self, ifname: &str, new_name: &str) -> Result<()> {
+        set_link(ifname, LinkNla::IfName(new_name.to_string()))
+    }
+    
+    fn set_link_address(&self, ifname: &str, mac: &str) -> Result<()> {
+        set_link(ifname, LinkNla::Address(parse_mac(mac)?))
+    }
+    
+    fn set_link_mtu(&self, ifname: &str, mtu: u32) -> Result<()> {
+        set_link(ifname, LinkNla::Mtu(mtu))
+    }
+    
+    fn move_link(&self, ifname: &str, netns: &str) -> Result<()> {
+        let index = link_index(ifname)?;
+        let fd = netns::open_netns(netns)?;
+        let sent = request(RtnlMessage::SetLink(link_message(index, vec![LinkNla::NetNsFd(fd)])), 0);
+        unsafe { libc::close(fd) };
+        sent
+    }
+    
+    fn delete_link(&self, ifname: &str) -> Result<()> {
+        request(RtnlMessage::DelLink(link_message(link_index(ifname)?, Vec::new())), 0)
+    }
+    
+    fn add_address(&self, ifname: &str, address: &str, lifetimes: AddressLifetimes) -> Result<()> {
+        let net: IpNetwork = address.parse()
+            .with_context(|| format!("Invalid address {}", address))?;
+        let octets = ip_octets(net.ip());
+        
+        // Like ip, loopback addresses get host scope, which the kernel requires
+        let mut message = AddressMessage::default();
+        message.header.family = address_family(net.ip());
+        message.header.prefix_len = net.prefix();
+        message.header.scope = if net.ip().is_loopback() { RT_SCOPE_HOST } else { RT_SCOPE_UNIVERSE };
+        message.header.index = link_index(ifname)?;
+        message.nlas = vec![AddressNla::Local(octets.clone()), AddressNla::Address(octets)];
+        if !lifetimes.is_forever() {
+            message.nlas.push(AddressNla::CacheInfo(cacheinfo(lifetimes)));
+        }
+        request(RtnlMessage::NewAddress(message), NLM_F_CREATE | NLM_F_EXCL)
+    }
+    
+    fn flush_addresses(&self, ifname: &str) -> Result<()> {
+        let index = link_index(ifname)?;
+        for reply in dump(RtnlMessage::GetAddress(AddressMessage::default()))? {
+            let RtnlMessage::NewAddress(mut address) = reply else {
+                continue;
+            };
+            if address.header.index != index || address.header.scope != RT_SCOPE_UNIVERSE {
+                continue;
+            }
+            address.nlas.retain(|nla| matches!(nla, AddressNla::Local(_) | AddressNla::Address(_)));
+            // Deleting a primary IPv4 address takes its secondaries along
+            match request(RtnlMessage::DelAddress(address), 0) {
+                Err(e) if errno_of(&e) == Some(libc::EADDRNOTAVAIL) => {}
+                sent => sent?,
+            }
+        }
+        Ok(())
+    }
+    
+    fn add_ifb(&self, name: &str) -> Result<()> {
+        let message = link_message(0, vec![
+            LinkNla::IfName(name.to_string()),
+            LinkNla::Info(vec![Info::Kind(InfoKind::Ifb)]),
+        ]);
+        request(RtnlMessage::NewLink(message), NLM_F_CREATE | NLM_F_EXCL)
+    }
+    
+    fn add_veth(&self, name: &str, peer: &str, peer_netns: &str) -> Result<()> {
+        let fd = netns::open_netns(peer_netns)?;
+        // The peer is described by its own ifinfomsg and attributes
+        let peer = link_message(0, vec![LinkNla::IfName(peer.to_string()), LinkNla::NetNsFd(fd)]);
+        let message = link_message(0, vec![
+            LinkNla::IfName(name.to_string()),
+            LinkNla::Info(vec![Info::Kind(InfoKind::Veth), Info::Data(InfoData::Veth(VethInfo::Peer(peer)))]),
+        ]);
+        let sent = request(RtnlMessage::NewLink(message), NLM_F_CREATE | NLM_F_EXCL);
+        unsafe { libc::close(fd) };
+        sent
+    }
+    
+    fn set_link_master(&self, ifname: &str, master: &str) -> Result<()> {
+        set_link(ifname, LinkNla::Master(link_index(master)?))
+    }
+    
+    fn add_macsec(&self, link: &NewMacsec) -> Result<()> {
+        let cipher = match link.cipher {
+            "gcm-aes-128" => MacSecCipherId::GcmAes128,
+            "gcm-aes-256" => MacSecCipherId::GcmAes256,
+            "gcm-aes-xpn-128" => MacSecCipherId::GcmAesXpn128,
+            "gcm-aes-xpn-256" => MacSecCipherId::GcmAesXpn256,
+            other => anyhow::bail!("Unknown MACsec cipher {}", other),
+        };
+        let data = vec![
+            InfoMacSec::Port(be16(1)),
+            InfoMacSec::CipherSuite(cipher),
+            InfoMacSec::Encrypt(link.encrypt as u8),
+        ];
+        let message = link_message(0, vec![
+            LinkNla::IfName(link.name.to_string()),
+            LinkNla::Link(link_index(link.lower)?),
+            LinkNla::Info(vec![Info::Kind(InfoKind::MacSec), Info::Data(InfoData::MacSec(data))]),
+        ]);
+        request(RtnlMessage::NewLink(message), NLM_F_CREATE | NLM_F_EXCL)
+    }
+    
+    fn add_macsec_sas(&self, ifname: &str, sas: &[MacsecSa]) -> Result<()> {
+        let index = link_index(ifname)?;
+        let socket = Socket::new(NETLINK_GENERIC).context("Failed to open generic netlink socket")?;
+        let family = macsec_family(&socket)?;
+        let request = |cmd: u8, nlas: Vec<DefaultNla>| GenlMessage {
+            family,
+            cmd,
+            version: MACSEC_GENL_VERSION,
+            nlas: std::iter::once(DefaultNla::new(MACSEC_ATTR_IFINDEX, index.to_ne_bytes().to_vec()))
+                .chain(nlas)
+                .collect(),
+        };
+        
+        for sa in sas {
+            let config = nested(MACSEC_ATTR_SA_CONFIG, &[
+                DefaultNla::new(MACSEC_SA_ATTR_AN, vec![0]),
+                DefaultNla::new(MACSEC_SA_ATTR_ACTIVE, vec![1]),
+                DefaultNla::new(MACSEC_SA_ATTR_PN, sa.pn.to_ne_bytes().to_vec()),
+                DefaultNla::new(MACSEC_SA_ATTR_KEY, sa.key.to_vec()),
+                DefaultNla::new(MACSEC_SA_ATTR_KEYID, sa.key_id.to_vec()),
+                DefaultNla::new(MACSEC_SA_ATTR_SSCI, MACSEC_SSCI.to_be_bytes().to_vec()),
+                DefaultNla::new(MACSEC_SA_ATTR_SALT, sa.salt.to_vec()),
+            ]);
+            
+            match sa.peer {
+                Some(peer) => {
+                    // The SCI is the peer's MAC followed by its port
+                    let mut sci = parse_mac(peer)?;
+                    sci.extend(1u16.to_be_bytes());
+                    let channel = nested(MACSEC_ATTR_RXSC_CONFIG, &[DefaultNla::new(MACSEC_RXSC_ATTR_SCI, sci)]);
+                    exchange(&socket, request(MACSEC_CMD_ADD_RXSC, vec![channel.clone()]), 0)?;
+                    exchange(&socket, request(MACSEC_CMD_ADD_RXSA, vec![channel, config]), 0)?;
+                }
+                None => {
+                    exchange(&socket, request(MACSEC_CMD_ADD_TXSA, vec![config]), 0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    fn add_route(&self, route: &NewRoute) -> Result<()> {
+        let default = match route.family() {
+            IpFamily::V4 => "0.0.0.0/0",
+            IpFamily::V6 => "::/0",
+        };
+        let dst = route.dst.unwrap_or(default);
+        let dst: IpNetwork = dst.parse()
+            .with_context(|| format!("Invalid route destination {}", dst))?;
+        let gateway = route.gateway
+            .map(|gw| gw.parse::<IpAddr>().with_context(|| format!("Invalid gateway {}", gw)))
+            .transpose()?;
+        let table = route.table.unwrap_or(RT_TABLE_MAIN as u32);
+        
+        // Like ip, a route without a gateway has link scope. Tables past the
+        // header's byte are only given by the attribute
+        let mut message = RouteMessage::default();
+        message.header.address_family = address_family(dst.ip());
+        message.header.destination_prefix_length = dst.prefix();
+        message.header.table = u8::try_from(table).unwrap_or(0);
+        message.header.protocol = RTPROT_BOOT;
+        message.header.scope = if gateway.is_some() { RT_SCOPE_UNIVERSE } else { RT_SCOPE_LINK };
+        message.header.kind = RTN_UNICAST;
+        if route.onlink {
+            // RouteFlags only names the RTM_F_* query flags, while the
+            // kernel reads the next hop flags from the same field
+            message.header.flags = unsafe { RouteFlags::from_bits_unchecked(RTNH_F_ONLINK as u32) };
+        }
+        
+        if dst.prefix() > 0 {
+            message.nlas.push(RouteNla::Destination(ip_octets(dst.ip())));
+        }
+        if let Some(gateway) = gateway {
+            message.nlas.push(RouteNla::Gateway(ip_octets(gateway)));
+        }
+        message.nlas.push(RouteNla::Oif(link_index(route.dev)?));
+        if let Some(metric) = route.metric {
+            message.nlas.push(RouteNla::Priority(metric));
+        }
+        message.nlas.push(RouteNla::Table(table));
+        request(RtnlMessage::NewRoute(message), NLM_F_CREATE | NLM_F_EXCL)
+    }
+    
+    fn add_source_rule(&self, source: &str, table: u32) -> Result<()> {
+        request(RtnlMessage::NewRule(rule_message(source, table)?), NLM_F_CREATE | NLM_F_EXCL)
+    }
+    
+    fn delete_source_rule(&self, source: &str, table: u32) -> Result<()> {
+        request(RtnlMessage::DelRule(rule_message(source, table)?), 0)
+    }
+    
+    fn set_neighbor(&self, ifname: &str, ip: &str, lladdr: &str) -> Result<()> {
+        let mut message = neighbor_message(ifname, ip, NUD_PERMANENT, 0)?;
+        message.nlas.push(NeighbourNla::LinkLocalAddress(parse_mac(lladdr)?));
+        request(RtnlMessage::NewNeighbour(message), NLM_F_CREATE | NLM_F_REPLACE)
+    }
+    
+    fn probe_neighbor(&self, ifname: &str, ip: &str) -> Result<()> {
+        let message = neighbor_message(ifname, ip, NUD_PERMANENT, NTF_USE)?;
+        request(RtnlMessage::NewNeighbour(message), NLM_F_CREATE | NLM_F_REPLACE)
+    }
+    
+    fn delete_neighbor(&self, ifname: &str, ip: &str) -> Result<()> {
+        request(RtnlMessage::DelNeighbour(neighbor_message(ifname, ip, 0, 0)?), 0)
+    }
+    
+    fn link(&self, ifname: &str) -> Result<Option<serde_json::Value>> {
+        Ok(get_link(ifname)?.map(|link| link_json(&link)))
+    }
+    
+    fn link_stats(&self, ifname: &str) -> Result<serde_json::Value> {
+        self.link(ifname)?
+            .ok_or_else(|| anyhow::anyhow!("Link {} not found", ifname))
+    }
+    
+    fn link_slaves(&self, master: &str) -> Result<Vec<serde_json::Value>> {
+        Ok(dump(RtnlMessage::GetLink(LinkMessage::default()))?
+            .iter()
+            .filter_map(|reply| match reply {
+                RtnlMessage::NewLink(link) => Some(link_json(link)),
+                _ => None,
+            })
+            .filter(|link| link.get("master").and_then(|m| m.as_str()) == Some(master))
+            .collect())
+    }
+    
+    fn addresses(&self, ifname: &str) -> Result<Option<serde_json::Value>> {
+        let Some(link) = get_link(ifname)? else {
+            return Ok(None);
+        };
+        let addr_info: Vec<serde_json::Value> = dump(RtnlMessage::GetAddress(AddressMessage::default()))?
+            .iter()
+            .filter_map(|reply| match reply {
+                RtnlMessage::NewAddress(address) if address.header.index == link.header.index => address_json(address),
+                _ => None,
+            })
+            .collect();
+        let mut link = link_json(&link);
+        link["addr_info"] = addr_info.into();
+        Ok(Some(link))
+    }
+    
+    fn routes(&self, ifname: &str, family: IpFamily) -> Result<Vec<serde_json::Value>> {
+        let index = link_index(ifname)?;
+        let mut message = RouteMessage::default();
+        message.header.address_family = family_of(family);
+        Ok(dump(RtnlMessage::GetRoute(message))?
+            .iter()
+            .filter_map(|reply| match reply {
+                RtnlMessage::NewRoute(route) => route_json(route, index, ifname),
+                _ => None,
+            })
+            .collect())
+    }
+    
+    fn rules(&self, family: IpFamily) -> Result<Vec<serde_json::Value>> {
+        let mut message = RuleMessage::default();
+        message.header.family = family_of(family);
+        Ok(dump(RtnlMessage::GetRule(message))?
+            .iter()
+            .filter_map(|reply| match reply {
+                RtnlMessage::NewRule(rule) => Some(rule_json(rule)),
+                _ => None,
+            })
+            .collect())
+    }
+    
+    fn neighbors(&self, ifname: &str, ip: Option<&str>) -> Result<Vec<serde_json::Value>> {
+        let index = link_index(ifname)?;
+        Ok(dump(RtnlMessage::GetNeighbour(NeighbourMessage::default()))?
+            .iter()
+            .filter_map(|reply| match reply {
+                RtnlMessage::NewNeighbour(neighbor) if neighbor.header.ifindex == index => neighbor_json(neighbor, ifname),
+                _ => None,
+            })
+            .filter(|neighbor| ip.map_or(true, |ip| neighbor["dst"] == ip))
+            .collect())
+    }
+}
+
+/// RTM_NEWLINK payload creating `link` on the link at index `parent`,
+/// inside the namespace open at `netns_fd` when given
+fn add_link_message(link: &NewLink, parent: u32, netns_fd: Option<c_int>) -> Result<LinkMessage> {
+    let (kind, data) = match &link.kind {
+        LinkKind::Vlan { id, protocol, flags } => {
+            let mut data = vec![InfoVlan::Id(*id)];
+            if let Some(protocol) = protocol {
+                let ethertype: u16 = match protocol.to_ascii_lowercase().as_str() {
+                    "802.1q" => 0x8100,
+                    "802.1ad" => 0x88a8,
+                    _ => anyhow::bail!("Unknown VLAN protocol {}", protocol),
+                };
+                data.push(InfoVlan::Protocol(be16(ethertype)));
+            }
+            let mut data = emit_nlas(&data);
+            
+            // InfoVlan::Flags writes the mask over the flags, so the
+            // attribute is encoded by hand
+            if !flags.is_empty() {
+                let (mut set, mut mask) = (0u32, 0u32);
+                for (flag, on) in flags {
+                    let bit = match *flag {
+                        "reorder_hdr" => VLAN_FLAG_REORDER_HDR,
+                        "gvrp" => VLAN_FLAG_GVRP,
+                        "loose_binding" => VLAN_FLAG_LOOSE_BINDING,
+                        "mvrp" => VLAN_FLAG_MVRP,
+                        other => anyhow::bail!("Unknown VLAN flag {}", other),
+                    };
+                    mask |= bit;
+                    if *on {
+                        set |= bit;
+                    }
+                }
+                let value = [set.to_ne_bytes(), mask.to_ne_bytes()].concat();
+                data.extend(emit_nlas(&[DefaultNla::new(IFLA_VLAN_FLAGS, value)]));
+            }
+            (InfoKind::Vlan, InfoData::Other(data))
+        }
+        LinkKind::Macvlan => (InfoKind::MacVlan, InfoData::MacVlan(vec![InfoMacVlan::Mode(MACVLAN_MODE_BRIDGE)])),
+    };
+    
+    let mut nlas = vec![LinkNla::IfName(link.name.to_string()), LinkNla::Link(parent)];
+    if let Some(mtu) = link.mtu {
+        nlas.push(LinkNla::Mtu(mtu));
+    }
+    if let Some(fd) = netns_fd {
+        nlas.push(LinkNla::NetNsFd(fd));
+    }
+    nlas.push(LinkNla::Info(vec![Info::Kind(kind), Info::Data(data)]));
+    Ok(link_message(0, nlas))
+}
+
+/// A link message addressing the link at `index`, 0 for a new one
+fn link_message(index: u32, nlas: Vec<LinkNla>) -> LinkMessage {
+    let mut message = LinkMessage::default();
+    message.header.index = index;
+    message.nlas = nlas;
+    message
+}
+
+/// Change one attribute of `ifname`
+fn set_link(ifname: &str, nla: LinkNla) -> Result<()> {
+    request(RtnlMessage::SetLink(link_message(link_index(ifname)?, vec![nla])), 0)
+}
+
+/// The rule looking up `table` for traffic from `source`
+fn rule_message(source: &str, table: u32) -> Result<RuleMessage> {
+    let source: IpAddr = source.parse()
+        .with_context(|| format!("Invalid rule source {}", source))?;
+    let mut message = RuleMessage::default();
+    message.header.family = address_family(source);
+    message.header.src_len = if source.is_ipv6() { 128 } else { 32 };
+    message.header.table = u8::try_from(table).unwrap_or(0);
+    message.header.action = FR_ACT_TO_TBL;
+    message.nlas = vec![RuleNla::Source(ip_octets(source)), RuleNla::Table(table)];
+    Ok(message)
+}
+
+/// The neighbor entry for `ip` on `ifname`
+fn neighbor_message(ifname: &str, ip: &str, state: u16, flags: u8) -> Result<NeighbourMessage> {
+    let ip: IpAddr = ip.parse()
+        .with_context(|| format!("Invalid neighbor address {}", ip))?;
+    let mut message = NeighbourMessage::default();
+    message.header.family = address_family(ip);
+    message.header.ifindex = link_index(ifname)?;
+    message.header.state = state;
+    message.header.flags = flags;
+    message.nlas = vec![NeighbourNla::Destination(ip_octets(ip))];
+    Ok(message)
+}
+
+/// Id of the generic netlink family MACsec is configured through
+fn macsec_family(socket: &Socket) -> Result<u16> {
+    let request = GenlMessage {
+        family: GENL_ID_CTRL,
+        cmd: CTRL_CMD_GETFAMILY,
+        version: CTRL_VERSION,
+        nlas: vec![DefaultNla::new(CTRL_ATTR_FAMILY_NAME, b"macsec\0".to_vec())],
+    };
+    let replies = exchange(socket, request, 0)
+        .context("Failed to look up the macsec generic netlink family")?;
+    replies.iter()
+        .flat_map(|reply| &reply.nlas)
+        .find(|nla| nla.kind() & NLA_TYPE_MASK == CTRL_ATTR_FAMILY_ID)
+        .and_then(|id| Some(u16::from_ne_bytes(nla_value(id).get(..2)?.try_into().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("The kernel reported no id for the macsec generic netlink family"))
+}
+
+/// Attribute `kind` holding `nlas`
+fn nested(kind: u16, nlas: &[DefaultNla]) -> DefaultNla {
+    DefaultNla::new(kind | NLA_F_NESTED, emit_nlas(nlas))
+}
+
+/// `nlas` encoded one after the other, as the payload of another attribute
+fn emit_nlas<T: Nla>(nlas: &[T]) -> Vec<u8> {
+    let mut buf = vec![0u8; nlas.buffer_len()];
+    nlas.emit(&mut buf);
+    buf
+}
+
+/// The payload of `nla`, without its header
+fn nla_value(nla: &impl Nla) -> Vec<u8> {
+    let mut value = vec![0u8; nla.value_len()];
+    nla.emit_value(&mut value);
+    value
+}
+
+/// Index of `ifname` in the current namespace, failing with ENODEV when there is none
+fn link_index(ifname: &str) -> Result<u32> {
+    let name = CString::new(ifname)?;
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(anyhow::Error::new(io::Error::from_raw_os_error(libc::ENODEV))
+            .context(format!("Cannot find device \"{}\"", ifname)));
+    }
+    Ok(index)
+}
+
+/// Name of the link at `index` in the current namespace
+fn index_name(index: u32) -> Option<String> {
+    let mut name = [0 as libc::c_char; libc::IF_NAMESIZE];
+    let found = unsafe { libc::if_indextoname(index, name.as_mut_ptr()) };
+    if found.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(name.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+/// The kernel's description of `ifname`, `None` when there is no such link
+fn get_link(ifname: &str) -> Result<Option<LinkMessage>> {
+    let found = link_index(ifname)
+        .and_then(|index| query(RtnlMessage::GetLink(link_message(index, Vec::new())), 0));
+    match found {
+        Ok(replies) => Ok(replies.into_iter().find_map(|reply| match reply {
+            RtnlMessage::NewLink(link) => Some(link),
+            _ => None,
+        })),
+        Err(e) if errno_of(&e) == Some(libc::ENODEV) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// `struct ifa_cacheinfo` carrying the lifetimes, where `u32::MAX` is forever
+fn cacheinfo(lifetimes: AddressLifetimes) -> Vec<u8> {
+    let valid = lifetimes.valid.unwrap_or(u32::MAX);
+    let mut info = CacheInfo::default();
+    info.ifa_valid = valid as i32;
+    info.ifa_preferred = lifetimes.preferred.unwrap_or(valid) as i32;
+    let mut buf = vec![0u8; info.buffer_len()];
+    info.emit(&mut buf);
+    buf
+}
+
+/// `value` laid out in network byte order, for the `__be16` attributes the
+/// crate writes in native order
+fn be16(value: u16) -> u16 {
+    u16::from_ne_bytes(value.to_be_bytes())
+}
+
+fn parse_mac(mac: &str) -> Result<Vec<u8>> {
+    let octets = mac.split(':')
+        .map(|octet| u8::from_str_radix(octet, 16))
+        .collect::<std::result::Result<Vec<u8>, _>>()
+        .ok()
+        .filter(|octets| octets.len() == 6);
+    octets.ok_or_else(|| anyhow::anyhow!("Invalid MAC address {}", mac))
+}
+
+fn format_mac(octets: &[u8]) -> String {
+    octets.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+}
+
+fn address_family(ip: IpAddr) -> u8 {
+    match ip {
+        IpAddr::V4(_) => libc::AF_INET as u8,
+        IpAddr::V6(_) => libc::AF_INET6 as u8,
+    }
+}
+
+fn family_of(family: IpFamily) -> u8 {
+    match family {
+        IpFamily::V4 => libc::AF_INET as u8,
+        IpFamily::V6 => libc::AF_INET6 as u8,
+    }
+}
+
+fn ip_octets(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+/// The address in an attribute, by its length
+fn ip_from(data: &[u8]) -> Option<IpAddr> {
+    match data.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+        _ => None,
+    }
+}
+
+/// Whether `len` covers all of `ip`, which `ip` prints without a prefix
+fn is_host_prefix(ip: IpAddr, len: u8) -> bool {
+    len == if ip.is_ipv6() { 128 } else { 32 }
+}
+
+/// A string attribute without its terminating NUL
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+/// A link in the shape `ip -d -s -j link show` prints it
+fn link_json(message: &LinkMessage) -> serde_json::Value {
+    let flags = message.header.flags;
+    let flag_names: Vec<&str> = [
+        (IFF_UP, "UP"),
+        (IFF_BROADCAST, "BROADCAST"),
+        (IFF_LOOPBACK, "LOOPBACK"),
+        (IFF_MULTICAST, "MULTICAST"),
+        (IFF_LOWER_UP, "LOWER_UP"),
+    ].into_iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| name)
+        .collect();
+    let mut link = serde_json::json!({"ifindex": message.header.index, "flags": flag_names});
+    
+    let (mut parent, mut parent_elsewhere) = (None, false);
+    for nla in &message.nlas {
+        match nla {
+            LinkNla::IfName(name) => link["ifname"] = name.as_str().into(),
+            LinkNla::Mtu(mtu) => link["mtu"] = (*mtu).into(),
+            LinkNla::Address(mac) => link["address"] = format_mac(mac).into(),
+            LinkNla::Link(index) => parent = Some(*index),
+            LinkNla::NetnsId(_) => parent_elsewhere = true,
+            LinkNla::Master(index) => {
+                if let Some(master) = index_name(*index) {
+                    link["master"] = master.into();
+                }
+            }
+            LinkNla::OperState(state) => link["operstate"] = operstate_name(u8::from(*state)).into(),
+            LinkNla::Stats64(data) => {
+                if let Some(stats) = stats64_json(data) {
+                    link["stats64"] = stats;
+                }
+            }
+            LinkNla::Info(info) => link["linkinfo"] = linkinfo_json(info),
+            _ => {}
+        }
+    }
+    
+    // The index of a parent in another namespace names nothing here
+    if let Some(parent) = parent {
+        match index_name(parent).filter(|_| !parent_elsewhere) {
+            Some(name) => link["link"] = name.into(),
+            None => link["link_index"] = parent.into(),
+        }
+    }
+    link
+}
+
+fn operstate_name(state: u8) -> &'static str {
+    match state {
+        1 => "NOTPRESENT",
+        2 => "DOWN",
+        3 => "LOWERLAYERDOWN",
+        4 => "TESTING",
+        5 => "DORMANT",
+        6 => "UP",
+        _ => "UNKNOWN",
+    }
+}
+
+/// `struct rtnl_link_stats64` as the `stats64` object of `ip -s -j`
+fn stats64_json(data: &[u8]) -> Option<serde_json::Value> {
+    let stats = Stats64::parse(&Stats64Buffer::new_checked(data).ok()?).ok()?;
+    Some(serde_json::json!({
+        "rx": {"bytes": stats.rx_bytes, "packets": stats.rx_packets, "errors": stats.rx_errors, "dropped": stats.rx_dropped},
+        "tx": {"bytes": stats.tx_bytes, "packets": stats.tx_packets, "errors": stats.tx_errors, "dropped": stats.tx_dropped},
+    }))
+}
+
+/// IFLA_LINKINFO as the `linkinfo` object of `ip -d -j`, with the VLAN
+/// details `info_data` has for VLAN links
+fn linkinfo_json(info: &[Info]) -> serde_json::Value {
+    let mut linkinfo = serde_json::json!({});
+    for info in info {
+        match info {
+            Info::Kind(kind) => linkinfo["info_kind"] = c_string(&nla_value(kind)).into(),
+            Info::Data(InfoData::Vlan(data)) => {
+                let mut vlan = serde_json::json!({});
+                for attr in data {
+                    match attr {
+                        InfoVlan::Id(id) => vlan["id"] = (*id).into(),
+                        InfoVlan::Protocol(protocol) => {
+                            vlan["protocol"] = if *protocol == 0x88a8 { "802.1ad" } else { "802.1Q" }.into();
+                        }
+                        _ => {}
+                    }
+                }
+                linkinfo["info_data"] = vlan;
+            }
+            _ => {}
+        }
+    }
+    linkinfo
+}
+
+/// An address as an `addr_info` entry
+fn address_json(message: &AddressMessage) -> Option<serde_json::Value> {
+    let (mut local, mut address) = (None, None);
+    for nla in &message.nlas {
+        match nla {
+            AddressNla::Local(data) => local = ip_from(data),
+            AddressNla::Address(data) => address = ip_from(data),
+            _ => {}
+        }
+    }
+    let ip = local.or(address)?;
+    let scope: serde_json::Value = match message.header.scope {
+        RT_SCOPE_UNIVERSE => "global".into(),
+        RT_SCOPE_LINK => "link".into(),
+        RT_SCOPE_HOST => "host".into(),
+        scope => scope.into(),
+    };
+    Some(serde_json::json!({
+        "family": if ip.is_ipv6() { "inet6" } else { "inet" },
+        "local": ip.to_string(),
+        "prefixlen": message.header.prefix_len,
+        "scope": scope,
+    }))
+}
+
+/// A main-table unicast route through the link at `index` as
+/// `ip -j route show` prints it
+fn route_json(message: &RouteMessage, index: u32, ifname: &str) -> Option<serde_json::Value> {
+    let mut table = message.header.table as u32;
+    let (mut dst, mut gateway, mut oif, mut metric) = (None, None, None, None);
+    for nla in &message.nlas {
+        match nla {
+            RouteNla::Destination(data) => dst = ip_from(data),
+            RouteNla::Gateway(data) => gateway = ip_from(data),
+            RouteNla::Oif(data) => oif = Some(*data),
+            RouteNla::Priority(data) => metric = Some(*data),
+            RouteNla::Table(data) => table = *data,
+            _ => {}
+        }
+    }
+    if oif != Some(index) || table != RT_TABLE_MAIN as u32 || message.header.kind != RTN_UNICAST {
+        return None;
+    }
+    
+    let dst_len = message.header.destination_prefix_length;
+    let dst = match dst {
+        Some(dst) if is_host_prefix(dst, dst_len) => dst.to_string(),
+        Some(dst) => format!("{}/{}", dst, dst_len),
+        None => "default".to_string(),
+    };
+    let mut route = serde_json::json!({"dst": dst, "dev": ifname});
+    if let Some(gateway) = gateway {
+        route["gateway"] = gateway.to_string().into();
+    }
+    if let Some(metric) = metric {
+        route["metric"] = metric.into();
+    }
+    Some(route)
+}
+
+/// A rule as `ip -j rule show` prints it
+fn rule_json(message: &RuleMessage) -> serde_json::Value {
+    let mut table = message.header.table as u32;
+    let (mut src, mut priority) = (None, None);
+    for nla in &message.nlas {
+        match nla {
+            RuleNla::Source(data) => src = ip_from(data),
+            RuleNla::Priority(data) => priority = Some(*data),
+            RuleNla::Table(data) => table = *data,
+            _ => {}
+        }
+    }
+    
+    let table: serde_json::Value = match table {
+        253 => "default".into(),
+        254 => "main".into(),
+        255 => "local".into(),
+        table => table.into(),
+    };
+    let mut rule = serde_json::json!({"table": table});
+    if let Some(priority) = priority {
+        rule["priority"] = priority.into();
+    }
+    if let Some(src) = src {
+        let src_len = message.header.src_len;
+        rule["src"] = src.to_string().into();
+        if !is_host_prefix(src, src_len) {
+            rule["srclen"] = src_len.into();
+        }
+    }
+    rule
+}
+
+/// A neighbor entry as `ip -j neigh show` prints it
+fn neighbor_json(message: &NeighbourMessage, ifname: &str) -> Option<serde_json::Value> {
+    let (mut dst, mut lladdr) = (None, None);
+    for nla in &message.nlas {
+        match nla {
+            NeighbourNla::Destination(data) => dst = ip_from(data),
+            NeighbourNla::LinkLocalAddress(data) => lladdr = Some(format_mac(data)),
+            _ => {}
+        }
+    }
+    
+    let state = message.header.state;
+    let states: Vec<&str> = [
+        (0x01, "INCOMPLETE"),
+        (0x02, "REACHABLE"),
+        (0x04, "STALE"),
+        (0x08, "DELAY"),
+        (0x10, "PROBE"),
+        (0x20, "FAILED"),
+        (0x40, "NOARP"),
+        (NUD_PERMANENT, "PERMANENT"),
+    ].into_iter()
+        .filter(|(bit, _)| state & bit != 0)
+        .map(|(_, name)| name)
+        .collect();
+    let mut neighbor = serde_json::json!({"dst": dst?.to_string(), "dev": ifname, "state": states});
+    if let Some(lladdr) = lladdr {
+        neighbor["lladdr"] = lladdr.into();
+    }
+    Some(neighbor)
+}
+
+/// A generic netlink message: `struct genlmsghdr`, then attributes
+#[derive(Debug, Clone)]
+struct GenlMessage {
+    family: u16,
+    cmd: u8,
+    version: u8,
+    nlas: Vec<DefaultNla>,
+}
+
+impl NetlinkSerializable for GenlMessage {
+    fn message_type(&self) -> u16 {
+        self.family
+    }
+    
+    fn buffer_len(&self) -> usize {
+        GENL_HDRLEN + self.nlas.as_slice().buffer_len()
+    }
+    
+    fn serialize(&self, buffer: &mut [u8]) {
+        buffer[..GENL_HDRLEN].copy_from_slice(&[self.cmd, self.version, 0, 0]);
+        self.nlas.as_slice().emit(&mut buffer[GENL_HDRLEN..]);
+    }
+}
+
+impl NetlinkDeserializable for GenlMessage {
+    type Error = DecodeError;
+    
+    fn deserialize(header: &NetlinkHeader, payload: &[u8]) -> std::result::Result<Self, DecodeError> {
+        if payload.len() < GENL_HDRLEN {
+            return Err("Truncated generic netlink header".into());
+        }
+        let nlas = NlasIterator::new(&payload[GENL_HDRLEN..])
+            .map(|nla| nla.and_then(|nla| DefaultNla::parse(&nla)))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(Self { family: header.message_type, cmd: payload[0], version: payload[1], nlas })
+    }
+}
+
+/// Send `message` with `flags` on a fresh rtnetlink socket and wait for
+/// the kernel's answer
+fn request(message: RtnlMessage, flags: u16) -> Result<()> {
+    query(message, flags).map(|_| ())
+}
+
+/// Send `message` with `flags` on a fresh rtnetlink socket and collect the
+/// messages the kernel answers with
+fn query(message: RtnlMessage, flags: u16) -> Result<Vec<RtnlMessage>> {
+    let socket = Socket::new(NETLINK_ROUTE).context("Failed to open rtnetlink socket")?;
+    Ok(exchange(&socket, message, flags)?)
+}
+
+/// Every object a dump asked for with `message` lists
+fn dump(message: RtnlMessage) -> Result<Vec<RtnlMessage>> {
+    query(message, NLM_F_DUMP)
+}
+
+/// Send one acknowledged request to the kernel and collect its answer up
+/// to the acknowledgement or the end of the dump
+///
+/// Each datagram is read whole, however large, so a dump of many links
+/// or routes is never cut short.
+fn exchange<T>(socket: &Socket, payload: T, flags: u16) -> io::Result<Vec<T>>
+where
+    T: NetlinkSerializable + NetlinkDeserializable,
+{
+    let mut header = NetlinkHeader::default();
+    header.flags = NLM_F_REQUEST | NLM_F_ACK | flags;
+    header.sequence_number = 1;
+    let mut message = NetlinkMessage::new(header, NetlinkPayload::InnerMessage(payload));
+    message.finalize();
+    let mut buf = vec![0u8; message.buffer_len()];
+    message.serialize(&mut buf);
+    socket.send_to(&buf, &SocketAddr::new(0, 0), 0)?;
+    
+    let mut replies = Vec::new();
+    loop {
+        let (datagram, _) = socket.recv_from_full()?;
+        if datagram.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "netlink socket closed"));
+        }
+        if let Some(outcome) = parse_replies(&datagram, &mut replies) {
+            return outcome.map(|()| replies);
+        }
+    }
+}
+
+/// Collect the messages in `datagram` into `replies` until the one ending
+/// the exchange: an error message, whose code 0 is the acknowledgement and
+/// anything else a negated errno, or the end of a dump, which carries an
+/// error code the same way
+///
+/// Returns `None` when `datagram` holds no such message yet.
+fn parse_replies<T: NetlinkDeserializable>(datagram: &[u8], replies: &mut Vec<T>) -> Option<io::Result<()>> {
+    let invalid = |e: DecodeError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let mut rest = datagram;
+    while !rest.is_empty() {
+        let len = match NetlinkBuffer::new_checked(rest) {
+            Ok(buffer) => buffer.length() as usize,
+            Err(e) => return Some(Err(invalid(e))),
+        };
+        let message = match NetlinkMessage::<T>::deserialize(&rest[..len]) {
+            Ok(message) => message,
+            Err(e) => return Some(Err(invalid(e))),
+        };
+        match message.payload {
+            NetlinkPayload::Error(e) if e.code.is_none() => return Some(Ok(())),
+            NetlinkPayload::Error(e) => return Some(Err(e.to_io())),
+            NetlinkPayload::Done(done) if done.code == 0 => return Some(Ok(())),
+            NetlinkPayload::Done(done) => return Some(Err(io::Error::from_raw_os_error(done.code.abs()))),
+            NetlinkPayload::InnerMessage(reply) => replies.push(reply),
+            _ => {}
+        }
+        rest = &rest[((len + 3) & !3).min(rest.len())..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn encode(message: RtnlMessage) -> Vec<u8> {
+        let mut message = NetlinkMessage::new(NetlinkHeader::default(), NetlinkPayload::InnerMessage(message));
+        message.finalize();
+        let mut buf = vec![0u8; message.buffer_len()];
+        message.serialize(&mut buf);
+        buf
+    }
+    
+    #[test]
+    fn test_vlan_link_encoding() {
+        let link = NewLink {
+            name: "eth0.100",
+            parent: "eth0",
+            kind: LinkKind::Vlan { id: 100, protocol: Some("802.1ad".to_string()), flags: vec![("mvrp", true)] },
+            mtu: Some(9000),
+            netns: None,
+        };
+        let buf = encode(RtnlMessage::NewLink(add_link_message(&link, 2, None).unwrap()));
+        
+        // The attributes after the nlmsghdr and ifinfomsg, as the kernel reads them
+        let attrs = |data: &[u8]| NlasIterator::new(data)
+            .map(|nla| nla.map(|nla| (nla.kind(), nla.value().to_vec())).unwrap())
+            .collect::<Vec<_>>();
+        let get = |attrs: &[(u16, Vec<u8>)], kind: u16| attrs.iter().find(|(k, _)| *k == kind).map(|(_, v)| v.clone()).unwrap();
+        let top = attrs(&buf[32..]);
+        assert_eq!(get(&top, libc::IFLA_IFNAME), b"eth0.100\0");
+        assert_eq!(get(&top, libc::IFLA_LINK), 2u32.to_ne_bytes());
+        assert_eq!(get(&top, libc::IFLA_MTU), 9000u32.to_ne_bytes());
+        
+        let linkinfo = attrs(&get(&top, libc::IFLA_LINKINFO));
+        assert_eq!(c_string(&get(&linkinfo, libc::IFLA_INFO_KIND)), "vlan");
+        let data = attrs(&get(&linkinfo, libc::IFLA_INFO_DATA));
+        assert_eq!(get(&data, 1), 100u16.to_ne_bytes());
+        assert_eq!(get(&data, 2), [8u32.to_ne_bytes(), 8u32.to_ne_bytes()].concat());
+        // The protocol is a __be16
+        assert_eq!(get(&data, 5), [0x88, 0xa8]);
+        
+        // And reads back the way `ip -d -j` shows it
+        let NetlinkPayload::InnerMessage(RtnlMessage::NewLink(parsed)) = NetlinkMessage::<RtnlMessage>::deserialize(&buf).unwrap().payload else {
+            panic!("not a link message");
+        };
+        let json = linkinfo_json(parsed.nlas.iter().find_map(|nla| match nla {
+            LinkNla::Info(info) => Some(info.as_slice()),
+            _ => None,
+        }).unwrap());
+        assert_eq!(json["info_data"], serde_json::json!({"id": 100, "protocol": "802.1ad"}));
+        
+        let bad = NewLink { kind: LinkKind::Vlan { id: 100, protocol: Some("802.1x".to_string()), flags: Vec::new() }, ..link };
+        assert!(add_link_message(&bad, 2, None).is_err());
+    }
+    
+    #[test]
+    fn test_replies_end_at_ack_or_done() {
+        let message = |kind: u16, payload: &[u8]| {
+            let mut message = ((16 + payload.len()) as u32).to_ne_bytes().to_vec();
+            message.extend(kind.to_ne_bytes());
+            message.extend([0u8; 10]);
+            message.extend(payload);
+            message
+        };
+        let ack = |code: i32| message(libc::NLMSG_ERROR as u16, &[code.to_ne_bytes().as_slice(), &[0u8; 16]].concat());
+        
+        let mut replies: Vec<RtnlMessage> = Vec::new();
+        assert!(parse_replies(&ack(0), &mut replies).unwrap().is_ok());
+        let err = parse_replies(&ack(-libc::EPERM), &mut replies).unwrap().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EPERM));
+        assert!(parse_replies(&ack(0)[..20], &mut replies).unwrap().is_err());
+        assert!(parse_replies(&[], &mut replies).is_none());
+        
+        // Links are collected until the dump ends, over several datagrams
+        let link = encode(RtnlMessage::NewLink(link_message(3, vec![LinkNla::IfName("eth0".to_string())])));
+        assert!(parse_replies(&link, &mut replies).is_none());
+        let done = [link.clone(), message(libc::NLMSG_DONE as u16, &0i32.to_ne_bytes())].concat();
+        assert!(parse_replies(&done, &mut replies).unwrap().is_ok());
+        assert_eq!(replies.len(), 2);
+        assert!(matches!(&replies[1], RtnlMessage::NewLink(link) if link.header.index == 3));
+        
+        // A failed dump reports its errno
+        let failed = message(libc::NLMSG_DONE as u16, &(-libc::ENODEV).to_ne_bytes());
+        let err = parse_replies(&failed, &mut Vec::<RtnlMessage>::new()).unwrap().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENODEV));
+    }
+}
@@ -1,13 +1,16 @@
 use anyhow::{Context, Result};
 use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::{Arc, Mutex};
 
+use socni::integrations::aranya::{vlan_label, AccessExplanation, DaemonInfo};
+
 /// Simplified Aranya client for the socni-ctl binary
 pub struct AranyaClient {
     socket_path: PathBuf,
     tenant_id: String,
     vlan_configs: Arc<Mutex<HashMap<u16, bool>>>,
+    vlan_tenants: Arc<Mutex<BTreeMap<u16, BTreeSet<String>>>>,
 }
 
 impl AranyaClient {
@@ -17,6 +20,7 @@ impl AranyaClient {
             socket_path,
             tenant_id,
             vlan_configs: Arc::new(Mutex::new(HashMap::new())),
+            vlan_tenants: Arc::new(Mutex::new(BTreeMap::new())),
         })
     }
 
@@ -31,6 +35,17 @@ impl AranyaClient {
         Ok(())
     }
 
+    /// Delete a VLAN and every tenant's access to it
+    pub fn delete_vlan(&mut self, vlan_id: u16) -> Result<()> {
+        // In a real implementation, this would delete the VLAN label
+        // For now, we'll just remove it from our local maps
+        self.vlan_configs.lock().unwrap().remove(&vlan_id);
+        self.vlan_tenants.lock().unwrap().remove(&vlan_id);
+        
+        println!("Deleted VLAN {} from Aranya", vlan_id);
+        Ok(())
+    }
+
     /// Check if we have access to a VLAN
     pub fn check_vlan_access(&self, vlan_id: u16) -> Result<bool> {
         // In a real implementation, this would check with the Aranya daemon
@@ -46,6 +61,9 @@ impl AranyaClient {
         let mut configs = self.vlan_configs.lock().unwrap();
         configs.insert(vlan_id, true);
         
+        let mut tenants = self.vlan_tenants.lock().unwrap();
+        tenants.entry(vlan_id).or_default().insert(tenant_id.to_string());
+        
         println!("Granted access to VLAN {} for tenant {}", vlan_id, tenant_id);
         Ok(())
     }
@@ -57,7 +75,95 @@ impl AranyaClient {
         let mut configs = self.vlan_configs.lock().unwrap();
         configs.remove(&vlan_id);
         
+        let mut tenants = self.vlan_tenants.lock().unwrap();
+        if let Some(assigned) = tenants.get_mut(&vlan_id) {
+            assigned.remove(tenant_id);
+        }
+        
         println!("Revoked access to VLAN {} for tenant {}", vlan_id, tenant_id);
         Ok(())
     }
-} 
\ No newline at end of file
+
+    /// Grant access to a VLAN for several tenants, returning each outcome
+    pub fn grant_vlan_access_many(&mut self, vlan_id: u16, tenant_ids: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        // In a real implementation, this would batch the assignments in a
+        // single call to the Aranya daemon
+        Ok(tenant_ids.iter()
+            .map(|tenant_id| (tenant_id.to_string(), self.grant_vlan_access(vlan_id, tenant_id)))
+            .collect())
+    }
+
+    /// Revoke access to a VLAN from several tenants, returning each outcome
+    pub fn revoke_vlan_access_many(&mut self, vlan_id: u16, tenant_ids: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        Ok(tenant_ids.iter()
+            .map(|tenant_id| (tenant_id.to_string(), self.revoke_vlan_access(vlan_id, tenant_id)))
+            .collect())
+    }
+
+    /// Break down the access decision for a tenant on a VLAN
+    pub fn explain_access(&self, vlan_id: u16, device_id: &str) -> Result<AccessExplanation> {
+        // In a real implementation, this would query the device's role and
+        // label assignments from the Aranya daemon; the role isn't tracked
+        // locally, so it is reported as unavailable
+        let configs = self.vlan_configs.lock().unwrap();
+        let tenants = self.vlan_tenants.lock().unwrap();
+        
+        let label = vlan_label(&self.tenant_id, vlan_id);
+        let label_exists = configs.contains_key(&vlan_id);
+        let labels: Vec<String> = tenants.iter()
+            .filter(|(_, assigned)| assigned.contains(device_id))
+            .map(|(id, _)| vlan_label(&self.tenant_id, *id))
+            .collect();
+        let has_label = labels.contains(&label);
+        
+        Ok(AccessExplanation {
+            vlan_id,
+            device_id: device_id.to_string(),
+            label,
+            role: "unavailable".to_string(),
+            labels,
+            label_exists,
+            has_label,
+            allowed: label_exists && has_label,
+        })
+    }
+
+    /// Device, role and visible VLAN labels as reported by the daemon
+    pub fn daemon_info(&self) -> Result<DaemonInfo> {
+        if !self.socket_path.exists() {
+            anyhow::bail!("Aranya daemon socket {} does not exist; is the daemon running?",
+                          self.socket_path.display());
+        }
+        
+        // In a real implementation, this would call get_device_id and
+        // device_role on the daemon and list the team's labels; until then
+        // the identity is reported as unknown rather than guessed
+        let configs = self.vlan_configs.lock().unwrap();
+        let mut vlan_labels: Vec<String> = configs.keys().map(|id| vlan_label(&self.tenant_id, *id)).collect();
+        vlan_labels.sort();
+        
+        Ok(DaemonInfo {
+            device_id: "unknown".to_string(),
+            team_id: self.tenant_id.clone(),
+            role: "unknown".to_string(),
+            vlan_labels,
+        })
+    }
+
+    /// List every VLAN label with the tenants assigned to it
+    pub fn list_vlan_tenants(&self) -> Result<BTreeMap<u16, Vec<String>>> {
+        // In a real implementation, this would query the team's labels and
+        // each device's label assignments from the Aranya daemon
+        let configs = self.vlan_configs.lock().unwrap();
+        let tenants = self.vlan_tenants.lock().unwrap();
+        
+        let mut topology: BTreeMap<u16, Vec<String>> = configs.keys()
+            .map(|id| (*id, Vec::new()))
+            .collect();
+        for (vlan_id, assigned) in tenants.iter() {
+            topology.entry(*vlan_id).or_default().extend(assigned.iter().cloned());
+        }
+        
+        Ok(topology)
+    }
+}
\ No newline at end of file
@@ -1,13 +1,28 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand, builder::TypedValueParser};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant};
 use tracing::{info, warn, error};
 use tracing_subscriber::{FmtSubscriber, EnvFilter};
 
+use socni::config::{netconf_schema, validate_path_component, NetConf, OnRevoke, SocniConfig, DEFAULT_LEASE_TTL};
+use socni::doctor::{likely_causes, Evidence};
+use socni::events::rfc3339;
+use socni::logging::{OpLog, OpRecord};
+use socni::metrics::{self, Snapshot};
+use socni::netns;
+use socni::plugin::{check_master_mtu, VlanPlugin};
+use socni::ipam::{vlan_usage, HostLocal};
+use socni::ipam::daemon::{self, DEFAULT_SOCKET};
+use socni::reconcile::{self, Drift};
+use socni::state::{now_secs, StateStore};
+use socni::types::CmdArgs;
+use socni::verify::CheckItem;
+
 // Import our local AranyaClient
 mod aranya;
 use aranya::AranyaClient;
@@ -58,6 +73,17 @@ enum Commands {
         label: Vec<(String, String)>,
     },
 
+    /// Delete a VLAN, refusing while pods on this node still use it
+    Delete {
+        /// VLAN ID to delete
+        #[arg(long)]
+        vlan: u16,
+        
+        /// Delete even if pods still use the VLAN
+        #[arg(long)]
+        force: bool,
+    },
+    
     /// List available VLANs
     List {
         /// Show detailed information
@@ -71,9 +97,9 @@ enum Commands {
         #[arg(long)]
         vlan_id: u16,
 
-        /// Target tenant ID to grant access to
-        #[arg(long)]
-        target_tenant: String,
+        /// Target tenant ID(s) to grant access to, comma-separated
+        #[arg(long, value_delimiter = ',', required = true)]
+        target_tenant: Vec<String>,
     },
 
     /// Revoke VLAN access from a tenant
@@ -82,9 +108,9 @@ enum Commands {
         #[arg(long)]
         vlan_id: u16,
 
-        /// Target tenant ID to revoke access from
-        #[arg(long)]
-        target_tenant: String,
+        /// Target tenant ID(s) to revoke access from, comma-separated
+        #[arg(long, value_delimiter = ',', required = true)]
+        target_tenant: Vec<String>,
     },
 
     /// Generate a VLAN configuration
@@ -134,6 +160,217 @@ enum Commands {
         /// VLAN ID to check
         #[arg(long)]
         id: Option<u16>,
+        
+        /// Report pod traffic since ADD instead of interface state
+        #[arg(long)]
+        delta: bool,
+    },
+
+    /// Print the JSON Schema for the plugin's network configuration
+    Schema {
+        /// Output file path
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Explain why a device is allowed or denied on a VLAN
+    Explain {
+        /// VLAN ID to check
+        #[arg(long)]
+        vlan: u16,
+
+        /// Device (or tenant) ID to check
+        #[arg(long)]
+        device: String,
+    },
+
+    /// Check the Aranya daemon is reachable and show the identity socni uses
+    AranyaInfo,
+
+    /// Show which tenants can reach which VLANs and the host interfaces backing them
+    Topology {
+        /// Output format (table or dot)
+        #[arg(long, default_value = "table", value_parser = ["table", "dot"])]
+        format: String,
+    },
+
+    /// Compare configured networks with live interfaces and IPAM state
+    Reconcile {
+        /// Directory of conflists to compare against (defaults to the global --config-dir)
+        #[arg(long)]
+        config_dir: Option<PathBuf>,
+
+        /// Fix what can be fixed: delete orphaned links socni made, reset MTUs, release stale leases
+        #[arg(long)]
+        apply: bool,
+    },
+
+    /// Change a master's MTU, refusing to lower it below the socni VLANs on it
+    MasterMtu {
+        /// Master interface
+        #[arg(long)]
+        master: String,
+        
+        /// New MTU
+        #[arg(long)]
+        mtu: u32,
+    },
+    
+    /// Show the plugin's ADD/CHECK/DEL history from the operation log
+    Logs {
+        /// Only show operations for this container ID (a prefix is enough)
+        #[arg(long)]
+        container: Option<String>,
+
+        /// Only show operations newer than this (e.g. 30s, 10m, 2h, 1d)
+        #[arg(long, value_parser = parse_since)]
+        since: Option<u64>,
+
+        /// Keep printing new operations as they are logged
+        #[arg(short, long)]
+        follow: bool,
+    },
+
+    /// Run the plugin's CHECK against a running pod and report each verification
+    Check {
+        /// Container ID the attachment was added for
+        #[arg(long)]
+        container: String,
+        
+        /// Path of the pod's network namespace (e.g. /var/run/netns/cni-1234)
+        #[arg(long)]
+        netns: PathBuf,
+        
+        /// Conflist (or single .conf) holding the socni network
+        #[arg(long)]
+        conflist: PathBuf,
+        
+        /// Interface name inside the pod
+        #[arg(long, default_value = "eth1")]
+        ifname: String,
+    },
+    
+    /// Diagnose a pod whose socni networking failed and list the likely causes
+    Doctor {
+        /// Container ID of the pod (a prefix is enough)
+        #[arg(long)]
+        container: String,
+    },
+    
+    /// Run ADD, CHECK and DEL against a throwaway namespace to validate an install (requires root)
+    E2e {
+        /// Master interface to attach the VLAN to
+        #[arg(long)]
+        master: String,
+        
+        /// VLAN ID (1-4094)
+        #[arg(long)]
+        vlan: u16,
+    },
+    
+    /// Time repeated ADD and DEL cycles against a throwaway namespace (requires root)
+    Bench {
+        /// Master interface to attach the VLAN to
+        #[arg(long)]
+        master: String,
+        
+        /// VLAN ID (1-4094)
+        #[arg(long)]
+        vlan: u16,
+        
+        /// Number of ADD and DEL cycles to time
+        #[arg(long, default_value_t = 100)]
+        iterations: usize,
+    },
+    
+    /// Watch VLAN access of running pods and apply each network's onRevoke policy
+    Watch {
+        /// Seconds between access checks
+        #[arg(long, default_value_t = 10)]
+        interval: u64,
+    },
+    
+    /// Serve node metrics in the OpenMetrics format to a scraper
+    Metrics {
+        /// TCP address to listen on
+        #[arg(long, default_value = "127.0.0.1:9465", conflicts_with = "unix")]
+        listen: String,
+        
+        /// Serve on this unix domain socket instead of a TCP port
+        #[arg(long)]
+        unix: Option<PathBuf>,
+    },
+    
+    /// Serve host-local allocation to `socni-ipamd` IPAM over a unix socket
+    Ipamd {
+        /// Unix socket to listen on
+        #[arg(long, default_value = DEFAULT_SOCKET)]
+        listen: PathBuf,
+    },
+    
+    /// Maintain host-local IPAM leases
+    Ipam {
+        #[command(subcommand)]
+        action: IpamCommands,
+    },
+
+    /// Inspect or reset persisted pod MAC reservations
+    Mac {
+        #[command(subcommand)]
+        action: MacCommands,
+    },
+    
+    /// Inspect the plugin's node-level configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the effective configuration as JSON with the source of each value
+    Show,
+}
+
+#[derive(Subcommand)]
+enum MacCommands {
+    /// List persisted MAC reservations
+    List,
+
+    /// Clear MAC reservations so pods get a fresh MAC on next ADD
+    Clear {
+        /// Pod identity (namespace/name) to clear
+        #[arg(long, conflicts_with = "all")]
+        pod: Option<String>,
+
+        /// Clear every reservation
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum IpamCommands {
+    /// Free leases whose container namespace is gone and whose TTL has expired
+    Gc {
+        /// TTL to apply to every network instead of each network's leaseTtl (e.g. 30m, 12h)
+        #[arg(long, value_parser = parse_since)]
+        ttl: Option<u64>,
+    },
+    
+    /// Show how many host-local addresses are allocated per VLAN
+    Status {
+        /// Only show this VLAN
+        #[arg(long)]
+        vlan: Option<u16>,
+    },
+    
+    /// Show which pods held an address and when
+    History {
+        /// Address to show the allocation timeline of
+        #[arg(long)]
+        address: std::net::IpAddr,
     },
 }
 
@@ -145,6 +382,19 @@ fn parse_key_val(s: &str) -> Result<(String, String), String> {
     Ok((parts[0].to_string(), parts[1].to_string()))
 }
 
+fn parse_since(s: &str) -> Result<u64, String> {
+    let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let value: u64 = value.parse().map_err(|_| format!("Invalid duration: {}", s))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("Invalid duration unit in {} (use s, m, h or d)", s)),
+    };
+    Ok(value * scale)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct VlanConfig {
     id: u16,
@@ -160,6 +410,9 @@ struct VlanStatus {
     name: String,
     state: String,
     master: String,
+    mtu: Option<u32>,
+    /// Container of a pod interface; `None` for a link on the host
+    container: Option<String>,
     tenants: Vec<String>,
 }
 
@@ -219,60 +472,935 @@ fn generate_network_config(
     }
 }
 
-fn get_vlan_status(id: Option<u16>) -> Result<Vec<VlanStatus>> {
+/// VLAN links on the host and the pod interfaces ADD recorded
+///
+/// Pod interfaces live in their pod's namespace, so their state and MTU are
+/// read there, falling back to the MTU recorded in the result when the
+/// namespace can't be entered.
+async fn get_vlan_status(id: Option<u16>) -> Result<Vec<VlanStatus>> {
+    // Without -d ip leaves out linkinfo, which is what tells a VLAN apart
     let output = Command::new("ip")
-        .args(&["-j", "link", "show"])
+        .args(&["-d", "-j", "link", "show", "type", "vlan"])
         .output()
         .context("Failed to execute ip link show command")?;
-
+    
     if !output.status.success() {
         anyhow::bail!(
             "Failed to get interface status: {}",
             String::from_utf8_lossy(&output.stderr)
         );
     }
-
+    
     let interfaces: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
         .context("Failed to parse ip link output")?;
-
+    
     let mut vlan_status = Vec::new();
     for iface in interfaces {
-        // Check if this is a VLAN interface
-        if let Some(link_info) = iface.get("linkinfo") {
-            if let Some(info_kind) = link_info.get("info_kind") {
-                if info_kind.as_str() == Some("vlan") {
-                    if let (Some(ifname), Some(iface_id), Some(state), Some(master)) = (
-                        iface.get("ifname").and_then(|v| v.as_str()),
-                        link_info
-                            .get("info_data")
-                            .and_then(|d| d.get("id"))
-                            .and_then(|v| v.as_u64()),
-                        iface.get("operstate").and_then(|v| v.as_str()),
-                        iface.get("master").and_then(|v| v.as_str()),
-                    ) {
-                        let vlan_id = iface_id as u16;
-                        
-                        // If specific ID was requested, filter for it
-                        if let Some(requested_id) = id {
-                            if vlan_id != requested_id {
-                                continue;
-                            }
-                        }
-                        
-                        vlan_status.push(VlanStatus {
-                            id: vlan_id,
-                            name: ifname.to_string(),
-                            state: state.to_string(),
-                            master: master.to_string(),
-                            tenants: Vec::new(), // We don't have this info from ip command
-                        });
-                    }
+        let vlan_id = iface.get("linkinfo")
+            .and_then(|l| l.get("info_data"))
+            .and_then(|d| d.get("id"))
+            .and_then(|v| v.as_u64());
+        // A VLAN's parent is its `link`; `master` is only set when it is enslaved
+        if let (Some(ifname), Some(vlan_id), Some(state), Some(master)) = (
+            iface.get("ifname").and_then(|v| v.as_str()),
+            vlan_id,
+            iface.get("operstate").and_then(|v| v.as_str()),
+            iface.get("link").or_else(|| iface.get("master")).and_then(|v| v.as_str()),
+        ) {
+            let vlan_id = vlan_id as u16;
+            
+            // If specific ID was requested, filter for it
+            if id.map_or(false, |requested| requested != vlan_id) {
+                continue;
+            }
+            
+            vlan_status.push(VlanStatus {
+                id: vlan_id,
+                name: ifname.to_string(),
+                state: state.to_string(),
+                master: master.to_string(),
+                mtu: iface.get("mtu").and_then(|v| v.as_u64()).map(|m| m as u32),
+                container: None,
+                tenants: Vec::new(), // We don't have this info from ip command
+            });
+        }
+    }
+    
+    let store = StateStore::new(SocniConfig::load().state_dir);
+    let mut attachments: Vec<_> = store.attachments()?
+        .into_values()
+        .filter(|a| id.map_or(true, |v| a.config.vlan == v))
+        .collect();
+    attachments.sort_by(|a, b| (a.config.vlan, &a.container_id).cmp(&(b.config.vlan, &b.container_id)));
+    
+    for attachment in attachments {
+        let result = store.load_result(&attachment.config.name, &attachment.container_id, &attachment.ifname)
+            .ok()
+            .flatten();
+        let link = VlanPlugin::for_attachment(&attachment).link().await.ok();
+        let live_mtu = link.as_ref().and_then(|l| l.get("mtu")).and_then(|m| m.as_u64()).map(|m| m as u32);
+        
+        vlan_status.push(VlanStatus {
+            id: attachment.config.vlan,
+            name: attachment.ifname.clone(),
+            state: link.as_ref()
+                .and_then(|l| l.get("operstate"))
+                .and_then(|s| s.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            master: result.as_ref()
+                .and_then(|r| r.socni("master").and_then(|m| m.as_str()).map(String::from))
+                .unwrap_or_else(|| attachment.config.master.clone()),
+            mtu: live_mtu.or_else(|| result.as_ref().and_then(|r| r.mtu())),
+            container: Some(attachment.container_id.clone()),
+            tenants: vec![attachment.tenant.clone()],
+        });
+    }
+    
+    Ok(vlan_status)
+}
+
+/// Print per-tenant outcomes of a bulk grant/revoke, failing if any failed
+fn report_bulk(results: &[(String, Result<()>)], vlan_id: u16, verb: &str) -> Result<()> {
+    let mut failed = 0;
+    for (tenant, outcome) in results {
+        match outcome {
+            Ok(()) => println!("Access to VLAN {} {} tenant {}", vlan_id, verb, tenant),
+            Err(e) => {
+                failed += 1;
+                println!("Access to VLAN {} could not be {} tenant {}: {}", vlan_id, verb, tenant, e);
+            }
+        }
+    }
+    
+    if failed > 0 {
+        anyhow::bail!("{} of {} tenant(s) failed", failed, results.len());
+    }
+    Ok(())
+}
+
+/// Print VLAN/tenant edges and host interfaces as a table or DOT graph
+fn print_topology(
+    vlan_tenants: &BTreeMap<u16, Vec<String>>,
+    interfaces: &[VlanStatus],
+    format: &str,
+) {
+    let mut vlan_ids: BTreeSet<u16> = vlan_tenants.keys().copied().collect();
+    vlan_ids.extend(interfaces.iter().map(|i| i.id));
+    
+    if format == "dot" {
+        println!("digraph socni {{");
+        println!("    rankdir=LR;");
+        for id in &vlan_ids {
+            println!("    \"vlan-{}\" [shape=box, label=\"VLAN {}\"];", id, id);
+            for tenant in vlan_tenants.get(id).into_iter().flatten() {
+                println!("    \"tenant:{}\" [shape=ellipse, label=\"{}\"];", tenant, tenant);
+                println!("    \"tenant:{}\" -> \"vlan-{}\";", tenant, id);
+            }
+            for iface in interfaces.iter().filter(|i| i.id == *id) {
+                // Pods reuse interface names, so theirs are keyed by container
+                let node = match &iface.container {
+                    Some(container) => format!("if:{}/{}", container, iface.name),
+                    None => format!("if:{}", iface.name),
+                };
+                println!("    \"{}\" [shape=note, label=\"{} ({}, {})\"];",
+                    node, iface.name, iface.master, iface.state);
+                println!("    \"vlan-{}\" -> \"{}\";", id, node);
+            }
+        }
+        println!("}}");
+        return;
+    }
+    
+    if vlan_ids.is_empty() {
+        println!("No VLANs found");
+        return;
+    }
+    
+    println!("{:<6} {:<30} {:<20} {:<10} {}", "VLAN", "TENANTS", "INTERFACE", "MASTER", "STATE");
+    for id in &vlan_ids {
+        let tenants = vlan_tenants.get(id)
+            .filter(|t| !t.is_empty())
+            .map(|t| t.join(","))
+            .unwrap_or_else(|| "-".to_string());
+        
+        let host: Vec<&VlanStatus> = interfaces.iter().filter(|i| i.id == *id).collect();
+        if host.is_empty() {
+            println!("{:<6} {:<30} {:<20} {:<10} {}", id, tenants, "-", "-", "absent");
+        }
+        for iface in host {
+            println!("{:<6} {:<30} {:<20} {:<10} {}", id, tenants, iface.name, iface.master, iface.state);
+        }
+    }
+}
+
+/// Report drift between conflists and the node, fixing it with `apply`
+fn run_reconcile(config_dir: &Path, apply: bool) -> Result<()> {
+    let networks = reconcile::load_networks(config_dir)?;
+    
+    let output = Command::new("ip")
+        .args(&["-d", "-j", "link", "show"])
+        .output()
+        .context("Failed to execute ip link show command")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to list interfaces: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    let links: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ip link output")?;
+    
+    let allocators = ipam_allocators(&networks);
+    let mut leases = BTreeMap::new();
+    let mut owners = BTreeMap::new();
+    for ipam in &allocators {
+        for network in ipam.networks()? {
+            leases.insert(network.clone(), ipam.state(&network)?);
+            owners.insert(network, ipam);
+        }
+    }
+    
+    // Only links socni recorded making are ever deleted
+    let recorded = StateStore::new(SocniConfig::load().state_dir).recorded_host_links()?;
+    
+    let drift = reconcile::diff(&networks, &links, &leases);
+    println!("Compared {} network(s) from {}", networks.len(), config_dir.display());
+    if drift.is_empty() {
+        println!("No drift detected");
+        return Ok(());
+    }
+    
+    for item in &drift {
+        println!("  {}", item);
+        if !apply {
+            continue;
+        }
+        
+        let fixed = match item {
+            Drift::MissingMaster { .. } => {
+                println!("    cannot fix automatically");
+                continue;
+            }
+            Drift::OrphanedLink { name } if !recorded.contains(name) => {
+                println!("    not recorded in socni state; leaving it");
+                continue;
+            }
+            Drift::OrphanedLink { name } => Command::new("ip")
+                .args(&["link", "delete", name])
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false),
+            Drift::MtuMismatch { name, expected, .. } => Command::new("ip")
+                .args(&["link", "set", "dev", name, "mtu", &expected.to_string()])
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false),
+            Drift::StaleLease { network, container_id, ifname, .. } => owners.get(network)
+                .map(|ipam| ipam.release(network, container_id, ifname).is_ok())
+                .unwrap_or(false),
+        };
+        println!("    {}", if fixed { "fixed" } else { "failed to fix" });
+    }
+    
+    if !apply {
+        println!("Run with --apply to fix the drift above");
+    }
+    Ok(())
+}
+
+/// Allocators for the node's state directory and every network's `dataDir`
+fn ipam_allocators(networks: &[NetConf]) -> Vec<HostLocal> {
+    let mut dirs = BTreeSet::new();
+    dirs.insert(SocniConfig::load().state_dir);
+    dirs.extend(networks.iter().filter_map(|n| n.data_dir.clone()));
+    dirs.into_iter().map(HostLocal::new).collect()
+}
+
+fn run_ipam_gc(config_dir: &Path, ttl: Option<u64>) -> Result<()> {
+    let networks = reconcile::load_networks(config_dir).unwrap_or_else(|e| {
+        warn!("Failed to load network configuration, using the default lease TTL: {:#}", e);
+        Vec::new()
+    });
+    
+    let mut total = 0;
+    for ipam in ipam_allocators(&networks) {
+        for network in ipam.networks()? {
+            let ttl = ttl
+                .or_else(|| networks.iter()
+                    .find(|n| network.split('/').next() == Some(n.name.as_str()))
+                    .and_then(|n| n.ipam.as_ref())
+                    .map(|i| i.lease_ttl))
+                .unwrap_or(DEFAULT_LEASE_TTL);
+            
+            for (ip, lease) in ipam.gc(&network, std::time::Duration::from_secs(ttl), &netns::netns_exists)? {
+                println!("{}: freed {} (container {}, {}, idle {}s)",
+                         network, ip, lease.container_id, lease.ifname, lease.idle_secs(now_secs()));
+                total += 1;
+            }
+        }
+    }
+    
+    println!("Freed {} expired lease(s)", total);
+    Ok(())
+}
+
+fn run_master_mtu(master: &str, mtu: u32) -> Result<()> {
+    let output = Command::new("ip")
+        .args(["-j", "link", "show", "dev", master])
+        .output()
+        .context("Failed to execute ip link show command")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to look up {}: {}", master, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let links: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse ip link output")?;
+    let current = links.first()
+        .and_then(|l| l.get("mtu"))
+        .and_then(|m| m.as_u64())
+        .map(|m| m as u32);
+    
+    let attachments = StateStore::new(SocniConfig::load().state_dir).attachments()?;
+    check_master_mtu(&attachments, master, current, mtu)?;
+    
+    let output = Command::new("ip")
+        .args(["link", "set", "dev", master, "mtu", &mtu.to_string()])
+        .output()
+        .context("Failed to execute ip link set command")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to set the MTU of {}: {}", master, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    
+    let users = attachments.values().filter(|a| a.config.master == master).count();
+    println!("Set the MTU of {} from {} to {} ({} socni VLAN attachment(s) on it)",
+             master, current.map_or_else(|| "?".to_string(), |m| m.to_string()), mtu, users);
+    Ok(())
+}
+
+fn run_delete(aranya: &mut AranyaClient, vlan: u16, force: bool) -> Result<()> {
+    let references = StateStore::new(SocniConfig::load().state_dir).vlan_references(vlan)?;
+    let pods: Vec<String> = references.iter()
+        .map(|a| format!("{} of container {} on network {}", a.ifname, a.container_id, a.config.name))
+        .collect();
+    
+    if !pods.is_empty() {
+        if !force {
+            anyhow::bail!("VLAN {} is still used by {} pod interface(s) on this node: {}. \
+                           Deleting it would cut them off; pass --force to delete it anyway",
+                          vlan, pods.len(), pods.join(", "));
+        }
+        warn!("Deleting VLAN {} while still in use by {}", vlan, pods.join(", "));
+    }
+    
+    aranya.delete_vlan(vlan)
+}
+
+async fn run_status_delta(vlan: Option<u16>) -> Result<()> {
+    let store = StateStore::new(SocniConfig::load().state_dir);
+    let mut attachments: Vec<_> = store.attachments()?
+        .into_values()
+        .filter(|a| vlan.map_or(true, |v| a.config.vlan == v))
+        .collect();
+    attachments.sort_by(|a, b| (a.config.vlan, &a.container_id).cmp(&(b.config.vlan, &b.container_id)));
+    
+    if attachments.is_empty() {
+        println!("No pod interfaces found");
+        return Ok(());
+    }
+    
+    let now = now_secs();
+    println!("Pod Traffic Since ADD:");
+    for attachment in attachments {
+        println!("  VLAN {} {} of container {} ({}):", attachment.config.vlan, attachment.ifname,
+                 attachment.container_id, attachment.config.name);
+        
+        // The result records the master ADD actually used
+        let master = store.load_result(&attachment.config.name, &attachment.container_id, &attachment.ifname)
+            .ok()
+            .flatten()
+            .and_then(|result| result.socni("master").and_then(|m| m.as_str()).map(String::from))
+            .unwrap_or_else(|| attachment.config.master.clone());
+        println!("    Master: {}", master);
+        
+        let baseline = match store.load_baseline(&attachment.config.name, &attachment.container_id, &attachment.ifname)? {
+            Some(baseline) => baseline,
+            None => {
+                println!("    No baseline recorded at ADD");
+                continue;
+            }
+        };
+        let current = match VlanPlugin::for_attachment(&attachment).link_stats().await {
+            Ok(current) => current,
+            Err(e) => {
+                println!("    Error reading statistics: {:#}", e);
+                continue;
+            }
+        };
+        
+        let delta = current.since(&baseline.stats);
+        println!("    Elapsed: {}s", now.saturating_sub(baseline.taken));
+        println!("    RX: {} bytes, {} packets, {} dropped", delta.rx_bytes, delta.rx_packets, delta.rx_dropped);
+        println!("    TX: {} bytes, {} packets, {} dropped", delta.tx_bytes, delta.tx_packets, delta.tx_dropped);
+    }
+    Ok(())
+}
+
+fn run_config_show() -> Result<()> {
+    let (config, sources) = SocniConfig::load_with_sources();
+    let values = serde_json::to_value(&config)?;
+    
+    let mut shown = serde_json::Map::new();
+    for (field, source) in sources {
+        let value = values.get(&field).cloned().unwrap_or_default();
+        shown.insert(field, serde_json::json!({"value": value, "source": source}));
+    }
+    
+    // The plugin reads the Aranya connection settings straight from its environment
+    for (field, var, default) in [
+        ("aranya_socket_path", "ARANYA_SOCKET_PATH", Some("/var/run/aranya/api.sock")),
+        ("aranya_tenant_id", "ARANYA_TENANT_ID", None),
+    ] {
+        let entry = match std::env::var(var) {
+            Ok(value) => serde_json::json!({"value": value, "source": format!("env {}", var)}),
+            Err(_) => serde_json::json!({"value": default, "source": "default"}),
+        };
+        shown.insert(field.to_string(), entry);
+    }
+    
+    println!("{}", serde_json::to_string_pretty(&shown)?);
+    Ok(())
+}
+
+fn run_ipam_history(config_dir: &Path, address: std::net::IpAddr) -> Result<()> {
+    let configs = reconcile::load_networks(config_dir).unwrap_or_else(|e| {
+        warn!("Failed to load network configuration, showing the state directory only: {:#}", e);
+        Vec::new()
+    });
+    let mut events = Vec::new();
+    for ipam in ipam_allocators(&configs) {
+        events.extend(ipam.history(address)?);
+    }
+    events.sort_by_key(|(_, event)| event.timestamp);
+    
+    if events.is_empty() {
+        println!("No allocation history for {}", address);
+        return Ok(());
+    }
+    
+    println!("{:<20} {:<9} {:<20} {:<5} {:<12} {:<8} {:<30} {}",
+             "TIME", "ACTION", "NETWORK", "VLAN", "CONTAINER", "IFNAME", "POD", "UID");
+    for (network, event) in events {
+        let container: String = event.container_id.chars().take(12).collect();
+        println!("{:<20} {:<9} {:<20} {:<5} {:<12} {:<8} {:<30} {}",
+                 rfc3339(event.timestamp), event.action, network,
+                 event.vlan.map_or("-".to_string(), |v| v.to_string()), container, event.ifname,
+                 event.identity.as_deref().unwrap_or("-"), event.uid.as_deref().unwrap_or("-"));
+    }
+    Ok(())
+}
+
+fn run_ipam_status(config_dir: &Path, vlan: Option<u16>) -> Result<()> {
+    let configs = reconcile::load_networks(config_dir).unwrap_or_else(|e| {
+        warn!("Failed to load network configuration, showing the state directory only: {:#}", e);
+        Vec::new()
+    });
+    let mut networks = Vec::new();
+    for ipam in ipam_allocators(&configs) {
+        networks.extend(ipam.usage()?);
+    }
+    let usage: Vec<_> = vlan_usage(&networks).into_iter()
+        .filter(|u| vlan.map_or(true, |vlan| u.vlan == vlan))
+        .collect();
+    
+    if usage.is_empty() {
+        match vlan {
+            Some(vlan) => println!("No host-local allocations recorded for VLAN {}", vlan),
+            None => println!("No host-local allocations recorded"),
+        }
+    }
+    for vlan_usage in &usage {
+        println!("VLAN {}: {} of {} address(es) allocated ({:.1}%)",
+                 vlan_usage.vlan, vlan_usage.allocated, vlan_usage.capacity, vlan_usage.utilization());
+        for network in networks.iter().filter(|n| vlan_usage.networks.contains(&n.network)) {
+            let capacity = network.capacity.map_or_else(|| "?".to_string(), |c| c.to_string());
+            println!("  {}: {} of {}", network.network, network.allocated, capacity);
+        }
+    }
+    
+    // State written before usage was recorded has no VLAN until its next allocation
+    if vlan.is_none() {
+        for network in networks.iter().filter(|n| n.vlan.is_none()) {
+            println!("{}: {} address(es) allocated, VLAN not yet recorded", network.network, network.allocated);
+        }
+    }
+    Ok(())
+}
+
+async fn run_check(container: &str, netns: &Path, conflist: &Path, ifname: &str) -> Result<bool> {
+    validate_path_component("Container ID", container)?;
+    let data = fs::read(conflist)
+        .with_context(|| format!("Failed to read {}", conflist.display()))?;
+    let doc: serde_json::Value = serde_json::from_slice(&data)
+        .with_context(|| format!("{} is not valid JSON", conflist.display()))?;
+    let plugin = reconcile::socni_plugins(&doc).into_iter().next()
+        .ok_or_else(|| anyhow::anyhow!("{} has no socni plugin", conflist.display()))?;
+    
+    let stdin_data = serde_json::to_vec(&plugin)?;
+    let conf = NetConf::parse(&stdin_data)
+        .with_context(|| format!("Invalid socni configuration in {}", conflist.display()))?;
+    let args = CmdArgs {
+        container_id: container.to_string(),
+        netns: netns.to_string_lossy().into_owned(),
+        ifname: ifname.to_string(),
+        args: HashMap::new(),
+        path: String::new(),
+        stdin_data,
+    };
+    
+    println!("Checking {} in {} (network {}, VLAN {})", ifname, netns.display(), conf.name, conf.vlan);
+    let items = VlanPlugin::new(conf, args).check_report().await?;
+    for item in &items {
+        println!("  {}", item);
+    }
+    
+    let failed = items.iter().filter(|item| item.error.is_some()).count();
+    if failed == 0 {
+        println!("All {} check(s) passed", items.len());
+    } else {
+        println!("{} of {} check(s) failed", failed, items.len());
+    }
+    Ok(failed == 0)
+}
+
+/// Recent operations shown by doctor
+const DOCTOR_RECORDS: usize = 5;
+
+async fn run_doctor(aranya: &AranyaClient, config_dir: &Path, container: &str) -> Result<()> {
+    let socni = SocniConfig::load();
+    let mut evidence = Evidence::default();
+    
+    println!("State:");
+    evidence.attachments = StateStore::new(&socni.state_dir).attachments()?
+        .into_values()
+        .filter(|a| a.container_id.starts_with(container))
+        .collect();
+    if evidence.attachments.is_empty() {
+        println!("  no attachment recorded");
+    }
+    for attachment in &evidence.attachments {
+        println!("  {} of {} on network {} (VLAN {}, netns {}, added {})", attachment.ifname,
+                 attachment.container_id, attachment.config.name, attachment.config.vlan,
+                 attachment.netns, rfc3339(attachment.added));
+    }
+    
+    println!("Recent operations:");
+    evidence.records = OpLog::new(&socni.state_dir).read()?
+        .into_iter()
+        .filter(|r| r.container_id.starts_with(container))
+        .collect();
+    if evidence.records.is_empty() {
+        println!("  none logged");
+    }
+    for record in evidence.records.iter().rev().take(DOCTOR_RECORDS).rev() {
+        print!("  ");
+        print_op(record);
+    }
+    
+    println!("Live interface:");
+    if evidence.attachments.is_empty() {
+        println!("  nothing to check");
+    }
+    for attachment in &evidence.attachments {
+        match VlanPlugin::for_attachment(attachment).check_report().await {
+            Ok(items) => {
+                for item in &items {
+                    println!("  {}", item);
                 }
+                evidence.checks.extend(items);
+            }
+            Err(e) => {
+                println!("  check failed: {:#}", e);
+                evidence.checks.push(CheckItem::fail("check", format!("{:#}", e)));
             }
         }
     }
+    
+    if let Some(vlan) = evidence.vlan() {
+        let access = aranya.check_vlan_access(vlan).map_err(|e| format!("{:#}", e));
+        match &access {
+            Ok(granted) => println!("Access to VLAN {}: {}", vlan, if *granted { "granted" } else { "denied" }),
+            Err(e) => println!("Access to VLAN {}: error: {}", vlan, e),
+        }
+        evidence.access = Some(access);
+        
+        let configs = reconcile::load_networks(config_dir).unwrap_or_default();
+        let mut networks = Vec::new();
+        for ipam in ipam_allocators(&configs) {
+            networks.extend(ipam.usage()?);
+        }
+        evidence.usage = vlan_usage(&networks).into_iter().find(|u| u.vlan == vlan);
+        match &evidence.usage {
+            Some(usage) => println!("IPAM on VLAN {}: {} of {} addresses ({:.1}%)",
+                                    vlan, usage.allocated, usage.capacity, usage.utilization()),
+            None => println!("IPAM on VLAN {}: no host-local allocations", vlan),
+        }
+    }
+    
+    println!();
+    let causes = likely_causes(&evidence);
+    if causes.is_empty() {
+        println!("No likely cause found; socni's view of this container looks healthy");
+    } else {
+        println!("Likely causes:");
+        for (i, cause) in causes.iter().enumerate() {
+            println!("  {}. {}", i + 1, cause);
+        }
+    }
+    Ok(())
+}
 
-    Ok(vlan_status)
+/// Print one e2e stage and whether it passed
+fn report_stage<T>(stage: &str, outcome: &Result<T>) -> bool {
+    match outcome {
+        Ok(_) => println!("  {:<8} PASS", stage),
+        Err(e) => println!("  {:<8} FAIL: {:#}", stage, e),
+    }
+    outcome.is_ok()
+}
+
+async fn run_e2e(master: &str, vlan: u16) -> Result<bool> {
+    let name = format!("socni-e2e-{}", std::process::id());
+    let conf = NetConf::new_default("socni-e2e", master, vlan, None);
+    let args = CmdArgs {
+        container_id: name.clone(),
+        netns: name.clone(),
+        ifname: "eth1".to_string(),
+        args: HashMap::new(),
+        path: String::new(),
+        stdin_data: serde_json::to_vec(&conf)?,
+    };
+    
+    println!("End-to-end test of VLAN {} on {} in namespace {}", vlan, master, name);
+    let created = ip_netns("add", &name);
+    if !report_stage("netns", &created) {
+        return Ok(false);
+    }
+    
+    let mut passed = true;
+    let added = VlanPlugin::new(conf.clone(), args.clone()).add_network().await;
+    if let Ok(result) = &added {
+        println!("{}", serde_json::to_string_pretty(result)?);
+    }
+    passed &= report_stage("add", &added);
+    
+    // Only check an interface that was added, but always clean up after a partial ADD
+    if added.is_ok() {
+        let checked = VlanPlugin::new(conf.clone(), args.clone()).check_network().await;
+        passed &= report_stage("check", &checked);
+    }
+    let deleted = VlanPlugin::new(conf, args).del_network().await;
+    passed &= report_stage("del", &deleted);
+    
+    let removed = ip_netns("delete", &name);
+    passed &= report_stage("cleanup", &removed);
+    
+    println!("{}", if passed { "End-to-end test passed" } else { "End-to-end test failed" });
+    Ok(passed)
+}
+
+/// Add or delete a named network namespace
+fn ip_netns(action: &str, name: &str) -> Result<()> {
+    let out = Command::new("ip")
+        .args(["netns", action, name])
+        .output()
+        .with_context(|| format!("Failed to execute ip netns {} command", action))?;
+    if !out.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&out.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[Duration], pct: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+async fn run_bench(master: &str, vlan: u16, iterations: usize) -> Result<()> {
+    if iterations == 0 {
+        anyhow::bail!("--iterations must be at least 1");
+    }
+    let name = format!("socni-bench-{}", std::process::id());
+    let conf = NetConf::new_default("socni-bench", master, vlan, None);
+    let args = CmdArgs {
+        container_id: name.clone(),
+        netns: name.clone(),
+        ifname: "eth1".to_string(),
+        args: HashMap::new(),
+        path: String::new(),
+        stdin_data: serde_json::to_vec(&conf)?,
+    };
+    
+    println!("Timing {} ADD/DEL cycles of VLAN {} on {} in namespace {}", iterations, vlan, master, name);
+    ip_netns("add", &name).context("Failed to create the benchmark namespace")?;
+    
+    // Phases keep the order add_network ran them in
+    let mut samples: Vec<(String, Vec<Duration>)> = Vec::new();
+    let mut record = |phase: &str, took: Duration| match samples.iter_mut().find(|(p, _)| p == phase) {
+        Some((_, durations)) => durations.push(took),
+        None => samples.push((phase.to_string(), vec![took])),
+    };
+    
+    let mut outcome = Ok(());
+    for i in 0..iterations {
+        let mut plugin = VlanPlugin::new(conf.clone(), args.clone());
+        let started = Instant::now();
+        let added = plugin.add_network().await;
+        record("add", started.elapsed());
+        for (phase, took) in plugin.phase_timings() {
+            record(phase, *took);
+        }
+        
+        // Always DEL, so a failed ADD doesn't leave the next cycle a stale link
+        let started = Instant::now();
+        let deleted = VlanPlugin::new(conf.clone(), args.clone()).del_network().await;
+        record("del", started.elapsed());
+        
+        if let Err(e) = added.and(deleted) {
+            outcome = Err(e.context(format!("Cycle {} failed", i + 1)));
+            break;
+        }
+    }
+    
+    let removed = ip_netns("delete", &name);
+    outcome?;
+    removed.context("Failed to delete the benchmark namespace")?;
+    
+    println!("{:<12} {:>10} {:>10}", "PHASE", "P50", "P99");
+    for (phase, mut durations) in samples {
+        durations.sort();
+        println!("{:<12} {:>10} {:>10}", phase,
+                 format!("{:.2}ms", percentile(&durations, 50).as_secs_f64() * 1000.0),
+                 format!("{:.2}ms", percentile(&durations, 99).as_secs_f64() * 1000.0));
+    }
+    Ok(())
+}
+
+async fn run_watch(aranya: &AranyaClient, interval: u64) -> Result<()> {
+    let store = StateStore::new(SocniConfig::load().state_dir);
+    let mut allowed: HashMap<(u16, String), bool> = HashMap::new();
+    
+    println!("Watching VLAN access of running pods every {}s", interval);
+    loop {
+        let attachments = store.attachments()?;
+        let tenants: BTreeSet<(u16, String)> = attachments.values()
+            .map(|a| (a.config.vlan, a.tenant.clone()))
+            .collect();
+        
+        for (vlan, tenant) in tenants {
+            let now = match aranya.explain_access(vlan, &tenant) {
+                Ok(explanation) => explanation.allowed,
+                Err(e) => {
+                    warn!("Failed to check access of tenant {} to VLAN {}: {:#}", tenant, vlan, e);
+                    continue;
+                }
+            };
+            
+            // Only a change from allowed to denied is a revocation; pods
+            // that were never allowed are left to the next ADD
+            let was = allowed.insert((vlan, tenant.clone()), now);
+            if was != Some(true) || now {
+                continue;
+            }
+            
+            info!("Access of tenant {} to VLAN {} was revoked", tenant, vlan);
+            for attachment in attachments.values().filter(|a| a.config.vlan == vlan && a.tenant == tenant) {
+                let target = format!("{} of container {} on network {}",
+                                     attachment.ifname, attachment.container_id, attachment.config.name);
+                match attachment.config.on_revoke {
+                    OnRevoke::Ignore => println!("Leaving {} attached (onRevoke: ignore)", target),
+                    OnRevoke::Teardown => match VlanPlugin::for_attachment(attachment).del_network().await {
+                        Ok(()) => println!("Tore down {}", target),
+                        Err(e) => error!("Failed to tear down {}: {:#}", target, e),
+                    },
+                }
+            }
+        }
+        
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+    }
+}
+
+/// Gather the metrics snapshot afresh for every scrape
+fn metrics_snapshot(config_dir: &Path) -> Result<Snapshot> {
+    let socni = SocniConfig::load();
+    let configs = reconcile::load_networks(config_dir).unwrap_or_default();
+    let mut networks = Vec::new();
+    for ipam in ipam_allocators(&configs) {
+        networks.extend(ipam.usage()?);
+    }
+    
+    Ok(Snapshot {
+        attachments: StateStore::new(&socni.state_dir).attachments()?.into_values().collect(),
+        usage: vlan_usage(&networks),
+        records: OpLog::new(&socni.state_dir).read()?,
+    })
+}
+
+/// How long the metrics endpoint waits for a scraper to send its request
+const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serve one scrape in its own task, so a stalled client holds up no other
+fn serve_metrics<S>(stream: S, config_dir: &Path)
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+{
+    let config_dir = config_dir.to_path_buf();
+    tokio::spawn(async move {
+        let body = match metrics_snapshot(&config_dir) {
+            Ok(snapshot) => snapshot.render(),
+            Err(e) => {
+                warn!("Failed to gather metrics: {:#}", e);
+                return;
+            }
+        };
+        if let Err(e) = metrics::respond(stream, &body, METRICS_REQUEST_TIMEOUT).await {
+            warn!("Failed to serve metrics: {:#}", e);
+        }
+    });
+}
+
+async fn run_metrics(config_dir: &Path, listen: &str, unix: Option<&Path>) -> Result<()> {
+    // Both transports serve the same text; only the listener differs
+    if let Some(path) = unix {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        remove_stale_socket(path)?;
+        let listener = tokio::net::UnixListener::bind(path)
+            .with_context(|| format!("Failed to bind {}", path.display()))?;
+        println!("Serving metrics on unix:{}", path.display());
+        loop {
+            let (stream, _) = listener.accept().await.context("Failed to accept connection")?;
+            serve_metrics(stream, config_dir);
+        }
+    }
+    
+    let listener = tokio::net::TcpListener::bind(listen).await
+        .with_context(|| format!("Failed to bind {}", listen))?;
+    println!("Serving metrics on http://{}/metrics", listen);
+    loop {
+        let (stream, _) = listener.accept().await.context("Failed to accept connection")?;
+        serve_metrics(stream, config_dir);
+    }
+}
+
+/// How long ipamd waits for a connected client to send its request
+const IPAMD_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Remove a socket left behind by a previous run, which would make bind
+/// fail; anything else at `path` is left alone
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display())),
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to inspect {}", path.display())),
+    }
+}
+
+async fn run_ipamd(listen: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    
+    if let Some(parent) = listen.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    remove_stale_socket(listen)?;
+    let listener = tokio::net::UnixListener::bind(listen)
+        .with_context(|| format!("Failed to bind {}", listen.display()))?;
+    fs::set_permissions(listen, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("Failed to restrict {}", listen.display()))?;
+    
+    // Requests run one at a time, so allocations on this node never wait on
+    // each other's file lock; reading them doesn't
+    let allocator = std::sync::Arc::new(tokio::sync::Mutex::new(HostLocal::new(SocniConfig::load().state_dir)));
+    let uid = nix::unistd::geteuid().as_raw();
+    println!("Serving host-local IPAM on unix:{}", listen.display());
+    loop {
+        let (stream, _) = listener.accept().await.context("Failed to accept connection")?;
+        // Only the daemon's own user may lease, whatever the socket's mode
+        match stream.peer_cred() {
+            Ok(cred) if cred.uid() == uid => {}
+            Ok(cred) => {
+                warn!("Refusing IPAM client with uid {}", cred.uid());
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to read IPAM client credentials: {}", e);
+                continue;
+            }
+        }
+        
+        let allocator = allocator.clone();
+        tokio::spawn(async move {
+            if let Err(e) = daemon::serve_connection(stream, &allocator, IPAMD_REQUEST_TIMEOUT).await {
+                warn!("Failed to serve IPAM request: {:#}", e);
+            }
+        });
+    }
+}
+
+fn print_op(record: &OpRecord) {
+    let container: String = record.container_id.chars().take(12).collect();
+    print!("{} {:<5} {:<12} {:<20} {:<5} {:<8} {}",
+           rfc3339(record.timestamp), record.command, container,
+           record.network, record.vlan, record.ifname, record.result);
+    match &record.error {
+        Some(error) => println!(": {}", error),
+        None => println!(),
+    }
+}
+
+fn run_logs(container: Option<&str>, since: Option<u64>, follow: bool) -> Result<()> {
+    let log = OpLog::new(SocniConfig::load().state_dir);
+    let cutoff = since.map(|secs| now_secs().saturating_sub(secs));
+    let wanted = |r: &OpRecord| {
+        container.map_or(true, |c| r.container_id.starts_with(c))
+            && cutoff.map_or(true, |t| r.timestamp >= t)
+    };
+    
+    let mut records = log.read()?;
+    let mut shown = 0;
+    for record in records.iter().filter(|r| wanted(r)) {
+        print_op(record);
+        shown += 1;
+    }
+    if !follow {
+        if shown == 0 {
+            println!("No matching operations found");
+        }
+        return Ok(());
+    }
+    
+    // Poll for new records; rotation only drops the oldest file, so the
+    // last record seen is still present on the next read
+    loop {
+        std::thread::sleep(std::time::Duration::from_secs(1));
+        let latest = log.read()?;
+        let start = match records.last() {
+            Some(last) => latest.iter().rposition(|r| r == last).map_or(0, |i| i + 1),
+            None => 0,
+        };
+        for record in latest[start..].iter().filter(|r| wanted(r)) {
+            print_op(record);
+        }
+        records = latest;
+    }
 }
 
 async fn run_install(bin_dir: &Path, yes: bool) -> Result<()> {
@@ -371,10 +1499,14 @@ async fn main() -> Result<()> {
             println!("VLAN {} created successfully", id);
         },
         
+        Commands::Delete { vlan, force } => {
+            run_delete(&mut aranya, vlan, force)?;
+        },
+        
         Commands::List { detailed } => {
             // In a real implementation, we would list VLANs from Aranya
             // For now, we'll use the status command to get VLAN information
-            let status = get_vlan_status(None)?;
+            let status = get_vlan_status(None).await?;
             
             if status.is_empty() {
                 println!("No VLAN interfaces found");
@@ -399,13 +1531,15 @@ async fn main() -> Result<()> {
         },
         
         Commands::Grant { vlan_id, target_tenant } => {
-            aranya.grant_vlan_access(vlan_id, &target_tenant)?;
-            println!("Access to VLAN {} granted to tenant {}", vlan_id, target_tenant);
+            let tenants: Vec<&str> = target_tenant.iter().map(String::as_str).collect();
+            let results = aranya.grant_vlan_access_many(vlan_id, &tenants)?;
+            report_bulk(&results, vlan_id, "granted to")?;
         },
         
         Commands::Revoke { vlan_id, target_tenant } => {
-            aranya.revoke_vlan_access(vlan_id, &target_tenant)?;
-            println!("Access to VLAN {} revoked from tenant {}", vlan_id, target_tenant);
+            let tenants: Vec<&str> = target_tenant.iter().map(String::as_str).collect();
+            let results = aranya.revoke_vlan_access_many(vlan_id, &tenants)?;
+            report_bulk(&results, vlan_id, "revoked from")?;
         },
         
         Commands::Generate { id, master, mtu, name, output, subnet, gateway } => {
@@ -432,8 +1566,12 @@ async fn main() -> Result<()> {
             run_install(&bin_dir, yes).await?;
         },
         
-        Commands::Status { id } => {
-            let status = get_vlan_status(id)?;
+        Commands::Status { id, delta: true } => {
+            run_status_delta(id).await?;
+        },
+        
+        Commands::Status { id, delta: false } => {
+            let status = get_vlan_status(id).await?;
             
             if status.is_empty() {
                 if let Some(vlan_id) = id {
@@ -445,8 +1583,14 @@ async fn main() -> Result<()> {
                 println!("VLAN Interface Status:");
                 for vlan in status {
                     println!("  VLAN {} ({}):", vlan.id, vlan.name);
+                    if let Some(container) = &vlan.container {
+                        println!("    Container: {}", container);
+                    }
                     println!("    State: {}", vlan.state);
                     println!("    Master: {}", vlan.master);
+                    if let Some(mtu) = vlan.mtu {
+                        println!("    MTU: {}", mtu);
+                    }
                     
                     // Check if we have access to this VLAN
                     match aranya.check_vlan_access(vlan.id) {
@@ -456,6 +1600,119 @@ async fn main() -> Result<()> {
                 }
             }
         },
+        
+        Commands::Schema { output } => {
+            let schema_json = serde_json::to_string_pretty(&netconf_schema())?;
+            
+            if let Some(path) = output {
+                fs::write(&path, schema_json)?;
+                println!("Schema written to {}", path.display());
+            } else {
+                println!("{}", schema_json);
+            }
+        },
+        
+        Commands::Explain { vlan, device } => {
+            let explanation = aranya.explain_access(vlan, &device)?;
+            println!("{}", explanation);
+        },
+        
+        Commands::AranyaInfo => {
+            let info = aranya.daemon_info()
+                .with_context(|| format!("Aranya daemon at {} is not usable", cli.socket.display()))?;
+            println!("Connected to Aranya daemon at {}", cli.socket.display());
+            println!("{}", info);
+        },
+        
+        Commands::Topology { format } => {
+            let vlan_tenants = aranya.list_vlan_tenants()?;
+            let interfaces = get_vlan_status(None).await?;
+            
+            print_topology(&vlan_tenants, &interfaces, &format);
+        },
+        
+        Commands::Reconcile { config_dir, apply } => {
+            run_reconcile(config_dir.as_deref().unwrap_or(&cli.config_dir), apply)?;
+        },
+        
+        Commands::MasterMtu { master, mtu } => {
+            run_master_mtu(&master, mtu)?;
+        },
+        
+        Commands::Logs { container, since, follow } => {
+            run_logs(container.as_deref(), since, follow)?;
+        },
+        
+        Commands::Check { container, netns, conflist, ifname } => {
+            if !run_check(&container, &netns, &conflist, &ifname).await? {
+                std::process::exit(1);
+            }
+        },
+        
+        Commands::Doctor { container } => {
+            run_doctor(&aranya, &cli.config_dir, &container).await?;
+        },
+        
+        Commands::E2e { master, vlan } => {
+            if !run_e2e(&master, vlan).await? {
+                std::process::exit(1);
+            }
+        },
+        
+        Commands::Bench { master, vlan, iterations } => {
+            run_bench(&master, vlan, iterations).await?;
+        },
+        
+        Commands::Watch { interval } => {
+            run_watch(&aranya, interval).await?;
+        },
+        
+        Commands::Metrics { listen, unix } => {
+            run_metrics(&cli.config_dir, &listen, unix.as_deref()).await?;
+        },
+        
+        Commands::Ipamd { listen } => {
+            run_ipamd(&listen).await?;
+        },
+        
+        Commands::Ipam { action } => match action {
+            IpamCommands::Gc { ttl } => run_ipam_gc(&cli.config_dir, ttl)?,
+            IpamCommands::Status { vlan } => run_ipam_status(&cli.config_dir, vlan)?,
+            IpamCommands::History { address } => run_ipam_history(&cli.config_dir, address)?,
+        },
+        
+        Commands::Config { action } => match action {
+            ConfigCommands::Show => run_config_show()?,
+        },
+        
+        Commands::Mac { action } => {
+            let store = StateStore::new(SocniConfig::load().state_dir);
+            
+            match action {
+                MacCommands::List => {
+                    let mut reservations: Vec<_> = store.mac_reservations()?.into_values().collect();
+                    reservations.sort_by(|a, b| a.identity.cmp(&b.identity));
+                    
+                    if reservations.is_empty() {
+                        println!("No MAC reservations found");
+                    } else {
+                        println!("{:<40} {:<20} {:<10} {}", "POD", "NETWORK", "IFNAME", "MAC");
+                        for r in reservations {
+                            println!("{:<40} {:<20} {:<10} {}", r.identity, r.network, r.ifname, r.mac);
+                        }
+                    }
+                },
+                
+                MacCommands::Clear { pod, all } => {
+                    if pod.is_none() && !all {
+                        anyhow::bail!("Specify --pod <namespace/name> or --all");
+                    }
+                    
+                    let removed = store.clear_macs(pod.as_deref())?;
+                    println!("Cleared {} MAC reservation(s)", removed);
+                },
+            }
+        },
     }
     
     Ok(())
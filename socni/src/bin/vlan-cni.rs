@@ -1,14 +1,15 @@
 use anyhow::Result;
 use tracing_subscriber::{FmtSubscriber, EnvFilter};
 use tracing::{info, error, Level};
-use socni::commands::run_cni;
-use socni::types::{CmdArgs, Result as CniResult};
+use socni::commands::{negotiated_version, run_cni};
+use socni::types::{CmdArgs, CniError, Result as CniResult};
 
 fn main() -> Result<()> {
-    // Set up tracing
+    // Set up tracing; stdout is reserved for the result or error
     let subscriber = FmtSubscriber::builder()
         .with_env_filter(EnvFilter::from_default_env())
         .with_max_level(Level::INFO)
+        .with_writer(std::io::stderr)
         .finish();
     
     let _ = tracing::subscriber::set_global_default(subscriber);
@@ -21,12 +22,9 @@ fn main() -> Result<()> {
     if let Err(err) = run_cni() {
         error!("CNI plugin error: {}", err);
         
-        // Output error in CNI format
-        let error_msg = format!(
-            r#"{{"cniVersion":"1.0.0","code":100,"msg":"{}","details":""}}"#,
-            err.to_string().replace("\"", "\\\"")
-        );
-        eprintln!("{}", error_msg);
+        // The runtime reads the error from stdout in CNI format, shaped for
+        // the version it speaks
+        println!("{}", CniError::to_json(&err, negotiated_version()));
         std::process::exit(1);
     }
     
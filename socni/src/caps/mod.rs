@@ -0,0 +1,107 @@
+//! Process capability and user namespace inspection
+//!
+//! Used to turn a generic "Operation not permitted" from `ip` or `setns`
+//! into an error that names the missing capability, and to refuse to start
+//! a command that is bound to hit one.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::io;
+
+use crate::backend::IpError;
+
+/// CAP_NET_ADMIN capability bit
+pub const CAP_NET_ADMIN: u32 = 12;
+/// CAP_SYS_ADMIN capability bit (required for setns)
+pub const CAP_SYS_ADMIN: u32 = 21;
+
+/// Effective capability set of this process (CapEff from /proc/self/status)
+pub fn effective_caps() -> Result<u64> {
+    let status = fs::read_to_string("/proc/self/status")
+        .context("Failed to read /proc/self/status")?;
+    parse_cap_eff(&status)
+        .ok_or_else(|| anyhow::anyhow!("CapEff not found in /proc/self/status"))
+}
+
+/// Parse the CapEff line out of a /proc/<pid>/status document
+pub fn parse_cap_eff(status: &str) -> Option<u64> {
+    status.lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+}
+
+/// Whether the process holds the given effective capability
+///
+/// Assumes the capability is present if CapEff can't be read, so callers
+/// fall through to the real operation and its own error.
+pub fn has_cap(cap: u32) -> bool {
+    effective_caps().map(|caps| caps & (1 << cap) != 0).unwrap_or(true)
+}
+
+/// Names of the capabilities in `required` missing from the `caps` set
+pub fn missing_caps(caps: u64, required: &[u32]) -> Vec<&'static str> {
+    required.iter()
+        .filter(|&&cap| caps & (1 << cap) == 0)
+        .map(|&cap| cap_name(cap))
+        .collect()
+}
+
+/// Fail unless the process holds every capability in `required`
+///
+/// Like [`has_cap`], passes when CapEff can't be read.
+pub fn require_caps(required: &[u32]) -> Result<()> {
+    let Ok(caps) = effective_caps() else { return Ok(()) };
+    let missing = missing_caps(caps, required);
+    if !missing.is_empty() {
+        anyhow::bail!("socni lacks {}: it needs CAP_NET_ADMIN to manage links and CAP_SYS_ADMIN to enter \
+                       the pod's network namespace; run it as root or grant the capabilities",
+                      missing.join(" and "));
+    }
+    Ok(())
+}
+
+/// Whether the process runs inside a non-initial user namespace
+pub fn in_user_namespace() -> bool {
+    fs::read_to_string("/proc/self/uid_map")
+        .map(|map| !is_initial_uid_map(&map))
+        .unwrap_or(false)
+}
+
+/// The initial user namespace maps the full uid range onto itself
+pub fn is_initial_uid_map(map: &str) -> bool {
+    let fields: Vec<&str> = map.split_whitespace().collect();
+    fields == ["0", "0", "4294967295"]
+}
+
+/// Build a precise error for a permission failure during `operation`
+pub fn permission_error(operation: &str, cap: u32) -> anyhow::Error {
+    let cap_name = cap_name(cap);
+    
+    if in_user_namespace() {
+        anyhow::anyhow!(
+            "{} denied: running in a user namespace (rootless), where {} does not apply to the host network; \
+             run the plugin in the initial user namespace",
+            operation, cap_name
+        )
+    } else if !has_cap(cap) {
+        anyhow::anyhow!("{} denied: missing {}", operation, cap_name)
+    } else {
+        anyhow::anyhow!("{} denied: operation not permitted", operation)
+    }
+}
+
+fn cap_name(cap: u32) -> &'static str {
+    match cap {
+        CAP_NET_ADMIN => "CAP_NET_ADMIN",
+        CAP_SYS_ADMIN => "CAP_SYS_ADMIN",
+        _ => "a required capability",
+    }
+}
+
+/// Whether a backend call failed with EPERM or EACCES
+pub fn is_permission_error(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| match cause.downcast_ref::<io::Error>() {
+        Some(e) => e.kind() == io::ErrorKind::PermissionDenied,
+        None => matches!(cause.downcast_ref::<IpError>().and_then(|e| e.errno), Some(libc::EPERM | libc::EACCES)),
+    })
+}
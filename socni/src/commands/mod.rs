@@ -1,10 +1,20 @@
 use anyhow::{Context, Result};
 use std::collections::HashMap;
 use std::env;
+use std::ffi::OsStr;
 use std::io::{self, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
 use tokio::runtime::Runtime;
+use tokio::signal::unix::{signal, SignalKind};
 
-use crate::config::NetConf;
+use crate::backend::{self, BackendKind};
+use crate::config::{validate_ifname, validate_path_component, NetConf, SocniConfig, CURRENT_CNI_VERSION, SUPPORTED_CNI_VERSIONS};
+use crate::caps;
+use crate::events;
+use crate::logging::{OpLog, OpRecord};
 use crate::plugin::VlanPlugin;
 use crate::types::CmdArgs;
 
@@ -13,12 +23,14 @@ pub fn parse_args() -> Result<CmdArgs> {
     // Get required environment variables
     let container_id = env::var("CNI_CONTAINERID")
         .context("CNI_CONTAINERID not found in environment")?;
+    validate_path_component("CNI_CONTAINERID", &container_id)?;
     
     let netns = env::var("CNI_NETNS")
         .context("CNI_NETNS not found in environment")?;
     
     let ifname = env::var("CNI_IFNAME")
         .context("CNI_IFNAME not found in environment")?;
+    validate_ifname(&ifname)?;
     
     let path = env::var("CNI_PATH")
         .context("CNI_PATH not found in environment")?;
@@ -59,19 +71,62 @@ fn parse_cni_args(args_str: &str) -> HashMap<String, String> {
     args
 }
 
+/// Version negotiated with the runtime, once the configuration is parsed
+static CNI_VERSION: OnceLock<String> = OnceLock::new();
+
+/// Version errors must be reported in: the negotiated one, or the newest
+/// when the configuration couldn't be read
+pub fn negotiated_version() -> &'static str {
+    CNI_VERSION.get().map_or(CURRENT_CNI_VERSION, String::as_str)
+}
+
+/// Parse the stdin configuration, remembering its version for error output
+fn parse_conf(args: &CmdArgs) -> Result<NetConf> {
+    let conf = NetConf::parse(&args.stdin_data)?;
+    let _ = CNI_VERSION.set(conf.cni_version.clone());
+    Ok(conf)
+}
+
+/// Append an invocation to the operation log
+///
+/// The log is diagnostic only, so a failure to write it never fails the command.
+fn log_op<T>(command: &str, conf: &NetConf, args: &CmdArgs, outcome: &Result<T>) {
+    let record = OpRecord::new(command, conf, args, outcome);
+    if let Err(e) = OpLog::new(SocniConfig::load().state_dir).append(&record) {
+        tracing::warn!("Failed to write operation log: {:#}", e);
+    }
+}
+
 /// Execute the add command
 pub fn cmd_add() -> Result<()> {
     let args = parse_args()?;
     
     // Parse network configuration
-    let conf = NetConf::parse(&args.stdin_data)?;
+    let conf = parse_conf(&args)?;
     
-    // Create plugin and add network
-    let mut plugin = VlanPlugin::new(conf, args);
+    // Keep what a failure Event needs before the plugin takes ownership
+    let events = conf.emit_k8s_events
+        .then(|| (conf.k8s_events.clone().unwrap_or_default(), args.args.clone()));
+    let (network, vlan) = (conf.name.clone(), conf.vlan);
     
     // Create a runtime to execute async code
     let runtime = Runtime::new().context("Failed to create Tokio runtime")?;
-    let result = runtime.block_on(plugin.add_network())?;
+    
+    // Create plugin and add network. A drained node's SIGTERM rolls the
+    // attachment back instead of killing us half way through it
+    let mut plugin = VlanPlugin::new(conf.clone(), args.clone())
+        .with_interrupt(on_sigterm(&runtime)?);
+    let outcome = runtime.block_on(plugin.add_network());
+    log_op("ADD", &conf, &args, &outcome);
+    let result = match outcome {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some((k8s, cni_args)) = &events {
+                events::emit_failure(k8s, cni_args, &network, vlan, &e);
+            }
+            return Err(e);
+        }
+    };
     
     // Output result as JSON
     result.print()?;
@@ -79,21 +134,40 @@ pub fn cmd_add() -> Result<()> {
     Ok(())
 }
 
+/// Flag raised when SIGTERM arrives, in place of the default of exiting at once
+///
+/// The listener runs on a runtime worker, so the flag is raised even while
+/// the plugin blocks on a command.
+fn on_sigterm(runtime: &Runtime) -> Result<Arc<AtomicBool>> {
+    let _entered = runtime.enter();
+    let mut sigterm = signal(SignalKind::terminate())
+        .context("Failed to install SIGTERM handler")?;
+    let flag = Arc::new(AtomicBool::new(false));
+    let raised = flag.clone();
+    runtime.spawn(async move {
+        if sigterm.recv().await.is_some() {
+            tracing::warn!("Received SIGTERM; rolling back the ADD in progress");
+            raised.store(true, Ordering::SeqCst);
+        }
+    });
+    Ok(flag)
+}
+
 /// Execute the delete command
 pub fn cmd_del() -> Result<()> {
     let args = parse_args()?;
     
     // Parse network configuration
-    let conf = NetConf::parse(&args.stdin_data)?;
+    let conf = parse_conf(&args)?;
     
     // Create plugin and delete network
-    let mut plugin = VlanPlugin::new(conf, args);
+    let mut plugin = VlanPlugin::new(conf.clone(), args.clone());
     
     // Create a runtime to execute async code
     let runtime = Runtime::new().context("Failed to create Tokio runtime")?;
-    runtime.block_on(plugin.del_network())?;
-    
-    Ok(())
+    let outcome = runtime.block_on(plugin.del_network());
+    log_op("DEL", &conf, &args, &outcome);
+    outcome
 }
 
 /// Execute the check command
@@ -101,15 +175,79 @@ pub fn cmd_check() -> Result<()> {
     let args = parse_args()?;
     
     // Parse network configuration
-    let conf = NetConf::parse(&args.stdin_data)?;
+    let conf = parse_conf(&args)?;
+    
+    // Operators opt out of CHECK as with upstream plugins
+    if conf.disable_check {
+        tracing::debug!("CHECK disabled for network {}", conf.name);
+        return Ok(());
+    }
     
     // Create plugin and check network
-    let mut plugin = VlanPlugin::new(conf, args);
+    let mut plugin = VlanPlugin::new(conf.clone(), args.clone());
     
     // Create a runtime to execute async code
     let runtime = Runtime::new().context("Failed to create Tokio runtime")?;
-    runtime.block_on(plugin.check_network())?;
+    let outcome = runtime.block_on(plugin.check_network());
+    log_op("CHECK", &conf, &args, &outcome);
+    outcome
+}
+
+/// Validate the stdin configuration without touching the network
+pub fn cmd_config_check() -> Result<()> {
+    let mut stdin_data = Vec::new();
+    io::stdin().read_to_end(&mut stdin_data)
+        .context("Failed to read from stdin")?;
+    
+    let conf = NetConf::parse(&stdin_data)?;
     
+    // A runtime's CHECK without a sandbox expects nothing on stdout, so the
+    // sentence is only printed when asked for explicitly
+    let msg = format!("Configuration for network {} (VLAN {} on {}) is valid",
+                      conf.name, conf.vlan, conf.master);
+    if config_check_requested() {
+        println!("{}", msg);
+    } else {
+        tracing::info!("{}", msg);
+    }
+    Ok(())
+}
+
+/// Whether `SOCNI_CONFIG_CHECK=1` asks for configuration validation
+fn config_check_requested() -> bool {
+    env::var("SOCNI_CONFIG_CHECK").map(|v| v == "1").unwrap_or(false)
+}
+
+/// Whether this invocation only asks for configuration validation
+fn is_config_check(cmd: &str) -> bool {
+    if config_check_requested() {
+        return true;
+    }
+    
+    // A CHECK without a sandbox can only be a packaging/config test
+    cmd == "CHECK" && env::var("CNI_NETNS").map(|v| v.is_empty()).unwrap_or(true)
+}
+
+/// Locate an executable named `name` in a `PATH`-style list of directories
+pub fn find_executable(name: &str, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.metadata()
+            .map_or(false, |m| m.is_file() && m.permissions().mode() & 0o111 != 0))
+}
+
+/// Fail early with one clear error when the iproute2 backend is used and
+/// `ip` isn't available
+///
+/// Every link, address, route and neighbor change runs `ip` then, so
+/// without it ADD would fail part way through with a less obvious error.
+/// The netlink backend doesn't need it.
+fn require_iproute2() -> Result<()> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    if find_executable("ip", &path).is_none() {
+        anyhow::bail!("iproute2 not found: `ip` is not in PATH ({}); install iproute2",
+                      path.to_string_lossy());
+    }
     Ok(())
 }
 
@@ -119,6 +257,18 @@ pub fn run_cni() -> Result<()> {
     let cmd = env::var("CNI_COMMAND")
         .context("CNI_COMMAND not found in environment")?;
     
+    if is_config_check(&cmd) {
+        return cmd_config_check();
+    }
+    
+    // Fail fast on what would otherwise surface midway as a failed setns
+    if matches!(cmd.as_str(), "ADD" | "DEL" | "CHECK") {
+        if backend::resolve(SocniConfig::load().backend) == BackendKind::Iproute2 {
+            require_iproute2()?;
+        }
+        caps::require_caps(&[caps::CAP_NET_ADMIN, caps::CAP_SYS_ADMIN])?;
+    }
+    
     // Execute the appropriate command
     match cmd.as_str() {
         "ADD" => cmd_add(),
@@ -126,7 +276,10 @@ pub fn run_cni() -> Result<()> {
         "CHECK" => cmd_check(),
         "VERSION" => {
             // Output supported CNI versions
-            println!(r#"{{"cniVersion":"1.0.0","supportedVersions":["0.3.0","0.3.1","0.4.0","1.0.0"]}}"#);
+            println!("{}", serde_json::json!({
+                "cniVersion": CURRENT_CNI_VERSION,
+                "supportedVersions": SUPPORTED_CNI_VERSIONS,
+            }));
             Ok(())
         },
         _ => anyhow::bail!("Unknown CNI command: {}", cmd),
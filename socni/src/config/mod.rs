@@ -1,7 +1,16 @@
 use anyhow::{Context, Result};
+use ipnetwork::IpNetwork;
+use schemars::{schema_for, JsonSchema};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::fs;
+use tracing::warn;
+
+use crate::backend::{AddressLifetimes, BackendKind};
+use crate::ipam::{AddressRange, AllocationStrategy};
+use crate::ipam::daemon::{DEFAULT_SOCKET, IPAMD_TYPE};
 
 /// Configuration for SOCNI
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +25,22 @@ pub struct SocniConfig {
     pub default_master: String,
     /// Default MTU for VLAN interfaces
     pub default_mtu: Option<u32>,
+    /// Minimum seconds between repeats of the same recurring warning
+    pub warn_interval_secs: u64,
+    /// How links and addresses are changed; `None` prefers netlink when available
+    pub backend: Option<BackendKind>,
+    /// Never contact Aranya and allow every VLAN, for clusters without the daemon
+    pub disable_aranya: bool,
+    /// VLANs this node's trunk carries; `None` allows any
+    pub allowed_vlan_ranges: Option<Vec<VlanRange>>,
+    /// Attempts at restoring the plugin's own netns before aborting
+    pub netns_restore_attempts: u32,
+    /// Glob patterns of the host interfaces VLANs may be created on; `None` allows any
+    pub allowed_masters: Option<Vec<String>>,
+    /// Public key that signs `SOCNI_TOKEN`; when set, ADD requires a valid token
+    pub token_public_key: Option<PathBuf>,
+    /// Team secret the MACsec keys are derived from; required for `macsec`
+    pub macsec_secret_file: Option<PathBuf>,
 }
 
 impl Default for SocniConfig {
@@ -26,16 +51,209 @@ impl Default for SocniConfig {
             state_dir: PathBuf::from("/var/lib/vlan-cni"),
             default_master: "eth0".to_string(),
             default_mtu: None,
+            warn_interval_secs: 300,
+            backend: None,
+            disable_aranya: false,
+            allowed_vlan_ranges: None,
+            netns_restore_attempts: crate::netns::RESTORE_ATTEMPTS,
+            allowed_masters: None,
+            token_public_key: None,
+            macsec_secret_file: None,
         }
     }
 }
 
+impl SocniConfig {
+    /// Load the configuration from defaults and environment overrides
+    pub fn load() -> Self {
+        Self::load_with_sources().0
+    }
+    
+    /// Load the configuration like [`load`](Self::load), also naming where
+    /// each field's value came from: `default` or the environment variable
+    /// that set it
+    pub fn load_with_sources() -> (Self, BTreeMap<String, String>) {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+    
+    /// Build the configuration like [`load_with_sources`](Self::load_with_sources),
+    /// reading each variable through `var` instead of the process environment
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> (Self, BTreeMap<String, String>) {
+        let mut config = Self::default();
+        let mut sources: BTreeMap<String, String> = match serde_json::to_value(&config) {
+            Ok(serde_json::Value::Object(fields)) => fields.into_iter()
+                .map(|(field, _)| (field, "default".to_string()))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        let mut set = |field: &str, name: &str| {
+            sources.insert(field.to_string(), format!("env {}", name));
+        };
+        
+        if let Some(dir) = var("SOCNI_STATE_DIR") {
+            config.state_dir = PathBuf::from(dir);
+            set("state_dir", "SOCNI_STATE_DIR");
+        }
+        
+        if let Some(secs) = var("SOCNI_WARN_INTERVAL")
+            .and_then(|v| v.parse().ok())
+        {
+            config.warn_interval_secs = secs;
+            set("warn_interval_secs", "SOCNI_WARN_INTERVAL");
+        }
+        
+        if let Some(disable) = var("SOCNI_DISABLE_ARANYA") {
+            config.disable_aranya = disable == "1";
+            set("disable_aranya", "SOCNI_DISABLE_ARANYA");
+        }
+        
+        if let Some(backend) = var("SOCNI_BACKEND") {
+            match backend.parse() {
+                Ok(backend) => {
+                    config.backend = Some(backend);
+                    set("backend", "SOCNI_BACKEND");
+                }
+                Err(e) => warn!("Ignoring SOCNI_BACKEND: {}", e),
+            }
+        }
+        
+        // A node that can't tell which VLANs it carries accepts none rather than all
+        if let Some(ranges) = var("SOCNI_ALLOWED_VLANS") {
+            config.allowed_vlan_ranges = Some(VlanRange::parse_list(&ranges).unwrap_or_else(|e| {
+                warn!("Invalid SOCNI_ALLOWED_VLANS, allowing no VLANs: {:#}", e);
+                Vec::new()
+            }));
+            set("allowed_vlan_ranges", "SOCNI_ALLOWED_VLANS");
+        }
+        
+        if let Some(attempts) = var("SOCNI_NETNS_RESTORE_ATTEMPTS")
+            .and_then(|v| v.parse().ok())
+            .filter(|&attempts: &u32| attempts > 0)
+        {
+            config.netns_restore_attempts = attempts;
+            set("netns_restore_attempts", "SOCNI_NETNS_RESTORE_ATTEMPTS");
+        }
+        
+        if let Some(masters) = var("SOCNI_ALLOWED_MASTERS") {
+            config.allowed_masters = Some(masters.split(',')
+                .map(str::trim)
+                .filter(|pattern| !pattern.is_empty())
+                .map(String::from)
+                .collect());
+            set("allowed_masters", "SOCNI_ALLOWED_MASTERS");
+        }
+        
+        if let Some(path) = var("SOCNI_TOKEN_PUBLIC_KEY") {
+            config.token_public_key = Some(PathBuf::from(path));
+            set("token_public_key", "SOCNI_TOKEN_PUBLIC_KEY");
+        }
+        
+        if let Some(path) = var("SOCNI_MACSEC_SECRET_FILE") {
+            config.macsec_secret_file = Some(PathBuf::from(path));
+            set("macsec_secret_file", "SOCNI_MACSEC_SECRET_FILE");
+        }
+        
+        (config, sources)
+    }
+    
+    /// Whether VLANs may be created on the host interface `master`
+    pub fn master_allowed(&self, master: &str) -> bool {
+        self.allowed_masters.as_ref()
+            .map_or(true, |patterns| patterns.iter().any(|p| glob_match(p, master)))
+    }
+    
+    /// Whether this node's trunk carries `vlan`
+    pub fn vlan_allowed(&self, vlan: u16) -> bool {
+        self.allowed_vlan_ranges.as_ref()
+            .map_or(true, |ranges| ranges.iter().any(|r| r.contains(vlan)))
+    }
+}
+
+/// Inclusive range of VLAN IDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VlanRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl VlanRange {
+    pub fn contains(&self, vlan: u16) -> bool {
+        (self.start..=self.end).contains(&vlan)
+    }
+    
+    /// Parse a comma-separated list of IDs and `start-end` ranges, e.g. `100-199,250`
+    pub fn parse_list(list: &str) -> Result<Vec<Self>> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (start, end) = part.split_once('-').unwrap_or((part, part));
+                let parse = |id: &str| id.trim().parse::<u16>()
+                    .ok()
+                    .filter(|id| (1..=4094).contains(id))
+                    .ok_or_else(|| anyhow::anyhow!("Invalid VLAN ID {} in {}", id.trim(), part));
+                let range = Self { start: parse(start)?, end: parse(end)? };
+                if range.start > range.end {
+                    anyhow::bail!("VLAN range {} ends before it starts", part);
+                }
+                Ok(range)
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for VlanRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.start == self.end {
+            true => write!(f, "{}", self.start),
+            false => write!(f, "{}-{}", self.start, self.end),
+        }
+    }
+}
+
+/// Match `name` against a shell-style pattern where `*` matches any run of
+/// characters and `?` any single one
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and the name position it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last `*` swallow one more character and retry
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Network configuration for the VLAN CNI
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct NetConf {
     /// CNI specification version
-    #[serde(rename = "cniVersion")]
+    #[serde(rename = "cniVersion", default)]
     pub cni_version: String,
+    /// Versions the configuration supports; the highest one socni supports wins
+    #[serde(rename = "cniVersions", default, skip_serializing_if = "Option::is_none")]
+    pub cni_versions: Option<Vec<String>>,
+    /// Conflist-level switch that makes CHECK succeed without inspecting the pod
+    #[serde(rename = "disableCheck", default)]
+    pub disable_check: bool,
     /// Name of the network
     pub name: String,
     /// Type of CNI plugin
@@ -43,67 +261,1327 @@ pub struct NetConf {
     pub plugin_type: String,
     /// Master interface to attach VLAN to
     pub master: String,
-    /// VLAN ID (1-4094)
+    /// VLAN ID (1-4094, or 0 with `native`)
+    #[schemars(range(min = 0, max = 4094))]
     pub vlan: u16,
+    /// Inner (customer) VLAN ID stacked on `vlan` for QinQ
+    #[serde(rename = "innerVlan")]
+    #[schemars(range(min = 1, max = 4094))]
+    pub inner_vlan: Option<u16>,
+    /// Protocol of the tag on the master: `802.1Q` or `802.1ad`
+    #[serde(rename = "vlanProtocol")]
+    pub vlan_protocol: Option<String>,
     /// Interface MTU
+    #[schemars(range(min = 68, max = 65535))]
     pub mtu: Option<u32>,
+    /// Unicast MAC address of the container interface, unless CNI_ARGS or `args` set one
+    pub mac: Option<String>,
     /// IPAM configuration
     pub ipam: Option<IPAMConfig>,
+    /// Executable run after the interface is up and addressed
+    #[serde(rename = "postHook")]
+    pub post_hook: Option<String>,
+    /// Executable run during DEL
+    #[serde(rename = "postDelHook")]
+    pub post_del_hook: Option<String>,
+    /// Fail ADD when the post hook exits non-zero
+    #[serde(rename = "failOnHookError", default = "default_true")]
+    pub fail_on_hook_error: bool,
+    /// Bring up `lo` inside the container namespace
+    #[serde(rename = "setupLoopback", default)]
+    pub setup_loopback: bool,
+    /// Plugin-specific data populated by the orchestrator (config-level `args`)
+    #[serde(default)]
+    pub args: Option<serde_json::Value>,
+    /// Flush existing global addresses before assigning the new one
+    #[serde(rename = "flushAddresses", default)]
+    pub flush_addresses: bool,
+    /// Static traffic shaping limits
+    pub bandwidth: Option<BandwidthConfig>,
+    /// Values injected by the runtime for declared capabilities
+    #[serde(rename = "runtimeConfig", default)]
+    pub runtime_config: Option<RuntimeConfig>,
+    /// Disable IPv6 on the container interface
+    #[serde(rename = "disableIpv6", default)]
+    pub disable_ipv6: bool,
+    /// Attach untagged to the master's native VLAN (requires `vlan: 0`)
+    #[serde(default)]
+    pub native: bool,
+    /// DNS settings reported in the result
+    pub dns: Option<DnsConfig>,
+    /// Use the master's MTU when `mtu` is unset
+    #[serde(rename = "inheritMtu", default = "default_true")]
+    pub inherit_mtu: bool,
+    /// Raise the master's MTU when `mtu` is larger
+    #[serde(rename = "raiseMasterMtu", default)]
+    pub raise_master_mtu: bool,
+    /// Post a Kubernetes Event for the pod when ADD fails
+    #[serde(rename = "emitK8sEvents", default)]
+    pub emit_k8s_events: bool,
+    /// Where and how to post Kubernetes Events
+    #[serde(rename = "k8sEvents")]
+    pub k8s_events: Option<K8sEventsConfig>,
+    /// Static neighbor (ARP/ND) entries installed on the container interface
+    pub neighbors: Option<Vec<Neighbor>>,
+    /// Ingress filtering on the container interface
+    pub firewall: Option<FirewallConfig>,
+    /// Steer the link's RPS/XPS queues to the master's NUMA node
+    #[serde(rename = "numaHint", default)]
+    pub numa_hint: bool,
+    /// VLAN `reorder_hdr` flag (kernel default on)
+    #[serde(rename = "reorderHdr")]
+    pub reorder_hdr: Option<bool>,
+    /// Register the VLAN with GVRP (kernel default off)
+    pub gvrp: Option<bool>,
+    /// Register the VLAN with MVRP (kernel default off)
+    pub mvrp: Option<bool>,
+    /// Keep the VLAN's operstate independent of the master's (kernel default off)
+    #[serde(rename = "looseBinding")]
+    pub loose_binding: Option<bool>,
+    /// Ignore router advertisements and never generate temporary addresses,
+    /// so the interface only uses the IPv6 addresses socni assigns
+    #[serde(rename = "staticIpv6", default)]
+    pub static_ipv6: bool,
+    /// Named namespace under /var/run/netns holding the master, when it isn't
+    /// in the root namespace
+    #[serde(rename = "masterNetns")]
+    pub master_netns: Option<String>,
+    /// Second interface on a node-local bridge, alongside the VLAN uplink
+    #[serde(rename = "bridgeCompanion")]
+    pub bridge_companion: Option<BridgeCompanion>,
+    /// What `socni-ctl watch` does with live pods when the tenant loses access to the VLAN
+    #[serde(rename = "onRevoke", default)]
+    pub on_revoke: OnRevoke,
+    /// DSCP value (0-63) set on packets leaving the pod's addresses
+    pub dscp: Option<u8>,
+    /// Route table holding the interface's routes for source routing
+    #[serde(rename = "routeTable")]
+    #[schemars(range(min = 1))]
+    pub route_table: Option<u32>,
+    /// Route traffic sourced from the pod's address through `route_table`
+    #[serde(rename = "sourceRouting", default)]
+    pub source_routing: bool,
+    /// Ping the gateway, and `connectivity_target` if set, on CHECK
+    #[serde(rename = "checkConnectivity", default)]
+    pub check_connectivity: bool,
+    /// Address CHECK also pings with `check_connectivity`
+    #[serde(rename = "connectivityTarget")]
+    pub connectivity_target: Option<String>,
+    /// Resolve the default gateway's MAC after ADD and report it in the result
+    #[serde(rename = "resolveGatewayMac", default)]
+    pub resolve_gateway_mac: bool,
+    /// Probe the default gateway after ADD and report whether it answered
+    #[serde(rename = "verifyGateway", default)]
+    pub verify_gateway: bool,
+    /// Fail ADD when the gateway doesn't answer the probe; implies `verify_gateway`
+    #[serde(rename = "requireGateway", default)]
+    pub require_gateway: bool,
+    /// Leave the interface up after ADD; when false an agent in the pod brings it up
+    #[serde(rename = "adminUp", default = "default_true")]
+    pub admin_up: bool,
+    /// Encrypt the pod's traffic on the VLAN with MACsec, keyed through Aranya
+    pub macsec: Option<MacsecConfig>,
+    /// Directory holding this network's host-local allocations instead of the state directory
+    #[serde(rename = "dataDir")]
+    pub data_dir: Option<PathBuf>,
+    /// Register the pod's address in a key-value store
+    pub registry: Option<RegistryConfig>,
+}
+
+/// Reaction to a tenant's VLAN access being revoked while its pods run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OnRevoke {
+    /// Leave running pods attached until their next ADD
+    #[default]
+    Ignore,
+    /// Remove the pod's interface as if DEL had been called
+    Teardown,
+}
+
+/// veth pair from the pod into a node-local bridge
+///
+/// Unknown fields are refused so a static `address`, which every pod on the
+/// bridge would share, isn't silently ignored.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BridgeCompanion {
+    /// Existing bridge on the host the host end is attached to
+    pub bridge: String,
+    /// Interface name of the pod end
+    #[serde(default = "default_companion_ifname")]
+    pub ifname: String,
+    /// Range the pod end's address is leased from, one per container
+    pub ipam: Option<IpamPool>,
+}
+
+fn default_companion_ifname() -> String {
+    "br1".to_string()
+}
+
+impl BridgeCompanion {
+    /// Host end of the veth pair, unique per container and pod interface
+    pub fn host_ifname(&self, container_id: &str) -> String {
+        let digest = ring::digest::digest(&ring::digest::SHA256,
+                                          format!("{}/{}", container_id, self.ifname).as_bytes());
+        let hash: String = digest.as_ref().iter()
+            .take(5)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        format!("socni{}", hash)
+    }
+}
+
+/// Key-value store the pod's address is registered in for service discovery
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RegistryConfig {
+    /// Kind of store, which decides the API used
+    #[serde(rename = "type")]
+    pub registry_type: RegistryType,
+    /// Base URL of the store's HTTP API, e.g. `http://127.0.0.1:2379`
+    pub endpoint: String,
+    /// Key template; `{namespace}`, `{name}`, `{network}`, `{vlan}` and
+    /// `{containerId}` are replaced with the pod's values
+    #[serde(default = "default_registry_key")]
+    pub key: String,
+    /// Seconds each request may take before it is given up
+    #[serde(default = "default_registry_timeout")]
+    pub timeout: u64,
+}
+
+/// Placeholders a registry key template may use
+pub const REGISTRY_KEY_VARS: &[&str] = &["namespace", "name", "network", "vlan", "containerId"];
+
+fn default_registry_key() -> String {
+    "/socni/{network}/{namespace}/{name}".to_string()
+}
+
+fn default_registry_timeout() -> u64 {
+    2
+}
+
+/// Key-value stores socni can register addresses in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryType {
+    /// etcd v3 through its JSON gateway
+    Etcd,
+    /// Consul's KV API
+    Consul,
+}
+
+/// MACsec device between the pod and its VLAN link
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct MacsecConfig {
+    /// Cipher suite of the secure channel
+    #[serde(default)]
+    pub cipher: MacsecCipher,
+    /// Encrypt frames; when false they are only integrity protected
+    #[serde(default = "default_true")]
+    pub encrypt: bool,
+    /// MAC addresses of the peers on the VLAN whose frames are accepted
+    #[serde(default)]
+    pub peers: Vec<String>,
+}
+
+/// MACsec cipher suites
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum MacsecCipher {
+    #[default]
+    GcmAes128,
+    GcmAes256,
+}
+
+impl MacsecCipher {
+    /// Name of the cipher for `ip link add ... type macsec cipher`
+    ///
+    /// The extended packet number variant is used, so a secure association
+    /// can start at a packet number no earlier association used.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GcmAes128 => "gcm-aes-xpn-128",
+            Self::GcmAes256 => "gcm-aes-xpn-256",
+        }
+    }
+    
+    /// Key length in bytes
+    pub fn key_len(&self) -> usize {
+        match self {
+            Self::GcmAes128 => 16,
+            Self::GcmAes256 => 32,
+        }
+    }
+}
+
+/// Allow-list filtering of traffic reaching the pod
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FirewallConfig {
+    /// Sources allowed to reach the pod
+    #[serde(default)]
+    pub allow: Vec<FirewallRule>,
+    /// Verdict for everything else (`drop`, `reject` or `accept`)
+    #[serde(rename = "defaultAction", default = "default_firewall_action")]
+    pub default_action: String,
+}
+
+fn default_firewall_action() -> String {
+    "drop".to_string()
+}
+
+/// One allowed source
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FirewallRule {
+    /// Source CIDR
+    pub cidr: String,
+    /// `tcp` or `udp`; both when ports are given without a protocol
+    pub protocol: Option<String>,
+    /// Destination ports (all when empty)
+    #[serde(default)]
+    pub ports: Vec<u16>,
+}
+
+/// Static neighbor entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Neighbor {
+    /// IPv4 or IPv6 address of the neighbor
+    pub ip: String,
+    /// Link-layer address
+    pub mac: String,
 }
 
+/// Kubernetes API access for failure Events
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct K8sEventsConfig {
+    /// API server URL (defaults to KUBERNETES_SERVICE_HOST/PORT)
+    #[serde(rename = "apiServer")]
+    pub api_server: Option<String>,
+    /// Service account token file
+    #[serde(rename = "tokenFile")]
+    pub token_file: Option<String>,
+    /// CA bundle for the API server
+    #[serde(rename = "caFile")]
+    pub ca_file: Option<String>,
+}
+
+/// CNI spec versions socni can honor, oldest first
+///
+/// Also reported by the VERSION command.
+pub const SUPPORTED_CNI_VERSIONS: &[&str] = &["0.3.0", "0.3.1", "0.4.0", "1.0.0"];
+
+/// CNI spec version socni implements natively
+pub const CURRENT_CNI_VERSION: &str = "1.0.0";
+
+/// Maximum nameservers honoured by glibc/musl resolvers (MAXNS)
+pub const MAX_NAMESERVERS: usize = 3;
+
+/// DNS configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct DnsConfig {
+    /// Nameservers of either family
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// Search domains
+    #[serde(default)]
+    pub search: Vec<String>,
+    /// Resolver options
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Host path to write a resolv.conf for the pod to
+    #[serde(rename = "writeResolvConf")]
+    pub write_resolv_conf: Option<String>,
+}
+
+impl DnsConfig {
+    /// Nameservers with the preferred family first, keeping the order within each family
+    pub fn ordered_nameservers(&self, prefer_v6: bool) -> Vec<String> {
+        let (mut preferred, other): (Vec<String>, Vec<String>) = self.nameservers.iter()
+            .cloned()
+            .partition(|ns| is_v6(ns) == prefer_v6);
+        preferred.extend(other);
+        preferred
+    }
+    
+    /// Nameservers for resolv.conf, limited to [`MAX_NAMESERVERS`]
+    ///
+    /// When both families are configured at least one resolver of each is
+    /// kept, so a dual-stack pod can still resolve if one family is down.
+    pub fn resolv_conf_nameservers(&self, prefer_v6: bool) -> Vec<String> {
+        let ordered = self.ordered_nameservers(prefer_v6);
+        if ordered.len() <= MAX_NAMESERVERS {
+            return ordered;
+        }
+        
+        let mut chosen: Vec<String> = Vec::new();
+        if let Some(first_other) = ordered.iter().find(|ns| is_v6(ns) != prefer_v6) {
+            chosen.push(first_other.clone());
+        }
+        for ns in &ordered {
+            if chosen.len() == MAX_NAMESERVERS {
+                break;
+            }
+            if !chosen.contains(ns) {
+                chosen.push(ns.clone());
+            }
+        }
+        
+        // Restore the family ordering after the reservation above
+        let chosen_set = chosen;
+        ordered.into_iter().filter(|ns| chosen_set.contains(ns)).collect()
+    }
+    
+    /// Render a resolv.conf document
+    pub fn resolv_conf(&self, prefer_v6: bool) -> String {
+        let mut out = String::new();
+        for ns in self.resolv_conf_nameservers(prefer_v6) {
+            out.push_str(&format!("nameserver {}\n", ns));
+        }
+        if !self.search.is_empty() {
+            out.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+        if !self.options.is_empty() {
+            out.push_str(&format!("options {}\n", self.options.join(" ")));
+        }
+        out
+    }
+}
+
+fn is_v6(addr: &str) -> bool {
+    addr.parse::<IpAddr>().map(|ip| ip.is_ipv6()).unwrap_or(false)
+}
+
+/// Runtime-injected configuration (`runtimeConfig`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RuntimeConfig {
+    /// Limits from the `bandwidth` capability
+    pub bandwidth: Option<BandwidthConfig>,
+}
+
+/// Traffic shaping limits, from the pod's point of view
+///
+/// Rates are in bits per second and bursts in bits, matching the CNI
+/// bandwidth capability.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct BandwidthConfig {
+    /// Rate limit for traffic entering the pod
+    #[serde(rename = "ingressRate")]
+    pub ingress_rate: Option<u64>,
+    /// Burst size for traffic entering the pod
+    #[serde(rename = "ingressBurst")]
+    pub ingress_burst: Option<u64>,
+    /// Rate limit for traffic leaving the pod
+    #[serde(rename = "egressRate")]
+    pub egress_rate: Option<u64>,
+    /// Burst size for traffic leaving the pod
+    #[serde(rename = "egressBurst")]
+    pub egress_burst: Option<u64>,
+}
+
+impl Default for NetConf {
+    fn default() -> Self {
+        Self {
+            cni_version: CURRENT_CNI_VERSION.to_string(),
+            cni_versions: None,
+            disable_check: false,
+            name: String::new(),
+            plugin_type: "vlan".to_string(),
+            master: String::new(),
+            vlan: 0,
+            inner_vlan: None,
+            vlan_protocol: None,
+            mtu: None,
+            mac: None,
+            ipam: None,
+            post_hook: None,
+            post_del_hook: None,
+            fail_on_hook_error: true,
+            setup_loopback: false,
+            args: None,
+            flush_addresses: false,
+            bandwidth: None,
+            runtime_config: None,
+            disable_ipv6: false,
+            native: false,
+            dns: None,
+            inherit_mtu: true,
+            raise_master_mtu: false,
+            emit_k8s_events: false,
+            k8s_events: None,
+            neighbors: None,
+            firewall: None,
+            numa_hint: false,
+            reorder_hdr: None,
+            gvrp: None,
+            mvrp: None,
+            loose_binding: None,
+            static_ipv6: false,
+            master_netns: None,
+            bridge_companion: None,
+            on_revoke: OnRevoke::Ignore,
+            dscp: None,
+            route_table: None,
+            source_routing: false,
+            check_connectivity: false,
+            connectivity_target: None,
+            resolve_gateway_mac: false,
+            verify_gateway: false,
+            require_gateway: false,
+            admin_up: true,
+            macsec: None,
+            data_dir: None,
+            registry: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// IPAM types socni can assign addresses with; `none` assigns none
+pub const IPAM_TYPES: &[&str] = &["host-local", IPAMD_TYPE, "http", "file", "none"];
+
 /// IPAM (IP Address Management) configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct IPAMConfig {
-    /// Type of IPAM plugin
-    #[serde(rename = "type")]
+    /// Type of IPAM plugin, one of [`IPAM_TYPES`]
+    #[serde(rename = "type", default)]
     pub ipam_type: String,
     /// Subnet CIDR
     pub subnet: Option<String>,
     /// Range of IPs
     pub range: Option<String>,
+    /// Ranges host-local allocates from in order, instead of `subnet` and `gateway`
+    pub ranges: Option<Vec<RangeSet>>,
     /// Gateway IP
     pub gateway: Option<String>,
     /// Routes
     pub routes: Option<Vec<Route>>,
+    /// Always install the gateway as directly reachable on the interface
+    #[serde(rename = "gatewayOnlink", default)]
+    pub gateway_onlink: bool,
+    /// Named address pools a pod can select through CNI_ARGS
+    pub pools: Option<HashMap<String, IpamPool>>,
+    /// CNI_ARGS key holding the requested pool name
+    #[serde(rename = "poolArg", default = "default_pool_arg")]
+    pub pool_arg: String,
+    /// Pool used when none, or an undefined one, is requested
+    #[serde(rename = "defaultPool")]
+    pub default_pool: Option<String>,
+    /// How host-local picks a free address
+    #[serde(rename = "allocationStrategy", default)]
+    pub allocation_strategy: AllocationStrategy,
+    /// Seconds a host-local lease may go unconfirmed before it can be swept
+    #[serde(rename = "leaseTtl", default = "default_lease_ttl")]
+    pub lease_ttl: u64,
+    /// Base URL of the IPAM service (`type: http`)
+    pub url: Option<String>,
+    /// Seconds to wait for the IPAM service
+    #[serde(default = "default_ipam_timeout")]
+    pub timeout: u64,
+    /// JSON file mapping pod identities to addresses (`type: file`)
+    pub file: Option<String>,
+    /// Unix socket of `socni-ctl ipamd` (`type: socni-ipamd`)
+    pub socket: Option<String>,
+    /// Second address on the interface from a separate subnet, e.g. for management traffic
+    pub secondary: Option<SecondaryAddress>,
+    /// Seconds IPv6 addresses stay valid (forever when unset)
+    #[serde(rename = "validLifetime")]
+    pub valid_lifetime: Option<u32>,
+    /// Seconds IPv6 addresses stay preferred (the valid lifetime when unset)
+    #[serde(rename = "preferredLifetime")]
+    pub preferred_lifetime: Option<u32>,
+    /// CIDRs a pod may request its address from through CNI_ARGS `IP=`;
+    /// without them `IP=` is refused
+    #[serde(rename = "allowedSelfAssign")]
+    pub allowed_self_assign: Option<Vec<String>>,
+}
+
+impl IPAMConfig {
+    /// Lifetimes to assign `address` with; IPv4 addresses are always forever
+    pub fn address_lifetimes(&self, address: &str) -> AddressLifetimes {
+        match address.parse::<IpNetwork>() {
+            Ok(IpNetwork::V6(_)) => AddressLifetimes {
+                valid: self.valid_lifetime,
+                preferred: self.preferred_lifetime,
+            },
+            _ => AddressLifetimes::default(),
+        }
+    }
+    
+    /// Address requested through CNI_ARGS `IP=`, checked against `allowedSelfAssign`
+    ///
+    /// Self-assignment is opt-in: a network without `allowedSelfAssign`
+    /// refuses `IP=`. Whether the address also lies in the subnet is checked
+    /// when it is leased.
+    pub fn requested_ip(&self, cni_args: &HashMap<String, String>) -> Result<Option<IpAddr>> {
+        let requested = match cni_args.get("IP") {
+            Some(requested) => requested,
+            None => return Ok(None),
+        };
+        let allowed = self.allowed_self_assign.as_ref().ok_or_else(|| anyhow::anyhow!(
+            "Address {} requested via IP, but the network sets no allowedSelfAssign", requested
+        ))?;
+        let ip: IpAddr = requested.parse()
+            .with_context(|| format!("Invalid address {} requested via IP", requested))?;
+        
+        let permitted = allowed.iter()
+            .filter_map(|cidr| cidr.parse::<IpNetwork>().ok())
+            .any(|cidr| cidr.contains(ip));
+        if !permitted {
+            anyhow::bail!("Address {} requested via IP is not in allowedSelfAssign ({})",
+                          ip, allowed.join(", "));
+        }
+        Ok(Some(ip))
+    }
+}
+
+/// Additional address assigned alongside the primary one
+///
+/// Leased from its own range like a selected pool would be. Only `routes`
+/// go via its gateway; the default route stays on the primary gateway.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SecondaryAddress {
+    #[serde(flatten)]
+    pub pool: IpamPool,
+    /// Routes via the secondary gateway
+    pub routes: Option<Vec<Route>>,
+}
+
+fn default_pool_arg() -> String {
+    "SOCNI_POOL".to_string()
+}
+
+fn default_ipam_timeout() -> u64 {
+    5
+}
+
+/// Default [`IPAMConfig::lease_ttl`]: long enough that a running pod is
+/// never at risk, as its namespace must also be gone before a sweep
+pub const DEFAULT_LEASE_TTL: u64 = 24 * 60 * 60;
+
+fn default_lease_ttl() -> u64 {
+    DEFAULT_LEASE_TTL
+}
+
+/// A named address pool within the IPAM configuration
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct IpamPool {
+    /// Subnet CIDR
+    pub subnet: String,
+    /// First address handed out (defaults to the second host of the subnet)
+    #[serde(rename = "rangeStart")]
+    pub range_start: Option<String>,
+    /// Gateway IP (defaults to the first host of the subnet)
+    pub gateway: Option<String>,
+}
+
+/// One range host-local allocates from
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct RangeSet {
+    /// Subnet CIDR
+    pub subnet: String,
+    /// First address handed out (defaults to the first host of the subnet)
+    #[serde(rename = "rangeStart")]
+    pub range_start: Option<String>,
+    /// Last address handed out (defaults to the last host of the subnet)
+    #[serde(rename = "rangeEnd")]
+    pub range_end: Option<String>,
+    /// Gateway IP (defaults to the first host of the subnet)
+    pub gateway: Option<String>,
+}
+
+impl RangeSet {
+    /// Range host-local allocates from
+    pub fn address_range(&self) -> Result<AddressRange> {
+        let mut range = address_range(&self.subnet, self.range_start.as_deref(), self.gateway.as_deref())?;
+        range.end = self.range_end.as_deref()
+            .map(|e| e.parse::<IpAddr>().with_context(|| format!("Invalid rangeEnd {}", e)))
+            .transpose()?;
+        Ok(range)
+    }
+}
+
+impl IPAMConfig {
+    /// Whether addresses are leased from the host-local store, in-process
+    /// or through `socni-ctl ipamd`
+    pub fn leases_host_local(&self) -> bool {
+        self.ipam_type == "host-local" || self.ipam_type == IPAMD_TYPE
+    }
+    
+    /// Socket `socni-ipamd` IPAM talks to
+    pub fn ipamd_socket(&self) -> &Path {
+        Path::new(self.socket.as_deref().unwrap_or(DEFAULT_SOCKET))
+    }
+    
+    /// Range host-local allocates from when no pool is selected
+    pub fn address_range(&self) -> Result<AddressRange> {
+        let subnet = self.subnet.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("host-local IPAM requires a subnet or ranges"))?;
+        address_range(subnet, None, self.gateway.as_deref())
+    }
+    
+    /// Ranges host-local walks in order when no pool is selected
+    ///
+    /// Without `ranges`, the flat `subnet` and `gateway` form the only range.
+    pub fn address_ranges(&self) -> Result<Vec<AddressRange>> {
+        match self.ranges.as_deref() {
+            Some(ranges) if !ranges.is_empty() => ranges.iter().map(RangeSet::address_range).collect(),
+            _ => Ok(vec![self.address_range()?]),
+        }
+    }
+    
+    /// [`address_ranges`](Self::address_ranges) split by address family, in
+    /// the order each family first appears
+    ///
+    /// A dual-stack configuration has two; one address is leased from each.
+    pub fn address_families(&self) -> Result<Vec<Vec<AddressRange>>> {
+        let mut families: Vec<Vec<AddressRange>> = Vec::new();
+        for range in self.address_ranges()? {
+            match families.iter_mut().find(|family| family[0].subnet.is_ipv4() == range.subnet.is_ipv4()) {
+                Some(family) => family.push(range),
+                None => families.push(vec![range]),
+            }
+        }
+        Ok(families)
+    }
+    
+    /// Subnets addresses may be leased from, for spotting stale leases
+    pub fn subnets(&self) -> Vec<IpNetwork> {
+        self.subnet.iter()
+            .chain(self.ranges.iter().flatten().map(|r| &r.subnet))
+            .chain(self.pools.iter().flatten().map(|(_, p)| &p.subnet))
+            .filter_map(|s| s.parse().ok())
+            .collect()
+    }
+    
+    /// Select the pool requested through CNI_ARGS
+    ///
+    /// Returns `None` when no pools are defined or none applies, in which
+    /// case the top-level subnet is used.
+    pub fn resolve_pool(&self, cni_args: &HashMap<String, String>) -> Result<Option<(&str, &IpamPool)>> {
+        let pools = match &self.pools {
+            Some(pools) if !pools.is_empty() => pools,
+            _ => return Ok(None),
+        };
+        
+        let default = self.default_pool.as_deref()
+            .and_then(|name| pools.get_key_value(name))
+            .map(|(name, pool)| (name.as_str(), pool));
+        
+        match cni_args.get(&self.pool_arg) {
+            Some(requested) => match pools.get_key_value(requested) {
+                Some((name, pool)) => Ok(Some((name.as_str(), pool))),
+                None => match default {
+                    Some(default) => {
+                        warn!("Pool {} requested via {} is not defined; using default pool {}",
+                             requested, self.pool_arg, default.0);
+                        Ok(Some(default))
+                    }
+                    None => anyhow::bail!("Requested IPAM pool {} is not defined", requested),
+                },
+            },
+            None => Ok(default),
+        }
+    }
+}
+
+impl IpamPool {
+    /// Range host-local allocates from when this pool is selected
+    pub fn address_range(&self) -> Result<AddressRange> {
+        address_range(&self.subnet, self.range_start.as_deref(), self.gateway.as_deref())
+    }
+    
+    /// Address (`addr/prefix`) and gateway handed out from this pool
+    pub fn address(&self) -> Result<(String, String)> {
+        let net: IpNetwork = self.subnet.parse()
+            .with_context(|| format!("Invalid pool subnet {}", self.subnet))?;
+        
+        let ip = match &self.range_start {
+            Some(start) => start.parse::<IpAddr>()
+                .with_context(|| format!("Invalid pool rangeStart {}", start))?,
+            None => nth_host(&net, 2)?,
+        };
+        let gateway = match &self.gateway {
+            Some(gw) => gw.parse::<IpAddr>()
+                .with_context(|| format!("Invalid pool gateway {}", gw))?,
+            None => nth_host(&net, 1)?,
+        };
+        
+        if !net.contains(ip) {
+            anyhow::bail!("Pool rangeStart {} is outside subnet {}", ip, self.subnet);
+        }
+        
+        Ok((format!("{}/{}", ip, net.prefix()), gateway.to_string()))
+    }
+}
+
+/// Build an allocation range, defaulting the gateway to the subnet's first host
+fn address_range(subnet: &str, start: Option<&str>, gateway: Option<&str>) -> Result<AddressRange> {
+    let subnet: IpNetwork = subnet.parse()
+        .with_context(|| format!("Invalid IPAM subnet {}", subnet))?;
+    let start = start
+        .map(|s| s.parse::<IpAddr>().with_context(|| format!("Invalid rangeStart {}", s)))
+        .transpose()?;
+    let gateway = match gateway {
+        Some(gw) => gw.parse::<IpAddr>()
+            .with_context(|| format!("Invalid IPAM gateway {}", gw))?,
+        None => nth_host(&subnet, 1)?,
+    };
+    
+    Ok(AddressRange { subnet, start, end: None, gateway: Some(gateway), vlan: None })
+}
+
+/// The `n`th address of a network, failing if it falls outside the prefix
+fn nth_host(net: &IpNetwork, n: u32) -> Result<IpAddr> {
+    let ip = match net.network() {
+        IpAddr::V4(base) => IpAddr::V4(u32::from(base).checked_add(n)
+            .map(std::net::Ipv4Addr::from)
+            .ok_or_else(|| anyhow::anyhow!("Subnet {} is too small", net))?),
+        IpAddr::V6(base) => IpAddr::V6(u128::from(base).checked_add(n as u128)
+            .map(std::net::Ipv6Addr::from)
+            .ok_or_else(|| anyhow::anyhow!("Subnet {} is too small", net))?),
+    };
+    
+    if !net.contains(ip) {
+        anyhow::bail!("Subnet {} is too small", net);
+    }
+    Ok(ip)
 }
 
 /// Route configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Route {
     /// Destination CIDR
     pub dst: String,
     /// Gateway for this route
     pub gw: Option<String>,
+    /// Route metric; the lowest metric wins between overlapping destinations
+    #[serde(default, alias = "priority", skip_serializing_if = "Option::is_none")]
+    pub metric: Option<u32>,
 }
 
 impl NetConf {
     /// Parse NetConf from bytes
     pub fn parse(bytes: &[u8]) -> Result<Self> {
-        let conf: NetConf = serde_json::from_slice(bytes)
+        let mut conf: NetConf = serde_json::from_slice(bytes)
             .context("Failed to parse network configuration")?;
         
-        // Validation
-        if conf.vlan < 1 || conf.vlan > 4094 {
-            anyhow::bail!("Invalid VLAN ID {} (must be between 1 and 4094)", conf.vlan);
+        conf.cni_version = conf.negotiate_cni_version()?;
+        conf.validate()?;
+        
+        Ok(conf)
+    }
+    
+    /// Version to use: the highest supported entry of `cniVersions` when the
+    /// list is present, otherwise `cniVersion`
+    pub fn negotiate_cni_version(&self) -> Result<String> {
+        match &self.cni_versions {
+            Some(versions) if !versions.is_empty() => SUPPORTED_CNI_VERSIONS.iter()
+                .rev()
+                .find(|v| versions.iter().any(|offered| offered == *v))
+                .map(|v| v.to_string())
+                .ok_or_else(|| anyhow::anyhow!(
+                    "none of cniVersions {:?} is supported; supported: {:?}", versions, SUPPORTED_CNI_VERSIONS
+                )),
+            _ if self.cni_version.is_empty() => {
+                anyhow::bail!("network configuration sets neither cniVersion nor cniVersions")
+            }
+            _ => Ok(self.cni_version.clone()),
+        }
+    }
+    
+    /// Validate the configuration without touching the network
+    pub fn validate(&self) -> Result<()> {
+        if !SUPPORTED_CNI_VERSIONS.contains(&self.cni_version.as_str()) {
+            anyhow::bail!("unsupported cniVersion {}; supported: {:?}", self.cni_version, SUPPORTED_CNI_VERSIONS);
+        }
+        
+        if self.native {
+            if self.vlan != 0 {
+                anyhow::bail!("native mode attaches untagged and requires vlan 0, got {}", self.vlan);
+            }
+        } else if self.vlan == 0 {
+            anyhow::bail!("VLAN ID 0 selects the untagged native VLAN; set \"native\": true to confirm");
+        } else if self.vlan > 4094 {
+            anyhow::bail!("Invalid VLAN ID {} (must be between 1 and 4094)", self.vlan);
         }
         
-        if conf.master.is_empty() {
+        // The name keys state files, lock files and leases on disk
+        validate_path_component("Network name", &self.name)?;
+        
+        if self.master.is_empty() {
             anyhow::bail!("Master interface name is required");
         }
         
-        Ok(conf)
+        if let Some(protocol) = &self.vlan_protocol {
+            if protocol != "802.1Q" && protocol != "802.1ad" {
+                anyhow::bail!("Invalid vlanProtocol {} (must be 802.1Q or 802.1ad)", protocol);
+            }
+            if self.native {
+                anyhow::bail!("vlanProtocol only applies to tagged VLANs, not native mode");
+            }
+        }
+        if let Some(inner) = self.inner_vlan {
+            if self.native {
+                anyhow::bail!("innerVlan stacks a tag on the outer VLAN and cannot be combined with native mode");
+            }
+            if !(1..=4094).contains(&inner) {
+                anyhow::bail!("Invalid innerVlan {} (must be between 1 and 4094)", inner);
+            }
+            if self.vlan_protocol.as_deref() == Some("802.1Q") {
+                anyhow::bail!("The outer tag of a QinQ VLAN must use vlanProtocol 802.1ad");
+            }
+        }
+        
+        if self.native && !self.vlan_flag_args().is_empty() {
+            anyhow::bail!("reorderHdr, gvrp, mvrp and looseBinding only apply to tagged VLANs, not native mode");
+        }
+        if self.gvrp == Some(true) && self.mvrp == Some(true) {
+            anyhow::bail!("gvrp and mvrp are alternative registration protocols; enable at most one");
+        }
+        
+        if let Some(master_netns) = &self.master_netns {
+            if master_netns.is_empty() || master_netns.contains('/') {
+                anyhow::bail!("masterNetns {:?} must be the name of a namespace under /var/run/netns", master_netns);
+            }
+            if self.numa_hint {
+                anyhow::bail!("numaHint reads the master through the host's sysfs and cannot be combined with masterNetns");
+            }
+        }
+        
+        if self.static_ipv6 && self.disable_ipv6 {
+            anyhow::bail!("staticIpv6 and disableIpv6 are mutually exclusive");
+        }
+        
+        if let Some(mtu) = self.mtu {
+            if !(68..=65535).contains(&mtu) {
+                anyhow::bail!("Invalid MTU {} (must be between 68 and 65535)", mtu);
+            }
+        }
+        
+        if let Some(ipam) = &self.ipam {
+            // Any other type would leave the pod without a usable address
+            if ipam.ipam_type.is_empty() {
+                anyhow::bail!("IPAM type is required; expected one of {}", IPAM_TYPES.join(", "));
+            }
+            if !IPAM_TYPES.contains(&ipam.ipam_type.as_str()) {
+                anyhow::bail!("Unsupported IPAM type {}; expected one of {}", ipam.ipam_type, IPAM_TYPES.join(", "));
+            }
+            if let Some(subnet) = &ipam.subnet {
+                subnet.parse::<IpNetwork>()
+                    .with_context(|| format!("Invalid IPAM subnet {}", subnet))?;
+            }
+            if let Some(gateway) = &ipam.gateway {
+                gateway.parse::<IpAddr>()
+                    .with_context(|| format!("Invalid IPAM gateway {}", gateway))?;
+            }
+            if let Some(ranges) = &ipam.ranges {
+                if ipam.subnet.is_some() {
+                    anyhow::bail!("IPAM subnet and ranges cannot both be set");
+                }
+                if ranges.is_empty() {
+                    anyhow::bail!("IPAM ranges must list at least one range");
+                }
+                for (i, set) in ranges.iter().enumerate() {
+                    let range = set.address_range()
+                        .with_context(|| format!("Invalid IPAM range {}", i))?;
+                    for bound in [range.start, range.end].into_iter().flatten() {
+                        if !range.subnet.contains(bound) {
+                            anyhow::bail!("IPAM range {} bound {} is outside subnet {}", i, bound, range.subnet);
+                        }
+                    }
+                    if let (Some(start), Some(end)) = (range.start, range.end) {
+                        if start > end {
+                            anyhow::bail!("IPAM range {} starts at {} after its end {}", i, start, end);
+                        }
+                    }
+                }
+            }
+            for route in ipam.routes.iter().flatten() {
+                route.dst.parse::<IpNetwork>()
+                    .with_context(|| format!("Invalid route destination {}", route.dst))?;
+            }
+            for (name, pool) in ipam.pools.iter().flatten() {
+                pool.address()
+                    .with_context(|| format!("Invalid IPAM pool {}", name))?;
+            }
+            // Without leases every pod selecting a pool would get the same address
+            if ipam.pools.as_ref().is_some_and(|pools| !pools.is_empty()) && !ipam.leases_host_local() {
+                anyhow::bail!("IPAM pools need host-local or {} IPAM to lease addresses from; {} IPAM can't",
+                              IPAMD_TYPE, ipam.ipam_type);
+            }
+            if let Some(default) = &ipam.default_pool {
+                if !ipam.pools.as_ref().map_or(false, |p| p.contains_key(default)) {
+                    anyhow::bail!("IPAM defaultPool {} is not defined in pools", default);
+                }
+            }
+            if ipam.ipam_type == "none" && self.disable_ipv6 {
+                anyhow::bail!("IPAM type none leaves only the IPv6 link-local address; it cannot be combined with disableIpv6");
+            }
+            if ipam.ipam_type == "http" {
+                match &ipam.url {
+                    Some(url) if url.starts_with("http://") || url.starts_with("https://") => {}
+                    Some(url) => anyhow::bail!("IPAM url {} must start with http:// or https://", url),
+                    None => anyhow::bail!("http IPAM requires a url"),
+                }
+                if ipam.timeout == 0 {
+                    anyhow::bail!("IPAM timeout must be greater than zero");
+                }
+            }
+            if ipam.ipam_type == IPAMD_TYPE {
+                if !ipam.ipamd_socket().is_absolute() {
+                    anyhow::bail!("IPAM socket {} must be an absolute path", ipam.ipamd_socket().display());
+                }
+                if ipam.timeout == 0 {
+                    anyhow::bail!("IPAM timeout must be greater than zero");
+                }
+                // The daemon allocates from its own state directory
+                if self.data_dir.is_some() {
+                    anyhow::bail!("dataDir is not supported with {} IPAM", IPAMD_TYPE);
+                }
+            }
+            if ipam.valid_lifetime == Some(0) {
+                anyhow::bail!("IPAM validLifetime must be greater than zero");
+            }
+            if let (Some(valid), Some(preferred)) = (ipam.valid_lifetime, ipam.preferred_lifetime) {
+                if preferred > valid {
+                    anyhow::bail!("IPAM preferredLifetime {} exceeds validLifetime {}", preferred, valid);
+                }
+            }
+            for cidr in ipam.allowed_self_assign.iter().flatten() {
+                cidr.parse::<IpNetwork>()
+                    .with_context(|| format!("Invalid allowedSelfAssign CIDR {}", cidr))?;
+            }
+            if ipam.ipam_type == "file" && ipam.file.is_none() {
+                anyhow::bail!("file IPAM requires a file");
+            }
+            if let Some(secondary) = &ipam.secondary {
+                if !ipam.leases_host_local() {
+                    anyhow::bail!("IPAM secondary is not supported with {} IPAM", ipam.ipam_type);
+                }
+                secondary.pool.address()
+                    .context("Invalid IPAM secondary")?;
+                for route in secondary.routes.iter().flatten() {
+                    route.dst.parse::<IpNetwork>()
+                        .with_context(|| format!("Invalid secondary route destination {}", route.dst))?;
+                }
+            }
+        }
+        
+        if let Some(bandwidth) = self.effective_bandwidth() {
+            for (direction, rate, burst) in [
+                ("ingress", bandwidth.ingress_rate, bandwidth.ingress_burst),
+                ("egress", bandwidth.egress_rate, bandwidth.egress_burst),
+            ] {
+                match (rate, burst) {
+                    (Some(0), _) | (_, Some(0)) => {
+                        anyhow::bail!("Bandwidth {} rate and burst must be greater than zero", direction)
+                    }
+                    (Some(_), None) | (None, Some(_)) => {
+                        anyhow::bail!("Bandwidth {} rate and burst must be set together", direction)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        
+        if let Some(dns) = &self.dns {
+            for ns in &dns.nameservers {
+                ns.parse::<IpAddr>()
+                    .with_context(|| format!("Invalid DNS nameserver {}", ns))?;
+            }
+        }
+        
+        for neighbor in self.neighbors.iter().flatten() {
+            neighbor.ip.parse::<IpAddr>()
+                .with_context(|| format!("Invalid neighbor address {}", neighbor.ip))?;
+            if !is_valid_mac(&neighbor.mac) {
+                anyhow::bail!("Invalid MAC address {} for neighbor {}", neighbor.mac, neighbor.ip);
+            }
+        }
+        
+        if let Some(firewall) = &self.firewall {
+            if !["drop", "reject", "accept"].contains(&firewall.default_action.as_str()) {
+                anyhow::bail!("Invalid firewall defaultAction {}", firewall.default_action);
+            }
+            for rule in &firewall.allow {
+                rule.cidr.parse::<IpNetwork>()
+                    .with_context(|| format!("Invalid firewall CIDR {}", rule.cidr))?;
+                if let Some(protocol) = &rule.protocol {
+                    if protocol != "tcp" && protocol != "udp" {
+                        anyhow::bail!("Invalid firewall protocol {}", protocol);
+                    }
+                }
+            }
+        }
+        
+        if let Some(target) = &self.connectivity_target {
+            if target.parse::<IpAddr>().is_err() {
+                anyhow::bail!("Invalid connectivityTarget {}: not an IP address", target);
+            }
+        }
+        
+        // 0 is unspec and 253-255 are the kernel's default, main and local tables
+        if let Some(table) = self.route_table {
+            if table == 0 || (253..=255).contains(&table) {
+                anyhow::bail!("Invalid routeTable {} (0 and 253-255 are reserved)", table);
+            }
+        }
+        if self.source_routing {
+            if self.route_table.is_none() {
+                anyhow::bail!("sourceRouting requires a routeTable");
+            }
+            if self.ipam.is_none() || self.link_local_only() {
+                anyhow::bail!("sourceRouting requires IPAM to assign an address");
+            }
+        }
+        
+        if let Some(dscp) = self.dscp {
+            if dscp > 63 {
+                anyhow::bail!("Invalid DSCP {} (must be between 0 and 63)", dscp);
+            }
+        }
+        
+        if let Some(companion) = &self.bridge_companion {
+            validate_ifname(&companion.ifname)
+                .context("Invalid bridgeCompanion ifname")?;
+            if companion.bridge.is_empty() {
+                anyhow::bail!("bridgeCompanion requires a bridge");
+            }
+            if let Some(pool) = &companion.ipam {
+                pool.address_range()
+                    .context("Invalid bridgeCompanion ipam")?;
+            }
+        }
+        
+        if let Some(macsec) = &self.macsec {
+            for peer in &macsec.peers {
+                if !is_valid_mac(peer) {
+                    anyhow::bail!("Invalid MACsec peer MAC address {}", peer);
+                }
+            }
+        }
+        
+        if let Some(registry) = &self.registry {
+            if !registry.endpoint.starts_with("http://") && !registry.endpoint.starts_with("https://") {
+                anyhow::bail!("registry endpoint {} must be an http:// or https:// URL", registry.endpoint);
+            }
+            if registry.key.is_empty() {
+                anyhow::bail!("registry key must not be empty");
+            }
+            let mut rest = registry.key.as_str();
+            while let Some(start) = rest.find('{') {
+                let end = rest[start..].find('}')
+                    .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in registry key {}", registry.key))?;
+                let var = &rest[start + 1..start + end];
+                if !REGISTRY_KEY_VARS.contains(&var) {
+                    anyhow::bail!("Unknown placeholder {{{}}} in registry key {}; expected one of {}",
+                                  var, registry.key, REGISTRY_KEY_VARS.join(", "));
+                }
+                rest = &rest[start + end + 1..];
+            }
+            if registry.timeout == 0 {
+                anyhow::bail!("registry timeout must be at least 1 second");
+            }
+        }
+        
+        if let Some(dir) = &self.data_dir {
+            if !dir.is_absolute() {
+                anyhow::bail!("dataDir {} must be an absolute path", dir.display());
+            }
+        }
+        
+        if let Some(mac) = &self.mac {
+            validate_interface_mac(mac)?;
+        }
+        
+        if let Some(mac) = self.conf_arg("mac") {
+            let mac = mac.as_str()
+                .ok_or_else(|| anyhow::anyhow!("Invalid MAC address {} in args", mac))?;
+            validate_interface_mac(mac)
+                .context("Invalid mac in args")?;
+        }
+        
+        for hook in [&self.post_hook, &self.post_del_hook].into_iter().flatten() {
+            if !hook.starts_with('/') {
+                anyhow::bail!("Hook path {} must be absolute", hook);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Bandwidth limits to apply; runtime-injected values win over the static block
+    pub fn effective_bandwidth(&self) -> Option<&BandwidthConfig> {
+        self.runtime_config.as_ref()
+            .and_then(|rc| rc.bandwidth.as_ref())
+            .or(self.bandwidth.as_ref())
+    }
+    
+    /// MTU to apply to the link given the master's current MTU
+    pub fn effective_mtu(&self, master_mtu: Option<u32>) -> Option<u32> {
+        self.mtu.or_else(|| master_mtu.filter(|_| self.inherit_mtu))
+    }
+    
+    /// IPv6 sysctls that pin the interface to statically assigned addresses
+    pub fn static_ipv6_sysctls(&self) -> &'static [(&'static str, &'static str)] {
+        if self.static_ipv6 {
+            &[("accept_ra", "0"), ("use_tempaddr", "0")]
+        } else {
+            &[]
+        }
+    }
+    
+    /// VLAN flags that are set, by their iproute2 names
+    pub fn vlan_flags(&self) -> Vec<(&'static str, bool)> {
+        [
+            ("reorder_hdr", self.reorder_hdr),
+            ("gvrp", self.gvrp),
+            ("mvrp", self.mvrp),
+            ("loose_binding", self.loose_binding),
+        ]
+        .into_iter()
+        .filter_map(|(flag, value)| value.map(|on| (flag, on)))
+        .collect()
+    }
+    
+    /// Protocol of the pod link's own tag when set; the inner tag of a QinQ
+    /// VLAN is always 802.1Q
+    pub fn link_vlan_protocol(&self) -> Option<&str> {
+        self.vlan_protocol.as_deref().filter(|_| self.inner_vlan.is_none())
+    }
+    
+    /// `ip link add ... type vlan` arguments for the flags that are set
+    ///
+    /// For a single tag this includes its `vlanProtocol`.
+    pub fn vlan_flag_args(&self) -> Vec<String> {
+        let mut args: Vec<String> = self.vlan_flags()
+            .into_iter()
+            .flat_map(|(flag, on)| [flag.to_string(), if on { "on" } else { "off" }.to_string()])
+            .collect();
+        if let Some(protocol) = self.link_vlan_protocol() {
+            args.extend(["protocol".to_string(), protocol.to_string()]);
+        }
+        args
+    }
+    
+    /// Protocol of the tag on the master, 802.1ad by default for QinQ
+    pub fn effective_vlan_protocol(&self) -> Option<&str> {
+        self.vlan_protocol.as_deref()
+            .or_else(|| self.inner_vlan.map(|_| "802.1ad"))
+    }
+    
+    /// VLAN id of the link that moves into the pod: the inner tag for QinQ
+    pub fn link_vlan(&self) -> u16 {
+        self.inner_vlan.unwrap_or(self.vlan)
+    }
+    
+    /// Host link carrying the outer tag of a QinQ VLAN, shared by its pods
+    pub fn outer_link_name(&self) -> Option<String> {
+        self.inner_vlan.map(|_| host_ifname(format!("{}.{}", self.master, self.vlan)))
+    }
+    
+    /// Link the pod's VLAN is created on: the master, or the outer link for QinQ
+    pub fn link_parent(&self) -> String {
+        self.outer_link_name().unwrap_or_else(|| self.master.clone())
+    }
+    
+    /// Whether the interface is an untagged attachment to the native VLAN
+    pub fn is_native(&self) -> bool {
+        self.native && self.vlan == 0
+    }
+    
+    /// Host-local network key the address of the second family is leased
+    /// under, when the IPAM ranges are dual-stack
+    ///
+    /// The key is namespaced under the network's own with a `/`, which no
+    /// network name may contain, so it never collides with another network.
+    pub fn dual_stack_network(&self) -> Option<String> {
+        let families = self.ipam.as_ref()?.address_families().ok()?;
+        let family = if families.get(1)?[0].subnet.is_ipv6() { 6 } else { 4 };
+        Some(format!("{}/ipv{}", self.name, family))
+    }
+    
+    /// Whether the interface is attached without any global address (`ipam.type: none`)
+    pub fn link_local_only(&self) -> bool {
+        self.ipam.as_ref().map_or(false, |ipam| ipam.ipam_type == "none")
+    }
+    
+    /// Name of the link created on the host before it moves into the pod
+    ///
+    /// `<master>.<vlan>`, or `<master>.<vlan>.<innerVlan>` for QinQ, unless
+    /// that isn't a valid interface name, e.g. for a long master name, in
+    /// which case it is sanitized.
+    pub fn host_link_name(&self) -> String {
+        match self.inner_vlan {
+            Some(inner) => host_ifname(format!("{}.{}.{}", self.master, self.vlan, inner)),
+            None => host_ifname(format!("{}.{}", self.master, self.vlan)),
+        }
+    }
+    
+    /// Look up a key in the config-level `args`
+    ///
+    /// Keys are read from `args.cni` (the CNI convention namespace) first and
+    /// then from the top level of `args`.
+    pub fn conf_arg(&self, key: &str) -> Option<&serde_json::Value> {
+        let args = self.args.as_ref()?;
+        args.get("cni")
+            .and_then(|cni| cni.get(key))
+            .or_else(|| args.get(key))
+    }
+    
+    /// Labels from `args.labels`, as an object or a `[{key, value}]` list
+    pub fn labels(&self) -> HashMap<String, String> {
+        let mut labels = HashMap::new();
+        
+        match self.conf_arg("labels") {
+            Some(serde_json::Value::Object(map)) => {
+                for (key, value) in map {
+                    if let Some(value) = value.as_str() {
+                        labels.insert(key.clone(), value.to_string());
+                    }
+                }
+            }
+            Some(serde_json::Value::Array(list)) => {
+                for entry in list {
+                    if let (Some(key), Some(value)) = (
+                        entry.get("key").and_then(|v| v.as_str()),
+                        entry.get("value").and_then(|v| v.as_str()),
+                    ) {
+                        labels.insert(key.to_string(), value.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+        
+        labels
+    }
+    
+    /// Resolve the MAC to apply: CNI_ARGS `MAC`, then `args.mac`, then `mac`
+    pub fn resolve_mac(&self, cni_args: &HashMap<String, String>) -> Option<String> {
+        cni_args.get("MAC")
+            .cloned()
+            .or_else(|| self.conf_arg("mac").and_then(|v| v.as_str()).map(String::from))
+            .or_else(|| self.mac.clone())
+    }
+    
+    /// Resolve the Aranya tenant: CNI_ARGS `ARANYA_TENANT_ID`, then the `tenant` label
+    pub fn resolve_tenant(&self, cni_args: &HashMap<String, String>) -> Option<String> {
+        cni_args.get("ARANYA_TENANT_ID")
+            .cloned()
+            .or_else(|| self.labels().get("tenant").cloned())
     }
     
     /// Create a default configuration for a VLAN
     pub fn new_default(name: &str, master: &str, vlan: u16, mtu: Option<u32>) -> Self {
         Self {
-            cni_version: "1.0.0".to_string(),
             name: name.to_string(),
-            plugin_type: "vlan".to_string(),
             master: master.to_string(),
             vlan,
             mtu,
-            ipam: None,
+            ..Default::default()
         }
     }
     
@@ -115,6 +1593,138 @@ impl NetConf {
     }
 }
 
+/// JSON Schema for NetConf (including IPAMConfig and Route)
+pub fn netconf_schema() -> serde_json::Value {
+    serde_json::to_value(schema_for!(NetConf)).unwrap_or_default()
+}
+
+/// Check that a string is a colon-separated 6-octet MAC address
+pub fn is_valid_mac(mac: &str) -> bool {
+    let octets: Vec<&str> = mac.split(':').collect();
+    octets.len() == 6
+        && octets.iter().all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Check that a valid MAC address is unicast (the group bit of its first octet clear)
+pub fn is_unicast_mac(mac: &str) -> bool {
+    matches!(u8::from_str_radix(&mac[..2], 16), Ok(octet) if octet & 1 == 0)
+}
+
+/// Check whether a valid MAC address is the all-zero address
+pub fn is_zero_mac(mac: &str) -> bool {
+    mac.split(':').all(|octet| octet == "00")
+}
+
+/// Validate a MAC address to set on an interface: well-formed, unicast and not all zeros
+pub fn validate_interface_mac(mac: &str) -> Result<()> {
+    if !is_valid_mac(mac) {
+        anyhow::bail!("Invalid MAC address {}: expected six colon-separated hex octets", mac);
+    }
+    if !is_unicast_mac(mac) {
+        anyhow::bail!("MAC address {} is multicast; an interface needs a unicast address", mac);
+    }
+    if is_zero_mac(mac) {
+        anyhow::bail!("MAC address {} is all zeros; an interface needs a unicast address", mac);
+    }
+    Ok(())
+}
+
+/// Maximum interface name length (IFNAMSIZ minus the NUL terminator)
+pub const MAX_IFNAME_LEN: usize = 15;
+
+/// `name` if it is a valid interface name, sanitized otherwise
+fn host_ifname(name: String) -> String {
+    match validate_ifname(&name) {
+        Ok(()) => name,
+        Err(_) => sanitize_ifname(&name),
+    }
+}
+
+/// Validate a Linux interface name as passed in CNI_IFNAME
+pub fn validate_ifname(name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("Interface name is empty; the runtime must set CNI_IFNAME");
+    }
+    
+    if name.len() > MAX_IFNAME_LEN {
+        anyhow::bail!("Interface name {} is longer than {} characters", name, MAX_IFNAME_LEN);
+    }
+    
+    if name == "." || name == ".." {
+        anyhow::bail!("Interface name {} is reserved", name);
+    }
+    
+    if name.chars().any(|c| c == '/' || c == ':' || c.is_whitespace()) {
+        anyhow::bail!("Interface name {} contains '/', ':' or whitespace", name);
+    }
+    
+    Ok(())
+}
+
+/// Validate an identifier that becomes one component of a path under the
+/// state directory, such as the network name or CNI_CONTAINERID
+pub fn validate_path_component(what: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        anyhow::bail!("{} is empty", what);
+    }
+    
+    if value == "." || value == ".." {
+        anyhow::bail!("{} {} is reserved", what, value);
+    }
+    
+    if value.contains(['/', '\0']) {
+        anyhow::bail!("{} {:?} contains '/' or NUL", what, value);
+    }
+    
+    Ok(())
+}
+
+/// Names that are valid for `ip` but mean something else to the kernel
+const RESERVED_IFNAMES: &[&str] = &["lo", "all", "default", "bonding_masters"];
+
+/// Hex digits of the hash suffix added by [`sanitize_ifname`]
+const IFNAME_HASH_LEN: usize = 6;
+
+/// Derive a valid interface name from an arbitrary identifier
+///
+/// Characters outside `[a-z0-9-]` become `-` (runs are collapsed). When the
+/// result differs from the input, is too long or is reserved, it is
+/// truncated and suffixed with a hash of the original, so distinct inputs
+/// that map to the same characters still get distinct names.
+pub fn sanitize_ifname(raw: &str) -> String {
+    let mut name = String::with_capacity(raw.len());
+    for c in raw.chars().flat_map(char::to_lowercase) {
+        let c = if c.is_ascii_lowercase() || c.is_ascii_digit() { c } else { '-' };
+        if c == '-' && (name.is_empty() || name.ends_with('-')) {
+            continue;
+        }
+        name.push(c);
+    }
+    while name.ends_with('-') {
+        name.pop();
+    }
+    
+    if !name.is_empty() && name == raw && name.len() <= MAX_IFNAME_LEN
+        && !RESERVED_IFNAMES.contains(&name.as_str()) {
+        return name;
+    }
+    
+    let digest = ring::digest::digest(&ring::digest::SHA256, raw.as_bytes());
+    let hash: String = digest.as_ref().iter()
+        .take(IFNAME_HASH_LEN / 2)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    
+    name.truncate(MAX_IFNAME_LEN - IFNAME_HASH_LEN - 1);
+    while name.ends_with('-') {
+        name.pop();
+    }
+    if name.is_empty() {
+        name.push_str("if");
+    }
+    format!("{}-{}", name, hash)
+}
+
 /// Installer for the VLAN CNI plugin
 pub struct Installer {
     config: SocniConfig,
@@ -0,0 +1,126 @@
+//! Triage of a pod whose socni networking failed
+//!
+//! `socni-ctl doctor` gathers what the node knows about a container into
+//! [`Evidence`] and [`likely_causes`] ranks what it points at, so on-call
+//! reads the most probable cause first.
+
+use std::fmt;
+
+use crate::ipam::VlanUsage;
+use crate::logging::OpRecord;
+use crate::state::Attachment;
+use crate::verify::CheckItem;
+
+/// Share of a VLAN's addresses in use above which the pool is reported as nearly full
+const NEARLY_FULL_PERCENT: f64 = 90.0;
+
+/// How likely a cause is to explain the failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::High => write!(f, "high"),
+            Priority::Medium => write!(f, "medium"),
+            Priority::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// One probable explanation with what to do about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cause {
+    pub priority: Priority,
+    pub summary: String,
+}
+
+impl Cause {
+    fn new(priority: Priority, summary: impl Into<String>) -> Self {
+        Self { priority, summary: summary.into() }
+    }
+}
+
+impl fmt::Display for Cause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.priority, self.summary)
+    }
+}
+
+/// Everything the node knows about one container's socni networking
+#[derive(Debug, Default)]
+pub struct Evidence {
+    /// Attachments recorded by ADD and not yet removed by DEL
+    pub attachments: Vec<Attachment>,
+    /// Operation log records for the container, oldest first
+    pub records: Vec<OpRecord>,
+    /// CHECK of each attachment's live interface
+    pub checks: Vec<CheckItem>,
+    /// Whether the VLAN is accessible, or why that couldn't be decided
+    pub access: Option<Result<bool, String>>,
+    /// Host-local pool usage of the VLAN
+    pub usage: Option<VlanUsage>,
+}
+
+impl Evidence {
+    /// VLAN the container was attached to, or last tried to be
+    pub fn vlan(&self) -> Option<u16> {
+        self.attachments.first().map(|a| a.config.vlan)
+            .or_else(|| self.records.last().map(|r| r.vlan))
+    }
+}
+
+/// Likely causes of the failure, most probable first
+pub fn likely_causes(evidence: &Evidence) -> Vec<Cause> {
+    let mut causes = Vec::new();
+    let vlan = evidence.vlan();
+    
+    if evidence.attachments.is_empty() && evidence.records.is_empty() {
+        causes.push(Cause::new(Priority::High, "socni has no record of this container; the runtime never \
+                                               invoked it, so check the conflist and the plugin binary"));
+    }
+    
+    // The most recent ADD error is usually the failure the runtime reported
+    if let Some(record) = evidence.records.iter().rev().find(|r| r.command == "ADD") {
+        if let Some(error) = record.error.as_deref().filter(|_| record.result == "error") {
+            causes.push(Cause::new(Priority::High, format!("last ADD failed: {}", error)));
+        }
+    }
+    
+    match (&evidence.access, vlan) {
+        (Some(Ok(false)), Some(vlan)) => causes.push(Cause::new(Priority::High, format!(
+            "the tenant has no access to VLAN {}; grant it with `socni-ctl grant`", vlan))),
+        (Some(Err(e)), _) => causes.push(Cause::new(Priority::Medium, format!(
+            "the Aranya access decision is unavailable: {}", e))),
+        _ => {}
+    }
+    
+    if let Some(usage) = &evidence.usage {
+        if usage.capacity > 0 && usage.allocated >= usage.capacity {
+            causes.push(Cause::new(Priority::High, format!(
+                "the address pool of VLAN {} is exhausted ({} of {}); run `socni-ctl ipam gc` or widen the range",
+                usage.vlan, usage.allocated, usage.capacity)));
+        } else if usage.utilization() >= NEARLY_FULL_PERCENT {
+            causes.push(Cause::new(Priority::Low, format!(
+                "the address pool of VLAN {} is nearly full ({:.0}%)", usage.vlan, usage.utilization())));
+        }
+    }
+    
+    for item in &evidence.checks {
+        if let Some(error) = &item.error {
+            causes.push(Cause::new(Priority::Medium, format!("live interface {}: {}", item.name, error)));
+        }
+    }
+    
+    if evidence.attachments.len() > 1 {
+        causes.push(Cause::new(Priority::Low, format!(
+            "{} attachments match this container ID; use a longer prefix", evidence.attachments.len())));
+    }
+    
+    causes.sort_by_key(|c| c.priority);
+    causes
+}
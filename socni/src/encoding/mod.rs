@@ -0,0 +1,87 @@
+//! Base64 as PEM, JWTs and etcd's JSON gateway use it
+//!
+//! The standard alphabet (`+/`) is always padded and the URL-safe one
+//! (`-_`, RFC 7515) never is. Decoding is strict: characters of the other
+//! alphabet, whitespace, padding where it doesn't belong and non-zero
+//! trailing bits are all rejected, so every input has one encoding.
+
+use anyhow::Result;
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Base64 variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    /// `+/` with `=` padding, as in PEM and etcd
+    Standard,
+    /// `-_` without padding, as in JWTs
+    UrlSafe,
+}
+
+impl Alphabet {
+    fn symbols(self) -> &'static [u8; 64] {
+        match self {
+            Alphabet::Standard => STANDARD,
+            Alphabet::UrlSafe => URL_SAFE,
+        }
+    }
+}
+
+/// Encode `bytes` in `alphabet`
+pub fn encode(bytes: &[u8], alphabet: Alphabet) -> String {
+    let symbols = alphabet.symbols();
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (chunk.get(1).copied().unwrap_or(0) as u32) << 8
+            | chunk.get(2).copied().unwrap_or(0) as u32;
+        for i in 0..=chunk.len() {
+            out.push(symbols[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        if alphabet == Alphabet::Standard {
+            out.push_str(&"=="[..3 - chunk.len()]);
+        }
+    }
+    out
+}
+
+/// Decode `input` in `alphabet`, rejecting anything but its one encoding
+pub fn decode(input: &str, alphabet: Alphabet) -> Result<Vec<u8>> {
+    let data = match alphabet {
+        Alphabet::Standard => {
+            if input.len() % 4 != 0 {
+                anyhow::bail!("Base64 length {} is not padded to a multiple of 4", input.len());
+            }
+            let padding = input.bytes().rev().take_while(|&c| c == b'=').count();
+            if padding > 2 {
+                anyhow::bail!("Base64 has {} padding characters", padding);
+            }
+            &input[..input.len() - padding]
+        }
+        Alphabet::UrlSafe => input,
+    };
+    if data.len() % 4 == 1 {
+        anyhow::bail!("Base64 length {} is not a whole number of bytes", data.len());
+    }
+    
+    let symbols = alphabet.symbols();
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in data.bytes() {
+        let value = symbols.iter()
+            .position(|&s| s == c)
+            .ok_or_else(|| anyhow::anyhow!("Invalid base64 character {:?}", c as char))?;
+        buffer = buffer << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    if buffer & ((1 << bits) - 1) != 0 {
+        anyhow::bail!("Base64 has non-zero trailing bits");
+    }
+    Ok(out)
+}
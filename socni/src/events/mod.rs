@@ -0,0 +1,162 @@
+//! Kubernetes Events for failed attachments
+//!
+//! When `emitK8sEvents` is set, a failed ADD posts a Warning Event against
+//! the pod named in `CNI_ARGS`. The request is handed to `curl` and never
+//! waited on, so it can't delay or change the CNI response. The token is
+//! passed on curl's stdin, never on its command line.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use tracing::{debug, warn};
+
+use crate::config::K8sEventsConfig;
+use crate::state::now_secs;
+
+/// Default in-cluster service account token
+pub const DEFAULT_TOKEN_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+/// Default in-cluster CA bundle
+pub const DEFAULT_CA_FILE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt";
+
+/// Coarse category of an ADD failure, used as the Event reason suffix
+pub fn error_category(err: &anyhow::Error) -> &'static str {
+    let msg = format!("{:#}", err);
+    
+    if msg.contains("Access denied") {
+        "AccessDenied"
+    } else if msg.contains("CAP_") || msg.contains("Operation not permitted") {
+        "Permission"
+    } else if msg.contains("Master interface") || msg.contains("Bond master") {
+        "MasterInterface"
+    } else if msg.contains("IPAM") || msg.contains("IP address") || msg.contains("pool") {
+        "IPAM"
+    } else if msg.contains("namespace") {
+        "Netns"
+    } else if msg.contains("hook") {
+        "Hook"
+    } else {
+        "Internal"
+    }
+}
+
+/// Build the Event object for a failed ADD of `vlan` in `namespace/pod`
+pub fn failure_event(
+    namespace: &str,
+    pod: &str,
+    network: &str,
+    vlan: u16,
+    err: &anyhow::Error,
+) -> serde_json::Value {
+    let category = error_category(err);
+    let now = rfc3339(now_secs());
+    
+    serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Event",
+        "metadata": {
+            "generateName": format!("{}.socni-", pod),
+            "namespace": namespace,
+        },
+        "involvedObject": {
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "namespace": namespace,
+            "name": pod,
+        },
+        "type": "Warning",
+        "reason": format!("SocniAdd{}", category),
+        "message": format!("socni failed to attach network {} (VLAN {}, {}): {:#}", network, vlan, category, err),
+        "source": {"component": "socni"},
+        "firstTimestamp": now,
+        "lastTimestamp": now,
+        "count": 1,
+    })
+}
+
+/// Post a failure Event in the background
+///
+/// Does nothing when the pod namespace/name are not in `CNI_ARGS`.
+pub fn emit_failure(
+    config: &K8sEventsConfig,
+    cni_args: &HashMap<String, String>,
+    network: &str,
+    vlan: u16,
+    err: &anyhow::Error,
+) {
+    let (namespace, pod) = match (cni_args.get("K8S_POD_NAMESPACE"), cni_args.get("K8S_POD_NAME")) {
+        (Some(ns), Some(pod)) => (ns, pod),
+        _ => {
+            debug!("No pod identity in CNI_ARGS; not emitting a Kubernetes Event");
+            return;
+        }
+    };
+    
+    let event = failure_event(namespace, pod, network, vlan, err);
+    if let Err(e) = post_event(config, namespace, &event) {
+        warn!("Failed to emit Kubernetes Event: {:#}", e);
+    }
+}
+
+fn post_event(config: &K8sEventsConfig, namespace: &str, event: &serde_json::Value) -> Result<()> {
+    let server = match &config.api_server {
+        Some(server) => server.clone(),
+        None => {
+            let host = std::env::var("KUBERNETES_SERVICE_HOST")
+                .context("No apiServer configured and KUBERNETES_SERVICE_HOST is unset")?;
+            let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+            format!("https://{}:{}", host, port)
+        }
+    };
+    
+    let token_file = config.token_file.as_deref().unwrap_or(DEFAULT_TOKEN_FILE);
+    let token = std::fs::read_to_string(token_file)
+        .with_context(|| format!("Failed to read service account token {}", token_file))?;
+    let ca_file = config.ca_file.as_deref().unwrap_or(DEFAULT_CA_FILE);
+    
+    let url = format!("{}/api/v1/namespaces/{}/events", server.trim_end_matches('/'), namespace);
+    
+    // Detached on purpose: the plugin exits without waiting for the apiserver.
+    // The token goes through curl's config on stdin, out of sight of `ps`
+    let mut child = Command::new("curl")
+        .args(["-sS", "--max-time", "5", "--cacert", ca_file, "--config", "-",
+               "-X", "POST", "-H", "Content-Type: application/json",
+               "--data", &event.to_string(), &url])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("Failed to spawn curl")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(auth_config(token.trim()).as_bytes())
+            .context("Failed to pass the token to curl")?;
+    }
+    
+    Ok(())
+}
+
+/// curl config setting the bearer token header
+pub fn auth_config(token: &str) -> String {
+    let escaped = token.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("header = \"Authorization: Bearer {}\"\n", escaped)
+}
+
+/// Format seconds since the epoch as an RFC 3339 UTC timestamp
+pub fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    
+    // Civil-from-days (Howard Hinnant)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
@@ -0,0 +1,211 @@
+//! Per-interface packet filtering with nftables
+//!
+//! Rules live in an `inet socni` table inside the container namespace, in a
+//! chain named after the interface that filters traffic arriving on it.
+//! Established flows and the configured sources are accepted, everything
+//! else hits the default action. The rule handles are returned so DEL can
+//! remove exactly what ADD installed.
+//!
+//! DSCP marking of the pod's egress traffic lives in the same table, in a
+//! postrouting chain of its own per interface. `nft` runs through the
+//! plugin's [`CommandRunner`].
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+
+use crate::config::FirewallConfig;
+use crate::runner::CommandRunner;
+
+/// nftables table holding socni chains
+pub const TABLE: &str = "socni";
+
+/// What ADD installed, persisted for teardown
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirewallState {
+    pub chain: String,
+    pub handles: Vec<u64>,
+}
+
+/// Chain filtering traffic arriving on `ifname`
+pub fn chain_name(ifname: &str) -> String {
+    format!("in-{}", ifname)
+}
+
+/// nft script installing the rules for `ifname`
+pub fn ruleset(ifname: &str, firewall: &FirewallConfig) -> Result<String> {
+    let chain = chain_name(ifname);
+    let prefix = format!("add rule inet {} {} iifname \"{}\"", TABLE, chain, ifname);
+    
+    let mut script = format!("add table inet {}\n", TABLE);
+    script.push_str(&format!(
+        "add chain inet {} {} {{ type filter hook input priority 0; policy accept; }}\n",
+        TABLE, chain
+    ));
+    script.push_str(&format!("{} ct state established,related accept\n", prefix));
+    
+    for rule in &firewall.allow {
+        let net: IpNetwork = rule.cidr.parse()
+            .with_context(|| format!("Invalid firewall CIDR {}", rule.cidr))?;
+        let family = match net.network() {
+            IpAddr::V4(_) => "ip",
+            IpAddr::V6(_) => "ip6",
+        };
+        
+        let mut line = format!("{} {} saddr {}", prefix, family, net);
+        let ports = rule.ports.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ");
+        match (rule.protocol.as_deref(), ports.is_empty()) {
+            (Some(proto), true) => line.push_str(&format!(" meta l4proto {}", proto)),
+            (Some(proto), false) => line.push_str(&format!(" {} dport {{ {} }}", proto, ports)),
+            (None, false) => line.push_str(&format!(" meta l4proto {{ tcp, udp }} th dport {{ {} }}", ports)),
+            (None, true) => {}
+        }
+        line.push_str(" accept\n");
+        script.push_str(&line);
+    }
+    
+    script.push_str(&format!("{} {}\n", prefix, firewall.default_action));
+    Ok(script)
+}
+
+/// Install the rules for `ifname` in the current namespace
+pub fn apply(runner: &dyn CommandRunner, ifname: &str, firewall: &FirewallConfig) -> Result<FirewallState> {
+    let script = ruleset(ifname, firewall)?;
+    run_script(runner, &script).context("Failed to install firewall rules")?;
+    
+    let chain = chain_name(ifname);
+    let list_cmd = runner.output("nft", &["-a", "-j", "list", "chain", "inet", TABLE, &chain])
+        .context("Failed to execute nft list chain")?;
+    if !list_cmd.status.success() {
+        anyhow::bail!("Failed to list firewall chain {}: {}", chain, String::from_utf8_lossy(&list_cmd.stderr));
+    }
+    
+    let listing: serde_json::Value = serde_json::from_slice(&list_cmd.stdout)
+        .context("Failed to parse nft output")?;
+    Ok(FirewallState { chain, handles: rule_handles(&listing) })
+}
+
+/// Remove what [`apply`] installed, falling back to the whole chain
+pub fn remove(runner: &dyn CommandRunner, ifname: &str, state: Option<&FirewallState>) -> Result<()> {
+    let chain = state.map(|s| s.chain.clone()).unwrap_or_else(|| chain_name(ifname));
+    
+    for handle in state.map(|s| s.handles.as_slice()).unwrap_or_default() {
+        let output = runner.output("nft", &["delete", "rule", "inet", TABLE, &chain, "handle", &handle.to_string()])
+            .context("Failed to execute nft delete rule")?;
+        if !output.status.success() {
+            anyhow::bail!("Failed to delete firewall rule {}: {}", handle, String::from_utf8_lossy(&output.stderr));
+        }
+    }
+    
+    let output = runner.output("nft", &["delete", "chain", "inet", TABLE, &chain])
+        .context("Failed to execute nft delete chain")?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && !stderr.contains("No such file or directory") {
+        anyhow::bail!("Failed to delete firewall chain {}: {}", chain, stderr);
+    }
+    
+    Ok(())
+}
+
+/// Feed `script` to `nft -f -`
+fn run_script(runner: &dyn CommandRunner, script: &str) -> Result<()> {
+    let output = runner.output_with_input("nft", &["-f", "-"], script.as_bytes())
+        .context("Failed to execute nft")?;
+    
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Chain marking traffic leaving through `ifname`
+pub fn dscp_chain_name(ifname: &str) -> String {
+    format!("dscp-{}", ifname)
+}
+
+/// nft script marking egress from `addresses` on `ifname` with `dscp`
+///
+/// Without addresses every packet leaving through the interface is marked.
+/// The chain is flushed first so re-running ADD doesn't duplicate rules.
+pub fn dscp_ruleset(ifname: &str, addresses: &[IpAddr], dscp: u8) -> String {
+    let chain = dscp_chain_name(ifname);
+    let prefix = format!("add rule inet {} {} oifname \"{}\"", TABLE, chain, ifname);
+    
+    let mut script = format!("add table inet {}\n", TABLE);
+    script.push_str(&format!(
+        "add chain inet {} {} {{ type filter hook postrouting priority -150; policy accept; }}\n",
+        TABLE, chain
+    ));
+    script.push_str(&format!("flush chain inet {} {}\n", TABLE, chain));
+    
+    if addresses.is_empty() {
+        script.push_str(&format!("{} ip dscp set {}\n", prefix, dscp));
+        script.push_str(&format!("{} ip6 dscp set {}\n", prefix, dscp));
+    }
+    for address in addresses {
+        let family = match address {
+            IpAddr::V4(_) => "ip",
+            IpAddr::V6(_) => "ip6",
+        };
+        script.push_str(&format!("{} {} saddr {} {} dscp set {}\n", prefix, family, address, family, dscp));
+    }
+    script
+}
+
+/// Mark egress from `addresses` on `ifname` in the current namespace
+pub fn apply_dscp(runner: &dyn CommandRunner, ifname: &str, addresses: &[IpAddr], dscp: u8) -> Result<()> {
+    run_script(runner, &dscp_ruleset(ifname, addresses, dscp)).context("Failed to install DSCP marking")
+}
+
+/// Remove the DSCP marking of `ifname`; a missing chain is not an error
+pub fn remove_dscp(runner: &dyn CommandRunner, ifname: &str) -> Result<()> {
+    let chain = dscp_chain_name(ifname);
+    let script = format!("flush chain inet {} {}\ndelete chain inet {} {}\n", TABLE, chain, TABLE, chain);
+    
+    match run_script(runner, &script) {
+        Err(e) if e.to_string().contains("No such file or directory") => Ok(()),
+        Err(e) => Err(e.context(format!("Failed to delete DSCP chain {}", chain))),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// DSCP values set by the marking chain of `ifname`, empty if it is missing
+pub fn installed_dscp(runner: &dyn CommandRunner, ifname: &str) -> Result<Vec<u64>> {
+    let output = runner.output("nft", &["-j", "list", "chain", "inet", TABLE, &dscp_chain_name(ifname)])
+        .context("Failed to execute nft list chain")?;
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    
+    let listing: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Failed to parse nft output")?;
+    Ok(dscp_marks(&listing))
+}
+
+/// DSCP values set by the rules in `nft -j list chain` output
+pub fn dscp_marks(listing: &serde_json::Value) -> Vec<u64> {
+    listing.get("nftables")
+        .and_then(|n| n.as_array())
+        .map(|items| items.iter()
+            .filter_map(|item| item.get("rule"))
+            .filter_map(|rule| rule.get("expr").and_then(|e| e.as_array()))
+            .flatten()
+            .filter_map(|expr| expr.get("mangle"))
+            .filter(|mangle| mangle.pointer("/key/payload/field").and_then(|f| f.as_str()) == Some("dscp"))
+            .filter_map(|mangle| mangle.get("value").and_then(|v| v.as_u64()))
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Rule handles from `nft -a -j list chain` output
+pub fn rule_handles(listing: &serde_json::Value) -> Vec<u64> {
+    listing.get("nftables")
+        .and_then(|n| n.as_array())
+        .map(|items| items.iter()
+            .filter_map(|item| item.get("rule"))
+            .filter_map(|rule| rule.get("handle").and_then(|h| h.as_u64()))
+            .collect())
+        .unwrap_or_default()
+}
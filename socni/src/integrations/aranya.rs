@@ -9,7 +9,8 @@ use aranya_crypto::{
     DeviceId as CryptoDeviceId,
     id::Id,
 };
-use std::path::PathBuf;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
 use tokio::runtime::Runtime;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -32,11 +33,188 @@ pub enum NetworkAction {
 /// VLAN access configuration with crypto
 #[derive(Clone, Debug)]
 struct VlanConfig {
-    label_id: String,
     admin_role: Role,
     device_id: CryptoDeviceId,
 }
 
+/// Inputs and outcome of a VLAN access decision
+#[derive(Clone, Debug)]
+pub struct AccessExplanation {
+    pub vlan_id: u16,
+    pub device_id: String,
+    /// Label guarding the VLAN for the caller's team
+    pub label: String,
+    /// Role of the device on the team
+    pub role: String,
+    /// Labels assigned to the device
+    pub labels: Vec<String>,
+    /// Whether the VLAN's label exists on the team
+    pub label_exists: bool,
+    /// Whether the device holds the VLAN's label
+    pub has_label: bool,
+    /// Final decision
+    pub allowed: bool,
+}
+
+impl std::fmt::Display for AccessExplanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Device {} on VLAN {}:", self.device_id, self.vlan_id)?;
+        writeln!(f, "  VLAN label {} exists: {}", self.label, if self.label_exists { "yes" } else { "no" })?;
+        writeln!(f, "  Role: {}", self.role)?;
+        writeln!(f, "  Labels: {}", if self.labels.is_empty() { "-".to_string() } else { self.labels.join(", ") })?;
+        writeln!(f, "  Holds VLAN label: {}", if self.has_label { "yes" } else { "no" })?;
+        write!(f, "  Decision: {}", if self.allowed { "ALLOW" } else { "DENY" })
+    }
+}
+
+/// Identity the client authenticates as, and the VLAN labels it can see
+#[derive(Clone, Debug)]
+pub struct DaemonInfo {
+    pub device_id: String,
+    pub team_id: String,
+    /// Role of the device on the team
+    pub role: String,
+    /// `{team}/vlan-<id>` labels of the device's team
+    pub vlan_labels: Vec<String>,
+}
+
+impl std::fmt::Display for DaemonInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Device: {}", self.device_id)?;
+        writeln!(f, "Team: {}", self.team_id)?;
+        writeln!(f, "Role: {}", self.role)?;
+        write!(f, "VLAN labels: {}", if self.vlan_labels.is_empty() { "-".to_string() } else { self.vlan_labels.join(", ") })
+    }
+}
+
+/// Key, key identifier and salt of one sender's MACsec secure association
+#[derive(Clone)]
+pub struct MacsecKey {
+    /// Identifies the key to peers; the same on every node of the team
+    pub id: [u8; 16],
+    pub key: Vec<u8>,
+    /// Salt the extended packet number is mixed with into the nonce
+    pub salt: [u8; 12],
+}
+
+impl MacsecKey {
+    /// Key identifier as hex, for `ip macsec`
+    pub fn id_hex(&self) -> String {
+        hex(&self.id)
+    }
+    
+    /// Key as hex, for `ip macsec`
+    pub fn key_hex(&self) -> String {
+        hex(&self.key)
+    }
+    
+    /// Salt as hex, for `ip macsec`
+    pub fn salt_hex(&self) -> String {
+        hex(&self.salt)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Name of the label guarding `vlan_id` for `team_id`: `{team}/vlan-{id}`
+///
+/// Scoping the label by team lets teams own the same VLAN id independently.
+pub fn vlan_label(team_id: &str, vlan_id: u16) -> String {
+    format!("{}/vlan-{}", team_id, vlan_id)
+}
+
+/// Team and VLAN of a label named by [`vlan_label`]
+///
+/// Only the exact form `vlan_label` produces is accepted: a team without
+/// `/` and a VLAN id without sign or leading zeros. Anything else, including
+/// the unscoped `vlan-{id}` labels of older releases, is `None`, so a label
+/// maps back to at most one (team, VLAN) pair.
+pub fn parse_vlan_label(label: &str) -> Option<(String, u16)> {
+    let (team_id, vlan) = label.split_once('/')?;
+    let vlan_id: u16 = vlan.strip_prefix("vlan-")?.parse().ok()?;
+    if team_id.is_empty() || vlan_label(team_id, vlan_id) != label {
+        return None;
+    }
+    Some((team_id.to_string(), vlan_id))
+}
+
+/// Output length for [`ring::hkdf`]
+struct KeyLen(usize);
+
+impl ring::hkdf::KeyType for KeyLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// Shortest team MACsec secret accepted, in bytes
+pub const MACSEC_SECRET_MIN_LEN: usize = 32;
+
+/// Read the team's MACsec secret, refusing one that is too short or that
+/// users other than its owner can read
+pub fn load_macsec_secret(path: &Path) -> Result<Vec<u8>> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("MACsec secret file {} is missing or inaccessible", path.display()))?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        anyhow::bail!("MACsec secret file {} is accessible by group or others; restrict it with chmod 600",
+                      path.display());
+    }
+    
+    let secret = std::fs::read(path)
+        .with_context(|| format!("Failed to read MACsec secret file {}", path.display()))?;
+    if secret.len() < MACSEC_SECRET_MIN_LEN {
+        anyhow::bail!("MACsec secret file {} holds {} bytes; at least {} are needed",
+                      path.display(), secret.len(), MACSEC_SECRET_MIN_LEN);
+    }
+    Ok(secret)
+}
+
+/// Keys of a VLAN's MACsec secure associations
+///
+/// HKDF-SHA256 over the team's MACsec secret, salted with the team and the
+/// VLAN's label ID, so knowing the IDs alone gives nothing. Each sender's key
+/// is expanded from its MAC address: every node holding the secret and the
+/// label derives a pod's key from the MAC listed in `peers`, without a key
+/// exchange, and no two senders share a key. Recreating the label rotates
+/// the keys.
+pub struct MacsecSecret {
+    prk: ring::hkdf::Prk,
+    vlan_id: u16,
+}
+
+impl MacsecSecret {
+    pub fn new(secret: &[u8], team_id: &str, label_id: &str, vlan_id: u16) -> Self {
+        let salt = ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, format!("{}/{}", team_id, label_id).as_bytes());
+        Self { prk: salt.extract(secret), vlan_id }
+    }
+    
+    /// Key of the secure association sent by the device with MAC `mac`
+    pub fn key_for(&self, mac: &str, key_len: usize) -> Result<MacsecKey> {
+        let info = format!("socni macsec vlan-{} {}", self.vlan_id, mac.to_ascii_lowercase());
+        let info = [info.as_bytes()];
+        let okm = self.prk.expand(&info, KeyLen(16 + 12 + key_len))
+            .map_err(|_| anyhow::anyhow!("Invalid MACsec key length {}", key_len))?;
+        
+        let mut material = vec![0u8; 16 + 12 + key_len];
+        okm.fill(&mut material)
+            .map_err(|_| anyhow::anyhow!("Failed to derive MACsec key for VLAN {}", self.vlan_id))?;
+        
+        let (mut id, mut salt) = ([0u8; 16], [0u8; 12]);
+        id.copy_from_slice(&material[..16]);
+        salt.copy_from_slice(&material[16..28]);
+        Ok(MacsecKey { id, key: material.split_off(28), salt })
+    }
+}
+
+/// Device has access if the VLAN label exists and:
+/// 1. they have the VLAN label OR
+/// 2. they are an Owner/Admin (who implicitly have access to all VLANs)
+fn access_decision(label_exists: bool, has_label: bool, role: &Role) -> bool {
+    label_exists && (has_label || matches!(role, Role::Owner | Role::Admin))
+}
+
 /// Aranya client for security policy enforcement and network sync
 pub struct AranyaClient {
     client: Client,
@@ -100,7 +278,7 @@ impl AranyaClient {
     
     /// Create a new VLAN with cryptographic isolation
     pub fn create_vlan(&mut self, vlan_id: u16) -> Result<()> {
-        let label_id = format!("vlan-{}", vlan_id);
+        let label_id = vlan_label(&self.team_id, vlan_id);
         
         self.runtime.block_on(async {
             let team_id = self.team_id.parse()?;
@@ -117,7 +295,6 @@ impl AranyaClient {
 
             // Store VLAN config
             let config = VlanConfig {
-                label_id: label_id.clone(),
                 admin_role: Role::Admin,
                 device_id: crypto_device_id,
             };
@@ -137,7 +314,7 @@ impl AranyaClient {
     
     /// Check if a device has access to a VLAN with crypto verification
     pub fn check_vlan_access(&mut self, vlan_id: u16) -> Result<bool> {
-        let label_id = format!("vlan-{}", vlan_id);
+        let label_id = vlan_label(&self.team_id, vlan_id);
         
         self.runtime.block_on(async {
             let team_id = self.team_id.parse()?;
@@ -160,16 +337,126 @@ impl AranyaClient {
             // Check if device has the VLAN label
             let has_label = labels.iter().any(|l| l.id.to_string() == label_id);
 
-            // Device has access if:
-            // 1. They have the VLAN label OR
-            // 2. They are an Owner/Admin (who implicitly have access to all VLANs)
-            Ok(has_label || matches!(device_role, Role::Owner | Role::Admin))
+            Ok(access_decision(true, has_label, &device_role))
+        })
+    }
+    
+    /// Break down the access decision for a device on a VLAN
+    ///
+    /// Uses the same inputs and rule as [`check_vlan_access`](Self::check_vlan_access),
+    /// but for any device and without stopping at the first failed check.
+    pub fn explain_access(&mut self, vlan_id: u16, device_id: &str) -> Result<AccessExplanation> {
+        let label_id = vlan_label(&self.team_id, vlan_id);
+        
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            let device: DaemonDeviceId = device_id.parse()?;
+            
+            let mut queries = self.client.queries(team_id);
+            let label_exists = queries.label_exists(label_id.parse()?).await?;
+            let device_role = queries.device_role(device).await?;
+            let labels = queries.device_label_assignments(device).await?;
+            
+            let labels: Vec<String> = labels.iter().map(|l| l.id.to_string()).collect();
+            let has_label = labels.iter().any(|l| *l == label_id);
+            
+            Ok(AccessExplanation {
+                vlan_id,
+                device_id: device_id.to_string(),
+                label: label_id.clone(),
+                role: format!("{:?}", device_role),
+                labels,
+                label_exists,
+                has_label,
+                allowed: access_decision(label_exists, has_label, &device_role),
+            })
+        })
+    }
+    
+    /// Device, role and visible VLAN labels as reported by the daemon
+    pub fn daemon_info(&mut self) -> Result<DaemonInfo> {
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            let device_id = self.client.get_device_id().await
+                .context("Failed to get device ID from the Aranya daemon")?;
+            
+            let mut queries = self.client.queries(team_id);
+            let role = queries.device_role(device_id).await?;
+            let mut vlan_labels: Vec<String> = queries.labels().await?
+                .into_iter()
+                .map(|l| l.name)
+                .filter(|name| matches!(parse_vlan_label(name), Some((team, _)) if team == self.team_id))
+                .collect();
+            vlan_labels.sort();
+            
+            Ok(DaemonInfo {
+                device_id: device_id.to_string(),
+                team_id: self.team_id.clone(),
+                role: format!("{:?}", role),
+                vlan_labels,
+            })
+        })
+    }
+    
+    /// VLANs of the caller's team this device may use
+    ///
+    /// Labels of other teams are skipped even where they guard the same
+    /// VLAN id, since access to them says nothing about this team's VLAN.
+    pub fn list_accessible_vlans(&mut self) -> Result<Vec<u16>> {
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            let device_id = self.client.get_device_id().await?;
+            
+            let mut queries = self.client.queries(team_id);
+            let role = queries.device_role(device_id).await?;
+            let assigned: Vec<String> = queries.device_label_assignments(device_id).await?
+                .iter()
+                .map(|l| l.id.to_string())
+                .collect();
+            
+            let mut vlans: Vec<u16> = queries.labels().await?
+                .into_iter()
+                .filter_map(|l| match parse_vlan_label(&l.name) {
+                    Some((team, vlan)) if team == self.team_id => {
+                        let has_label = assigned.contains(&l.name);
+                        access_decision(true, has_label, &role).then_some(vlan)
+                    }
+                    _ => None,
+                })
+                .collect();
+            vlans.sort_unstable();
+            vlans.dedup();
+            Ok(vlans)
         })
     }
     
+    /// MACsec keys of a VLAN this device has access to, derived from the
+    /// team's `secret`
+    ///
+    /// The keys are bound to the VLAN's label, which only team members can
+    /// read, and are refused when the device may not use the VLAN.
+    pub fn macsec_secret(&mut self, vlan_id: u16, secret: &[u8]) -> Result<MacsecSecret> {
+        if !self.check_vlan_access(vlan_id)? {
+            anyhow::bail!("No access to VLAN {}; refusing to derive its MACsec key", vlan_id);
+        }
+        
+        let label_name = vlan_label(&self.team_id, vlan_id);
+        let label_id = self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            let mut queries = self.client.queries(team_id);
+            queries.labels().await?
+                .into_iter()
+                .find(|l| l.name == label_name)
+                .map(|l| l.id.to_string())
+                .ok_or_else(|| anyhow::anyhow!("VLAN label {} does not exist", label_name))
+        })?;
+        
+        Ok(MacsecSecret::new(secret, &self.team_id, &label_id, vlan_id))
+    }
+    
     /// Grant VLAN access to a device with crypto key distribution
     pub fn grant_vlan_access(&mut self, vlan_id: u16, target_device: &str) -> Result<()> {
-        let label_id = format!("vlan-{}", vlan_id);
+        let label_id = vlan_label(&self.team_id, vlan_id);
         
         self.runtime.block_on(async {
             let team_id = self.team_id.parse()?;
@@ -200,7 +487,7 @@ impl AranyaClient {
     
     /// Revoke VLAN access from a device
     pub fn revoke_vlan_access(&mut self, vlan_id: u16, target_device: &str) -> Result<()> {
-        let label_id = format!("vlan-{}", vlan_id);
+        let label_id = vlan_label(&self.team_id, vlan_id);
         
         self.runtime.block_on(async {
             let team_id = self.team_id.parse()?;
@@ -216,34 +503,114 @@ impl AranyaClient {
         })
     }
 
+    /// Grant VLAN access to several devices in one round trip
+    ///
+    /// The label is created once if needed and every assignment goes through
+    /// the same team handle. Returns the outcome for each device, in order.
+    pub fn grant_vlan_access_many(&mut self, vlan_id: u16, target_devices: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        let label_id = vlan_label(&self.team_id, vlan_id);
+        
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            
+            // Check if label exists
+            {
+                let client_ref = &mut self.client;
+                let mut queries = client_ref.queries(team_id);
+                
+                if !queries.label_exists(label_id.parse()?).await? {
+                    // Create label if it doesn't exist
+                    let mut team = self.client.team(team_id);
+                    team.create_label(label_id.clone()).await?;
+                }
+            }
+            
+            let mut team = self.client.team(team_id);
+            let mut results = Vec::with_capacity(target_devices.len());
+            for device in target_devices {
+                let outcome: Result<()> = async {
+                    team.assign_label(device.parse()?, label_id.parse()?, ChanOp::SendRecv).await?;
+                    Ok(())
+                }.await;
+                results.push((device.to_string(), outcome));
+            }
+            
+            Ok(results)
+        })
+    }
+    
+    /// Revoke VLAN access from several devices in one round trip
+    ///
+    /// Returns the outcome for each device, in order.
+    pub fn revoke_vlan_access_many(&mut self, vlan_id: u16, target_devices: &[&str]) -> Result<Vec<(String, Result<()>)>> {
+        let label_id = vlan_label(&self.team_id, vlan_id);
+        
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            let mut team = self.client.team(team_id);
+            
+            let mut results = Vec::with_capacity(target_devices.len());
+            for device in target_devices {
+                let outcome: Result<()> = async {
+                    team.revoke_label(device.parse()?, label_id.parse()?).await?;
+                    Ok(())
+                }.await;
+                results.push((device.to_string(), outcome));
+            }
+            
+            Ok(results)
+        })
+    }
+    
+    /// Drop this node's registration of a VLAN
+    ///
+    /// The team's label and every device's assignment stay, since other
+    /// nodes may still have pods on the VLAN; deleting it is left to
+    /// [`delete_vlan`](Self::delete_vlan).
+    pub fn release_vlan(&mut self, vlan_id: u16) {
+        self.vlan_configs.lock().unwrap().remove(&vlan_id);
+        
+        // Notify subscribers
+        let _ = self.config_tx.send(NetworkConfigEvent {
+            vlan_id,
+            action: NetworkAction::Delete,
+        });
+    }
+    
     /// Delete a VLAN and its associated policy
+    ///
+    /// Only a VLAN this client created is deleted, and only the caller's
+    /// team's label; other teams' VLANs with the same id are untouched.
     pub fn delete_vlan(&mut self, vlan_id: u16) -> Result<()> {
-        let configs = self.vlan_configs.lock().unwrap();
-        if let Some(config) = configs.get(&vlan_id) {
-            let label_id = config.label_id.clone();
-            drop(configs); // Release lock before async block
-            
-            self.runtime.block_on(async {
-                let team_id = self.team_id.parse()?;
-                let mut team = self.client.team(team_id);
-                
-                // Delete the VLAN label
-                team.delete_label(label_id.parse()?).await?;
-                
-                // Remove from local config
-                let mut configs = self.vlan_configs.lock().unwrap();
-                configs.remove(&vlan_id);
-                
-                // Notify subscribers
-                let _ = self.config_tx.send(NetworkConfigEvent {
-                    vlan_id,
-                    action: NetworkAction::Delete,
-                });
-                
-                Ok(())
-            })
-        } else {
-            Ok(()) // VLAN doesn't exist, nothing to do
+        let label_id = vlan_label(&self.team_id, vlan_id);
+        if !self.vlan_configs.lock().unwrap().contains_key(&vlan_id) {
+            return Ok(()); // VLAN wasn't created here, nothing to do
         }
+        
+        self.runtime.block_on(async {
+            let team_id = self.team_id.parse()?;
+            
+            // Label already gone, nothing to do
+            let mut queries = self.client.queries(team_id);
+            if !queries.label_exists(label_id.parse()?).await? {
+                return Ok(());
+            }
+            
+            // Delete the VLAN label
+            let mut team = self.client.team(team_id);
+            team.delete_label(label_id.parse()?).await?;
+            
+            // Remove from local config
+            let mut configs = self.vlan_configs.lock().unwrap();
+            configs.remove(&vlan_id);
+            
+            // Notify subscribers
+            let _ = self.config_tx.send(NetworkConfigEvent {
+                vlan_id,
+                action: NetworkAction::Delete,
+            });
+            
+            Ok(())
+        })
     }
 } 
\ No newline at end of file
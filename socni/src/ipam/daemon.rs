@@ -0,0 +1,176 @@
+//! Host-local allocation served by one long-running process
+//!
+//! `socni-ctl ipamd` listens on a unix socket and performs every lease
+//! operation itself, so plugin invocations with `ipam.type: socni-ipamd`
+//! queue on the daemon instead of contending for the per-network `flock`.
+//! The daemon still takes the lock, so it can share a state directory with
+//! invocations that use plain `host-local`.
+//!
+//! Each connection carries one newline-terminated JSON [`LeaseRequest`] and
+//! gets one [`LeaseResponse`] line back. `host-local` runs the same requests
+//! in-process through [`handle`].
+//!
+//! Connections are read concurrently, each within a time and size bound,
+//! and only [`handle`] is serialized, so a client that connects and stalls
+//! doesn't hold up the others.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::IpAddr;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::{AddressRange, AllocationStrategy, HostLocal, Lease};
+use crate::netns;
+use crate::types::CniError;
+
+/// `ipam.type` that allocates through the daemon
+pub const IPAMD_TYPE: &str = "socni-ipamd";
+
+/// Socket the daemon listens on unless configured otherwise
+pub const DEFAULT_SOCKET: &str = "/run/socni/ipamd.sock";
+
+/// Longest request line the daemon reads
+pub const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+
+/// A lease operation on one network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum LeaseRequest {
+    /// Lease `requested`, or a free address in `ranges` picked by
+    /// `strategy`, after sweeping leases unconfirmed for `ttl` seconds when
+    /// it is set
+    Allocate {
+        network: String,
+        ranges: Vec<AddressRange>,
+        owner: Lease,
+        strategy: AllocationStrategy,
+        requested: Option<IpAddr>,
+        ttl: Option<u64>,
+    },
+    /// Confirm the interface's existing lease
+    Refresh {
+        network: String,
+        container_id: String,
+        ifname: String,
+    },
+    /// Free the interface's lease
+    Release {
+        network: String,
+        container_id: String,
+        ifname: String,
+    },
+}
+
+/// The leased, refreshed or freed address, or why the request failed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LeaseResponse {
+    pub ip: Option<IpAddr>,
+    pub error: Option<String>,
+}
+
+/// Run `request` against the leases in `allocator`
+pub fn handle(allocator: &HostLocal, request: LeaseRequest) -> Result<Option<IpAddr>> {
+    match request {
+        LeaseRequest::Allocate { network, ranges, owner, strategy, requested, ttl } => {
+            // Opportunistically reclaim leases leaked by containers that are long gone
+            if let Some(ttl) = ttl {
+                match allocator.gc(&network, Duration::from_secs(ttl), &netns::netns_exists) {
+                    Ok(freed) => for (ip, lease) in freed {
+                        info!("Freed expired lease {} of {}", ip, lease.container_id);
+                    },
+                    Err(e) => warn!("Failed to sweep expired leases: {}", e),
+                }
+            }
+            
+            let ip = match requested {
+                Some(ip) => {
+                    info!("Allocating requested address {}", ip);
+                    allocator.allocate_requested(&network, &ranges, &owner, ip)?
+                }
+                None => allocator.allocate(&network, &ranges, &owner, strategy)?,
+            };
+            Ok(Some(ip))
+        }
+        LeaseRequest::Refresh { network, container_id, ifname } => allocator.refresh(&network, &container_id, &ifname),
+        LeaseRequest::Release { network, container_id, ifname } => allocator.release(&network, &container_id, &ifname),
+    }
+}
+
+/// Answer the one request on `stream`
+///
+/// The request has to arrive within `timeout` and [`MAX_REQUEST_BYTES`];
+/// only running it waits on `allocator`.
+pub async fn serve_connection<S>(stream: S, allocator: &Mutex<HostLocal>, timeout: Duration) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut reader = tokio::io::BufReader::new(stream).take(MAX_REQUEST_BYTES);
+    let mut line = String::new();
+    tokio::time::timeout(timeout, reader.read_line(&mut line)).await
+        .map_err(|_| anyhow::anyhow!("No request within {:?}", timeout))?
+        .context("Failed to read request")?;
+    if !line.ends_with('\n') && reader.limit() == 0 {
+        anyhow::bail!("Request exceeds {} bytes", MAX_REQUEST_BYTES);
+    }
+    
+    let response = match serde_json::from_str::<LeaseRequest>(&line) {
+        Ok(request) => match handle(&*allocator.lock().await, request) {
+            Ok(ip) => LeaseResponse { ip, error: None },
+            Err(e) => LeaseResponse { ip: None, error: Some(format!("{:#}", e)) },
+        },
+        Err(e) => LeaseResponse { ip: None, error: Some(format!("Invalid request: {}", e)) },
+    };
+    
+    let mut body = serde_json::to_vec(&response)?;
+    body.push(b'\n');
+    let mut stream = reader.into_inner().into_inner();
+    stream.write_all(&body).await.context("Failed to write response")?;
+    stream.shutdown().await.context("Failed to close connection")?;
+    Ok(())
+}
+
+/// Client for the daemon behind one socket
+pub struct IpamdClient {
+    socket: PathBuf,
+    timeout: Duration,
+}
+
+impl IpamdClient {
+    pub fn new(socket: &Path, timeout: Duration) -> Self {
+        Self { socket: socket.to_path_buf(), timeout }
+    }
+    
+    /// Send `request` and wait for its answer
+    ///
+    /// An unreachable daemon is a retryable CNI error, so the runtime backs
+    /// off while it restarts; an error it reports is returned as is.
+    pub fn request(&self, request: &LeaseRequest) -> Result<Option<IpAddr>> {
+        let response = self.exchange(request).map_err(|e| anyhow::Error::new(CniError::try_again_later(format!(
+            "IPAM daemon at {} is unavailable: {:#}", self.socket.display(), e
+        ))))?;
+        match response.error {
+            Some(error) => Err(anyhow::anyhow!("IPAM daemon at {}: {}", self.socket.display(), error)),
+            None => Ok(response.ip),
+        }
+    }
+    
+    fn exchange(&self, request: &LeaseRequest) -> Result<LeaseResponse> {
+        let mut stream = UnixStream::connect(&self.socket).context("Failed to connect")?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        
+        let mut body = serde_json::to_vec(request)?;
+        body.push(b'\n');
+        stream.write_all(&body).context("Failed to send request")?;
+        
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).context("Failed to read response")?;
+        serde_json::from_str(&line).context("Invalid response")
+    }
+}
@@ -0,0 +1,36 @@
+//! Pre-provisioned addresses read from a file
+//!
+//! For nodes without an IPAM service, assignments are managed outside socni
+//! in a JSON object mapping each pod identity (`namespace/name`) to the
+//! [`Allocation`](super::Allocation) it gets. The file is read on every ADD
+//! and CHECK, so edits apply to the next pod without restarting anything.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::Allocation;
+
+/// Parse an assignments file
+pub fn parse_assignments(data: &[u8]) -> Result<HashMap<String, Allocation>> {
+    let assignments: HashMap<String, Allocation> = serde_json::from_slice(data)
+        .context("Invalid IPAM assignments file")?;
+    
+    for (identity, allocation) in &assignments {
+        allocation.validate()
+            .with_context(|| format!("Invalid assignment for {}", identity))?;
+    }
+    Ok(assignments)
+}
+
+/// The assignment for the pod `identity` in the file at `path`
+pub fn lookup(path: &Path, identity: &str) -> Result<Allocation> {
+    let data = fs::read(path)
+        .with_context(|| format!("Failed to read IPAM assignments file {}", path.display()))?;
+    
+    parse_assignments(&data)
+        .with_context(|| format!("Invalid IPAM assignments file {}", path.display()))?
+        .remove(identity)
+        .ok_or_else(|| anyhow::anyhow!("No address assigned to pod {} in {}", identity, path.display()))
+}
@@ -0,0 +1,97 @@
+//! Address allocation through an external HTTP IPAM service
+//!
+//! ADD POSTs an [`AllocationRequest`] to `<url>/allocate` and expects an
+//! [`Allocation`](super::Allocation) back; DEL POSTs the same request to `<url>/release`. The
+//! service is expected to be idempotent per container ID and interface.
+//! Requests go through `curl`, bounded by the configured timeout.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::time::Duration;
+
+use super::Allocation;
+use crate::runner::CommandRunner;
+use crate::types::CniError;
+
+/// Body of allocate and release requests
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocationRequest {
+    pub network: String,
+    pub vlan: u16,
+    pub container_id: String,
+    pub ifname: String,
+    pub tenant: Option<String>,
+}
+
+/// Client for one IPAM service
+pub struct HttpIpam<'a> {
+    url: String,
+    timeout: Duration,
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> HttpIpam<'a> {
+    pub fn new(url: &str, timeout: Duration, runner: &'a dyn CommandRunner) -> Self {
+        Self { url: url.trim_end_matches('/').to_string(), timeout, runner }
+    }
+    
+    /// Allocate an address
+    ///
+    /// Any failure is reported as a retryable CNI error, so the runtime backs
+    /// off instead of giving up on the pod while the service is unavailable.
+    pub fn allocate(&self, request: &AllocationRequest) -> Result<Allocation> {
+        let allocated = self.post("allocate", request)
+            .and_then(|body| parse_allocation(&body));
+        allocated.map_err(|e| anyhow::Error::new(CniError::try_again_later(format!(
+            "IPAM service {} could not allocate an address for {}: {:#}",
+            self.url, request.container_id, e
+        ))))
+    }
+    
+    /// Release the address of a container interface
+    ///
+    /// Failures are retryable as well, so DEL is retried rather than the
+    /// lease leaking while the service is unavailable.
+    pub fn release(&self, request: &AllocationRequest) -> Result<()> {
+        self.post("release", request).map(|_| ()).map_err(|e| anyhow::Error::new(CniError::try_again_later(format!(
+            "IPAM service {} could not release the address of {}: {:#}",
+            self.url, request.container_id, e
+        ))))
+    }
+    
+    fn post(&self, endpoint: &str, request: &AllocationRequest) -> Result<Vec<u8>> {
+        let url = format!("{}/{}", self.url, endpoint);
+        let body = serde_json::to_vec(request)?;
+        let timeout = self.timeout.as_secs().max(1).to_string();
+        let args = ["-sS", "-X", "POST", "-H", "Content-Type: application/json",
+                    "--data-binary", "@-", "-w", "\n%{http_code}",
+                    "--max-time", timeout.as_str(), url.as_str()];
+        
+        let output = self.runner.output_with_input("curl", &args, &body)
+            .context("Failed to run curl")?;
+        if !output.status.success() {
+            anyhow::bail!("request to {} failed: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        
+        // curl appends the status code on its own line after the body
+        let stdout = output.stdout;
+        let split = stdout.iter().rposition(|b| *b == b'\n').unwrap_or(0);
+        let status = String::from_utf8_lossy(&stdout[split..]).trim().to_string();
+        let response = stdout[..split].to_vec();
+        if !status.starts_with('2') {
+            anyhow::bail!("{} returned HTTP {}: {}", url, status, String::from_utf8_lossy(&response).trim());
+        }
+        
+        Ok(response)
+    }
+}
+
+/// Parse and check an allocation response
+pub fn parse_allocation(body: &[u8]) -> Result<Allocation> {
+    let allocation: Allocation = serde_json::from_slice(body)
+        .context("Invalid allocation response")?;
+    
+    allocation.validate()?;
+    
+    Ok(allocation)
+}
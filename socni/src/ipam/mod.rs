@@ -0,0 +1,733 @@
+//! Host-local address allocation
+//!
+//! Leases for each network are kept in `ipam/<network>.json` under the
+//! state directory, or the network's `dataDir`, and every read-modify-write
+//! happens under a per-network `flock`, so concurrent ADDs on the same node
+//! never hand out the same address. Leases carry the container's namespace
+//! and the time they were last confirmed, so ones leaked by a crashed node
+//! can be swept later. Each file carries its format [`STATE_VERSION`];
+//! older files are upgraded on read and rewritten by [`HostLocal::migrate`]
+//! before the first allocation after an upgrade, and files from a newer
+//! socni are refused.
+
+use anyhow::{Context, Result};
+use ipnetwork::IpNetwork;
+use ring::rand::{SecureRandom, SystemRandom};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Route;
+use crate::state::{now_secs, StateStore};
+
+pub mod daemon;
+pub mod file;
+pub mod http;
+
+/// Attempts at a random pick before falling back to a lowest-free walk
+const RANDOM_ATTEMPTS: usize = 64;
+
+/// Allocation events kept per network; the oldest are dropped first
+pub const HISTORY_LIMIT: usize = 4096;
+
+/// Format of the state files written by this version
+///
+/// 1 is the unversioned format, whose leases may predate refresh tracking.
+pub const STATE_VERSION: u32 = 2;
+
+/// File under the state directory recording the format all state files were migrated to
+const VERSION_FILE: &str = "ipam/VERSION";
+
+/// How the allocator picks a free address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationStrategy {
+    /// First free address from the start of the range
+    #[default]
+    Lowest,
+    /// Any free address in the range
+    Random,
+    /// The pod's previous address if still free, otherwise the lowest free one
+    Sticky,
+}
+
+/// Bounds an address is allocated from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressRange {
+    pub subnet: IpNetwork,
+    /// First usable address (defaults to the first host after the network address)
+    pub start: Option<IpAddr>,
+    /// Last usable address (defaults to the last host of the subnet)
+    pub end: Option<IpAddr>,
+    /// Gateway, which is never handed out
+    pub gateway: Option<IpAddr>,
+    /// VLAN carrying the subnet, recorded for usage reporting
+    pub vlan: Option<u16>,
+}
+
+/// Address handed to a container interface by an IPAM backend
+#[derive(Debug, Clone, Deserialize)]
+pub struct Allocation {
+    /// Address with prefix length (`10.0.0.5/24`)
+    pub address: String,
+    pub gateway: String,
+    /// Routes to install besides the default route
+    #[serde(default)]
+    pub routes: Vec<Route>,
+}
+
+impl Allocation {
+    /// Check the address, gateway and routes handed out by a backend
+    pub fn validate(&self) -> Result<()> {
+        let net = self.address.contains('/')
+            .then(|| self.address.parse::<IpNetwork>().ok())
+            .flatten()
+            .ok_or_else(|| anyhow::anyhow!("Allocated address {} is not in addr/prefix form", self.address))?;
+        let gateway: IpAddr = self.gateway.parse()
+            .with_context(|| format!("Invalid allocated gateway {}", self.gateway))?;
+        if gateway.is_ipv4() != net.is_ipv4() {
+            anyhow::bail!("Allocated gateway {} is not in the address family of {}", gateway, self.address);
+        }
+        for route in &self.routes {
+            route.dst.parse::<IpNetwork>()
+                .with_context(|| format!("Invalid allocated route destination {}", route.dst))?;
+        }
+        
+        Ok(())
+    }
+}
+
+/// Who an address is leased to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    pub container_id: String,
+    pub ifname: String,
+    /// Stable pod identity (`namespace/name`), used by the sticky strategy
+    pub identity: Option<String>,
+    /// Unix timestamp the lease was taken
+    pub allocated: u64,
+    /// Unix timestamp of the last ADD or CHECK that confirmed the lease
+    #[serde(default)]
+    pub refreshed: u64,
+    /// Network namespace of the container, checked before an expired lease is freed
+    #[serde(default)]
+    pub netns: Option<String>,
+    /// Kubernetes pod UID from CNI_ARGS, recorded for auditing
+    #[serde(default)]
+    pub uid: Option<String>,
+}
+
+impl Lease {
+    /// Seconds since the lease was last confirmed
+    pub fn idle_secs(&self, now: u64) -> u64 {
+        now.saturating_sub(self.allocated.max(self.refreshed))
+    }
+}
+
+/// Persisted allocation state of one network
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IpamState {
+    /// Format of the file, see [`STATE_VERSION`]; absent in version 1
+    #[serde(default = "unversioned")]
+    pub version: u32,
+    /// Leases keyed by address
+    pub leases: BTreeMap<String, Lease>,
+    /// Last address held by each pod identity
+    #[serde(default)]
+    pub sticky: BTreeMap<String, String>,
+    /// VLAN of the last allocation
+    #[serde(default)]
+    pub vlan: Option<u16>,
+    /// Addresses the range of the last allocation can hand out
+    #[serde(default)]
+    pub capacity: Option<u64>,
+    /// Allocation events, oldest first, capped at [`HISTORY_LIMIT`]
+    #[serde(default)]
+    pub history: Vec<AllocationEvent>,
+}
+
+fn unversioned() -> u32 {
+    1
+}
+
+impl IpamState {
+    /// Bring state read from disk to the current format
+    pub fn upgrade(mut self) -> Result<Self> {
+        if self.version > STATE_VERSION {
+            anyhow::bail!("State format {} was written by a newer socni (this one reads up to {})",
+                          self.version, STATE_VERSION);
+        }
+        
+        // Version 1 leases written before refreshes were tracked were last
+        // confirmed by their allocation
+        if self.version < 2 {
+            for lease in self.leases.values_mut().filter(|l| l.refreshed == 0) {
+                lease.refreshed = lease.allocated;
+            }
+        }
+        
+        self.version = STATE_VERSION;
+        Ok(self)
+    }
+    
+    /// Append an event for `address` held by `lease`, dropping the oldest past the limit
+    fn record(&mut self, action: AllocationAction, address: &str, lease: &Lease, vlan: Option<u16>) {
+        self.history.push(AllocationEvent {
+            timestamp: now_secs(),
+            action,
+            address: address.to_string(),
+            container_id: lease.container_id.clone(),
+            ifname: lease.ifname.clone(),
+            identity: lease.identity.clone(),
+            uid: lease.uid.clone(),
+            vlan,
+        });
+        if self.history.len() > HISTORY_LIMIT {
+            let excess = self.history.len() - HISTORY_LIMIT;
+            self.history.drain(..excess);
+        }
+    }
+}
+
+/// What happened to a lease
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AllocationAction {
+    /// Leased by ADD
+    Allocated,
+    /// Released by DEL
+    Released,
+    /// Freed by the sweep of leases whose namespace is gone
+    Expired,
+}
+
+impl std::fmt::Display for AllocationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocationAction::Allocated => write!(f, "allocated"),
+            AllocationAction::Released => write!(f, "released"),
+            AllocationAction::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+/// One entry of the audit trail of which pod held which address and when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AllocationEvent {
+    /// Unix timestamp of the event
+    pub timestamp: u64,
+    pub action: AllocationAction,
+    pub address: String,
+    pub container_id: String,
+    pub ifname: String,
+    /// Pod `namespace/name`
+    pub identity: Option<String>,
+    /// Pod UID
+    pub uid: Option<String>,
+    pub vlan: Option<u16>,
+}
+
+/// Address usage of one host-local network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NetworkUsage {
+    pub network: String,
+    pub vlan: Option<u16>,
+    pub allocated: u64,
+    /// `None` until an allocation has recorded the range
+    pub capacity: Option<u64>,
+}
+
+/// Address usage of every network on one VLAN
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VlanUsage {
+    pub vlan: u16,
+    pub allocated: u64,
+    pub capacity: u64,
+    pub networks: Vec<String>,
+}
+
+impl VlanUsage {
+    /// Allocated share of the capacity in percent
+    pub fn utilization(&self) -> f64 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.allocated as f64 * 100.0 / self.capacity as f64
+    }
+}
+
+/// Sum network usage per VLAN, skipping networks with no recorded VLAN
+pub fn vlan_usage(networks: &[NetworkUsage]) -> Vec<VlanUsage> {
+    let mut by_vlan: BTreeMap<u16, VlanUsage> = BTreeMap::new();
+    for usage in networks {
+        let Some(vlan) = usage.vlan else { continue };
+        let entry = by_vlan.entry(vlan).or_insert_with(|| VlanUsage {
+            vlan,
+            allocated: 0,
+            capacity: 0,
+            networks: Vec::new(),
+        });
+        entry.allocated += usage.allocated;
+        entry.capacity = entry.capacity.saturating_add(usage.capacity.unwrap_or(0));
+        entry.networks.push(usage.network.clone());
+    }
+    by_vlan.into_values().collect()
+}
+
+/// Host-local allocator over a [`StateStore`]
+pub struct HostLocal {
+    store: StateStore,
+}
+
+impl HostLocal {
+    /// Create an allocator keeping its state under `state_dir`
+    pub fn new(state_dir: impl Into<PathBuf>) -> Self {
+        Self { store: StateStore::new(state_dir) }
+    }
+    
+    /// Current allocation state of a network
+    pub fn state(&self, network: &str) -> Result<IpamState> {
+        let _lock = self.store.lock(&lock_name(network))?;
+        self.load(network)
+    }
+    
+    /// Rewrite every network's state file in the current format, returning
+    /// the networks that were upgraded
+    ///
+    /// Runs once per directory: the version reached is recorded and later
+    /// calls return at once. Each file is rewritten under its network's lock,
+    /// so a concurrent ADD or DEL sees either format but never loses a lease.
+    pub fn migrate(&self) -> Result<Vec<String>> {
+        let _lock = self.store.lock("ipam-migrate")?;
+        let migrated_to: Option<u32> = self.store.read_json(VERSION_FILE)?;
+        if migrated_to >= Some(STATE_VERSION) {
+            return Ok(Vec::new());
+        }
+        
+        let mut migrated = Vec::new();
+        for network in self.networks()? {
+            let _lock = self.store.lock(&lock_name(&network))?;
+            let state: IpamState = self.store.read_json(&state_file(&network))?;
+            if state.version < STATE_VERSION {
+                self.save(&network, &state.upgrade()?)?;
+                migrated.push(network);
+            }
+        }
+        
+        self.store.write_json(VERSION_FILE, &STATE_VERSION)?;
+        Ok(migrated)
+    }
+    
+    /// Read a network's state in the current format; callers hold its lock
+    fn load(&self, network: &str) -> Result<IpamState> {
+        let state: IpamState = self.store.read_json(&state_file(network))?;
+        state.upgrade()
+            .with_context(|| format!("Failed to read IPAM state of network {}", network))
+    }
+    
+    fn save(&self, network: &str, state: &IpamState) -> Result<()> {
+        self.store.write_json(&state_file(network), state)
+    }
+    
+    /// Names of the networks with allocation state
+    pub fn networks(&self) -> Result<Vec<String>> {
+        let dir = self.store.dir().join("ipam");
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+        };
+        
+        // Keys derived from a network, like `corp/ipv6`, are one level down
+        let mut networks = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if let Some(network) = name.strip_suffix(".json") {
+                networks.push(network.to_string());
+            } else if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                let derived = std::fs::read_dir(entry.path())
+                    .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+                networks.extend(derived
+                    .filter_map(|entry| entry.ok())
+                    .filter_map(|entry| entry.file_name().to_str()
+                        .and_then(|key| key.strip_suffix(".json"))
+                        .map(|key| format!("{}/{}", name, key))));
+            }
+        }
+        networks.sort();
+        Ok(networks)
+    }
+    
+    /// Usage of every network with allocation state
+    pub fn usage(&self) -> Result<Vec<NetworkUsage>> {
+        self.networks()?
+            .into_iter()
+            .map(|network| {
+                let state = self.state(&network)?;
+                Ok(NetworkUsage {
+                    vlan: state.vlan,
+                    allocated: state.leases.len() as u64,
+                    capacity: state.capacity,
+                    network,
+                })
+            })
+            .collect()
+    }
+    
+    /// Lease an address in `ranges` to `owner`
+    ///
+    /// The ranges are walked in order and the first with a free address is
+    /// used. Repeating an allocation for the same container and interface
+    /// returns the address already leased.
+    pub fn allocate(
+        &self,
+        network: &str,
+        ranges: &[AddressRange],
+        owner: &Lease,
+        strategy: AllocationStrategy,
+    ) -> Result<IpAddr> {
+        self.migrate()?;
+        let _lock = self.store.lock(&lock_name(network))?;
+        let mut state = self.load(network)?;
+        
+        if let Some(existing) = find_lease(&state, &owner.container_id, &owner.ifname) {
+            return Ok(existing);
+        }
+        
+        let bounds = ranges.iter()
+            .map(|range| host_bounds(range).map(|bounds| (range, bounds)))
+            .collect::<Result<Vec<_>>>()?;
+        let is_free = |range: &AddressRange, ip: IpAddr| {
+            Some(ip) != range.gateway && !state.leases.contains_key(&ip.to_string())
+        };
+        
+        // The previous address is reused from whichever range still holds it
+        let mut picked = match (strategy, &owner.identity) {
+            (AllocationStrategy::Sticky, Some(identity)) => state.sticky.get(identity)
+                .and_then(|ip| ip.parse::<IpAddr>().ok())
+                .and_then(|ip| bounds.iter()
+                    .find(|(range, (first, last))| range.subnet.contains(ip) && (*first..=*last).contains(&to_u128(ip)))
+                    .filter(|(range, _)| is_free(range, ip))
+                    .map(|(range, _)| (*range, ip))),
+            _ => None,
+        };
+        
+        for (range, (first, last)) in &bounds {
+            if picked.is_some() {
+                break;
+            }
+            let is_free = |ip: IpAddr| is_free(range, ip);
+            picked = match strategy {
+                AllocationStrategy::Random => random_free(range, *first, *last, &is_free)?
+                    .or_else(|| lowest_free(range, *first, *last, &is_free)),
+                _ => lowest_free(range, *first, *last, &is_free),
+            }
+            .map(|ip| (*range, ip));
+        }
+        let (range, ip) = picked.ok_or_else(|| anyhow::anyhow!(
+            "No free addresses left in {} for network {}", subnets(ranges), network
+        ))?;
+        
+        self.record_lease(network, state, range, total_capacity(&bounds), owner, ip)?;
+        Ok(ip)
+    }
+    
+    /// Lease the specific address `ip` in one of `ranges` to `owner`, as
+    /// requested through CNI_ARGS `IP=`
+    ///
+    /// The address must lie in a range, must not be its gateway and must
+    /// not be leased to another interface. Repeating the request for the
+    /// same container and interface returns the address already leased.
+    pub fn allocate_requested(
+        &self,
+        network: &str,
+        ranges: &[AddressRange],
+        owner: &Lease,
+        ip: IpAddr,
+    ) -> Result<IpAddr> {
+        self.migrate()?;
+        let _lock = self.store.lock(&lock_name(network))?;
+        let state = self.load(network)?;
+        
+        if let Some(existing) = find_lease(&state, &owner.container_id, &owner.ifname) {
+            if existing != ip {
+                anyhow::bail!("{} of container {} already holds {}, not the requested {}",
+                              owner.ifname, owner.container_id, existing, ip);
+            }
+            return Ok(existing);
+        }
+        
+        let bounds = ranges.iter()
+            .map(|range| host_bounds(range).map(|bounds| (range, bounds)))
+            .collect::<Result<Vec<_>>>()?;
+        let range = bounds.iter()
+            .find(|(range, (first, last))| range.subnet.contains(ip) && (*first..=*last).contains(&to_u128(ip)))
+            .map(|(range, _)| *range)
+            .ok_or_else(|| anyhow::anyhow!(
+                "Requested address {} is outside the range of {} for network {}", ip, subnets(ranges), network
+            ))?;
+        if Some(ip) == range.gateway {
+            anyhow::bail!("Requested address {} is the gateway of network {}", ip, network);
+        }
+        if let Some(lease) = state.leases.get(&ip.to_string()) {
+            anyhow::bail!("Requested address {} is already leased to container {}", ip, lease.container_id);
+        }
+        
+        self.record_lease(network, state, range, total_capacity(&bounds), owner, ip)?;
+        Ok(ip)
+    }
+    
+    /// Record `ip` in `range` as leased to `owner` and save the state
+    fn record_lease(
+        &self,
+        network: &str,
+        mut state: IpamState,
+        range: &AddressRange,
+        capacity: u64,
+        owner: &Lease,
+        ip: IpAddr,
+    ) -> Result<()> {
+        // Keep the usage summary current with the ranges as configured now
+        state.vlan = range.vlan.or(state.vlan);
+        state.capacity = Some(capacity);
+        
+        let mut lease = owner.clone();
+        lease.allocated = now_secs();
+        lease.refreshed = lease.allocated;
+        if let Some(identity) = &lease.identity {
+            state.sticky.insert(identity.clone(), ip.to_string());
+        }
+        state.record(AllocationAction::Allocated, &ip.to_string(), &lease, range.vlan);
+        state.leases.insert(ip.to_string(), lease);
+        self.save(network, &state)
+    }
+    
+    /// Address currently leased to a container interface
+    pub fn lookup(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<IpAddr>> {
+        Ok(find_lease(&self.state(network)?, container_id, ifname))
+    }
+    
+    /// Mark the lease of a container interface as still in use
+    ///
+    /// Returns the leased address, or `None` if the interface has no lease.
+    pub fn refresh(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<IpAddr>> {
+        let _lock = self.store.lock(&lock_name(network))?;
+        let mut state = self.load(network)?;
+        
+        let leased = find_lease(&state, container_id, ifname);
+        if let Some(ip) = leased {
+            if let Some(lease) = state.leases.get_mut(&ip.to_string()) {
+                lease.refreshed = now_secs();
+            }
+            self.save(network, &state)?;
+        }
+        
+        Ok(leased)
+    }
+    
+    /// Free leases idle for at least `ttl` whose namespace no longer exists
+    ///
+    /// A lease without a recorded namespace is never freed, since there is
+    /// no way to tell whether its container is still running. Returns the
+    /// freed addresses with their leases.
+    pub fn gc(
+        &self,
+        network: &str,
+        ttl: Duration,
+        netns_exists: &dyn Fn(&str) -> bool,
+    ) -> Result<Vec<(String, Lease)>> {
+        let _lock = self.store.lock(&lock_name(network))?;
+        let mut state = self.load(network)?;
+        
+        let now = now_secs();
+        let expired: Vec<String> = state.leases.iter()
+            .filter(|(_, lease)| lease.idle_secs(now) >= ttl.as_secs())
+            .filter(|(_, lease)| lease.netns.as_deref().map_or(false, |netns| !netns_exists(netns)))
+            .map(|(ip, _)| ip.clone())
+            .collect();
+        if expired.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let freed: Vec<(String, Lease)> = expired.into_iter()
+            .filter_map(|ip| state.leases.remove(&ip).map(|lease| (ip, lease)))
+            .collect();
+        let vlan = state.vlan;
+        for (ip, lease) in &freed {
+            state.record(AllocationAction::Expired, ip, lease, vlan);
+        }
+        self.save(network, &state)?;
+        
+        Ok(freed)
+    }
+    
+    /// Release the lease of a container interface, returning the freed address
+    ///
+    /// The sticky record is kept so the pod can get the address back.
+    pub fn release(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<IpAddr>> {
+        let _lock = self.store.lock(&lock_name(network))?;
+        let mut state = self.load(network)?;
+        
+        let freed = find_lease(&state, container_id, ifname);
+        if let Some(ip) = freed {
+            if let Some(lease) = state.leases.remove(&ip.to_string()) {
+                let vlan = state.vlan;
+                state.record(AllocationAction::Released, &ip.to_string(), &lease, vlan);
+            }
+            self.save(network, &state)?;
+        }
+        
+        Ok(freed)
+    }
+    
+    /// Every recorded event for `address` across the networks here, oldest first
+    pub fn history(&self, address: IpAddr) -> Result<Vec<(String, AllocationEvent)>> {
+        let address = address.to_string();
+        let mut events = Vec::new();
+        for network in self.networks()? {
+            events.extend(self.state(&network)?.history.into_iter()
+                .filter(|event| event.address == address)
+                .map(|event| (network.clone(), event)));
+        }
+        events.sort_by_key(|(_, event)| event.timestamp);
+        Ok(events)
+    }
+}
+
+fn lock_name(network: &str) -> String {
+    format!("ipam-{}", network)
+}
+
+fn state_file(network: &str) -> String {
+    format!("ipam/{}.json", network)
+}
+
+fn find_lease(state: &IpamState, container_id: &str, ifname: &str) -> Option<IpAddr> {
+    state.leases.iter()
+        .find(|(_, l)| l.container_id == container_id && l.ifname == ifname)
+        .and_then(|(ip, _)| ip.parse().ok())
+}
+
+/// First and last assignable addresses of a range as integers
+fn host_bounds(range: &AddressRange) -> Result<(u128, u128)> {
+    let net = range.subnet;
+    let network = to_u128(net.network());
+    let size_bits = match net {
+        IpNetwork::V4(n) => 32 - n.prefix() as u32,
+        IpNetwork::V6(n) => 128 - n.prefix() as u32,
+    };
+    let broadcast = if size_bits >= 128 { u128::MAX } else { network + ((1u128 << size_bits) - 1) };
+    
+    // Skip the network address, and the broadcast address for IPv4 subnets
+    // larger than a point-to-point link
+    let mut first = network + 1;
+    let mut last = match net {
+        IpNetwork::V4(_) if size_bits > 1 => broadcast - 1,
+        _ => broadcast,
+    };
+    if size_bits <= 1 {
+        first = network;
+    }
+    
+    if let Some(start) = range.start {
+        if !net.contains(start) {
+            anyhow::bail!("Range start {} is outside subnet {}", start, net);
+        }
+        first = first.max(to_u128(start));
+    }
+    if let Some(end) = range.end {
+        if !net.contains(end) {
+            anyhow::bail!("Range end {} is outside subnet {}", end, net);
+        }
+        last = last.min(to_u128(end));
+    }
+    
+    if first > last {
+        anyhow::bail!("Subnet {} has no assignable addresses", net);
+    }
+    Ok((first, last))
+}
+
+/// Addresses that can be handed out across ranges and their bounds
+fn total_capacity(bounds: &[(&AddressRange, (u128, u128))]) -> u64 {
+    bounds.iter()
+        .map(|(range, (first, last))| capacity(range, *first, *last))
+        .fold(0, u64::saturating_add)
+}
+
+/// Subnets of `ranges` for messages, e.g. `10.0.0.0/24, 10.0.1.0/24`
+fn subnets(ranges: &[AddressRange]) -> String {
+    ranges.iter().map(|range| range.subnet.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Addresses between `first` and `last` that can be handed out
+fn capacity(range: &AddressRange, first: u128, last: u128) -> u64 {
+    let gateway_inside = range.gateway
+        .map(to_u128)
+        .map_or(false, |gw| (first..=last).contains(&gw));
+    let size = (last - first).saturating_add(1) - gateway_inside as u128;
+    u64::try_from(size).unwrap_or(u64::MAX)
+}
+
+fn lowest_free(
+    range: &AddressRange,
+    first: u128,
+    last: u128,
+    is_free: &dyn Fn(IpAddr) -> bool,
+) -> Option<IpAddr> {
+    let mut n = first;
+    loop {
+        let ip = from_u128(&range.subnet, n);
+        if is_free(ip) {
+            return Some(ip);
+        }
+        if n == last {
+            return None;
+        }
+        n += 1;
+    }
+}
+
+fn random_free(
+    range: &AddressRange,
+    first: u128,
+    last: u128,
+    is_free: &dyn Fn(IpAddr) -> bool,
+) -> Result<Option<IpAddr>> {
+    let rng = SystemRandom::new();
+    let span = last - first;
+    
+    for _ in 0..RANDOM_ATTEMPTS {
+        let mut bytes = [0u8; 16];
+        rng.fill(&mut bytes)
+            .map_err(|_| anyhow::anyhow!("Failed to read random bytes"))
+            .context("Random allocation")?;
+        
+        let offset = if span == u128::MAX { u128::from_be_bytes(bytes) } else { u128::from_be_bytes(bytes) % (span + 1) };
+        let ip = from_u128(&range.subnet, first + offset);
+        if is_free(ip) {
+            return Ok(Some(ip));
+        }
+    }
+    
+    Ok(None)
+}
+
+fn to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u32::from(v4) as u128,
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+fn from_u128(net: &IpNetwork, n: u128) -> IpAddr {
+    match net {
+        IpNetwork::V4(_) => IpAddr::V4(Ipv4Addr::from(n as u32)),
+        IpNetwork::V6(_) => IpAddr::V6(Ipv6Addr::from(n)),
+    }
+}
@@ -11,6 +11,26 @@ pub mod plugin;
 pub mod types;
 pub mod commands;
 pub mod integrations;
+pub mod caps;
+pub mod logging;
+pub mod netns;
+pub mod shaping;
+pub mod firewall;
+pub mod sysctl;
+pub mod numa;
+pub mod verify;
+pub mod state;
+pub mod stats;
+pub mod events;
+pub mod ipam;
+pub mod reconcile;
+pub mod runner;
+pub mod backend;
+pub mod doctor;
+pub mod registry;
+pub mod metrics;
+pub mod token;
+pub mod encoding;
 
 // Re-export commonly used items
 pub use config::NetConf;
@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::NetConf;
+use crate::state::{now_secs, StateStore};
+use crate::types::CmdArgs;
+
+/// Operation log file under the state directory
+pub const OPLOG_FILE: &str = "ops.log";
+
+/// Size at which the operation log is rotated
+pub const OPLOG_MAX_BYTES: u64 = 1024 * 1024;
+
+/// Rotated operation logs kept next to the live one (`ops.log.1` is the newest)
+pub const OPLOG_KEEP: usize = 3;
+
+/// Log a recurring warning at most once per `interval`
+///
+/// Every CNI invocation is a fresh process, so the last emission time is
+/// kept as a timestamp file under `state_dir/warnings/<key>`. Repeats within
+/// the window are logged at debug level instead. Failures to read or write
+/// the timestamp never suppress the warning.
+pub fn warn_throttled(state_dir: &Path, key: &str, interval: Duration, msg: &str) {
+    let dir = state_dir.join("warnings");
+    let path = dir.join(key);
+    let now = now_secs();
+    
+    let last = fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok());
+    
+    if let Some(last) = last {
+        if now.saturating_sub(last) < interval.as_secs() {
+            debug!("{} (repeat suppressed)", msg);
+            return;
+        }
+    }
+    
+    warn!("{}", msg);
+    
+    if fs::create_dir_all(&dir).is_ok() {
+        let _ = fs::write(&path, now.to_string());
+    }
+}
+
+/// One CNI invocation, as written to the operation log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpRecord {
+    /// Unix timestamp the command finished
+    pub timestamp: u64,
+    /// CNI command (ADD, DEL or CHECK)
+    pub command: String,
+    pub container_id: String,
+    pub network: String,
+    pub vlan: u16,
+    pub ifname: String,
+    /// `ok` or `error`
+    pub result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl OpRecord {
+    /// Record the outcome of `command` for the given invocation
+    pub fn new<T>(command: &str, conf: &NetConf, args: &CmdArgs, outcome: &Result<T>) -> Self {
+        Self {
+            timestamp: now_secs(),
+            command: command.to_string(),
+            container_id: args.container_id.clone(),
+            network: conf.name.clone(),
+            vlan: conf.vlan,
+            ifname: args.ifname.clone(),
+            result: if outcome.is_ok() { "ok" } else { "error" }.to_string(),
+            error: outcome.as_ref().err().map(|e| format!("{:#}", e)),
+        }
+    }
+}
+
+/// Append-only JSON-lines log of CNI operations with size-based rotation
+///
+/// Each invocation is a separate process, so this is the only place a pod's
+/// whole ADD/CHECK/DEL history can be read back from.
+pub struct OpLog {
+    store: StateStore,
+    max_bytes: u64,
+}
+
+impl OpLog {
+    /// Open the operation log under `state_dir`
+    pub fn new(state_dir: impl Into<PathBuf>) -> Self {
+        Self { store: StateStore::new(state_dir), max_bytes: OPLOG_MAX_BYTES }
+    }
+    
+    /// Rotate once the live file would grow past `max_bytes`
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+    
+    fn path(&self, generation: usize) -> PathBuf {
+        match generation {
+            0 => self.store.dir().join(OPLOG_FILE),
+            n => self.store.dir().join(format!("{}.{}", OPLOG_FILE, n)),
+        }
+    }
+    
+    /// Append a record, rotating the file first if it is full
+    pub fn append(&self, record: &OpRecord) -> Result<()> {
+        let _lock = self.store.lock("ops")?;
+        
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        
+        let current = self.path(0);
+        let size = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            let _ = fs::remove_file(self.path(OPLOG_KEEP));
+            for generation in (0..OPLOG_KEEP).rev() {
+                let from = self.path(generation);
+                if from.exists() {
+                    fs::rename(&from, self.path(generation + 1))
+                        .with_context(|| format!("Failed to rotate {}", from.display()))?;
+                }
+            }
+        }
+        
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)
+            .with_context(|| format!("Failed to open {}", current.display()))?;
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write {}", current.display()))?;
+        
+        Ok(())
+    }
+    
+    /// Every record in the live and rotated files, oldest first
+    ///
+    /// Lines that don't parse, such as one cut short by a crash, are skipped.
+    pub fn read(&self) -> Result<Vec<OpRecord>> {
+        let mut records = Vec::new();
+        for generation in (0..=OPLOG_KEEP).rev() {
+            let path = self.path(generation);
+            let data = match fs::read_to_string(&path) {
+                Ok(data) => data,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+            };
+            records.extend(data.lines().filter_map(|line| serde_json::from_str::<OpRecord>(line).ok()));
+        }
+        
+        Ok(records)
+    }
+}
@@ -0,0 +1,110 @@
+//! OpenMetrics exposition of the node's socni state
+//!
+//! `socni-ctl metrics` renders a [`Snapshot`] of attachments, host-local
+//! pool usage and the operation log on every scrape. The same text is served
+//! over TCP or, where no port may be opened, over a unix domain socket that a
+//! sidecar scraper reads; [`respond`] speaks just enough HTTP for either.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::ipam::VlanUsage;
+use crate::logging::OpRecord;
+use crate::state::Attachment;
+
+/// Content type of the OpenMetrics text format
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Longest request, headers included, that [`respond`] reads
+pub const MAX_REQUEST_BYTES: u64 = 8 * 1024;
+
+/// What the node knows at the time of a scrape
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub attachments: Vec<Attachment>,
+    pub usage: Vec<VlanUsage>,
+    pub records: Vec<OpRecord>,
+}
+
+impl Snapshot {
+    /// Render the snapshot in the OpenMetrics text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        
+        let mut attached: BTreeMap<(&str, u16), u64> = BTreeMap::new();
+        for attachment in &self.attachments {
+            *attached.entry((attachment.config.name.as_str(), attachment.config.vlan)).or_default() += 1;
+        }
+        out.push_str("# TYPE socni_attachments gauge\n");
+        out.push_str("# HELP socni_attachments Pod interfaces attached on this node.\n");
+        for ((network, vlan), count) in attached {
+            let _ = writeln!(out, "socni_attachments{{network=\"{}\",vlan=\"{}\"}} {}", escape(network), vlan, count);
+        }
+        
+        out.push_str("# TYPE socni_ipam_allocated gauge\n");
+        out.push_str("# HELP socni_ipam_allocated Host-local addresses allocated per VLAN.\n");
+        for usage in &self.usage {
+            let _ = writeln!(out, "socni_ipam_allocated{{vlan=\"{}\"}} {}", usage.vlan, usage.allocated);
+        }
+        out.push_str("# TYPE socni_ipam_capacity gauge\n");
+        out.push_str("# HELP socni_ipam_capacity Host-local addresses available per VLAN.\n");
+        for usage in &self.usage {
+            let _ = writeln!(out, "socni_ipam_capacity{{vlan=\"{}\"}} {}", usage.vlan, usage.capacity);
+        }
+        
+        // The operation log is rotated, so this counts what it still holds
+        // and can go down; that makes it a gauge, not a counter
+        let mut operations: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+        for record in &self.records {
+            *operations.entry((record.command.as_str(), record.result.as_str())).or_default() += 1;
+        }
+        out.push_str("# TYPE socni_operations gauge\n");
+        out.push_str("# HELP socni_operations CNI commands still in the operation log by outcome.\n");
+        for ((command, result), count) in operations {
+            let _ = writeln!(out, "socni_operations{{command=\"{}\",result=\"{}\"}} {}",
+                             escape(command), escape(result), count);
+        }
+        
+        out.push_str("# EOF\n");
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Answer one HTTP request on `stream` with `body`, whatever path was asked for
+///
+/// The request has to arrive within `timeout` and [`MAX_REQUEST_BYTES`], so
+/// a client that connects and stalls only holds its own connection.
+pub async fn respond<S: AsyncRead + AsyncWrite + Unpin>(stream: S, body: &str, timeout: Duration) -> Result<()> {
+    let mut reader = BufReader::new(stream).take(MAX_REQUEST_BYTES);
+    
+    // Scrapers only GET, so the headers are read and discarded
+    let headers = async {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line).await.context("Failed to read request")?;
+            if read == 0 || line == "\r\n" || line == "\n" {
+                return Ok(());
+            }
+            if reader.limit() == 0 {
+                anyhow::bail!("Request exceeds {} bytes", MAX_REQUEST_BYTES);
+            }
+        }
+    };
+    tokio::time::timeout(timeout, headers).await
+        .map_err(|_| anyhow::anyhow!("No request within {:?}", timeout))??;
+    
+    let response = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                           CONTENT_TYPE, body.len(), body);
+    let mut stream = reader.into_inner().into_inner();
+    stream.write_all(response.as_bytes()).await.context("Failed to write response")?;
+    stream.shutdown().await.context("Failed to close connection")?;
+    Ok(())
+}
@@ -0,0 +1,295 @@
+//! Network namespace switching for the current thread
+//!
+//! `setns` only affects the calling thread, and a plugin that keeps running
+//! in a container's namespace by accident will configure the wrong links.
+//! [`NetnsGuard`] therefore restores the original namespace on drop, which
+//! covers early returns, panics and cancelled futures as well as the normal
+//! path.
+//!
+//! Invariant: a thread never keeps running outside the namespace it came from.
+//! A failed restore is retried a bounded number of times, and if it still
+//! fails the process aborts rather than returning an error a long-lived
+//! caller could recover from while configuring the container's links.
+
+use anyhow::Result;
+use libc::{self, c_int};
+use std::ffi::CString;
+use std::time::Duration;
+use tracing::{error, warn};
+
+use crate::caps;
+
+// Define platform-specific constants and functions
+#[cfg(target_os = "linux")]
+const CLONE_NEWNET: c_int = 0x40000000;
+
+#[cfg(not(target_os = "linux"))]
+const CLONE_NEWNET: c_int = 0;
+
+#[cfg(target_os = "linux")]
+unsafe fn setns(fd: c_int, nstype: c_int) -> c_int {
+    libc::setns(fd, nstype)
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn setns(_fd: c_int, _nstype: c_int) -> c_int {
+    // On non-Linux platforms, this is a no-op
+    // In a real implementation, you might want to return an error
+    0
+}
+
+/// Directory holding named network namespaces
+pub const NETNS_DIR: &str = "/var/run/netns";
+
+/// Open `name` beneath `dirfd` without following symlinks
+///
+/// Returns `ENOSYS` on kernels older than 5.6, which lack openat2.
+#[cfg(target_os = "linux")]
+fn openat2_beneath(dirfd: c_int, name: &CString) -> std::io::Result<c_int> {
+    let mut how: libc::open_how = unsafe { std::mem::zeroed() };
+    how.flags = (libc::O_RDONLY | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_SYMLINKS | libc::RESOLVE_NO_MAGICLINKS;
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            dirfd,
+            name.as_ptr(),
+            &how as *const libc::open_how,
+            std::mem::size_of::<libc::open_how>(),
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(fd as c_int)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn openat2_beneath(_dirfd: c_int, _name: &CString) -> std::io::Result<c_int> {
+    Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+}
+
+/// `ioctl` returning the `CLONE_NEW*` type of the namespace behind a descriptor
+#[cfg(target_os = "linux")]
+const NS_GET_NSTYPE: libc::c_ulong = 0xb703;
+
+/// Whether `fd` refers to a network namespace
+#[cfg(target_os = "linux")]
+fn is_netns(fd: c_int) -> bool {
+    unsafe { libc::ioctl(fd, NS_GET_NSTYPE as _) == CLONE_NEWNET }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_netns(_fd: c_int) -> bool {
+    true
+}
+
+/// Whether `path` is a process's namespace link under /proc, a magic link
+/// the kernel resolves to the namespace itself
+fn is_proc_netns_link(path: &str) -> bool {
+    path.starts_with("/proc/")
+        && path.ends_with("/ns/net")
+        && !path.split('/').any(|part| part == "." || part == "..")
+}
+
+/// Open a namespace given as an absolute path or as a name under [`NETNS_DIR`]
+///
+/// The value comes from the runtime and the files may be reachable from a
+/// container. For a name, openat2 refuses symlinks and paths escaping the
+/// directory; kernels without openat2 fall back to a plain open. A path is
+/// opened without following a symlink in its last component, except for the
+/// `/proc/<pid>/ns/net` links runtimes pass. Either way the file must turn
+/// out to be a network namespace.
+pub fn open_netns(netns: &str) -> Result<c_int> {
+    let fd = match netns.starts_with('/') {
+        true => open_netns_path(netns)?,
+        false => open_netns_name(netns)?,
+    };
+    if !is_netns(fd) {
+        unsafe { libc::close(fd) };
+        anyhow::bail!("Failed to open netns {}: not a network namespace", netns);
+    }
+    Ok(fd)
+}
+
+fn open_netns_path(path: &str) -> Result<c_int> {
+    let cpath = CString::new(path)?;
+    let mut flags = libc::O_RDONLY | libc::O_CLOEXEC;
+    if !is_proc_netns_link(path) {
+        flags |= libc::O_NOFOLLOW;
+    }
+
+    let fd = unsafe { libc::open(cpath.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(anyhow::anyhow!("Failed to open netns {}: {}", path, std::io::Error::last_os_error()));
+    }
+    Ok(fd)
+}
+
+fn open_netns_name(netns: &str) -> Result<c_int> {
+    let dir = CString::new(NETNS_DIR)?;
+    let name = CString::new(netns)?;
+
+    let dirfd = unsafe { libc::open(dir.as_ptr(), libc::O_RDONLY | libc::O_DIRECTORY | libc::O_CLOEXEC) };
+    if dirfd < 0 {
+        return Err(anyhow::anyhow!("Failed to open {}: {}", NETNS_DIR, std::io::Error::last_os_error()));
+    }
+
+    let opened = openat2_beneath(dirfd, &name);
+    let fd = match opened {
+        Ok(fd) => Ok(fd),
+        Err(e) if e.raw_os_error() == Some(libc::ENOSYS) => {
+            let fd = unsafe { libc::openat(dirfd, name.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+            if fd < 0 { Err(std::io::Error::last_os_error()) } else { Ok(fd) }
+        }
+        Err(e) => Err(e),
+    };
+    unsafe { libc::close(dirfd) };
+
+    fd.map_err(|e| anyhow::anyhow!("Failed to open netns {}: {}", netns, e))
+}
+
+/// Whether a namespace given as a path or as a name under [`NETNS_DIR`] exists
+pub fn netns_exists(netns: &str) -> bool {
+    let path = std::path::Path::new(netns);
+    if path.is_absolute() {
+        path.exists()
+    } else {
+        std::path::Path::new(NETNS_DIR).join(netns).exists()
+    }
+}
+
+/// Upper bound on how long a closure may run inside a namespace
+pub const NETNS_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Attempts at restoring the original namespace before aborting
+pub const RESTORE_ATTEMPTS: u32 = 3;
+
+/// Pause before the first restore retry, doubled for each further one
+const RESTORE_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Holds the current thread in a target namespace until restored or dropped
+pub struct NetnsGuard {
+    /// Descriptor of the namespace we came from
+    cur_netns: c_int,
+    /// Descriptor of the namespace we entered
+    target: c_int,
+    /// Whether the original namespace has already been restored
+    restored: bool,
+    /// Attempts at restoring before aborting
+    restore_attempts: u32,
+}
+
+impl NetnsGuard {
+    /// Switch the current thread into the named namespace
+    pub fn enter(netns: &str) -> Result<Self> {
+        // Open the network namespace
+        let fd = open_netns(netns)?;
+
+        // Get current namespace
+        let cur_path = CString::new("/proc/thread-self/ns/net")?;
+        let cur_netns = unsafe { libc::open(cur_path.as_ptr(), libc::O_RDONLY) };
+        if cur_netns < 0 {
+            unsafe { libc::close(fd) };
+            return Err(anyhow::anyhow!("Failed to open current netns"));
+        }
+
+        // Set the namespace
+        let result = unsafe { setns(fd, CLONE_NEWNET) };
+        if result < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe {
+                libc::close(cur_netns);
+                libc::close(fd);
+            };
+            if err.raw_os_error() == Some(libc::EPERM) {
+                return Err(caps::permission_error(&format!("setns into {}", netns), caps::CAP_SYS_ADMIN));
+            }
+            return Err(anyhow::anyhow!("Failed to set netns {}: {}", netns, err));
+        }
+
+        Ok(Self { cur_netns, target: fd, restored: false, restore_attempts: RESTORE_ATTEMPTS })
+    }
+
+    /// Try restoring the original namespace `attempts` times before aborting
+    pub fn restore_attempts(mut self, attempts: u32) -> Self {
+        self.restore_attempts = attempts.max(1);
+        self
+    }
+
+    /// Restore the original namespace
+    ///
+    /// Never returns with the thread outside its original namespace: when
+    /// every attempt fails the process is aborted.
+    pub fn restore(mut self) {
+        self.restore_inner()
+    }
+
+    fn restore_inner(&mut self) {
+        if self.restored {
+            return;
+        }
+
+        let mut backoff = RESTORE_BACKOFF;
+        for attempt in 1..=self.restore_attempts {
+            if unsafe { setns(self.cur_netns, CLONE_NEWNET) } == 0 {
+                self.restored = true;
+                return;
+            }
+
+            let err = std::io::Error::last_os_error();
+            if attempt == self.restore_attempts {
+                error!("FATAL: failed to restore the original netns after {} attempt(s): {}; \
+                        aborting instead of running on in the container namespace", attempt, err);
+                std::process::abort();
+            }
+            warn!("Failed to restore the original netns (attempt {} of {}): {}; retrying",
+                  attempt, self.restore_attempts, err);
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+}
+
+impl Drop for NetnsGuard {
+    fn drop(&mut self) {
+        self.restore_inner();
+
+        // Close file descriptors
+        unsafe {
+            libc::close(self.cur_netns);
+            libc::close(self.target);
+        };
+    }
+}
+
+/// Execute a closure in a network namespace
+///
+/// The original namespace is restored when the closure finishes, fails,
+/// panics, exceeds [`NETNS_TIMEOUT`] or the returned future is dropped.
+pub async fn in_netns<F, Fut, T>(netns: &str, f: F) -> Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    in_netns_with_attempts(netns, RESTORE_ATTEMPTS, f).await
+}
+
+/// Like [`in_netns`], trying the restore `restore_attempts` times before aborting
+pub async fn in_netns_with_attempts<F, Fut, T>(netns: &str, restore_attempts: u32, f: F) -> Result<T>
+where
+    F: FnOnce() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let guard = NetnsGuard::enter(netns)?.restore_attempts(restore_attempts);
+
+    // Execute the closure
+    let result = tokio::time::timeout(NETNS_TIMEOUT, f()).await
+        .map_err(|_| anyhow::anyhow!("Operation in netns {} timed out after {:?}", netns, NETNS_TIMEOUT));
+
+    // Restore the original namespace
+    guard.restore();
+
+    result?
+}
@@ -0,0 +1,82 @@
+//! NUMA-local queue steering for the VLAN interface
+//!
+//! The master NIC reports its NUMA node in sysfs. Writing that node's CPU
+//! mask to the VLAN link's `rps_cpus` and `xps_cpus` keeps packet
+//! processing on CPUs close to the NIC. Queue attributes belong to the
+//! device, so this is done on the host before the link moves into the pod.
+//! The attributes are written with `tee` through the plugin's
+//! [`CommandRunner`]; sysfs is only read directly.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::runner::CommandRunner;
+
+/// Default sysfs mount point
+pub const SYSFS: &str = "/sys";
+
+/// NUMA node of `master`, or `None` when the device isn't NUMA-local
+pub fn master_numa_node(sysfs: &Path, master: &str) -> Result<Option<u32>> {
+    let path = sysfs.join("class/net").join(master).join("device/numa_node");
+    let node = match fs::read_to_string(&path) {
+        Ok(node) => node,
+        // Virtual devices have no backing device
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    
+    // -1 means the platform doesn't report locality
+    Ok(node.trim().parse::<i32>().ok().filter(|n| *n >= 0).map(|n| n as u32))
+}
+
+/// CPU mask of a NUMA node, in the format the queue attributes accept
+pub fn node_cpumap(sysfs: &Path, node: u32) -> Result<String> {
+    let path = sysfs.join(format!("devices/system/node/node{}/cpumap", node));
+    fs::read_to_string(&path)
+        .map(|mask| mask.trim().to_string())
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Write `mask` to every RPS and XPS queue attribute of `ifname`
+///
+/// Returns how many queue attributes were written.
+pub fn set_queue_affinity(runner: &dyn CommandRunner, sysfs: &Path, ifname: &str, mask: &str) -> Result<usize> {
+    let queues = sysfs.join("class/net").join(ifname).join("queues");
+    let entries = fs::read_dir(&queues)
+        .with_context(|| format!("Failed to read {}", queues.display()))?;
+    
+    let mut written = 0;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name();
+        let attr = match name.to_str() {
+            Some(n) if n.starts_with("rx-") => "rps_cpus",
+            Some(n) if n.starts_with("tx-") => "xps_cpus",
+            _ => continue,
+        };
+        
+        let path = entry.path().join(attr);
+        let output = runner.output_with_input("tee", &[&path.to_string_lossy()], mask.as_bytes())
+            .with_context(|| format!("Failed to execute tee {}", path.display()))?;
+        if !output.status.success() {
+            anyhow::bail!("Failed to write {}: {}", path.display(), String::from_utf8_lossy(&output.stderr).trim());
+        }
+        written += 1;
+    }
+    
+    Ok(written)
+}
+
+/// Steer `ifname`'s queues to the CPUs of `master`'s NUMA node
+///
+/// Returns the node used, or `None` if the master has no NUMA locality.
+pub fn apply_numa_affinity(runner: &dyn CommandRunner, sysfs: &Path, master: &str, ifname: &str) -> Result<Option<u32>> {
+    let node = match master_numa_node(sysfs, master)? {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    
+    let mask = node_cpumap(sysfs, node)?;
+    set_queue_affinity(runner, sysfs, ifname, &mask)?;
+    Ok(Some(node))
+}
@@ -1,35 +1,38 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
 use std::env;
 use std::process::Command;
-use libc::{self, c_int};
+use std::time::{Duration, Instant};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use ipnetwork::IpNetwork;
 use anyhow::{Result, Context};
 use tracing::{info, warn};
 
-use crate::config::NetConf;
-use crate::types::{CmdArgs, Result as CniResult, Interface, IPConfig, Route as CniRoute};
-use crate::integrations::aranya::AranyaClient;
+use crate::backend::{self, AddressLifetimes, IpCommand, IpFamily, LinkKind, MacsecSa, NetBackend, NewLink, NewMacsec, NewRoute};
+use crate::caps;
+use crate::firewall;
+use crate::config::{validate_interface_mac, validate_ifname, BridgeCompanion, MacsecConfig, NetConf, RegistryConfig, Route, SocniConfig};
+use crate::ipam::daemon::{self, IpamdClient, LeaseRequest, IPAMD_TYPE};
+use crate::ipam::http::{AllocationRequest, HttpIpam};
+use crate::ipam::{self, Allocation, HostLocal, Lease};
+use crate::logging::warn_throttled;
+use crate::netns;
+use crate::runner::{CommandRunner, SystemRunner};
+use crate::numa;
+use crate::registry::{self, Registry};
+use crate::shaping;
+use crate::sysctl;
+use crate::verify::{self, CheckItem};
+use crate::state::{now_secs, pod_identity, Attachment, StateStore};
+use crate::stats::{LinkStats, StatsBaseline};
+use crate::token::{self, TokenSubject, TokenVerifier};
+use crate::types::{CmdArgs, CniError, Result as CniResult, Interface, IPConfig, Route as CniRoute, DNS, CNI_ERR_TRY_AGAIN_LATER};
+use crate::integrations::aranya::{load_macsec_secret, AranyaClient, MacsecKey, MacsecSecret};
 use aranya_client::client::Queries;
 use aranya_crypto::DeviceId as CryptoDeviceId;
 
-// Define platform-specific constants and functions
-#[cfg(target_os = "linux")]
-const CLONE_NEWNET: c_int = 0x40000000;
-
-#[cfg(not(target_os = "linux"))]
-const CLONE_NEWNET: c_int = 0;
-
-#[cfg(target_os = "linux")]
-unsafe fn setns(fd: c_int, nstype: c_int) -> c_int {
-    libc::setns(fd, nstype)
-}
-
-#[cfg(not(target_os = "linux"))]
-unsafe fn setns(_fd: c_int, _nstype: c_int) -> c_int {
-    // On non-Linux platforms, this is a no-op
-    // In a real implementation, you might want to return an error
-    0
-}
-
 /// VLAN plugin implementation
 pub struct VlanPlugin {
     /// Network configuration
@@ -38,102 +41,268 @@ pub struct VlanPlugin {
     args: CmdArgs,
     /// Aranya client for security
     aranya: Option<AranyaClient>,
+    /// Node-level plugin configuration
+    socni: SocniConfig,
+    /// Runs sysctl, firewall, `tc` and `ping` commands
+    runner: Arc<dyn CommandRunner>,
+    /// Makes link, address, route and neighbor changes and reads them back
+    backend: Arc<dyn NetBackend>,
+    /// How long each phase of the last ADD took
+    phases: Vec<(&'static str, Duration)>,
+    /// Raised to make an ADD in progress stop and roll back
+    interrupt: Arc<AtomicBool>,
+    /// What the ADD in progress has created so far
+    partial: Partial,
+    /// Tenant claimed by the pod's verified `SOCNI_TOKEN`
+    attested_tenant: Option<String>,
+}
+
+/// Resources an ADD has created and must undo if it fails
+#[derive(Debug, Default)]
+struct Partial {
+    /// Current name of our link in the pod's namespace
+    link: Option<String>,
+    /// Whether an address was leased
+    leased: bool,
+    /// Whether the outer link of a QinQ VLAN is in place
+    outer: bool,
+    /// Host end of the bridge companion veth, which takes the pod end with it
+    companion: Option<String>,
 }
 
 impl VlanPlugin {
     /// Create a new VLAN plugin
     pub fn new(config: NetConf, args: CmdArgs) -> Self {
+        let socni = SocniConfig::load();
+        let runner: Arc<dyn CommandRunner> = Arc::new(SystemRunner);
+        let backend = backend::select(socni.backend, runner.clone());
         Self { 
             config, 
             args,
             aranya: None,
+            socni,
+            runner,
+            backend,
+            phases: Vec::new(),
+            interrupt: Arc::new(AtomicBool::new(false)),
+            partial: Partial::default(),
+            attested_tenant: None,
+        }
+    }
+    
+    /// Plugin for an interface recorded by ADD, as DEL would be invoked for it
+    pub fn for_attachment(attachment: &Attachment) -> Self {
+        let args = CmdArgs {
+            container_id: attachment.container_id.clone(),
+            netns: attachment.netns.clone(),
+            ifname: attachment.ifname.clone(),
+            args: HashMap::new(),
+            path: String::new(),
+            stdin_data: Vec::new(),
+        };
+        Self::new(attachment.config.clone(), args)
+    }
+    
+    /// Run commands through `runner` instead of spawning them directly
+    ///
+    /// Link, address, route and neighbor changes are made with `ip` through
+    /// the same runner.
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.backend = Arc::new(IpCommand::new(runner.clone()));
+        self.runner = runner;
+        self
+    }
+    
+    /// Make link, address, route and neighbor changes through `backend`
+    pub fn with_backend(mut self, backend: Arc<dyn NetBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+    
+    /// Use `socni` as the node-level configuration instead of the one
+    /// loaded from the environment
+    ///
+    /// The backend chosen by [`new`](Self::new) is kept; pick another with
+    /// [`with_backend`](Self::with_backend) or [`with_runner`](Self::with_runner).
+    pub fn with_socni_config(mut self, socni: SocniConfig) -> Self {
+        self.socni = socni;
+        self
+    }
+    
+    /// Time spent in each phase of the last [`add_network`](Self::add_network)
+    ///
+    /// Phases are `aranya`, `link-create`, `netns-move` and `ipam`, in the
+    /// order they ran. `netns-move` is only recorded when the link had to be
+    /// created on the host and moved, rather than created in the pod's namespace.
+    pub fn phase_timings(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+    
+    fn record_phase(&mut self, phase: &'static str, started: Instant) {
+        self.phases.push((phase, started.elapsed()));
+    }
+    
+    /// Stop an ADD between phases and roll it back once `flag` is raised
+    ///
+    /// `vlan-cni` raises it on SIGTERM, so a node being drained doesn't keep
+    /// half-created interfaces or leases nobody will release.
+    pub fn with_interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt = flag;
+        self
+    }
+    
+    fn check_interrupted(&self) -> Result<()> {
+        if self.interrupt.load(Ordering::SeqCst) {
+            anyhow::bail!("Interrupted by SIGTERM; rolled back the partial attachment");
+        }
+        Ok(())
+    }
+    
+    /// Remove the link and lease of an ADD that failed part way
+    async fn roll_back(&mut self) {
+        let partial = std::mem::take(&mut self.partial);
+        if let Some(link) = partial.link {
+            let backend = self.backend.clone();
+            let name = link.clone();
+            let removed = self.in_netns(&self.args.netns, || async move { backend.delete_link(&name) }).await;
+            match removed {
+                Ok(()) => info!("Removed partially configured interface {}", link),
+                Err(e) => warn!("Failed to remove partially configured interface {}: {:#}", link, e),
+            }
+        }
+        if let Some(host_ifname) = partial.companion {
+            match self.backend.delete_link(&host_ifname) {
+                Ok(()) => info!("Removed partially configured bridge companion {}", host_ifname),
+                Err(e) => warn!("Failed to remove partially configured bridge companion {}: {:#}", host_ifname, e),
+            }
+        }
+        if partial.leased {
+            if let Err(e) = self.release_address() {
+                warn!("Failed to release address of partially configured interface: {:#}", e);
+            }
+        }
+        if partial.outer {
+            self.remove_unused_outer_link(&StateStore::new(&self.socni.state_dir));
         }
     }
+    
+    /// Emit a warning that recurs on every invocation, throttled on disk
+    fn warn_recurring(&self, key: &str, msg: &str) {
+        let interval = std::time::Duration::from_secs(self.socni.warn_interval_secs);
+        warn_throttled(&self.socni.state_dir, key, interval, msg);
+    }
 
     /// Initialize Aranya security
     async fn init_aranya(&mut self) -> Result<()> {
+        // Deployments without Aranya opt out explicitly; there is nothing to warn about
+        if self.socni.disable_aranya {
+            return Ok(());
+        }
+        
         // Get Aranya socket path from environment or use default
         let socket_path = env::var("ARANYA_SOCKET_PATH")
             .unwrap_or_else(|_| "/var/run/aranya/api.sock".to_string());
         
-        // Get tenant ID from environment or use container ID as fallback
-        let tenant_id = env::var("ARANYA_TENANT_ID")
-            .unwrap_or_else(|_| self.args.container_id.clone());
+        let tenant_id = self.tenant_id();
         
-        // Create Aranya client
-        let aranya = AranyaClient::new(PathBuf::from(socket_path), tenant_id)?;
+        // The client drives its own runtime, which can't be started from this task
+        let aranya = tokio::task::spawn_blocking(move || AranyaClient::new(PathBuf::from(socket_path), tenant_id))
+            .await
+            .context("Aranya client setup panicked")??;
         self.aranya = Some(aranya);
         Ok(())
     }
     
+    /// Tenant from a verified token, the environment, then CNI_ARGS/config
+    /// args, then the container ID
+    fn tenant_id(&self) -> String {
+        self.attested_tenant.clone()
+            .or_else(|| env::var("ARANYA_TENANT_ID").ok())
+            .or_else(|| self.config.resolve_tenant(&self.args.args))
+            .unwrap_or_else(|| self.args.container_id.clone())
+    }
+    
     /// Check if the current device has access to the VLAN
     fn check_vlan_access(&mut self) -> Result<bool> {
+        if self.socni.disable_aranya {
+            return Ok(true);
+        }
+        
         if let Some(aranya) = &mut self.aranya {
             info!("Checking VLAN {} access through Aranya policy engine", self.config.vlan);
             aranya.check_vlan_access(self.config.vlan)
         } else {
-            warn!("Aranya security not initialized");
+            self.warn_recurring("aranya-uninitialized", "Aranya security not initialized");
             Ok(true) // Allow access for backward compatibility
         }
     }
     
+    /// Lease for this interface, carrying the pod metadata kept for auditing
+    fn lease_owner(&self) -> Lease {
+        Lease {
+            container_id: self.args.container_id.clone(),
+            ifname: self.args.ifname.clone(),
+            identity: pod_identity(&self.args.args),
+            allocated: 0,
+            refreshed: 0,
+            netns: Some(self.args.netns.clone()),
+            uid: self.args.args.get("K8S_POD_UID").cloned(),
+        }
+    }
+    
+    /// Directory holding this network's host-local allocations
+    fn ipam_dir(&self) -> &Path {
+        self.config.data_dir.as_deref().unwrap_or(&self.socni.state_dir)
+    }
+    
     /// Execute a closure in a network namespace
     async fn in_netns<F, Fut, T>(&self, netns: &str, f: F) -> Result<T>
     where
         F: FnOnce() -> Fut,
         Fut: std::future::Future<Output = Result<T>>,
     {
-        // Open the network namespace
-        let netns_path = format!("/var/run/netns/{}", netns);
-        let fd = unsafe { libc::open(netns_path.as_ptr() as *const i8, libc::O_RDONLY) };
-        if fd < 0 {
-            return Err(anyhow::anyhow!("Failed to open netns: {}", netns));
-        }
-
-        // Get current namespace
-        let cur_netns = unsafe { libc::open("/proc/self/ns/net".as_ptr() as *const i8, libc::O_RDONLY) };
-        if cur_netns < 0 {
-            unsafe { libc::close(fd) };
-            return Err(anyhow::anyhow!("Failed to open current netns"));
-        }
-
-        // Set the namespace
-        let result = unsafe { setns(fd, CLONE_NEWNET) };
-        if result < 0 {
-            unsafe { 
-                libc::close(cur_netns);
-                libc::close(fd);
-            };
-            return Err(anyhow::anyhow!("Failed to set netns: {}", netns));
-        }
-
-        // Execute the closure
-        let result = f().await;
-
-        // Restore the original namespace
-        let restore_result = unsafe { setns(cur_netns, CLONE_NEWNET) };
-        if restore_result < 0 {
-            unsafe { 
-                libc::close(cur_netns);
-                libc::close(fd);
-            };
-            return Err(anyhow::anyhow!("Failed to restore original netns"));
+        if !self.runner.enter_netns(netns) {
+            return f().await;
         }
-
-        // Close file descriptors
-        unsafe { 
-            libc::close(cur_netns);
-            libc::close(fd);
-        };
-
-        result
+        netns::in_netns_with_attempts(netns, self.socni.netns_restore_attempts, f).await
     }
 
     /// Add a VLAN network
+    ///
+    /// A failed or interrupted ADD removes the link and releases the lease it
+    /// had created by then, before returning the error.
     pub async fn add_network(&mut self) -> Result<CniResult> {
+        self.phases.clear();
+        self.partial = Partial::default();
+        let added = self.try_add_network().await;
+        if added.is_err() {
+            self.roll_back().await;
+        }
+        added
+    }
+    
+    async fn try_add_network(&mut self) -> Result<CniResult> {
+        // Issues that degrade but don't fail the attachment, reported in the result
+        let mut warnings = Vec::new();
+        
+        // A node that verifies tokens only takes the tenant from a valid one
+        if let Some(key) = &self.socni.token_public_key {
+            let token = self.args.args.get(token::TOKEN_ARG)
+                .ok_or_else(|| anyhow::anyhow!("CNI_ARGS has no {}; this node requires a signed tenant token",
+                                               token::TOKEN_ARG))?;
+            let pod = TokenSubject::from_cni_args(&self.args.args)?;
+            let tenant = TokenVerifier::load(key)?
+                .tenant(token, now_secs(), &pod)
+                .context("Rejected the pod's tenant token")?;
+            info!("Tenant {} attested by {}", tenant, token::TOKEN_ARG);
+            self.attested_tenant = Some(tenant);
+        }
+        
         // Initialize Aranya security
+        let started = Instant::now();
         if self.init_aranya().await.is_err() {
-            warn!("Failed to initialize Aranya security. Continuing with reduced security.");
+            self.warn_recurring("aranya-init", "Failed to initialize Aranya security. Continuing with reduced security.");
+            warnings.push("Aranya security is not initialized; VLAN access was not enforced".to_string());
         }
 
         // Check VLAN access using Aranya policy engine
@@ -142,69 +311,136 @@ impl VlanPlugin {
                 anyhow::bail!("Access denied by Aranya policy engine: No permission to use VLAN {}", self.config.vlan);
             }
         }
+        self.record_phase("aranya", started);
+        self.check_interrupted()?;
         
-        // Get master interface
-        self.verify_master_interface()?;
+        // A pod scheduled to a node whose trunk doesn't carry the VLAN can't work here
+        if !self.config.is_native() && !self.socni.vlan_allowed(self.config.vlan) {
+            let carried: Vec<String> = self.socni.allowed_vlan_ranges.iter().flatten()
+                .map(|r| r.to_string())
+                .collect();
+            anyhow::bail!("VLAN {} is not carried by this node (SOCNI_ALLOWED_VLANS: {}); \
+                           the pod was scheduled to a node that can't serve it",
+                          self.config.vlan, if carried.is_empty() { "none".to_string() } else { carried.join(",") });
+        }
         
-        // Create VLAN interface
-        let vlan_name = format!("{}.{}", self.config.master, self.config.vlan);
-        info!("Creating VLAN interface: {}", vlan_name);
+        // The runtime-provided name is used as-is for the container interface
+        validate_ifname(&self.args.ifname)?;
         
-        // Create the VLAN interface on the host
-        let create_cmd = Command::new("ip")
-            .args(&["link", "add", "link", &self.config.master, "name", &vlan_name,
-                  "type", "vlan", "id", &self.config.vlan.to_string()])
-            .output()
-            .context("Failed to execute ip link add command")?;
+        // Key the MACsec channel before creating anything that would need undoing
+        let macsec_secret = match &self.config.macsec {
+            Some(_) => {
+                if self.config.host_link_name() == self.args.ifname {
+                    anyhow::bail!("MACsec needs the VLAN link {} under the pod interface; choose another ifname",
+                                  self.args.ifname);
+                }
+                let vlan = self.config.vlan;
+                let aranya = self.aranya.as_mut()
+                    .ok_or_else(|| anyhow::anyhow!("MACsec on VLAN {} needs its key from Aranya, which is not available", vlan))?;
+                let path = self.socni.macsec_secret_file.as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("MACsec on VLAN {} needs the team secret; set SOCNI_MACSEC_SECRET_FILE", vlan))?;
+                let secret = load_macsec_secret(path)?;
+                Some(aranya.macsec_secret(vlan, &secret)
+                    .context("Failed to get the MACsec keys")?)
+            }
+            None => None,
+        };
+        
+        // Look up the master and create the link from the master's namespace.
+        // The guard puts this thread back in our own namespace on every return
+        let started = Instant::now();
+        let master_guard = self.config.master_netns.as_deref()
+            .filter(|ns| self.runner.enter_netns(ns))
+            .map(|ns| netns::NetnsGuard::enter(ns)
+                .map(|guard| guard.restore_attempts(self.socni.netns_restore_attempts))
+                .with_context(|| format!("Failed to enter master namespace {}", ns)))
+            .transpose()?;
         
-        if !create_cmd.status.success() && !String::from_utf8_lossy(&create_cmd.stderr).contains("File exists") {
-            anyhow::bail!("Failed to create VLAN interface: {}", 
-                         String::from_utf8_lossy(&create_cmd.stderr));
+        // Get master interface
+        let master = self.verify_master_interface()?;
+        
+        // A requested MAC is checked before there is a link to undo
+        let mac = self.config.resolve_mac(&self.args.args);
+        if let Some(mac) = &mac {
+            validate_interface_mac(mac)
+                .with_context(|| format!("Rejected the MAC address requested for {}", self.args.ifname))?;
+            // Two interfaces with one MAC on a VLAN break each other's traffic
+            if let Some(holder) = self.mac_holder(mac)? {
+                anyhow::bail!("MAC address {} is already used by {} on network {}", mac, holder, self.config.name);
+            }
         }
         
-        // Set link up
-        let up_cmd = Command::new("ip")
-            .args(&["link", "set", "dev", &vlan_name, "up"])
-            .output()
-            .context("Failed to execute ip link set up command")?;
+        if self.config.raise_master_mtu {
+            self.raise_master_mtu(&master)?;
+        }
+        let mtu = self.config.effective_mtu(master.mtu);
         
-        if !up_cmd.status.success() {
-            anyhow::bail!("Failed to set VLAN interface up: {}", 
-                         String::from_utf8_lossy(&up_cmd.stderr));
+        // QinQ stacks the pod's VLAN on an outer link that stays on the host.
+        // The pod counts as a user before the link is made, under the link's
+        // lock, so a concurrent DEL of the last other pod can't delete it
+        if let Some(outer) = self.config.outer_link_name() {
+            let store = StateStore::new(&self.socni.state_dir);
+            let key = self.host_link_key(&outer);
+            let _lock = store.lock_outer_link(&key)?;
+            store.add_outer_link_user(&key, &self.config.name, &self.args.container_id, &self.args.ifname)?;
+            self.partial.outer = true;
+            self.ensure_outer_link(&outer)?;
         }
         
-        // Set MTU if configured
-        if let Some(mtu) = self.config.mtu {
-            let mtu_cmd = Command::new("ip")
-                .args(&["link", "set", "dev", &vlan_name, "mtu", &mtu.to_string()])
-                .output()
-                .context("Failed to execute ip link set mtu command")?;
+        // Create VLAN interface
+        let vlan_name = self.config.host_link_name();
+        info!("Creating VLAN interface: {}", vlan_name);
+        
+        // Create the link straight in the pod's namespace so it never exists,
+        // let alone comes up, on the host. numaHint has to write the link's
+        // queue attributes through the host's sysfs, so it keeps the two-step path
+        let created = !self.config.numa_hint && self.create_in_netns(&vlan_name, mtu)?;
+        if !created {
+            self.create_on_host(&vlan_name, mtu, &mut warnings)?;
+            self.record_phase("link-create", started);
             
-            if !mtu_cmd.status.success() {
-                warn!("Failed to set MTU on VLAN interface: {}", 
-                     String::from_utf8_lossy(&mtu_cmd.stderr));
+            // Until it's moved the link is on the host, in the master's namespace
+            let started = Instant::now();
+            if let Err(e) = self.check_interrupted().and_then(|_| self.move_to_netns(&vlan_name)) {
+                match self.backend.delete_link(&vlan_name) {
+                    Ok(()) => self.release_host_link(&vlan_name),
+                    Err(e) => warn!("Failed to remove VLAN interface {} from the host: {:#}", vlan_name, e),
+                }
+                return Err(e);
             }
+            // Gone from the host, the name is free for the next pod
+            self.release_host_link(&vlan_name);
+            self.record_phase("netns-move", started);
+        } else {
+            self.record_phase("link-create", started);
         }
+        self.partial.link = Some(vlan_name.clone());
         
-        // Move interface to container namespace
-        let move_cmd = Command::new("ip")
-            .args(&["link", "set", "dev", &vlan_name, "netns", &self.args.netns])
-            .output()
-            .context("Failed to execute ip link set netns command")?;
-        
-        if !move_cmd.status.success() {
-            anyhow::bail!("Failed to move VLAN interface to container namespace: {}", 
-                         String::from_utf8_lossy(&move_cmd.stderr));
+        if let Some(guard) = master_guard {
+            guard.restore();
         }
         
         // Configure IP addressing inside the container
         let mut result = CniResult::new(&self.config.cni_version);
+        for warning in warnings {
+            result.add_warning(warning);
+        }
+        // Ties the VLAN to its uplink for post-mortems
+        result.set_socni("master", self.config.master.clone());
+        
+        // Interfaces only carry an MTU from cniVersion 1.1.0, so the effective
+        // MTU is reported under the extension for every version
+        let link_mtu = mtu.map(|m| if self.config.macsec.is_some() { m - MACSEC_OVERHEAD } else { m });
+        if let Some(link_mtu) = link_mtu {
+            result.set_socni("mtu", link_mtu);
+        }
         
         // Add interface to result
         let interface = Interface {
             name: self.args.ifname.clone(),
             mac: None,
             sandbox: Some(self.args.netns.clone()),
+            mtu: link_mtu,
         };
         result.add_interface(interface);
         
@@ -212,95 +448,288 @@ impl VlanPlugin {
         let ifname = self.args.ifname.clone();
         let vlan_name_clone = vlan_name.clone();
         let config = self.config.clone();
-        let vlan_id = self.config.vlan;
+        
+        // Allocate the address before touching the pod's namespace
+        self.check_interrupted()?;
+        let started = Instant::now();
+        let assigned = self.ipam_address(true)?;
+        self.partial.leased = true;
+        let secondary = self.secondary_address(true)
+            .context("Failed to allocate the secondary address")?;
+        let dual_stack = self.dual_stack_address(true)
+            .context("Failed to allocate the dual-stack address")?;
+        self.record_phase("ipam", started);
+        self.check_interrupted()?;
+        
+        // Reapply the MAC this pod had before a restart, unless one was requested
+        let store = StateStore::new(&self.socni.state_dir);
+        let identity = pod_identity(&self.args.args);
+        let mac = match (mac, &identity) {
+            (Some(mac), _) => Some(mac),
+            (None, Some(identity)) => store
+                .reserved_mac(&self.config.name, identity, &self.args.ifname)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read MAC reservation for {}: {}", identity, e);
+                    None
+                }),
+            (None, None) => None,
+        };
         
         // Create a mutable reference to result that can be moved into the closure
         let result_ref = &mut result;
+        let mut link_mac = None;
+        let link_mac_ref = &mut link_mac;
+        let mut baseline = None;
+        let baseline_ref = &mut baseline;
+        let mut firewall_state = None;
+        let firewall_ref = &mut firewall_state;
+        let mut renamed = false;
+        let renamed_ref = &mut renamed;
         
         // Execute inside container network namespace
-        self.in_netns(&self.args.netns, || async move {
-            // Rename interface to the requested name if different
-            if vlan_name_clone != ifname {
-                let rename_cmd = Command::new("ip")
-                    .args(&["link", "set", "dev", &vlan_name_clone, "name", &ifname])
-                    .output()
-                    .context("Failed to execute ip link set name command")?;
-                
-                if !rename_cmd.status.success() {
-                    anyhow::bail!("Failed to rename interface in container: {}", 
-                                 String::from_utf8_lossy(&rename_cmd.stderr));
+        let runner = self.runner.clone();
+        let backend = self.backend.clone();
+        let configured = self.in_netns(&self.args.netns, || async move {
+            // Bring up loopback so a separate loopback plugin isn't needed
+            if config.setup_loopback {
+                if let Err(e) = backend.set_link_up("lo") {
+                    anyhow::bail!("Failed to bring up loopback in container: {:#}", e);
+                }
+            }
+            
+            // With MACsec the VLAN link keeps its name and carries the device
+            // the pod uses. Otherwise rename it to the requested name; skipped
+            // when the host link name already matches so we don't issue a no-op rename
+            if let (Some(macsec), Some(secret)) = (&config.macsec, &macsec_secret) {
+                add_macsec(backend.as_ref(), &vlan_name_clone, &ifname, mac.as_deref(), macsec, secret)?;
+            } else if vlan_name_clone != ifname {
+                if let Err(e) = backend.rename_link(&vlan_name_clone, &ifname) {
+                    anyhow::bail!("Failed to rename interface in container: {:#}", e);
                 }
+                *renamed_ref = true;
+            }
+            
+            // Apply the requested MAC before the link comes up
+            if let Some(mac) = &mac {
+                if let Err(e) = backend.set_link_address(&ifname, mac) {
+                    anyhow::bail!("Failed to set MAC address {} on interface: {:#}", mac, e);
+                }
+            }
+            
+            // Disable IPv6 before the link comes up so no link-local address is generated
+            if config.disable_ipv6 {
+                sysctl::set_iface(runner.as_ref(), "ipv6", &ifname, "disable_ipv6", "1")?;
+            }
+            
+            // Likewise ignore RAs and privacy addresses before any can be generated
+            for (key, value) in config.static_ipv6_sysctls() {
+                sysctl::set_iface(runner.as_ref(), "ipv6", &ifname, key, value)?;
             }
             
             // Set interface up
-            let up_cmd = Command::new("ip")
-                .args(&["link", "set", "dev", &ifname, "up"])
-                .output()
-                .context("Failed to execute ip link set up command in container")?;
-            
-            if !up_cmd.status.success() {
-                anyhow::bail!("Failed to set interface up in container: {}", 
-                             String::from_utf8_lossy(&up_cmd.stderr));
-            }
-            
-            // Configure IPAM if provided
-            if let Some(ipam) = &config.ipam {
-                // Use a simple allocation based on VLAN ID
-                // In a real implementation, this would use Aranya's IPAM service
-                let _subnet = ipam.subnet.as_deref().unwrap_or("192.168.0.0/24");
-                let ip = format!("192.168.{}.2/24", vlan_id % 256);
-                let gateway = format!("192.168.{}.1", vlan_id % 256);
+            if let Err(e) = backend.set_link_up(&ifname) {
+                anyhow::bail!("Failed to set interface up in container: {:#}", e);
+            }
+            
+            // One read gives the MAC to reserve and the counters traffic is measured from
+            if let Ok(link) = backend.link_stats(&ifname) {
+                *link_mac_ref = link.get("address").and_then(|a| a.as_str()).map(String::from);
+                *baseline_ref = LinkStats::from_link(&link);
+            }
+            
+            // Apply traffic shaping, preferring runtime-injected limits
+            if let Some(bandwidth) = config.effective_bandwidth() {
+                shaping::apply_bandwidth(runner.as_ref(), backend.as_ref(), &ifname, bandwidth)
+                    .context("Failed to apply bandwidth limits")?;
+            }
+            
+            // Pin neighbors that don't answer ARP/ND; replace keeps re-ADD idempotent
+            for neighbor in config.neighbors.iter().flatten() {
+                if let Err(e) = backend.set_neighbor(&ifname, &neighbor.ip, &neighbor.mac) {
+                    anyhow::bail!("Failed to add neighbor {}: {:#}", neighbor.ip, e);
+                }
+            }
+            
+            if let Some(firewall) = &config.firewall {
+                *firewall_ref = Some(firewall::apply(runner.as_ref(), &ifname, firewall)
+                    .context("Failed to apply firewall rules")?);
+            }
+            
+            // Configure IPAM if provided; type none leaves only the kernel's link-local address
+            if let Some(ipam) = config.ipam.as_ref().filter(|_| !config.link_local_only()) {
+                let Allocation { address: ip, gateway, routes: allocated_routes } = assigned.clone()
+                    .ok_or_else(|| anyhow::anyhow!("{} IPAM assigned no address to {}", ipam.ipam_type, ifname))?;
                 
                 info!("Configuring IP: {}, Gateway: {}", ip, gateway);
                 
-                // Add IP to interface
-                let addr_cmd = Command::new("ip")
-                    .args(&["addr", "add", &ip, "dev", &ifname])
-                    .output()
-                    .context("Failed to execute ip addr add command")?;
+                // Drop stale addresses left on a reused link so they don't accumulate
+                if config.flush_addresses {
+                    if let Err(e) = backend.flush_addresses(&ifname) {
+                        anyhow::bail!("Failed to flush addresses on interface: {:#}", e);
+                    }
+                }
                 
-                if !addr_cmd.status.success() {
-                    anyhow::bail!("Failed to add IP address to interface: {}", 
-                                 String::from_utf8_lossy(&addr_cmd.stderr));
+                // Add IP to interface
+                if let Err(e) = backend.add_address(&ifname, &ip, ipam.address_lifetimes(&ip)) {
+                    anyhow::bail!("Failed to add IP address to interface: {:#}", e);
                 }
                 
                 // Add default route if IPAM provided gateway
-                let route_cmd = Command::new("ip")
-                    .args(&["route", "add", "default", "via", &gateway])
-                    .output()
-                    .context("Failed to execute ip route add command")?;
-                
-                if !route_cmd.status.success() {
-                    warn!("Failed to add default route: {}", 
-                         String::from_utf8_lossy(&route_cmd.stderr));
-                }
+                let onlink = ipam.gateway_onlink || gateway_needs_onlink(&ip, &gateway);
+                add_default_route(backend.as_ref(), result_ref, &ifname, &ip, &gateway, onlink)?;
                 
                 // Add IP details to result
                 result_ref.add_ip(IPConfig {
-                    interface: None,
+                    interface: Some(0),
                     address: ip.to_string(),
                     gateway: Some(gateway.to_string()),
                 });
                 
-                // Add routing details to result
-                result_ref.add_route(CniRoute {
-                    dst: "0.0.0.0/0".to_string(),
-                    gw: Some(gateway.to_string()),
-                });
+                // A dual-stack pod gets the other family's address and default route too
+                if let Some(other) = &dual_stack {
+                    if let Err(e) = backend.add_address(&ifname, &other.address, ipam.address_lifetimes(&other.address)) {
+                        anyhow::bail!("Failed to add dual-stack IP address to interface: {:#}", e);
+                    }
+                    
+                    let onlink = ipam.gateway_onlink || gateway_needs_onlink(&other.address, &other.gateway);
+                    add_default_route(backend.as_ref(), result_ref, &ifname, &other.address, &other.gateway, onlink)?;
+                    result_ref.add_ip(IPConfig {
+                        interface: Some(0),
+                        address: other.address.clone(),
+                        gateway: Some(other.gateway.clone()),
+                    });
+                }
+                
+                // Install routes handed out by the IPAM backend and configured ones,
+                // via the gateway of the destination's family
+                for route in allocated_routes.iter().chain(ipam.routes.iter().flatten()) {
+                    let via = match &dual_stack {
+                        Some(other) if is_ipv6(&route.dst) == is_ipv6(&other.address) => &other.gateway,
+                        _ => &gateway,
+                    };
+                    add_route(backend.as_ref(), result_ref, &ifname, route, via)?;
+                }
+                
+                // Traffic from the pod's address leaves through this interface's
+                // gateway even when another interface holds the main default route
+                if let Some(table) = config.route_table.filter(|_| config.source_routing) {
+                    add_source_routing(backend.as_ref(), &ifname, &ip, &gateway, onlink, table)?;
+                }
+                
+                // A silent gateway only costs the MAC and a warning, unless it is required
+                let verify = config.verify_gateway || config.require_gateway;
+                if config.resolve_gateway_mac || verify {
+                    let mac = resolve_neighbor(backend.as_ref(), &ifname, &gateway, GATEWAY_RESOLVE_TIMEOUT).await;
+                    if mac.is_none() && config.require_gateway {
+                        anyhow::bail!("Gateway {} did not answer within {:?}", gateway, GATEWAY_RESOLVE_TIMEOUT);
+                    }
+                    
+                    let mut resolved = serde_json::json!({"ip": gateway});
+                    if verify {
+                        resolved["reachable"] = mac.is_some().into();
+                    }
+                    match mac {
+                        Some(mac) if config.resolve_gateway_mac => resolved["mac"] = mac.into(),
+                        Some(_) => {}
+                        None => {
+                            let warning = if verify {
+                                format!("Gateway {} did not answer within {:?}; the pod may not reach it",
+                                        gateway, GATEWAY_RESOLVE_TIMEOUT)
+                            } else {
+                                format!("Gateway {} did not resolve within {:?}; omitting its MAC",
+                                        gateway, GATEWAY_RESOLVE_TIMEOUT)
+                            };
+                            warn!("{}", warning);
+                            result_ref.add_warning(warning);
+                        }
+                    }
+                    result_ref.set_socni("gateway", resolved);
+                }
                 
-                // Add additional routes if configured
-                if let Some(routes) = &ipam.routes {
-                    for route in routes {
-                        result_ref.add_route(CniRoute {
-                            dst: route.dst.clone(),
-                            gw: route.gw.clone(),
-                        });
+                // The secondary address only carries its own routes, never the default
+                if let Some(secondary) = &secondary {
+                    if let Err(e) = backend.add_address(&ifname, &secondary.address, ipam.address_lifetimes(&secondary.address)) {
+                        anyhow::bail!("Failed to add secondary IP address to interface: {:#}", e);
                     }
+                    
+                    result_ref.add_ip(IPConfig {
+                        interface: Some(0),
+                        address: secondary.address.clone(),
+                        gateway: Some(secondary.gateway.clone()),
+                    });
+                    for route in &secondary.routes {
+                        add_route(backend.as_ref(), result_ref, &ifname, route, &secondary.gateway)?;
+                    }
+                }
+            }
+            
+            // Mark egress once the pod's addresses are known
+            if let Some(dscp) = config.dscp {
+                firewall::apply_dscp(runner.as_ref(), &ifname, &pod_addresses(result_ref), dscp)?;
+            }
+            
+            // Routes need the link up to be installed, so it only goes down
+            // once everything is configured. The kernel drops routes through a
+            // down link; IPv6 addresses are kept for the agent bringing it up
+            if !config.admin_up {
+                if !config.disable_ipv6 {
+                    sysctl::set_iface(runner.as_ref(), "ipv6", &ifname, "keep_addr_on_down", "1")?;
+                }
+                if let Err(e) = backend.set_link_down(&ifname) {
+                    anyhow::bail!("Failed to set interface down in container: {:#}", e);
+                }
+                
+                // The result only lists routes that exist; the agent finds the
+                // ones it has to install under socni.deferredRoutes
+                if let Some(routes) = result_ref.routes.take() {
+                    result_ref.set_socni("deferredRoutes", serde_json::to_value(routes)?);
                 }
             }
             
             Ok(())
-        }).await?;
+        }).await;
+        
+        // MACsec keeps the VLAN link's name; removing it takes the device along
+        if renamed {
+            self.partial.link = Some(self.args.ifname.clone());
+        }
+        configured?;
+        
+        // Chained plugins and CHECK identify the pod interface by its hardware address
+        if let Some(interface) = result.interfaces.as_mut().and_then(|interfaces| interfaces.first_mut()) {
+            interface.mac = link_mac.clone();
+        }
+        
+        if let Some(companion) = self.config.bridge_companion.clone() {
+            self.add_bridge_companion(&companion, &mut result).await
+                .context("Failed to attach bridge companion")?;
+        }
+        
+        // From here the attachment is recorded and left for DEL to clean up
+        self.check_interrupted()?;
+        self.partial = Partial::default();
+        
+        // Remember the rule handles so DEL removes exactly what we installed
+        if let Some(state) = &firewall_state {
+            if let Err(e) = store.save_firewall(&self.config.name, &self.args.container_id, &self.args.ifname, state) {
+                warn!("Failed to persist firewall state: {}", e);
+            }
+        }
+        
+        // Traffic reported by `socni-ctl status --delta` counts from here
+        if let Some(stats) = baseline {
+            let baseline = StatsBaseline { stats, taken: now_secs() };
+            if let Err(e) = store.save_baseline(&self.config.name, &self.args.container_id, &self.args.ifname, &baseline) {
+                warn!("Failed to persist interface statistics baseline: {}", e);
+            }
+        }
+        
+        // Persist the MAC so the same pod gets it back after a restart
+        if let (Some(identity), Some(mac)) = (&identity, &link_mac) {
+            if let Err(e) = store.reserve_mac(&self.config.name, identity, &self.args.ifname, mac) {
+                warn!("Failed to persist MAC reservation for {}: {}", identity, e);
+            }
+        }
         
         // Register VLAN with Aranya
         if let Some(aranya) = &mut self.aranya {
@@ -309,20 +738,173 @@ impl VlanPlugin {
             }
         }
         
+        // Report DNS with the pod's primary address family first
+        if let Some(dns) = &self.config.dns {
+            let prefer_v6 = result.ips.as_ref()
+                .and_then(|ips| ips.first())
+                .map(|ip| ip.address.contains(':'))
+                .unwrap_or(false);
+            
+            result.set_dns(DNS {
+                nameservers: Some(dns.ordered_nameservers(prefer_v6)),
+                search: Some(dns.search.clone()).filter(|s| !s.is_empty()),
+                options: Some(dns.options.clone()).filter(|o| !o.is_empty()),
+            });
+            
+            if let Some(path) = &dns.write_resolv_conf {
+                if let Err(e) = std::fs::write(path, dns.resolv_conf(prefer_v6)) {
+                    let warning = format!("Failed to write resolv.conf to {}: {}", path, e);
+                    warn!("{}", warning);
+                    result.add_warning(warning);
+                }
+            }
+        }
+        
+        // Record the result so CHECK can compare against it
+        if let Err(e) = store.save_result(&self.config.name, &self.args.container_id, &self.args.ifname, &result) {
+            warn!("Failed to persist CNI result: {}", e);
+        }
+        
+        // Record the live interface so a revoked tenant's pods can be found
+        let attachment = Attachment {
+            config: self.config.clone(),
+            container_id: self.args.container_id.clone(),
+            netns: self.args.netns.clone(),
+            ifname: self.args.ifname.clone(),
+            tenant: self.tenant_id(),
+            added: now_secs(),
+        };
+        if let Err(e) = store.record_attachment(attachment) {
+            warn!("Failed to record attachment: {}", e);
+        }
+        
+        // Publish the address for service discovery; an outage only warns
+        if let Some(registry) = &self.config.registry {
+            let address = result.ips.as_ref()
+                .and_then(|ips| ips.first())
+                .and_then(|ip| ip.address.parse::<IpNetwork>().ok());
+            if let Some(address) = address {
+                let registered = self.registry_key(registry)
+                    .and_then(|key| Registry::new(registry, self.runner.as_ref()).put(&key, &address.ip().to_string()));
+                if let Err(e) = registered {
+                    let warning = format!("Failed to register {} in the {:?} registry: {:#}", address.ip(), registry.registry_type, e);
+                    warn!("{}", warning);
+                    result.add_warning(warning);
+                }
+            }
+        }
+        
+        // Run the site-specific post hook
+        if let Some(hook) = &self.config.post_hook {
+            let ip = result.ips.as_ref()
+                .and_then(|ips| ips.first())
+                .map(|ip| ip.address.clone())
+                .unwrap_or_default();
+            
+            if let Err(e) = self.run_hook(hook, "ADD", &ip) {
+                if self.config.fail_on_hook_error {
+                    return Err(e.context("Post hook failed"));
+                }
+                warn!("Post hook failed: {}", e);
+                result.add_warning(format!("Post hook failed: {}", e));
+            }
+        }
+        
         Ok(result)
     }
     
+    /// Key the pod's address is registered under in `registry`
+    fn registry_key(&self, registry: &RegistryConfig) -> Result<String> {
+        let mut vars = HashMap::new();
+        if let Some(namespace) = self.args.args.get("K8S_POD_NAMESPACE") {
+            vars.insert("namespace", namespace.clone());
+        }
+        if let Some(name) = self.args.args.get("K8S_POD_NAME") {
+            vars.insert("name", name.clone());
+        }
+        vars.insert("network", self.config.name.clone());
+        vars.insert("vlan", self.config.vlan.to_string());
+        vars.insert("containerId", self.args.container_id.clone());
+        registry::render_key(&registry.key, &vars)
+    }
+    
+    /// Run a hook executable with the pod details in its environment
+    fn run_hook(&self, hook: &str, command: &str, ip: &str) -> Result<()> {
+        info!("Running hook {} for container {}", hook, self.args.container_id);
+        
+        let output = Command::new(hook)
+            .env("SOCNI_COMMAND", command)
+            .env("SOCNI_CONTAINER_ID", &self.args.container_id)
+            .env("SOCNI_NETNS", &self.args.netns)
+            .env("SOCNI_IFNAME", &self.args.ifname)
+            .env("SOCNI_VLAN", self.config.vlan.to_string())
+            .env("SOCNI_IP", ip)
+            .output()
+            .with_context(|| format!("Failed to execute hook {}", hook))?;
+        
+        if !output.status.success() {
+            anyhow::bail!("Hook {} exited with {}: {}", 
+                         hook, output.status, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        
+        Ok(())
+    }
+    
+    /// The pod interface as read inside its namespace, with its counters
+    pub async fn link(&self) -> Result<serde_json::Value> {
+        let backend = self.backend.clone();
+        let ifname = self.args.ifname.clone();
+        self.in_netns(&self.args.netns, || async move {
+            backend.link_stats(&ifname)
+        }).await
+    }
+    
+    /// Current counters of the pod interface
+    pub async fn link_stats(&self) -> Result<LinkStats> {
+        let link = self.link().await?;
+        LinkStats::from_link(&link)
+            .ok_or_else(|| anyhow::anyhow!("Link {} reported no statistics", self.args.ifname))
+    }
+    
     /// Delete a VLAN network
     pub async fn del_network(&mut self) -> Result<()> {
         // Initialize Aranya security
         if self.init_aranya().await.is_err() {
-            warn!("Failed to initialize Aranya security. Continuing with cleanup.");
+            self.warn_recurring("aranya-init", "Failed to initialize Aranya security. Continuing with cleanup.");
         }
 
-        // Clean up IPAM allocations if specified
-        if let Some(ipam) = &self.config.ipam {
-            if let Some(aranya) = &mut self.aranya {
-                // No need to deallocate IP since it's not implemented
+        // Read what ADD recorded before any state is removed, for the audit record
+        let store = StateStore::new(&self.socni.state_dir);
+        let cached = store.load_result(&self.config.name, &self.args.container_id, &self.args.ifname)
+            .unwrap_or_else(|e| {
+                warn!("Failed to load cached result: {}", e);
+                None
+            });
+        
+        // Clean up IPAM allocations if specified; an unreachable IPAM daemon
+        // or service fails DEL before anything else goes, so the retry finds
+        // the same state
+        let released = self.release_address()?;
+        
+        let address = released.map(|ip| ip.to_string())
+            .or_else(|| cached.as_ref()
+                .and_then(|r| r.ips.as_ref())
+                .and_then(|ips| ips.first())
+                .map(|ip| ip.address.clone()));
+        
+        // Withdraw the address from service discovery, but only while the key
+        // still holds it; an outage only warns
+        if let Some(registry) = &self.config.registry {
+            let registered = address.as_deref()
+                .and_then(|address| address.split('/').next())
+                .map(str::to_string);
+            let withdrawn = match registered {
+                Some(ip) => self.registry_key(registry)
+                    .and_then(|key| Registry::new(registry, self.runner.as_ref()).delete(&key, &ip)),
+                None => Err(anyhow::anyhow!("the registered address is unknown")),
+            };
+            if let Err(e) = withdrawn {
+                warn!("Failed to remove the pod from the {:?} registry: {:#}", registry.registry_type, e);
             }
         }
         
@@ -332,26 +914,124 @@ impl VlanPlugin {
         
         // The VLAN link should already be removed when the container's netns is deleted
         // But we can try to clean it up if the namespace still exists
+        let shaped = self.config.effective_bandwidth().is_some();
+        let neighbors = self.config.neighbors.clone().unwrap_or_default();
+        let companion = self.config.bridge_companion.as_ref().map(|c| c.ifname.clone());
+        let marked = self.config.dscp.is_some();
+        let source_rule = self.config.route_table
+            .filter(|_| self.config.source_routing)
+            .zip(address.as_deref().and_then(|a| a.split('/').next()).map(String::from));
+        let macsec_lower = self.config.macsec.as_ref().map(|_| self.config.host_link_name());
+        let mut link_deleted = None;
+        let link_deleted_ref = &mut link_deleted;
+        let firewall_state = if self.config.firewall.is_some() {
+            Some(store.load_firewall(&self.config.name, &self.args.container_id, &self.args.ifname)
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read firewall state: {}", e);
+                    None
+                }))
+        } else {
+            None
+        };
+        let runner = self.runner.clone();
+        let backend = self.backend.clone();
         if let Ok(()) = self.in_netns(&netns, || async move {
-            let del_cmd = Command::new("ip")
-                .args(&["link", "delete", &ifname])
-                .output()
-                .context("Failed to execute ip link delete command")?;
+            if let Some(state) = &firewall_state {
+                if let Err(e) = firewall::remove(runner.as_ref(), &ifname, state.as_ref()) {
+                    warn!("Failed to remove firewall rules: {}", e);
+                }
+            }
+            
+            if marked {
+                if let Err(e) = firewall::remove_dscp(runner.as_ref(), &ifname) {
+                    warn!("Failed to remove DSCP marking: {}", e);
+                }
+            }
+            
+            // Rules outlive the link, so they go explicitly
+            if let Some((table, source)) = &source_rule {
+                if let Err(e) = backend.delete_source_rule(source, *table) {
+                    warn!("Failed to remove source routing rule for {}: {:#}", source, e);
+                }
+            }
+            
+            if shaped {
+                if let Err(e) = shaping::remove_bandwidth(backend.as_ref(), &ifname) {
+                    warn!("Failed to remove bandwidth shaping: {}", e);
+                }
+            }
+            
+            for neighbor in &neighbors {
+                if let Err(e) = backend.delete_neighbor(&ifname, &neighbor.ip) {
+                    warn!("Failed to remove neighbor {}: {:#}", neighbor.ip, e);
+                }
+            }
+            
+            // Deleting either end of the companion veth removes the pair
+            if let Some(companion) = &companion {
+                if let Err(e) = backend.delete_link(companion) {
+                    warn!("Failed to delete bridge companion {}: {:#}", companion, e);
+                }
+            }
+            
+            let deleted = backend.delete_link(&ifname);
+            *link_deleted_ref = Some(deleted.is_ok());
+            if let Err(e) = deleted {
+                warn!("Failed to delete interface in container: {:#}", e);
+            }
             
-            if !del_cmd.status.success() {
-                warn!("Failed to delete interface in container: {}", 
-                     String::from_utf8_lossy(&del_cmd.stderr));
+            // The MACsec device's VLAN link stays behind when the device goes
+            if let Some(lower) = &macsec_lower {
+                if let Err(e) = backend.delete_link(lower) {
+                    warn!("Failed to delete MACsec VLAN link {}: {:#}", lower, e);
+                }
             }
             
             Ok(())
         }).await {
             info!("Cleaned up VLAN interface in container namespace");
         }
+        
+        // One line per DEL so every release can be traced in the audit trail
+        let link = match link_deleted {
+            Some(true) => "removed",
+            Some(false) => "persisted",
+            None => "gone with namespace",
+        };
+        info!("DEL container={} network={} vlan={} ifname={} address={} link={}",
+              self.args.container_id, self.config.name, self.config.vlan, self.args.ifname,
+              address.as_deref().unwrap_or("none"), link);
 
-        // Deregister VLAN from Aranya
+        if let Err(e) = store.remove_result(&self.config.name, &self.args.container_id, &self.args.ifname) {
+            warn!("Failed to remove cached result: {}", e);
+        }
+        if let Err(e) = store.remove_firewall(&self.config.name, &self.args.container_id, &self.args.ifname) {
+            warn!("Failed to remove firewall state: {}", e);
+        }
+        if let Err(e) = store.remove_baseline(&self.config.name, &self.args.container_id, &self.args.ifname) {
+            warn!("Failed to remove statistics baseline: {}", e);
+        }
+        if let Err(e) = store.remove_attachment(&self.config.name, &self.args.container_id, &self.args.ifname) {
+            warn!("Failed to remove attachment: {}", e);
+        }
+        self.remove_leaked_host_link(&store);
+        self.remove_unused_outer_link(&store);
+        
+        // Drop this node's registration once no other pod here uses the VLAN.
+        // The team's label stays: pods on other nodes may still use it
         if let Some(aranya) = &mut self.aranya {
-            if let Err(e) = aranya.delete_vlan(self.config.vlan) {
-                warn!("Failed to deregister VLAN from Aranya: {}", e);
+            match store.vlan_references(self.config.vlan) {
+                Ok(references) if references.is_empty() => aranya.release_vlan(self.config.vlan),
+                Ok(references) => info!("Keeping VLAN {} registered for {} other interface(s)",
+                                        self.config.vlan, references.len()),
+                Err(e) => warn!("Failed to read attachments; keeping VLAN {} registered: {}", self.config.vlan, e),
+            }
+        }
+        
+        // DEL must stay best-effort, so hook failures are only logged
+        if let Some(hook) = &self.config.post_del_hook {
+            if let Err(e) = self.run_hook(hook, "DEL", "") {
+                warn!("Post DEL hook failed: {}", e);
             }
         }
         
@@ -360,67 +1040,1302 @@ impl VlanPlugin {
     
     /// Check a VLAN network
     pub async fn check_network(&mut self) -> Result<()> {
+        let items = self.check_report().await?;
+        verify::first_failure(&items)
+    }
+    
+    /// Run every CHECK verification, reporting each outcome
+    ///
+    /// Verification continues past a failed item where later items can still
+    /// be evaluated; only a missing interface ends the report early. Errors
+    /// are returned only when the checks themselves could not run.
+    pub async fn check_report(&mut self) -> Result<Vec<CheckItem>> {
         // Initialize Aranya security
         if self.init_aranya().await.is_err() {
-            warn!("Failed to initialize Aranya security. Continuing with reduced security.");
+            self.warn_recurring("aranya-init", "Failed to initialize Aranya security. Continuing with reduced security.");
         }
 
         // Check access permissions with Aranya
+        let mut items = Vec::new();
         if let Ok(has_access) = self.check_vlan_access() {
-            if !has_access {
-                anyhow::bail!("Access denied by Aranya policy engine: No permission to use VLAN {}", self.config.vlan);
-            }
+            items.push(match has_access {
+                true => CheckItem::pass("access"),
+                false => CheckItem::fail("access", format!(
+                    "Access denied by Aranya policy engine: No permission to use VLAN {}", self.config.vlan
+                )),
+            });
         }
         
         // Clone values needed by the closure to avoid borrow checker issues
         let ifname = self.args.ifname.clone();
-        let vlan_id = self.config.vlan;
         let netns = self.args.netns.clone();
         let config = self.config.clone();
+        let assigned = self.ipam_address(false)?;
+        let secondary = self.secondary_address(false)?;
+        let dual_stack = self.dual_stack_address(false)?;
+        
+        let store = StateStore::new(&self.socni.state_dir);
+        let cached = store.load_result(&self.config.name, &self.args.container_id, &self.args.ifname)
+            .unwrap_or_else(|e| {
+                warn!("Failed to load cached result: {}", e);
+                None
+            });
+        
+        // The recorded result knows the gateway whatever the IPAM type
+        let gateway = cached.as_ref()
+            .and_then(|r| r.ips.as_ref())
+            .and_then(|ips| ips.first())
+            .and_then(|ip| ip.gateway.clone())
+            .or_else(|| assigned.as_ref().map(|a| a.gateway.clone()));
         
         // Verify the interface exists in the container's namespace
+        let items_ref = &mut items;
+        let runner = self.runner.clone();
+        let backend = self.backend.clone();
         self.in_netns(&netns, || async move {
-            let ip_cmd = Command::new("ip")
-                .args(&["addr", "show", "dev", &ifname])
-                .output()
-                .context("Failed to execute ip addr show command")?;
+            let items = items_ref;
+            let link = backend.addresses(&ifname)
+                .context("Failed to read interface addresses")?;
+            let link = match &link {
+                Some(link) => link,
+                None => {
+                    items.push(CheckItem::fail("interface", format!(
+                        "Interface {} does not exist in container namespace", ifname
+                    )));
+                    return Ok(());
+                }
+            };
+            items.push(CheckItem::pass("interface"));
+            
+            // With MACsec the pod interface is the MACsec device and the VLAN
+            // is the link under it
+            let vlan_link = match &config.macsec {
+                Some(_) => {
+                    let kind = link.get("linkinfo").and_then(|l| l.get("info_kind")).and_then(|k| k.as_str());
+                    items.push(CheckItem::new("macsec", (kind != Some("macsec"))
+                        .then(|| format!("Interface {} is not a MACsec device", ifname))));
+                    
+                    let lower = config.host_link_name();
+                    backend.link(&lower).ok().flatten().unwrap_or_default()
+                }
+                None => link.clone(),
+            };
             
-            if !ip_cmd.status.success() {
-                anyhow::bail!("Interface {} does not exist in container namespace", 
-                             ifname);
+            // Verify it's a VLAN interface (or the macvlan used for the native VLAN)
+            let linkinfo = vlan_link.get("linkinfo");
+            let kind_issue = if config.is_native() {
+                let kind = linkinfo.and_then(|l| l.get("info_kind")).and_then(|k| k.as_str());
+                (kind != Some("macvlan"))
+                    .then(|| format!("Interface {} is not a native VLAN attachment", ifname))
+            } else {
+                let link_vlan = linkinfo
+                    .and_then(|l| l.get("info_data"))
+                    .and_then(|d| d.get("id"))
+                    .and_then(|id| id.as_u64());
+                (link_vlan != Some(config.link_vlan() as u64))
+                    .then(|| format!("Interface {} is not VLAN {}", ifname, config.link_vlan()))
+            };
+            items.push(CheckItem::new("vlan", kind_issue));
+            
+            // Without IPAM there is nothing to compare beyond the link being up,
+            // which an in-pod agent decides when adminUp is false
+            let up = link.get("flags")
+                .and_then(|f| f.as_array())
+                .map_or(false, |flags| flags.iter().any(|f| f.as_str() == Some("UP")));
+            if config.link_local_only() {
+                if config.admin_up {
+                    items.push(CheckItem::new("up", (!up).then(|| format!("Interface {} is not up", ifname))));
+                }
+                return Ok(());
             }
             
-            // Verify it's a VLAN interface
-            let output = String::from_utf8_lossy(&ip_cmd.stdout);
-            if !output.contains(&format!("vlan {}", vlan_id)) {
-                anyhow::bail!("Interface {} is not VLAN {}", ifname, vlan_id);
+            if config.disable_ipv6 {
+                let disabled = sysctl::get_iface(runner.as_ref(), "ipv6", &ifname, "disable_ipv6")? == "1";
+                items.push(CheckItem::new("ipv6", (!disabled)
+                    .then(|| format!("IPv6 is not disabled on interface {}", ifname))));
             }
             
-            // If IPAM was specified, verify IP configuration
-            if let Some(ipam) = &config.ipam {
-                // Verify there's at least one IP address
-                if !output.contains("inet ") {
-                    anyhow::bail!("Interface {} has no IP address", ifname);
+            if config.static_ipv6 {
+                let mut issues = Vec::new();
+                for (key, value) in config.static_ipv6_sysctls() {
+                    let actual = sysctl::get_iface(runner.as_ref(), "ipv6", &ifname, key)?;
+                    if actual != *value {
+                        issues.push(format!("{} is {}, expected {}", key, actual, value));
+                    }
                 }
+                items.push(CheckItem::new("ipv6", (!issues.is_empty())
+                    .then(|| format!("Interface {} accepts autoconfigured IPv6 addresses: {}", ifname, issues.join("; ")))));
             }
             
-            Ok(())
-        }).await?;
-        
-        Ok(())
+            if let Some(neighbors) = &config.neighbors {
+                let entries = backend.neighbors(&ifname, None).unwrap_or_default();
+                
+                let issues = verify::neighbor_issues(neighbors, &entries);
+                items.push(CheckItem::new("neighbors", (!issues.is_empty())
+                    .then(|| format!("Interface {} neighbors do not match: {}", ifname, issues.join("; ")))));
+            }
+            
+            // Compare against the result recorded by ADD when we have one
+            if let Some(expected) = &cached {
+                let mut routes = backend.routes(&ifname, IpFamily::V4).unwrap_or_default();
+                routes.extend(backend.routes(&ifname, IpFamily::V6).unwrap_or_default());
+                
+                // Routes only exist once the agent has brought a down link up
+                let mut expected = expected.clone();
+                if !config.admin_up && up {
+                    expected.routes = expected.socni("deferredRoutes")
+                        .and_then(|routes| serde_json::from_value(routes.clone()).ok());
+                }
+                let issues = verify::diff_result(&expected, &ifname, link, &routes);
+                items.push(CheckItem::new("result", (!issues.is_empty())
+                    .then(|| format!("Interface {} does not match the ADD result: {}", ifname, issues.join("; ")))));
+            } else if let Some(ipam) = config.ipam.as_ref().filter(|ipam| ipam.ipam_type != "http") {
+                // No recorded result: verify the exact address set we would assign.
+                // http leases live in the service, so only a recorded result can be checked
+                let Some(assigned) = assigned else {
+                    items.push(CheckItem::fail("addresses", format!(
+                        "Interface {} holds no {} IPAM address", ifname, ipam.ipam_type
+                    )));
+                    return Ok(());
+                };
+                let mut expected = vec![assigned.address];
+                expected.extend(dual_stack.map(|d| d.address));
+                expected.extend(secondary.map(|s| s.address));
+                let mut actual = verify::global_addresses(link);
+                expected.sort();
+                actual.sort();
+                
+                items.push(CheckItem::new("addresses", (actual != expected)
+                    .then(|| format!("Interface {} has addresses {:?}, expected exactly {:?}", ifname, actual, expected))));
+            }
+            
+            if let Some(dscp) = config.dscp {
+                let marks = firewall::installed_dscp(runner.as_ref(), &ifname)?;
+                let marked = !marks.is_empty() && marks.iter().all(|m| *m == dscp as u64);
+                items.push(CheckItem::new("dscp", (!marked)
+                    .then(|| format!("Egress from interface {} is not marked with DSCP {}", ifname, dscp))));
+            }
+            
+            if let Some(table) = config.route_table.filter(|_| config.source_routing) {
+                let mut rules = Vec::new();
+                for family in [IpFamily::V4, IpFamily::V6] {
+                    rules.extend(backend.rules(family).unwrap_or_default());
+                }
+                
+                // Only the primary address, which the kernel lists first, is source routed
+                let primary: Vec<String> = verify::global_addresses(link).into_iter().take(1).collect();
+                let issues = verify::source_rule_issues(&primary, table, &rules);
+                items.push(CheckItem::new("rules", (!issues.is_empty())
+                    .then(|| format!("Interface {} is not source routed: {}", ifname, issues.join("; ")))));
+            }
+            
+            // The companion's pod end must still be there
+            if let Some(companion) = &config.bridge_companion {
+                let exists = backend.link(&companion.ifname)
+                    .with_context(|| format!("Failed to look up bridge companion {}", companion.ifname))?
+                    .is_some();
+                items.push(CheckItem::new("companion", (!exists)
+                    .then(|| format!("Bridge companion {} does not exist in container namespace", companion.ifname))));
+            }
+            
+            // Catches an underlay that is down behind a healthy interface,
+            // e.g. a switch port; a link left down for an agent isn't probed
+            if config.check_connectivity && (config.admin_up || up) {
+                let unreachable: Vec<String> = gateway.iter()
+                    .chain(config.connectivity_target.iter())
+                    .filter(|target| !ping(runner.as_ref(), &ifname, target))
+                    .map(|target| format!("{} did not answer within {:?}", target, PING_TIMEOUT))
+                    .collect();
+                items.push(CheckItem::new("connectivity", (!unreachable.is_empty())
+                    .then(|| format!("Interface {} has no connectivity: {}", ifname, unreachable.join("; ")))));
+            }
+            
+            Ok(())
+        }).await?;
+        
+        Ok(items)
     }
     
-    /// Verify the master interface exists
-    fn verify_master_interface(&self) -> Result<()> {
-        let check_cmd = Command::new("ip")
-            .args(&["link", "show", "dev", &self.config.master])
-            .output()
-            .context("Failed to execute ip link show command")?;
+    /// Create the companion veth pair, attach the host end to the bridge and
+    /// report both ends, with the pod end's address pointing at its own index
+    ///
+    /// The pod end's address is leased per container, since every pod on the
+    /// network shares the bridge. Once created, the pair is left for
+    /// `roll_back` to remove if anything after it fails.
+    async fn add_bridge_companion(&mut self, companion: &BridgeCompanion, result: &mut CniResult) -> Result<()> {
+        if companion.ifname == self.args.ifname {
+            anyhow::bail!("bridgeCompanion ifname {} is already used by the VLAN interface", companion.ifname);
+        }
+        
+        // Leased before the pair exists; roll_back releases it with the others
+        let address = self.companion_address()
+            .context("Failed to allocate the bridge companion address")?;
+        
+        let host_ifname = companion.host_ifname(&self.args.container_id);
+        if let Err(e) = self.backend.add_veth(&host_ifname, &companion.ifname, &self.args.netns) {
+            if caps::is_permission_error(&e) {
+                return Err(caps::permission_error("Creating bridge companion veth", caps::CAP_NET_ADMIN));
+            }
+            anyhow::bail!("Failed to create veth pair {}: {:#}", host_ifname, e);
+        }
+        self.partial.companion = Some(host_ifname.clone());
+        
+        let attached = self.backend.set_link_master(&host_ifname, &companion.bridge)
+            .and_then(|()| self.backend.set_link_up(&host_ifname));
+        if let Err(e) = attached {
+            anyhow::bail!("Failed to attach {} to bridge {}: {:#}", host_ifname, companion.bridge, e);
+        }
+        let host_mac = self.backend.link(&host_ifname)
+            .with_context(|| format!("Failed to read {}", host_ifname))?
+            .and_then(|link| link.get("address").and_then(|a| a.as_str()).map(String::from));
+        
+        let ifname = companion.ifname.clone();
+        let pod_address = address.as_ref().map(|a| a.address.clone());
+        let backend = self.backend.clone();
+        let pod_mac = self.in_netns(&self.args.netns, || async move {
+            if let Some(address) = &pod_address {
+                if let Err(e) = backend.add_address(&ifname, address, AddressLifetimes::default()) {
+                    anyhow::bail!("Failed to add IP address to {}: {:#}", ifname, e);
+                }
+            }
+            
+            if let Err(e) = backend.set_link_up(&ifname) {
+                anyhow::bail!("Failed to set {} up in container: {:#}", ifname, e);
+            }
+            
+            let link = backend.link(&ifname)
+                .with_context(|| format!("Failed to read {} in container", ifname))?;
+            Ok(link.and_then(|link| link.get("address").and_then(|a| a.as_str()).map(String::from)))
+        }).await?;
+        
+        result.add_interface(Interface {
+            name: host_ifname,
+            mac: host_mac,
+            sandbox: None,
+            mtu: None,
+        });
+        result.add_interface(Interface {
+            name: companion.ifname.clone(),
+            mac: pod_mac,
+            sandbox: Some(self.args.netns.clone()),
+            mtu: None,
+        });
+        if let Some(address) = address {
+            let index = result.interfaces.as_ref().map_or(0, |i| i.len() - 1);
+            result.add_ip(IPConfig {
+                interface: Some(index),
+                address: address.address,
+                gateway: None,
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Type of the link that moves into the pod
+    ///
+    /// The native VLAN carries no tag, so it gets a macvlan directly on the
+    /// master instead of a VLAN.
+    fn pod_link_kind(&self) -> LinkKind {
+        if self.config.is_native() {
+            return LinkKind::Macvlan;
+        }
+        LinkKind::Vlan {
+            id: self.config.link_vlan(),
+            protocol: self.config.link_vlan_protocol().map(str::to_string),
+            flags: self.config.vlan_flags(),
+        }
+    }
+    
+    /// Create the link inside the pod's namespace in a single step
+    ///
+    /// The create request carries IFLA_NET_NS_FD. Returns false when that
+    /// fails, e.g. on kernels or link types that don't support it, so the
+    /// caller can fall back to [`Self::create_on_host`].
+    fn create_in_netns(&self, vlan_name: &str, mtu: Option<u32>) -> Result<bool> {
+        let parent = self.config.link_parent();
+        let link = NewLink {
+            name: vlan_name,
+            parent: &parent,
+            kind: self.pod_link_kind(),
+            mtu,
+            netns: Some(&self.args.netns),
+        };
+        
+        if let Err(e) = self.backend.add_link(&link) {
+            info!("Creating {} in the container namespace failed ({:#}); creating it on the host instead",
+                  vlan_name, e);
+            return Ok(false);
+        }
+        
+        Ok(true)
+    }
+    
+    /// Create the link on the host and bring it up, ready to be moved into the pod's namespace
+    fn create_on_host(&self, vlan_name: &str, mtu: Option<u32>, warnings: &mut Vec<String>) -> Result<()> {
+        let parent = self.config.link_parent();
+        let link = NewLink {
+            name: vlan_name,
+            parent: &parent,
+            kind: self.pod_link_kind(),
+            mtu: None,
+            netns: None,
+        };
+        // A re-ADD finds the link it created before. Only a link that is really
+        // ours is reused, never another network's; otherwise create it and
+        // surface whatever the kernel reports
+        self.claim_host_link(vlan_name, false)?;
+        if let Some(link) = self.existing_link(vlan_name)? {
+            let protocol = self.config.link_vlan_protocol().unwrap_or("802.1Q");
+            if let Some(mismatch) = existing_link_mismatch(&link, &parent, self.config.link_vlan(), protocol, self.config.is_native()) {
+                self.release_host_link(vlan_name);
+                anyhow::bail!(
+                    "Interface {} already exists but {}; refusing to reuse it for network {}",
+                    vlan_name, mismatch, self.config.name
+                );
+            }
+            info!("Reusing existing VLAN interface {}", vlan_name);
+        } else if let Err(e) = self.backend.add_link(&link) {
+            self.release_host_link(vlan_name);
+            if caps::is_permission_error(&e) {
+                return Err(caps::permission_error("Creating VLAN interface", caps::CAP_NET_ADMIN));
+            }
+            anyhow::bail!("Failed to create VLAN interface: {:#}", e);
+        }
+        
+        // Set link up
+        if let Err(e) = self.backend.set_link_up(vlan_name) {
+            anyhow::bail!("Failed to set VLAN interface up: {:#}", e);
+        }
+        
+        // Set MTU if configured, or inherited from the master
+        if let Some(mtu) = mtu {
+            if let Err(e) = self.backend.set_link_mtu(vlan_name, mtu) {
+                let warning = format!("Failed to set MTU {} on VLAN interface: {:#}", mtu, e);
+                warn!("{}", warning);
+                warnings.push(warning);
+            }
+        }
+        
+        // Queue affinity is a performance hint, so failures only warn
+        if self.config.numa_hint {
+            match numa::apply_numa_affinity(self.runner.as_ref(), Path::new(numa::SYSFS), &self.config.master, vlan_name) {
+                Ok(Some(node)) => info!("Steered {} queues to NUMA node {}", vlan_name, node),
+                Ok(None) => info!("Master {} reports no NUMA node; leaving queue affinity unset", self.config.master),
+                Err(e) => warn!("Failed to set NUMA queue affinity on {}: {}", vlan_name, e),
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Move a link made by [`create_on_host`](Self::create_on_host) into the container namespace
+    fn move_to_netns(&self, vlan_name: &str) -> Result<()> {
+        if let Err(e) = self.backend.move_link(vlan_name, &self.args.netns) {
+            if caps::is_permission_error(&e) {
+                return Err(caps::permission_error("Moving VLAN interface to container namespace", caps::CAP_NET_ADMIN));
+            }
+            anyhow::bail!("Failed to move VLAN interface to container namespace: {:#}", e);
+        }
+        Ok(())
+    }
+    
+    /// Registry key of a host link, qualified by the namespace holding the master
+    fn host_link_key(&self, name: &str) -> String {
+        match &self.config.master_netns {
+            Some(netns) => format!("{}/{}", netns, name),
+            None => name.to_string(),
+        }
+    }
+    
+    /// Claim the host link `name` for this network before creating or reusing
+    /// it; `shared` links belong to the network rather than this container
+    fn claim_host_link(&self, name: &str, shared: bool) -> Result<()> {
+        let container_id = (!shared).then_some(self.args.container_id.as_str());
+        StateStore::new(&self.socni.state_dir)
+            .claim_host_link(&self.host_link_key(name), &self.config.name, container_id)
+    }
+    
+    /// Give up this network's claim on the host link `name`
+    fn release_host_link(&self, name: &str) {
+        if let Err(e) = StateStore::new(&self.socni.state_dir).release_host_link(&self.host_link_key(name), &self.config.name) {
+            warn!("Failed to release host link {}: {}", name, e);
+        }
+    }
+    
+    /// Create the outer link of a QinQ VLAN on the master, or reuse the one
+    /// an earlier pod created
+    fn ensure_outer_link(&self, outer: &str) -> Result<()> {
+        let protocol = self.config.effective_vlan_protocol().unwrap_or("802.1ad");
+        self.claim_host_link(outer, true)?;
+        let existing = match self.existing_link(outer)? {
+            Some(link) => Some(link),
+            None => {
+                let link = NewLink {
+                    name: outer,
+                    parent: &self.config.master,
+                    kind: LinkKind::Vlan { id: self.config.vlan, protocol: Some(protocol.to_string()), flags: Vec::new() },
+                    mtu: None,
+                    netns: None,
+                };
+                match self.backend.add_link(&link) {
+                    Ok(()) => None,
+                    Err(e) => {
+                        // A concurrent ADD for another pod may have just created it
+                        if caps::is_permission_error(&e) {
+                            return Err(caps::permission_error("Creating outer VLAN interface", caps::CAP_NET_ADMIN));
+                        }
+                        match self.existing_link(outer)? {
+                            Some(link) => Some(link),
+                            None => {
+                                self.release_host_link(outer);
+                                anyhow::bail!("Failed to create outer VLAN interface {}: {:#}", outer, e);
+                            }
+                        }
+                    }
+                }
+            }
+        };
+        
+        if let Some(link) = existing {
+            if let Some(mismatch) = existing_link_mismatch(&link, &self.config.master, self.config.vlan, protocol, false) {
+                self.release_host_link(outer);
+                anyhow::bail!("Outer VLAN interface {} already exists but {}; refusing to stack VLAN {} on it",
+                              outer, mismatch, self.config.link_vlan());
+            }
+            info!("Reusing outer VLAN interface {}", outer);
+        }
+        
+        if let Err(e) = self.backend.set_link_up(outer) {
+            anyhow::bail!("Failed to set outer VLAN interface {} up: {:#}", outer, e);
+        }
+        Ok(())
+    }
+    
+    /// Delete a link an ADD created on the host but never moved into the pod
+    ///
+    /// A process killed between creating and moving the link leaves it
+    /// behind, and a later ADD would otherwise reuse or trip over it. Only a
+    /// link this container's ADD claimed is deleted; one that isn't there is fine.
+    fn remove_leaked_host_link(&self, store: &StateStore) {
+        let name = self.config.host_link_name();
+        match store.host_links() {
+            Ok(claims) => match claims.get(&self.host_link_key(&name)) {
+                Some(claim) if claim.network == self.config.name
+                    && claim.container_id.as_deref() == Some(self.args.container_id.as_str()) => {}
+                Some(claim) => {
+                    info!("Leaving host link {} to network {} container {}", name, claim.network,
+                          claim.container_id.as_deref().unwrap_or("none"));
+                    return;
+                }
+                None => return,
+            },
+            Err(e) => {
+                warn!("Failed to read host link owners; leaving host link {}: {}", name, e);
+                return;
+            }
+        }
+        
+        let guard = match self.config.master_netns.as_deref().filter(|ns| self.runner.enter_netns(ns)) {
+            Some(ns) => match netns::NetnsGuard::enter(ns) {
+                Ok(guard) => Some(guard.restore_attempts(self.socni.netns_restore_attempts)),
+                Err(e) => {
+                    warn!("Failed to enter master namespace {}; leaving host link {}: {:#}", ns, name, e);
+                    return;
+                }
+            },
+            None => None,
+        };
+        match self.backend.delete_link(&name) {
+            Ok(()) => {
+                info!("Removed leaked host link {}", name);
+                self.release_host_link(&name);
+            }
+            Err(e) if backend::errno_of(&e) == Some(libc::ENODEV) => {}
+            Err(e) => warn!("Failed to delete host link {}: {:#}", name, e),
+        }
+        if let Some(guard) = guard {
+            guard.restore();
+        }
+    }
+    
+    /// Delete the outer link of a QinQ VLAN once no pod on this node is stacked on it
+    fn remove_unused_outer_link(&self, store: &StateStore) {
+        let Some(outer) = self.config.outer_link_name() else {
+            return;
+        };
+        let key = self.host_link_key(&outer);
+        let _lock = match store.lock_outer_link(&key) {
+            Ok(lock) => lock,
+            Err(e) => {
+                warn!("Failed to lock outer VLAN interface {}; keeping it: {:#}", outer, e);
+                return;
+            }
+        };
+        match store.remove_outer_link_user(&key, &self.config.name, &self.args.container_id, &self.args.ifname) {
+            Ok(0) => {}
+            Ok(users) => {
+                info!("Keeping outer VLAN interface {} for {} other interface(s)", outer, users);
+                return;
+            }
+            Err(e) => {
+                warn!("Failed to update users of outer VLAN interface {}; keeping it: {}", outer, e);
+                return;
+            }
+        }
+        
+        // Pods added before users were recorded only show up as attachments
+        match store.attachments() {
+            Ok(attachments) => {
+                let stacked = attachments.values()
+                    .filter(|a| a.config.outer_link_name().as_deref() == Some(outer.as_str())
+                        && a.config.master_netns == self.config.master_netns)
+                    .count();
+                if stacked > 0 {
+                    info!("Keeping outer VLAN interface {} for {} other interface(s)", outer, stacked);
+                    return;
+                }
+            }
+            Err(e) => {
+                warn!("Failed to read attachments; keeping outer VLAN interface {}: {}", outer, e);
+                return;
+            }
+        }
+        
+        let guard = match self.config.master_netns.as_deref().filter(|ns| self.runner.enter_netns(ns)) {
+            Some(ns) => match netns::NetnsGuard::enter(ns) {
+                Ok(guard) => Some(guard.restore_attempts(self.socni.netns_restore_attempts)),
+                Err(e) => {
+                    warn!("Failed to enter master namespace {}; keeping outer VLAN interface {}: {:#}", ns, outer, e);
+                    return;
+                }
+            },
+            None => None,
+        };
+        match self.backend.delete_link(&outer) {
+            Ok(()) => {
+                info!("Removed outer VLAN interface {}", outer);
+                self.release_host_link(&outer);
+            }
+            Err(e) => warn!("Failed to delete outer VLAN interface {}: {:#}", outer, e),
+        }
+        if let Some(guard) = guard {
+            guard.restore();
+        }
+    }
+    
+    /// Host-local network key the secondary address is leased under,
+    /// namespaced like [`NetConf::dual_stack_network`]
+    fn secondary_network(&self) -> String {
+        format!("{}/secondary", self.config.name)
+    }
+    
+    /// Network the bridge companion's addresses are leased on
+    fn companion_network(&self) -> String {
+        format!("{}/companion", self.config.name)
+    }
+    
+    /// Lease an address for the pod end of the bridge companion from its
+    /// `ipam` range, like the secondary address
+    fn companion_address(&self) -> Result<Option<Allocation>> {
+        let pool = match self.config.bridge_companion.as_ref().and_then(|c| c.ipam.as_ref()) {
+            Some(pool) => pool,
+            None => return Ok(None),
+        };
+        
+        let ranges = vec![pool.address_range()?];
+        let ip = self.lease(LeaseRequest::Allocate {
+            network: self.companion_network(),
+            ranges: ranges.clone(),
+            owner: self.lease_owner(),
+            strategy: self.config.ipam.as_ref().map(|ipam| ipam.allocation_strategy).unwrap_or_default(),
+            requested: None,
+            ttl: None,
+        })?;
+        
+        Ok(ip.and_then(|ip| leased_allocation(&ranges, ip, Vec::new())))
+    }
+    
+    /// Container of another live attachment on this network whose interface
+    /// has `mac`, going by the results ADD recorded
+    fn mac_holder(&self, mac: &str) -> Result<Option<String>> {
+        let store = StateStore::new(&self.socni.state_dir);
+        for attachment in store.attachments()?.into_values() {
+            if attachment.config.name != self.config.name
+                || (attachment.container_id == self.args.container_id && attachment.ifname == self.args.ifname) {
+                continue;
+            }
+            let result = store.load_result(&attachment.config.name, &attachment.container_id, &attachment.ifname)?;
+            let in_use = result.iter()
+                .flat_map(|r| r.interfaces.iter().flatten())
+                .filter_map(|i| i.mac.as_deref())
+                .any(|m| m.eq_ignore_ascii_case(mac));
+            if in_use {
+                return Ok(Some(attachment.container_id));
+            }
+        }
+        Ok(None)
+    }
+    
+    /// Address, gateway and routes of the IPAM `secondary`, if configured
+    ///
+    /// Allocates (or, when `allocate` is false, refreshes) a host-local lease
+    /// like [`ipam_address`](Self::ipam_address) does for the primary.
+    fn secondary_address(&self, allocate: bool) -> Result<Option<Allocation>> {
+        let (ipam, secondary) = match &self.config.ipam {
+            Some(ipam) => match &ipam.secondary {
+                Some(secondary) => (ipam, secondary),
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let routes = secondary.routes.clone().unwrap_or_default();
+        
+        let mut range = secondary.pool.address_range()?;
+        range.vlan = Some(self.config.vlan);
+        let ranges = vec![range];
+        let ip = self.lease(if allocate {
+            LeaseRequest::Allocate {
+                network: self.secondary_network(),
+                ranges: ranges.clone(),
+                owner: self.lease_owner(),
+                strategy: ipam.allocation_strategy,
+                requested: None,
+                ttl: None,
+            }
+        } else {
+            self.lease_of(self.secondary_network(), false)
+        })?;
+        
+        Ok(ip.and_then(|ip| leased_allocation(&ranges, ip, routes)))
+    }
+    
+    /// Address (`addr/prefix`) and gateway for the container interface
+    ///
+    /// `host-local` leases an address from the state directory, or only looks
+    /// up and refreshes the existing lease when `allocate` is false;
+    /// `socni-ipamd` does the same through the daemon. `http` asks the IPAM
+    /// service, and has nothing to look up locally. `file` reads the pod's
+    /// entry from the assignments file. `None` means there is no address:
+    /// IPAM is absent or `none`, or no lease is held when only looking up.
+    fn ipam_address(&self, allocate: bool) -> Result<Option<Allocation>> {
+        let ipam = match &self.config.ipam {
+            Some(ipam) if ipam.ipam_type != "none" => ipam,
+            _ => return Ok(None),
+        };
+        
+        if let Some(client) = self.http_ipam() {
+            return match allocate {
+                true => client.allocate(&self.allocation_request()).map(Some),
+                false => Ok(None),
+            };
+        }
+        
+        // Assignments are managed outside socni and keyed by pod
+        if let Some(file) = ipam.file.as_deref().filter(|_| ipam.ipam_type == "file") {
+            let identity = pod_identity(&self.args.args).ok_or_else(|| anyhow::anyhow!(
+                "file IPAM needs K8S_POD_NAMESPACE and K8S_POD_NAME in CNI_ARGS"
+            ))?;
+            return ipam::file::lookup(Path::new(file), &identity).map(Some);
+        }
         
-        if !check_cmd.status.success() {
-            anyhow::bail!("Master interface {} does not exist", self.config.master);
+        let pool = ipam.resolve_pool(&self.args.args)?;
+        if let Some((name, _)) = pool {
+            info!("Allocating from IPAM pool {}", name);
+        }
+        
+        // Validation only admits types that assign addresses
+        if !ipam.leases_host_local() {
+            anyhow::bail!("{} IPAM cannot assign addresses", ipam.ipam_type);
+        }
+        let requested = ipam.requested_ip(&self.args.args)?;
+        
+        // The second family of a dual-stack configuration is leased separately
+        let mut ranges = match pool {
+            Some((_, pool)) => vec![pool.address_range()?],
+            None => ipam.address_families()?.swap_remove(0),
+        };
+        for range in &mut ranges {
+            range.vlan = Some(self.config.vlan);
+        }
+        let ip = self.lease(if allocate {
+            LeaseRequest::Allocate {
+                network: self.config.name.clone(),
+                ranges: ranges.clone(),
+                owner: self.lease_owner(),
+                strategy: ipam.allocation_strategy,
+                requested,
+                ttl: Some(ipam.lease_ttl),
+            }
+        } else {
+            self.lease_of(self.config.name.clone(), false)
+        })?;
+        
+        Ok(ip.and_then(|ip| leased_allocation(&ranges, ip, Vec::new())))
+    }
+    
+    /// Address and gateway of the second address family when the IPAM
+    /// ranges are dual-stack
+    ///
+    /// Leased under its own network key like the secondary address, so the
+    /// pod holds one lease per family. A selected pool is single-stack.
+    fn dual_stack_address(&self, allocate: bool) -> Result<Option<Allocation>> {
+        let (ipam, network) = match (&self.config.ipam, self.config.dual_stack_network()) {
+            (Some(ipam), Some(network)) if ipam.leases_host_local() => (ipam, network),
+            _ => return Ok(None),
+        };
+        if ipam.resolve_pool(&self.args.args)?.is_some() {
+            return Ok(None);
+        }
+        
+        let mut ranges = ipam.address_families()?.swap_remove(1);
+        for range in &mut ranges {
+            range.vlan = Some(self.config.vlan);
+        }
+        let ip = self.lease(if allocate {
+            LeaseRequest::Allocate {
+                network,
+                ranges: ranges.clone(),
+                owner: self.lease_owner(),
+                strategy: ipam.allocation_strategy,
+                requested: None,
+                ttl: Some(ipam.lease_ttl),
+            }
+        } else {
+            self.lease_of(network, false)
+        })?;
+        
+        Ok(ip.and_then(|ip| leased_allocation(&ranges, ip, Vec::new())))
+    }
+    
+    /// Run a host-local lease operation, through `socni-ctl ipamd` when
+    /// `ipam.type` is `socni-ipamd`
+    fn lease(&self, request: LeaseRequest) -> Result<Option<IpAddr>> {
+        match self.config.ipam.as_ref().filter(|ipam| ipam.ipam_type == IPAMD_TYPE) {
+            Some(ipam) => IpamdClient::new(ipam.ipamd_socket(), Duration::from_secs(ipam.timeout)).request(&request),
+            None => daemon::handle(&HostLocal::new(self.ipam_dir()), request),
+        }
+    }
+    
+    /// Refresh, or with `release` free, this interface's lease on `network`
+    fn lease_of(&self, network: String, release: bool) -> LeaseRequest {
+        let container_id = self.args.container_id.clone();
+        let ifname = self.args.ifname.clone();
+        match release {
+            true => LeaseRequest::Release { network, container_id, ifname },
+            false => LeaseRequest::Refresh { network, container_id, ifname },
+        }
+    }
+    
+    /// Client for the external IPAM service when `ipam.type` is `http`
+    fn http_ipam(&self) -> Option<HttpIpam<'_>> {
+        let ipam = self.config.ipam.as_ref().filter(|ipam| ipam.ipam_type == "http")?;
+        let url = ipam.url.as_deref()?;
+        Some(HttpIpam::new(url, Duration::from_secs(ipam.timeout), self.runner.as_ref()))
+    }
+    
+    fn allocation_request(&self) -> AllocationRequest {
+        AllocationRequest {
+            network: self.config.name.clone(),
+            vlan: self.config.vlan,
+            container_id: self.args.container_id.clone(),
+            ifname: self.args.ifname.clone(),
+            tenant: self.config.resolve_tenant(&self.args.args),
+        }
+    }
+    
+    /// Free the address of this container interface, returning a freed
+    /// host-local address
+    ///
+    /// Failures are only logged, except that an IPAM daemon or service that
+    /// can't be reached fails with its retryable error so DEL is retried
+    /// instead of leaking the lease.
+    fn release_address(&self) -> Result<Option<IpAddr>> {
+        let release = |what: &str, request: LeaseRequest| match self.lease(request) {
+            Err(e) if CniError::code_of(&e) == CNI_ERR_TRY_AGAIN_LATER => Err(e),
+            Err(e) => {
+                warn!("Failed to release {} for {}: {}", what, self.args.container_id, e);
+                Ok(None)
+            }
+            freed => freed,
+        };
+        
+        // The companion leases host-local whatever IPAM the VLAN uses
+        if self.config.bridge_companion.as_ref().map_or(false, |c| c.ipam.is_some()) {
+            release("bridge companion address", self.lease_of(self.companion_network(), true))?;
+        }
+        
+        if let Some(client) = self.http_ipam() {
+            client.release(&self.allocation_request())?;
+            return Ok(None);
+        }
+        
+        let host_local = self.config.ipam.as_ref()
+            .map_or(false, |ipam| ipam.leases_host_local());
+        if !host_local {
+            return Ok(None);
+        }
+        
+        if self.config.ipam.as_ref().map_or(false, |ipam| ipam.secondary.is_some()) {
+            release("secondary address", self.lease_of(self.secondary_network(), true))?;
+        }
+        if let Some(network) = self.config.dual_stack_network() {
+            release("dual-stack address", self.lease_of(network, true))?;
+        }
+        release("address", self.lease_of(self.config.name.clone(), true))
+    }
+    
+    /// Verify the master interface exists and can carry a VLAN
+    fn verify_master_interface(&self) -> Result<MasterLink> {
+        // Checked before the master is even looked up, so a conflist can't
+        // probe or attach to interfaces the node keeps off limits
+        if !self.socni.master_allowed(&self.config.master) {
+            anyhow::bail!("Master interface {} is not allowed on this node (SOCNI_ALLOWED_MASTERS: {})",
+                          self.config.master, self.socni.allowed_masters.iter().flatten().cloned()
+                              .collect::<Vec<_>>().join(","));
+        }
+        
+        let link = self.backend.link(&self.config.master)
+            .with_context(|| format!("Failed to read master interface {}", self.config.master))?
+            .ok_or_else(|| anyhow::anyhow!("Master interface {} does not exist", self.config.master))?;
+        
+        let kind = MasterKind::from_link(&link);
+        match &kind {
+            MasterKind::Bridge => {
+                anyhow::bail!(
+                    "Master interface {} is a bridge; VLAN sub-interfaces cannot be attached to a bridge. \
+                     Enable vlan_filtering on the bridge and use a bridge-vlan setup instead, \
+                     or set master to the bridge's physical uplink",
+                    self.config.master
+                );
+            }
+            MasterKind::Bond => self.verify_bond_master(&link)?,
+            MasterKind::Physical | MasterKind::Other(_) => {}
+        }
+        
+        let mtu = link.get("mtu")
+            .and_then(|m| m.as_u64())
+            .map(|m| m as u32);
+        
+        info!("Master interface {} is a {} link (mtu {:?})", self.config.master, kind, mtu);
+        Ok(MasterLink { kind, mtu })
+    }
+    
+    /// Raise the master's MTU to the configured MTU when it is lower
+    fn raise_master_mtu(&self, master: &MasterLink) -> Result<()> {
+        let mtu = match self.config.mtu {
+            Some(mtu) if master.mtu.map_or(true, |current| current < mtu) => mtu,
+            _ => return Ok(()),
+        };
+        
+        let store = StateStore::new(&self.socni.state_dir);
+        let attachments = store.attachments().context("Failed to read attachments")?;
+        check_master_mtu(&attachments, &self.config.master, master.mtu, mtu)?;
+        
+        if let Err(e) = self.backend.set_link_mtu(&self.config.master, mtu) {
+            anyhow::bail!("Failed to raise the MTU of master {} to {}: {:#}", self.config.master, mtu, e);
+        }
+        info!("Raised the MTU of master {} from {:?} to {}", self.config.master, master.mtu, mtu);
+        Ok(())
+    }
+    
+    /// The host link named `name`, or `None` when there is no such link
+    fn existing_link(&self, name: &str) -> Result<Option<serde_json::Value>> {
+        self.backend.link(name)
+            .with_context(|| format!("Failed to look up link {}", name))
+    }
+    
+    /// Verify a bond master is up and has at least one active slave
+    fn verify_bond_master(&self, link: &serde_json::Value) -> Result<()> {
+        let is_up = link.get("flags")
+            .and_then(|f| f.as_array())
+            .map(|flags| flags.iter().any(|f| f.as_str() == Some("UP")))
+            .unwrap_or(false);
+        
+        if !is_up {
+            anyhow::bail!(
+                "Bond master {} is administratively down; bring it up with `ip link set dev {} up` before attaching VLANs",
+                self.config.master, self.config.master
+            );
+        }
+        
+        let slaves = match self.backend.link_slaves(&self.config.master) {
+            Ok(slaves) => slaves,
+            Err(e) => anyhow::bail!("Failed to list slaves of bond {}: {:#}", self.config.master, e),
+        };
+        
+        if slaves.is_empty() {
+            anyhow::bail!(
+                "Bond master {} has no slave interfaces; enslave at least one NIC before attaching VLANs",
+                self.config.master
+            );
+        }
+        
+        let active = slaves.iter()
+            .filter(|s| s.get("operstate").and_then(|v| v.as_str()) == Some("UP"))
+            .count();
+        
+        if active == 0 {
+            anyhow::bail!(
+                "Bond master {} has {} slave(s) but none are active (operstate UP); check cabling and switch ports",
+                self.config.master, slaves.len()
+            );
         }
         
         Ok(())
     }
+}
+
+/// Allocation of a leased `ip`, with the prefix and gateway of the range
+/// holding it (the first range if the configuration no longer covers it)
+fn leased_allocation(ranges: &[ipam::AddressRange], ip: IpAddr, routes: Vec<Route>) -> Option<Allocation> {
+    let range = ranges.iter().find(|range| range.subnet.contains(ip)).or(ranges.first())?;
+    Some(Allocation {
+        address: format!("{}/{}", ip, range.subnet.prefix()),
+        gateway: range.gateway.map(|gw| gw.to_string()).unwrap_or_default(),
+        routes,
+    })
+}
+
+/// Why an existing link (`ip -d -j link show` entry) isn't the expected VLAN
+///
+/// Returns `None` when it is a VLAN with id `vlan` and tag `protocol` on
+/// `master`, or a macvlan on `master` for the native VLAN.
+pub fn existing_link_mismatch(link: &serde_json::Value, master: &str, vlan: u16, protocol: &str, native: bool) -> Option<String> {
+    let linkinfo = link.get("linkinfo");
+    let kind = linkinfo
+        .and_then(|li| li.get("info_kind"))
+        .and_then(|k| k.as_str())
+        .unwrap_or("none");
+    let expected_kind = if native { "macvlan" } else { "vlan" };
+    if kind != expected_kind {
+        return Some(format!("is a {} link, not {}", kind, expected_kind));
+    }
+    
+    let parent = link.get("link").and_then(|l| l.as_str());
+    if parent != Some(master) {
+        return Some(format!("is attached to {}, not {}", parent.unwrap_or("no master"), master));
+    }
+    
+    if !native {
+        let id = linkinfo
+            .and_then(|li| li.get("info_data"))
+            .and_then(|d| d.get("id"))
+            .and_then(|id| id.as_u64());
+        if id != Some(vlan as u64) {
+            return Some(format!("has VLAN id {}, not {}",
+                id.map(|id| id.to_string()).unwrap_or_else(|| "none".to_string()), vlan));
+        }
+        
+        let found = linkinfo
+            .and_then(|li| li.get("info_data"))
+            .and_then(|d| d.get("protocol"))
+            .and_then(|p| p.as_str());
+        if !found.is_some_and(|found| found.eq_ignore_ascii_case(protocol)) {
+            return Some(format!("uses protocol {}, not {}", found.unwrap_or("none"), protocol));
+        }
+    }
+    
+    None
+}
+
+/// Whether `gateway` lies outside the prefix of `address` (`addr/prefix`)
+///
+/// Unparseable input is treated as on-subnet so the plain route is tried.
+pub fn gateway_needs_onlink(address: &str, gateway: &str) -> bool {
+    match (address.parse::<IpNetwork>(), gateway.parse::<IpAddr>()) {
+        (Ok(net), Ok(gw)) => !net.contains(gw),
+        _ => false,
+    }
+}
+
+/// Whether `addr` (an address or CIDR) is IPv6
+fn is_ipv6(addr: &str) -> bool {
+    addr.contains(':')
+}
+
+/// Install the default route of `address`'s family via `gateway` and report it
+/// in `result`
+///
+/// A gateway outside the assigned prefix is only reachable via the interface
+/// itself, so with `onlink` a device route to it is installed first. Failing
+/// to install either is only a warning.
+fn add_default_route(
+    backend: &dyn NetBackend,
+    result: &mut CniResult,
+    ifname: &str,
+    address: &str,
+    gateway: &str,
+    onlink: bool,
+) -> Result<()> {
+    let default = if is_ipv6(address) { "::/0" } else { "0.0.0.0/0" };
+    let mut warn_failed = |added: Result<()>, what: String| {
+        if let Err(e) = added {
+            let warning = format!("Failed to add {}: {:#}", what, e);
+            warn!("{}", warning);
+            result.add_warning(warning);
+        }
+    };
+    
+    if onlink {
+        info!("Gateway {} is outside {}; adding on-link route", gateway, address);
+        let route = NewRoute { dst: Some(gateway), dev: ifname, ..Default::default() };
+        warn_failed(backend.add_route(&route), format!("on-link route to gateway {}", gateway));
+    }
+    
+    let route = NewRoute { gateway: Some(gateway), dev: ifname, onlink, ..Default::default() };
+    warn_failed(backend.add_route(&route), format!("default route via {}", gateway));
+    
+    result.add_route(CniRoute {
+        dst: default.to_string(),
+        gw: Some(gateway.to_string()),
+        priority: None,
+    });
+    Ok(())
+}
+
+/// Install `route` on `ifname`, via `gateway` unless the route names its
+/// own, and report it in `result`
+///
+/// A route that can't be installed is only a warning, like the default route.
+fn add_route(backend: &dyn NetBackend, result: &mut CniResult, ifname: &str, route: &Route, gateway: &str) -> Result<()> {
+    let via = route.gw.as_deref().unwrap_or(gateway);
+    let new_route = NewRoute {
+        dst: Some(&route.dst),
+        gateway: Some(via),
+        dev: ifname,
+        metric: route.metric,
+        ..Default::default()
+    };
+    
+    if let Err(e) = backend.add_route(&new_route) {
+        let warning = format!("Failed to add route to {}: {:#}", route.dst, e);
+        warn!("{}", warning);
+        result.add_warning(warning);
+    }
+    
+    result.add_route(CniRoute {
+        dst: route.dst.clone(),
+        gw: Some(via.to_string()),
+        priority: route.metric,
+    });
+    Ok(())
+}
+
+/// Install the subnet and default routes of `ifname` in `table` and a rule
+/// looking up `table` for traffic sourced from `address`
+fn add_source_routing(
+    backend: &dyn NetBackend,
+    ifname: &str,
+    address: &str,
+    gateway: &str,
+    onlink: bool,
+    table: u32,
+) -> Result<()> {
+    let net: IpNetwork = address.parse()
+        .with_context(|| format!("Invalid pod address {}", address))?;
+    let subnet = format!("{}/{}", net.network(), net.prefix());
+    let source = net.ip().to_string();
+    
+    let subnet_route = NewRoute { dst: Some(&subnet), dev: ifname, table: Some(table), ..Default::default() };
+    if let Err(e) = backend.add_route(&subnet_route) {
+        anyhow::bail!("Failed to set up source routing: route to {} in table {}: {:#}", subnet, table, e);
+    }
+    let default_route = NewRoute { gateway: Some(gateway), dev: ifname, table: Some(table), onlink, ..Default::default() };
+    if let Err(e) = backend.add_route(&default_route) {
+        anyhow::bail!("Failed to set up source routing: default route via {} in table {}: {:#}", gateway, table, e);
+    }
+    if let Err(e) = backend.add_source_rule(&source, table) {
+        anyhow::bail!("Failed to set up source routing: rule from {} to table {}: {:#}", source, table, e);
+    }
+    Ok(())
+}
+
+/// Send one echo request to `target` through `ifname`, waiting at most `PING_TIMEOUT`
+fn ping(runner: &dyn CommandRunner, ifname: &str, target: &str) -> bool {
+    let timeout = PING_TIMEOUT.as_secs().to_string();
+    runner.output("ping", &["-c", "1", "-W", &timeout, "-I", ifname, target])
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Bytes MACsec adds to every frame: the SecTAG with the SCI and the ICV
+const MACSEC_OVERHEAD: u32 = 32;
+
+/// Create the MACsec device `ifname` on `lower` with the VLAN's keys for
+/// transmitting and for receiving from each configured peer
+///
+/// The device takes `lower`'s MAC, set to `mac` first when one was requested,
+/// so its channel is identified by the MAC peers list and the keys are
+/// derived from it. Packet numbers start at the clock in nanoseconds: a pod
+/// re-added with the same MAC, and so the same key, continues past any
+/// number its earlier device could have sent. The keys never appear on a
+/// command line.
+pub fn add_macsec(
+    backend: &dyn NetBackend,
+    lower: &str,
+    ifname: &str,
+    mac: Option<&str>,
+    macsec: &MacsecConfig,
+    secret: &MacsecSecret,
+) -> Result<()> {
+    if let Some(mac) = mac {
+        if let Err(e) = backend.set_link_address(lower, mac) {
+            anyhow::bail!("Failed to set MAC address {} on MACsec VLAN link {}: {:#}", mac, lower, e);
+        }
+    }
+    if let Err(e) = backend.set_link_up(lower) {
+        anyhow::bail!("Failed to set MACsec VLAN link {} up: {:#}", lower, e);
+    }
+    
+    let device = NewMacsec { name: ifname, lower, cipher: macsec.cipher.as_str(), encrypt: macsec.encrypt };
+    if let Err(e) = backend.add_macsec(&device) {
+        anyhow::bail!("Failed to create MACsec device: {:#}", e);
+    }
+    
+    let own_mac = backend.link_stats(ifname).ok()
+        .and_then(|link| link.get("address").and_then(|a| a.as_str()).map(String::from))
+        .ok_or_else(|| anyhow::anyhow!("Failed to read the MAC address of MACsec device {}", ifname))?;
+    let key_len = macsec.cipher.key_len();
+    
+    let first_pn = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or(0)
+        .max(1);
+    let tx_key = secret.key_for(&own_mac, key_len)?;
+    let rx_keys = macsec.peers.iter()
+        .map(|peer| Ok((peer.as_str(), secret.key_for(peer, key_len)?)))
+        .collect::<Result<Vec<(&str, MacsecKey)>>>()?;
+    
+    let mut sas = vec![MacsecSa { peer: None, pn: first_pn, key_id: &tx_key.id, key: &tx_key.key, salt: &tx_key.salt }];
+    for (peer, key) in &rx_keys {
+        sas.push(MacsecSa { peer: Some(*peer), pn: 1, key_id: &key.id, key: &key.key, salt: &key.salt });
+    }
+    
+    if let Err(e) = backend.add_macsec_sas(ifname, &sas) {
+        anyhow::bail!("Failed to add MACsec secure associations: {:#}", e);
+    }
+    Ok(())
+}
+
+/// How long ADD waits for the gateway to answer ARP/ND
+const GATEWAY_RESOLVE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long CHECK waits for each connectivity probe to be answered
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Resolve the link-layer address of `ip` on `ifname`, or `None` after `timeout`
+///
+/// Marking the neighbor entry in use makes the kernel probe it; the entry
+/// is then polled until it has a usable address.
+async fn resolve_neighbor(backend: &dyn NetBackend, ifname: &str, ip: &str, timeout: Duration) -> Option<String> {
+    ip.parse::<IpAddr>().ok()?;
+    if let Err(e) = backend.probe_neighbor(ifname, ip) {
+        warn!("Failed to start resolving {} on {}: {:#}", ip, ifname, e);
+    }
+    
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        let lladdr = backend.neighbors(ifname, Some(ip))
+            .ok()
+            .and_then(|entries| verify::neighbor_lladdr(&entries, ip));
+        if lladdr.is_some() || tokio::time::Instant::now() >= deadline {
+            return lladdr;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Addresses the result assigns to the primary interface
+fn pod_addresses(result: &CniResult) -> Vec<IpAddr> {
+    result.ips.iter()
+        .flatten()
+        .filter(|ip| ip.interface == Some(0))
+        .filter_map(|ip| ip.address.parse::<IpNetwork>().ok())
+        .map(|net| net.ip())
+        .collect()
+}
+
+/// Check changing the MTU of `master` from `current` to `mtu` is safe for
+/// the other socni VLANs on it
+///
+/// Lowering the master below the MTU of any VLAN recorded on it is refused,
+/// since the kernel would clamp that VLAN and silently shrink its path MTU.
+/// Raising is allowed and logs the VLANs that share the master.
+pub fn check_master_mtu(
+    attachments: &HashMap<String, Attachment>,
+    master: &str,
+    current: Option<u32>,
+    mtu: u32,
+) -> Result<()> {
+    let mut users: Vec<&Attachment> = attachments.values()
+        .filter(|a| a.config.master == master)
+        .collect();
+    users.sort_by(|a, b| (&a.config.name, &a.container_id).cmp(&(&b.config.name, &b.container_id)));
+    
+    if matches!(current, Some(current) if mtu < current) {
+        // VLANs without an explicit MTU got the master's at the time
+        let conflicts: Vec<String> = users.iter()
+            .filter_map(|a| a.config.mtu.or(current).filter(|&m| m > mtu)
+                .map(|m| format!("VLAN {} of container {} on network {} (mtu {})",
+                                 a.config.vlan, a.container_id, a.config.name, m)))
+            .collect();
+        if !conflicts.is_empty() {
+            anyhow::bail!("Refusing to lower the MTU of master {} to {}: it is below the MTU of {}",
+                          master, mtu, conflicts.join(", "));
+        }
+        return Ok(());
+    }
+    
+    for a in users {
+        info!("Raising the MTU of master {} to {} affects VLAN {} of container {} on network {}",
+              master, mtu, a.config.vlan, a.container_id, a.config.name);
+    }
+    Ok(())
+}
+
+/// The master interface as seen when preparing an attachment
+#[derive(Debug, Clone)]
+pub struct MasterLink {
+    pub kind: MasterKind,
+    pub mtu: Option<u32>,
+}
+
+/// Kind of link a VLAN is attached to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MasterKind {
+    /// Plain physical NIC (no link info kind)
+    Physical,
+    /// Linux bonding device
+    Bond,
+    /// Linux bridge
+    Bridge,
+    /// Any other link type (e.g. veth, vxlan)
+    Other(String),
+}
+
+impl MasterKind {
+    /// Determine the kind from an `ip -d -j link show` entry
+    pub fn from_link(link: &serde_json::Value) -> Self {
+        match link.get("linkinfo")
+            .and_then(|l| l.get("info_kind"))
+            .and_then(|k| k.as_str())
+        {
+            None => MasterKind::Physical,
+            Some("bond") => MasterKind::Bond,
+            Some("bridge") => MasterKind::Bridge,
+            Some(other) => MasterKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for MasterKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MasterKind::Physical => write!(f, "physical"),
+            MasterKind::Bond => write!(f, "bond"),
+            MasterKind::Bridge => write!(f, "bridge"),
+            MasterKind::Other(kind) => write!(f, "{}", kind),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,205 @@
+//! Drift detection between the CNI configuration directory and the node
+//!
+//! The desired state is every socni plugin entry in the conflists under
+//! the configuration directory. The live state is the host's VLAN links
+//! (`ip -d -j link show type vlan`) and the host-local IPAM leases.
+
+use anyhow::{Context, Result};
+use ipnetwork::IpNetwork;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+
+use crate::config::NetConf;
+use crate::ipam::IpamState;
+
+/// Plugin type names socni is installed under
+pub const PLUGIN_TYPES: &[&str] = &["vlan", "socni"];
+
+/// One difference between desired and live state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// A network's master interface does not exist
+    MissingMaster { network: String, master: String },
+    /// A host VLAN link that no configured network would create
+    OrphanedLink { name: String },
+    /// A host VLAN link whose MTU differs from the configured one
+    MtuMismatch { name: String, expected: u32, actual: u32 },
+    /// A lease that no configured network or subnet accounts for
+    StaleLease { network: String, address: String, container_id: String, ifname: String, reason: String },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::MissingMaster { network, master } => {
+                write!(f, "network {}: master interface {} is missing", network, master)
+            }
+            Drift::OrphanedLink { name } => {
+                write!(f, "link {}: not referenced by any configured network", name)
+            }
+            Drift::MtuMismatch { name, expected, actual } => {
+                write!(f, "link {}: mtu {} but configuration expects {}", name, actual, expected)
+            }
+            Drift::StaleLease { network, address, container_id, reason, .. } => {
+                write!(f, "network {}: lease {} for {} is stale ({})", network, address, container_id, reason)
+            }
+        }
+    }
+}
+
+/// The socni plugin entries of a `.conf` or `.conflist` document
+///
+/// `name`, `cniVersion` and `cniVersions` of a conflist are applied to each
+/// of its plugins, giving the configuration the runtime would pass on stdin.
+pub fn socni_plugins(doc: &serde_json::Value) -> Vec<serde_json::Value> {
+    let plugins = match doc.get("plugins").and_then(|p| p.as_array()) {
+        Some(plugins) => plugins.clone(),
+        None => vec![doc.clone()],
+    };
+    
+    let mut found = Vec::new();
+    for mut plugin in plugins {
+        let is_socni = plugin.get("type")
+            .and_then(|t| t.as_str())
+            .map_or(false, |t| PLUGIN_TYPES.contains(&t));
+        if !is_socni {
+            continue;
+        }
+        
+        if let Some(obj) = plugin.as_object_mut() {
+            for key in ["name", "cniVersion", "cniVersions"] {
+                if let (false, Some(value)) = (obj.contains_key(key), doc.get(key)) {
+                    obj.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+        found.push(plugin);
+    }
+    found
+}
+
+/// Load every socni network from the `.conf`, `.conflist` and `.json` files in `dir`
+///
+/// Files that aren't valid CNI configuration are skipped.
+pub fn load_networks(dir: &Path) -> Result<Vec<NetConf>> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read config directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("conf" | "conflist" | "json")))
+        .collect();
+    paths.sort();
+    
+    let mut networks = Vec::new();
+    for path in paths {
+        let data = match fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        let doc: serde_json::Value = match serde_json::from_slice(&data) {
+            Ok(doc) => doc,
+            Err(_) => continue,
+        };
+        
+        for plugin in socni_plugins(&doc) {
+            let bytes = serde_json::to_vec(&plugin)?;
+            let conf = NetConf::parse(&bytes)
+                .with_context(|| format!("Invalid socni configuration in {}", path.display()))?;
+            networks.push(conf);
+        }
+    }
+    
+    Ok(networks)
+}
+
+/// Compare the desired networks with live links and leases
+///
+/// `links` is `ip -d -j link show` output for the host, `leases` the IPAM
+/// state of each network keyed by network name.
+pub fn diff(
+    networks: &[NetConf],
+    links: &[serde_json::Value],
+    leases: &BTreeMap<String, IpamState>,
+) -> Vec<Drift> {
+    let mut drift = Vec::new();
+    
+    let link_names: Vec<&str> = links.iter()
+        .filter_map(|l| l.get("ifname").and_then(|n| n.as_str()))
+        .collect();
+    for network in networks {
+        if !link_names.contains(&network.master.as_str()) {
+            drift.push(Drift::MissingMaster {
+                network: network.name.clone(),
+                master: network.master.clone(),
+            });
+        }
+    }
+    
+    for link in links {
+        let kind = link.get("linkinfo")
+            .and_then(|li| li.get("info_kind"))
+            .and_then(|k| k.as_str());
+        if kind != Some("vlan") {
+            continue;
+        }
+        
+        let name = link.get("ifname").and_then(|n| n.as_str()).unwrap_or_default();
+        let parent = link.get("link").and_then(|l| l.as_str());
+        let id = link.get("linkinfo")
+            .and_then(|li| li.get("info_data"))
+            .and_then(|d| d.get("id"))
+            .and_then(|id| id.as_u64());
+        
+        let owner = networks.iter()
+            .find(|n| parent == Some(n.master.as_str()) && id == Some(n.vlan as u64));
+        match owner {
+            None => drift.push(Drift::OrphanedLink { name: name.to_string() }),
+            Some(network) => {
+                let actual = link.get("mtu").and_then(|m| m.as_u64()).map(|m| m as u32);
+                let master_mtu = links.iter()
+                    .find(|l| l.get("ifname").and_then(|n| n.as_str()) == Some(network.master.as_str()))
+                    .and_then(|l| l.get("mtu"))
+                    .and_then(|m| m.as_u64())
+                    .map(|m| m as u32);
+                if let (Some(expected), Some(actual)) = (network.effective_mtu(master_mtu), actual) {
+                    if expected != actual {
+                        drift.push(Drift::MtuMismatch { name: name.to_string(), expected, actual });
+                    }
+                }
+            }
+        }
+    }
+    
+    for (network_name, state) in leases {
+        let network = networks.iter()
+            .find(|n| &n.name == network_name || n.dual_stack_network().as_ref() == Some(network_name));
+        let subnets: Vec<IpNetwork> = network
+            .and_then(|n| n.ipam.as_ref())
+            .map(|ipam| ipam.subnets())
+            .unwrap_or_default();
+        
+        for (address, lease) in &state.leases {
+            let reason = if network.is_none() {
+                Some("network is no longer configured")
+            } else if !address.parse::<IpAddr>().map_or(false, |ip| subnets.iter().any(|s| s.contains(ip))) {
+                Some("address is outside the configured subnets")
+            } else {
+                None
+            };
+            
+            if let Some(reason) = reason {
+                drift.push(Drift::StaleLease {
+                    network: network_name.clone(),
+                    address: address.clone(),
+                    container_id: lease.container_id.clone(),
+                    ifname: lease.ifname.clone(),
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+    
+    drift
+}
@@ -0,0 +1,144 @@
+//! Registration of pod addresses in a key-value store
+//!
+//! ADD writes the pod's address under a key rendered from the network's
+//! `registry.key` template and DEL removes it, so service discovery can find
+//! pods by name. Requests go through `curl` against etcd's v3 JSON gateway or
+//! Consul's KV API, each bounded by the configured timeout. Registration is
+//! best-effort: callers only warn when it fails, so a registry outage never
+//! blocks pod networking.
+//!
+//! Removal compares before it deletes (an etcd transaction on the value, a
+//! Consul `?cas=` delete on the index the value was read at), so a DEL never
+//! withdraws a key that a newer pod of the same name has since registered.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+use crate::config::{RegistryConfig, RegistryType};
+use crate::encoding::{self, Alphabet};
+use crate::runner::CommandRunner;
+
+/// Render a key template, failing when a placeholder has no value
+pub fn render_key(template: &str, vars: &HashMap<&str, String>) -> Result<String> {
+    let mut key = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        key.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in registry key {}", template))?;
+        let var = &rest[start + 1..start + end];
+        let value = vars.get(var)
+            .ok_or_else(|| anyhow::anyhow!("No value for {{{}}} in registry key {}", var, template))?;
+        key.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    key.push_str(rest);
+    Ok(key)
+}
+
+/// Client for the store of one network
+pub struct Registry<'a> {
+    config: &'a RegistryConfig,
+    runner: &'a dyn CommandRunner,
+}
+
+impl<'a> Registry<'a> {
+    pub fn new(config: &'a RegistryConfig, runner: &'a dyn CommandRunner) -> Self {
+        Self { config, runner }
+    }
+    
+    /// Store `value` under `key`
+    pub fn put(&self, key: &str, value: &str) -> Result<()> {
+        match self.config.registry_type {
+            RegistryType::Etcd => {
+                let body = serde_json::json!({
+                    "key": encoding::encode(key.as_bytes(), Alphabet::Standard),
+                    "value": encoding::encode(value.as_bytes(), Alphabet::Standard),
+                });
+                self.request("POST", &self.url("v3/kv/put"), Some(&body.to_string()))
+            }
+            RegistryType::Consul => self.request("PUT", &self.consul_url(key), Some(value)),
+        }
+    }
+    
+    /// Remove `key` if it still holds `value`; a key that doesn't exist, or
+    /// has since been given another value, is left alone without an error
+    pub fn delete(&self, key: &str, value: &str) -> Result<()> {
+        match self.config.registry_type {
+            RegistryType::Etcd => {
+                let key = encoding::encode(key.as_bytes(), Alphabet::Standard);
+                let body = serde_json::json!({
+                    "compare": [{
+                        "key": key,
+                        "target": "VALUE",
+                        "result": "EQUAL",
+                        "value": encoding::encode(value.as_bytes(), Alphabet::Standard),
+                    }],
+                    "success": [{"request_delete_range": {"key": key}}],
+                });
+                self.request("POST", &self.url("v3/kv/txn"), Some(&body.to_string()))
+            }
+            RegistryType::Consul => {
+                let Some(index) = self.consul_index(key, value)? else {
+                    return Ok(());
+                };
+                self.request("DELETE", &format!("{}?cas={}", self.consul_url(key), index), None)
+            }
+        }
+    }
+    
+    /// Modify index of a Consul `key` that holds `value`, or `None` when it
+    /// is missing or holds something else
+    fn consul_index(&self, key: &str, value: &str) -> Result<Option<u64>> {
+        let url = self.consul_url(key);
+        let timeout = self.config.timeout.to_string();
+        // Without -f a missing key is an empty 404 body rather than a failure
+        let output = self.runner.output("curl", &["-sS", "--max-time", timeout.as_str(), "-X", "GET", url.as_str()])
+            .map_err(|e| anyhow::anyhow!("Failed to run curl: {}", e))?;
+        if !output.status.success() {
+            anyhow::bail!("GET {} failed: {}", url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let Ok(entries) = serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout) else {
+            return Ok(None);
+        };
+        let Some(entry) = entries.first() else {
+            return Ok(None);
+        };
+        
+        let stored = entry.get("Value")
+            .and_then(|v| v.as_str())
+            .map(|v| encoding::decode(v, Alphabet::Standard))
+            .transpose()?;
+        if stored.as_deref() != Some(value.as_bytes()) {
+            return Ok(None);
+        }
+        entry.get("ModifyIndex")
+            .and_then(|i| i.as_u64())
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("Consul entry {} has no ModifyIndex", key))
+    }
+    
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.config.endpoint.trim_end_matches('/'), path)
+    }
+    
+    fn consul_url(&self, key: &str) -> String {
+        self.url(&format!("v1/kv/{}", key.trim_start_matches('/')))
+    }
+    
+    fn request(&self, method: &str, url: &str, body: Option<&str>) -> Result<()> {
+        let timeout = self.config.timeout.to_string();
+        let mut args = vec!["-sS", "-f", "--max-time", timeout.as_str(), "-X", method];
+        if let Some(body) = body {
+            args.extend(["--data-binary", body]);
+        }
+        args.push(url);
+        
+        let output = self.runner.output("curl", &args)
+            .map_err(|e| anyhow::anyhow!("Failed to run curl: {}", e))?;
+        if !output.status.success() {
+            anyhow::bail!("{} {} failed: {}", method, url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! Execution of the external commands the plugin drives
+//!
+//! [`VlanPlugin`](crate::plugin::VlanPlugin) runs its sysctl, firewall, `tc`
+//! and `ping` commands through a [`CommandRunner`], and every `ip` invocation
+//! when the iproute2 backend is used. [`SystemRunner`] spawns the real binaries;
+//! [`MockRunner`] records each call and answers from canned outputs, so the
+//! sequence of commands for a configuration can be asserted without root or
+//! a kernel that supports VLANs.
+
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+
+/// Runs external commands on behalf of the plugin
+pub trait CommandRunner: Send + Sync {
+    /// Run `program` with `args` to completion and capture its output
+    fn output(&self, program: &str, args: &[&str]) -> io::Result<Output>;
+    
+    /// Like [`output`](Self::output), writing `input` to the program's stdin
+    ///
+    /// Secrets go this way, since arguments are readable by any local user
+    /// in /proc.
+    fn output_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> io::Result<Output>;
+    
+    /// Called before commands are run inside `netns`
+    ///
+    /// Returns whether the calling thread has to actually switch into the
+    /// namespace for them to take effect.
+    fn enter_netns(&self, _netns: &str) -> bool {
+        true
+    }
+}
+
+/// Spawns the real binaries from PATH
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        Command::new(program).args(args).output()
+    }
+    
+    fn output_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> io::Result<Output> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        
+        // Dropping stdin closes it so the program sees the end of its input
+        let written = child.stdin.take().map_or(Ok(()), |mut stdin| stdin.write_all(input));
+        let output = child.wait_with_output()?;
+        written.map(|()| output)
+    }
+}
+
+/// Records every command and replies from canned outputs
+///
+/// Calls are recorded as the program and its arguments joined by spaces,
+/// with `netns <path>` marking each switch into a namespace. A call gets the
+/// output of the first [`respond`](MockRunner::respond) or
+/// [`fail`](MockRunner::fail) prefix it starts with, or empty successful
+/// output. Input written to stdin is recorded apart from the calls.
+#[derive(Debug, Default)]
+pub struct MockRunner {
+    calls: Mutex<Vec<String>>,
+    inputs: Mutex<Vec<String>>,
+    responses: Mutex<Vec<(String, Output)>>,
+}
+
+impl MockRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Reply to calls starting with `prefix` with `stdout` and exit `code`
+    pub fn respond(self, prefix: &str, code: i32, stdout: &str) -> Self {
+        let output = Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: if code == 0 { Vec::new() } else { b"mock failure".to_vec() },
+        };
+        self.responses.lock().unwrap().push((prefix.to_string(), output));
+        self
+    }
+    
+    /// Fail calls starting with `prefix` with exit `code` and `stderr`, for
+    /// callers that tell failures apart by what the program reports
+    pub fn fail(self, prefix: &str, code: i32, stderr: &str) -> Self {
+        let output = Output {
+            status: ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        self.responses.lock().unwrap().push((prefix.to_string(), output));
+        self
+    }
+    
+    /// Every call so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+    
+    /// Everything written to stdin so far, one entry per call
+    pub fn inputs(&self) -> Vec<String> {
+        self.inputs.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for MockRunner {
+    fn output(&self, program: &str, args: &[&str]) -> io::Result<Output> {
+        let call = std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+        self.calls.lock().unwrap().push(call.clone());
+        
+        let responses = self.responses.lock().unwrap();
+        Ok(responses.iter()
+            .find(|(prefix, _)| call.starts_with(prefix.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or_else(|| Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            }))
+    }
+    
+    fn output_with_input(&self, program: &str, args: &[&str], input: &[u8]) -> io::Result<Output> {
+        self.inputs.lock().unwrap().push(String::from_utf8_lossy(input).into_owned());
+        self.output(program, args)
+    }
+    
+    fn enter_netns(&self, netns: &str) -> bool {
+        self.calls.lock().unwrap().push(format!("netns {}", netns));
+        false
+    }
+}
@@ -0,0 +1,82 @@
+//! Traffic shaping for the container interface
+//!
+//! Egress (pod to network) is limited with a tbf qdisc on the interface.
+//! Ingress can't be shaped directly, so it is redirected to an ifb device
+//! with its own tbf qdisc. All of this runs inside the container namespace,
+//! so the ifb name only needs to be unique per pod. The ifb device is made
+//! through the plugin's [`NetBackend`]; `tc` runs through its [`CommandRunner`].
+
+use anyhow::{Context, Result};
+
+use crate::backend::{self, NetBackend};
+use crate::config::BandwidthConfig;
+use crate::runner::CommandRunner;
+
+/// Latency bound for the tbf queue
+const TBF_LATENCY: &str = "25ms";
+
+/// Name of the ifb device used for ingress shaping of `ifname`
+pub fn ifb_name(ifname: &str) -> String {
+    let mut name = format!("ifb-{}", ifname);
+    name.truncate(15);
+    name
+}
+
+/// Apply the bandwidth limits to `ifname` in the current namespace
+pub fn apply_bandwidth(
+    runner: &dyn CommandRunner,
+    backend: &dyn NetBackend,
+    ifname: &str,
+    bandwidth: &BandwidthConfig,
+) -> Result<()> {
+    if let (Some(rate), Some(burst)) = (bandwidth.egress_rate, bandwidth.egress_burst) {
+        add_tbf(runner, ifname, rate, burst)?;
+    }
+    
+    if let (Some(rate), Some(burst)) = (bandwidth.ingress_rate, bandwidth.ingress_burst) {
+        let ifb = ifb_name(ifname);
+        
+        backend.add_ifb(&ifb)
+            .with_context(|| format!("Failed to create ifb device {}", ifb))?;
+        backend.set_link_up(&ifb)
+            .with_context(|| format!("Failed to set ifb device {} up", ifb))?;
+        run(runner, "tc", &["qdisc", "add", "dev", ifname, "handle", "ffff:", "ingress"])?;
+        run(runner, "tc", &["filter", "add", "dev", ifname, "parent", "ffff:", "protocol", "all",
+                    "u32", "match", "u32", "0", "0",
+                    "action", "mirred", "egress", "redirect", "dev", &ifb])?;
+        add_tbf(runner, &ifb, rate, burst)?;
+    }
+    
+    Ok(())
+}
+
+/// Remove the ifb device created for `ifname`, if any
+pub fn remove_bandwidth(backend: &dyn NetBackend, ifname: &str) -> Result<()> {
+    let ifb = ifb_name(ifname);
+    match backend.delete_link(&ifb) {
+        Err(e) if backend::errno_of(&e) != Some(libc::ENODEV) => {
+            anyhow::bail!("Failed to delete ifb device {}: {:#}", ifb, e);
+        }
+        _ => Ok(()),
+    }
+}
+
+fn add_tbf(runner: &dyn CommandRunner, dev: &str, rate: u64, burst: u64) -> Result<()> {
+    // tc takes the burst in bytes, the CNI capability in bits
+    let rate = format!("{}bit", rate);
+    let burst = format!("{}b", (burst / 8).max(1));
+    run(runner, "tc", &["qdisc", "add", "dev", dev, "root", "tbf",
+                "rate", &rate, "burst", &burst, "latency", TBF_LATENCY])
+}
+
+fn run(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> Result<()> {
+    let output = runner.output(program, args)
+        .with_context(|| format!("Failed to execute {} {}", program, args.join(" ")))?;
+    
+    if !output.status.success() {
+        anyhow::bail!("{} {} failed: {}", 
+                     program, args.join(" "), String::from_utf8_lossy(&output.stderr));
+    }
+    
+    Ok(())
+}
@@ -0,0 +1,437 @@
+use anyhow::{Context, Result};
+use nix::fcntl::{flock, FlockArg};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::NetConf;
+use crate::firewall::FirewallState;
+use crate::stats::StatsBaseline;
+use crate::types::Result as CniResult;
+
+/// File holding persisted MAC reservations
+const MACS_FILE: &str = "macs.json";
+
+/// File holding the live attachments
+const ATTACHMENTS_FILE: &str = "attachments.json";
+
+/// File mapping host link names to the network that owns them
+const HOST_LINKS_FILE: &str = "host-links.json";
+
+/// File mapping each QinQ outer link to the interfaces stacked on it
+const OUTER_LINKS_FILE: &str = "outer-links.json";
+
+/// Claim on a link socni creates on the host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostLinkClaim {
+    /// Network that owns the link
+    pub network: String,
+    /// Container whose ADD created the link; none for a link every pod of
+    /// the network shares, like the outer link of a QinQ VLAN
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_id: Option<String>,
+}
+
+/// Persisted MAC address for a pod interface
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacReservation {
+    /// Network name from the NetConf
+    pub network: String,
+    /// Stable pod identity (`namespace/name`)
+    pub identity: String,
+    /// Container interface name
+    pub ifname: String,
+    /// Reserved MAC address
+    pub mac: String,
+    /// Unix timestamp of the last update
+    pub updated: u64,
+}
+
+/// A pod interface added by ADD and not yet removed by DEL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    /// Network configuration the interface was added with
+    pub config: NetConf,
+    pub container_id: String,
+    /// Network namespace path of the pod
+    pub netns: String,
+    /// Container interface name
+    pub ifname: String,
+    /// Tenant whose VLAN access the interface was added under
+    pub tenant: String,
+    /// Unix timestamp of the ADD
+    pub added: u64,
+}
+
+/// Exclusive lock on a state file, released on drop
+pub struct StateLock {
+    _file: File,
+}
+
+/// On-disk plugin state under `SocniConfig.state_dir`
+///
+/// Every CNI invocation is a separate process, so anything that must survive
+/// between invocations is kept here as JSON files guarded by `flock`.
+pub struct StateStore {
+    dir: PathBuf,
+}
+
+impl StateStore {
+    /// Create a store rooted at the given directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    
+    /// Root directory of the store
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    
+    /// Take an exclusive lock named `name`, blocking until it is available
+    pub fn lock(&self, name: &str) -> Result<StateLock> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create state directory {}", self.dir.display()))?;
+        
+        let path = self.dir.join(format!("{}.lock", name));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+        
+        flock(file.as_raw_fd(), FlockArg::LockExclusive)
+            .with_context(|| format!("Failed to lock {}", path.display()))?;
+        
+        Ok(StateLock { _file: file })
+    }
+    
+    /// Read a JSON state file, returning the default value if it doesn't exist
+    pub fn read_json<T: DeserializeOwned + Default>(&self, name: &str) -> Result<T> {
+        let path = self.dir.join(name);
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)
+                .with_context(|| format!("Failed to parse state file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read state file {}", path.display())),
+        }
+    }
+    
+    /// Atomically replace a JSON state file
+    pub fn write_json<T: Serialize>(&self, name: &str, value: &T) -> Result<()> {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create state directory {}", parent.display()))?;
+        }
+        
+        let tmp = path.with_extension("tmp");
+        let json = serde_json::to_vec_pretty(value)?;
+        fs::write(&tmp, json)
+            .with_context(|| format!("Failed to write state file {}", tmp.display()))?;
+        fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to replace state file {}", path.display()))?;
+        
+        Ok(())
+    }
+    
+    /// All MAC reservations, keyed by `network/identity/ifname`
+    pub fn mac_reservations(&self) -> Result<HashMap<String, MacReservation>> {
+        let _lock = self.lock("macs")?;
+        self.read_json(MACS_FILE)
+    }
+    
+    /// Look up the reserved MAC for a pod interface
+    pub fn reserved_mac(&self, network: &str, identity: &str, ifname: &str) -> Result<Option<String>> {
+        let reservations = self.mac_reservations()?;
+        Ok(reservations.get(&mac_key(network, identity, ifname)).map(|r| r.mac.clone()))
+    }
+    
+    /// Record the MAC used by a pod interface
+    pub fn reserve_mac(&self, network: &str, identity: &str, ifname: &str, mac: &str) -> Result<()> {
+        let _lock = self.lock("macs")?;
+        let mut reservations: HashMap<String, MacReservation> = self.read_json(MACS_FILE)?;
+        
+        reservations.insert(mac_key(network, identity, ifname), MacReservation {
+            network: network.to_string(),
+            identity: identity.to_string(),
+            ifname: ifname.to_string(),
+            mac: mac.to_string(),
+            updated: now_secs(),
+        });
+        
+        self.write_json(MACS_FILE, &reservations)
+    }
+    
+    /// Remove reservations for one pod identity (or all of them), returning how many were removed
+    pub fn clear_macs(&self, identity: Option<&str>) -> Result<usize> {
+        let _lock = self.lock("macs")?;
+        let mut reservations: HashMap<String, MacReservation> = self.read_json(MACS_FILE)?;
+        
+        let before = reservations.len();
+        reservations.retain(|_, r| identity.map(|id| r.identity != id).unwrap_or(false));
+        let removed = before - reservations.len();
+        
+        self.write_json(MACS_FILE, &reservations)?;
+        Ok(removed)
+    }
+}
+
+impl StateStore {
+    /// Persist the result returned by ADD so CHECK can compare against it
+    pub fn save_result(&self, network: &str, container_id: &str, ifname: &str, result: &CniResult) -> Result<()> {
+        self.write_json(&result_file(network, container_id, ifname), result)
+    }
+    
+    /// Load the result persisted by ADD, if any
+    pub fn load_result(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<CniResult>> {
+        self.read_json(&result_file(network, container_id, ifname))
+    }
+    
+    /// Remove the persisted result on DEL
+    pub fn remove_result(&self, network: &str, container_id: &str, ifname: &str) -> Result<()> {
+        let path = self.dir.join(result_file(network, container_id, ifname));
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove state file {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StateStore {
+    /// Every live attachment, keyed by `network/container_id/ifname`
+    pub fn attachments(&self) -> Result<HashMap<String, Attachment>> {
+        let _lock = self.lock("attachments")?;
+        self.read_json(ATTACHMENTS_FILE)
+    }
+    
+    /// Record an interface added by ADD
+    pub fn record_attachment(&self, attachment: Attachment) -> Result<()> {
+        let _lock = self.lock("attachments")?;
+        let mut attachments: HashMap<String, Attachment> = self.read_json(ATTACHMENTS_FILE)?;
+        
+        let key = attachment_key(&attachment.config.name, &attachment.container_id, &attachment.ifname);
+        attachments.insert(key, attachment);
+        self.write_json(ATTACHMENTS_FILE, &attachments)
+    }
+    
+    /// Forget an interface on DEL
+    pub fn remove_attachment(&self, network: &str, container_id: &str, ifname: &str) -> Result<()> {
+        let _lock = self.lock("attachments")?;
+        let mut attachments: HashMap<String, Attachment> = self.read_json(ATTACHMENTS_FILE)?;
+        
+        if attachments.remove(&attachment_key(network, container_id, ifname)).is_some() {
+            self.write_json(ATTACHMENTS_FILE, &attachments)?;
+        }
+        Ok(())
+    }
+    
+    /// Live interfaces on `vlan`, ordered by network, container and interface
+    pub fn vlan_references(&self, vlan: u16) -> Result<Vec<Attachment>> {
+        let mut references: Vec<(String, Attachment)> = self.attachments()?
+            .into_iter()
+            .filter(|(_, a)| a.config.vlan == vlan)
+            .collect();
+        references.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(references.into_iter().map(|(_, a)| a).collect())
+    }
+}
+
+impl StateStore {
+    /// Claim on every host link socni has made, keyed by link name
+    pub fn host_links(&self) -> Result<HashMap<String, HostLinkClaim>> {
+        let _lock = self.lock("host-links")?;
+        self.read_json(HOST_LINKS_FILE)
+    }
+    
+    /// Record `network` as the owner of the host link `name`
+    ///
+    /// Claiming a link the network already owns succeeds and hands it to
+    /// `container_id`; a link owned by another network is a conflict, so
+    /// distinct conflists that pick the same master and VLAN never silently
+    /// share an interface.
+    pub fn claim_host_link(&self, name: &str, network: &str, container_id: Option<&str>) -> Result<()> {
+        let _lock = self.lock("host-links")?;
+        let mut links: HashMap<String, HostLinkClaim> = self.read_json(HOST_LINKS_FILE)?;
+        
+        let claim = HostLinkClaim {
+            network: network.to_string(),
+            container_id: container_id.map(str::to_string),
+        };
+        match links.get(name) {
+            Some(existing) if *existing == claim => Ok(()),
+            Some(existing) if existing.network != network => anyhow::bail!(
+                "Host link {} belongs to network {}; refusing to use it for network {}",
+                name, existing.network, network
+            ),
+            _ => {
+                links.insert(name.to_string(), claim);
+                self.write_json(HOST_LINKS_FILE, &links)
+            }
+        }
+    }
+    
+    /// Names of the links in the host namespace that socni made, from the
+    /// host link claims and the live attachments
+    pub fn recorded_host_links(&self) -> Result<BTreeSet<String>> {
+        let mut names: BTreeSet<String> = self.host_links()?
+            .into_keys()
+            .filter(|name| !name.contains('/'))
+            .collect();
+        for attachment in self.attachments()?.into_values().filter(|a| a.config.master_netns.is_none()) {
+            names.insert(attachment.config.host_link_name());
+            names.extend(attachment.config.outer_link_name());
+        }
+        Ok(names)
+    }
+    
+    /// Forget the host link `name` if `network` owns it
+    pub fn release_host_link(&self, name: &str, network: &str) -> Result<()> {
+        let _lock = self.lock("host-links")?;
+        let mut links: HashMap<String, HostLinkClaim> = self.read_json(HOST_LINKS_FILE)?;
+        
+        if links.get(name).is_some_and(|claim| claim.network == network) {
+            links.remove(name);
+            self.write_json(HOST_LINKS_FILE, &links)?;
+        }
+        Ok(())
+    }
+    
+    /// Exclusive lock on the outer link `name`
+    ///
+    /// Held while an ADD records itself as a user and creates the link, and
+    /// while a DEL drops its use and deletes the link once unused, so a link
+    /// is never deleted under a pod being stacked on it.
+    pub fn lock_outer_link(&self, name: &str) -> Result<StateLock> {
+        self.lock(&format!("outer-{}", name.replace('/', "_")))
+    }
+    
+    /// Interfaces recorded as stacked on the outer link `name`
+    pub fn outer_link_users(&self, name: &str) -> Result<BTreeSet<String>> {
+        let _lock = self.lock("outer-links")?;
+        let mut links: HashMap<String, BTreeSet<String>> = self.read_json(OUTER_LINKS_FILE)?;
+        Ok(links.remove(name).unwrap_or_default())
+    }
+    
+    /// Record an interface as stacked on the outer link `name`
+    pub fn add_outer_link_user(&self, name: &str, network: &str, container_id: &str, ifname: &str) -> Result<()> {
+        let _lock = self.lock("outer-links")?;
+        let mut links: HashMap<String, BTreeSet<String>> = self.read_json(OUTER_LINKS_FILE)?;
+        
+        if links.entry(name.to_string()).or_default().insert(attachment_key(network, container_id, ifname)) {
+            self.write_json(OUTER_LINKS_FILE, &links)?;
+        }
+        Ok(())
+    }
+    
+    /// Forget an interface stacked on the outer link `name`, returning how
+    /// many are left
+    pub fn remove_outer_link_user(&self, name: &str, network: &str, container_id: &str, ifname: &str) -> Result<usize> {
+        let _lock = self.lock("outer-links")?;
+        let mut links: HashMap<String, BTreeSet<String>> = self.read_json(OUTER_LINKS_FILE)?;
+        
+        let Some(users) = links.get_mut(name) else {
+            return Ok(0);
+        };
+        users.remove(&attachment_key(network, container_id, ifname));
+        let left = users.len();
+        if left == 0 {
+            links.remove(name);
+        }
+        self.write_json(OUTER_LINKS_FILE, &links)?;
+        Ok(left)
+    }
+}
+
+impl StateStore {
+    /// Persist the firewall rules installed by ADD
+    pub fn save_firewall(&self, network: &str, container_id: &str, ifname: &str, state: &FirewallState) -> Result<()> {
+        self.write_json(&firewall_file(network, container_id, ifname), state)
+    }
+    
+    /// Load the firewall rules installed by ADD, if any
+    pub fn load_firewall(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<FirewallState>> {
+        self.read_json(&firewall_file(network, container_id, ifname))
+    }
+    
+    /// Forget the firewall rules on DEL
+    pub fn remove_firewall(&self, network: &str, container_id: &str, ifname: &str) -> Result<()> {
+        let path = self.dir.join(firewall_file(network, container_id, ifname));
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove state file {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl StateStore {
+    /// Persist the interface counters recorded by ADD
+    pub fn save_baseline(&self, network: &str, container_id: &str, ifname: &str, baseline: &StatsBaseline) -> Result<()> {
+        self.write_json(&baseline_file(network, container_id, ifname), baseline)
+    }
+    
+    /// Load the interface counters recorded by ADD, if any
+    pub fn load_baseline(&self, network: &str, container_id: &str, ifname: &str) -> Result<Option<StatsBaseline>> {
+        self.read_json(&baseline_file(network, container_id, ifname))
+    }
+    
+    /// Forget the interface counters on DEL
+    pub fn remove_baseline(&self, network: &str, container_id: &str, ifname: &str) -> Result<()> {
+        let path = self.dir.join(baseline_file(network, container_id, ifname));
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove state file {}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Stable pod identity (`namespace/name`) from the Kubernetes CNI_ARGS keys
+pub fn pod_identity(args: &HashMap<String, String>) -> Option<String> {
+    match (args.get("K8S_POD_NAMESPACE"), args.get("K8S_POD_NAME")) {
+        (Some(namespace), Some(name)) if !namespace.is_empty() && !name.is_empty() => {
+            Some(format!("{}/{}", namespace, name))
+        }
+        _ => None,
+    }
+}
+
+/// Current time as Unix seconds
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn mac_key(network: &str, identity: &str, ifname: &str) -> String {
+    format!("{}/{}/{}", network, identity, ifname)
+}
+
+fn attachment_key(network: &str, container_id: &str, ifname: &str) -> String {
+    format!("{}/{}/{}", network, container_id, ifname)
+}
+
+fn result_file(network: &str, container_id: &str, ifname: &str) -> String {
+    format!("results/{}/{}-{}.json", network, container_id, ifname)
+}
+
+fn firewall_file(network: &str, container_id: &str, ifname: &str) -> String {
+    format!("firewall/{}/{}-{}.json", network, container_id, ifname)
+}
+
+fn baseline_file(network: &str, container_id: &str, ifname: &str) -> String {
+    format!("baselines/{}/{}-{}.json", network, container_id, ifname)
+}
@@ -0,0 +1,64 @@
+//! Traffic counters of a pod interface
+//!
+//! Counters come from the `stats64` object printed by `ip -s -j link show`.
+//! ADD records them as a baseline so traffic can be reported per attachment
+//! instead of over the lifetime of the link.
+
+use serde::{Deserialize, Serialize};
+
+/// Byte and packet counters of a link
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkStats {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_dropped: u64,
+}
+
+impl LinkStats {
+    /// Counters of one link from `ip -s -j link show`, if it printed any
+    pub fn from_link(link: &serde_json::Value) -> Option<Self> {
+        let stats = link.get("stats64").or_else(|| link.get("stats"))?;
+        let counter = |dir: &str, name: &str| {
+            stats.get(dir).and_then(|d| d.get(name)).and_then(|v| v.as_u64()).unwrap_or(0)
+        };
+        Some(Self {
+            rx_bytes: counter("rx", "bytes"),
+            rx_packets: counter("rx", "packets"),
+            rx_dropped: counter("rx", "dropped"),
+            tx_bytes: counter("tx", "bytes"),
+            tx_packets: counter("tx", "packets"),
+            tx_dropped: counter("tx", "dropped"),
+        })
+    }
+    
+    /// Traffic since `baseline`
+    ///
+    /// Counters lower than the baseline mean the link was recreated, so the
+    /// current counters are all traffic since then.
+    pub fn since(&self, baseline: &LinkStats) -> LinkStats {
+        let reset = self.rx_bytes < baseline.rx_bytes || self.tx_bytes < baseline.tx_bytes
+            || self.rx_packets < baseline.rx_packets || self.tx_packets < baseline.tx_packets;
+        if reset {
+            return *self;
+        }
+        LinkStats {
+            rx_bytes: self.rx_bytes - baseline.rx_bytes,
+            rx_packets: self.rx_packets - baseline.rx_packets,
+            rx_dropped: self.rx_dropped.saturating_sub(baseline.rx_dropped),
+            tx_bytes: self.tx_bytes - baseline.tx_bytes,
+            tx_packets: self.tx_packets - baseline.tx_packets,
+            tx_dropped: self.tx_dropped.saturating_sub(baseline.tx_dropped),
+        }
+    }
+}
+
+/// Counters recorded when ADD finished configuring the interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsBaseline {
+    pub stats: LinkStats,
+    /// Unix timestamp the counters were read at
+    pub taken: u64,
+}
@@ -0,0 +1,37 @@
+//! Per-interface sysctls under /proc/sys/net
+//!
+//! Network sysctls resolve against the namespace of the calling thread, so
+//! these must be called from inside `in_netns` to affect the container.
+//! They run `sysctl` through the plugin's [`CommandRunner`], with the key
+//! written in its `/` form so interface names containing dots stay intact.
+
+use anyhow::{Context, Result};
+
+use crate::runner::CommandRunner;
+
+fn iface_key(family: &str, ifname: &str, key: &str) -> String {
+    format!("net/{}/conf/{}/{}", family, ifname, key)
+}
+
+/// Set `net.<family>.conf.<ifname>.<key>`
+pub fn set_iface(runner: &dyn CommandRunner, family: &str, ifname: &str, key: &str, value: &str) -> Result<()> {
+    let assignment = format!("{}={}", iface_key(family, ifname, key), value);
+    let output = runner.output("sysctl", &["-q", "-w", &assignment])
+        .context("Failed to execute sysctl")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to set net.{}.conf.{}.{}={}: {}",
+                      family, ifname, key, value, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Read `net.<family>.conf.<ifname>.<key>`
+pub fn get_iface(runner: &dyn CommandRunner, family: &str, ifname: &str, key: &str) -> Result<String> {
+    let output = runner.output("sysctl", &["-n", &iface_key(family, ifname, key)])
+        .context("Failed to execute sysctl")?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to read net.{}.conf.{}.{}: {}",
+                      family, ifname, key, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,217 @@
+//! Signed workload tokens attesting a pod's tenant
+//!
+//! An admission webhook puts a JWT in the pod's CNI_ARGS as `SOCNI_TOKEN`.
+//! When the node has a token public key configured, ADD verifies the token's
+//! signature and lifetime and uses its `tenant` claim for the Aranya access
+//! check, instead of trusting `ARANYA_TENANT_ID` or a label.
+//!
+//! A token is only good for the pod it was issued to: its `aud` must name the
+//! pod's namespace and its `sub` the pod's name or UID, as given in CNI_ARGS,
+//! so a token copied from one pod's spec doesn't attest another.
+//!
+//! The key is a PEM (or DER) SubjectPublicKeyInfo for RSA, P-256 or Ed25519,
+//! verifying RS256, ES256 and EdDSA tokens respectively.
+
+use anyhow::{Context, Result};
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::encoding::{self, Alphabet};
+
+/// CNI_ARGS key carrying the token
+pub const TOKEN_ARG: &str = "SOCNI_TOKEN";
+
+const OID_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+
+/// Claims socni reads from a token
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    /// Tenant the workload belongs to
+    pub tenant: Option<String>,
+    /// Expiry, in seconds since the epoch
+    pub exp: Option<u64>,
+    /// Not valid before, in seconds since the epoch
+    pub nbf: Option<u64>,
+    /// Namespaces the token is for; a single string or a list
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub aud: Vec<String>,
+    /// Name or UID of the pod the token is for
+    pub sub: Option<String>,
+}
+
+/// `aud` as RFC 7519 allows it: one string or a list of them
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(aud) => vec![aud],
+        OneOrMany::Many(aud) => aud,
+    })
+}
+
+/// Pod a token has to be issued to, from CNI_ARGS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSubject {
+    pub namespace: String,
+    pub name: String,
+    pub uid: Option<String>,
+}
+
+impl TokenSubject {
+    /// Pod named by `K8S_POD_NAMESPACE`, `K8S_POD_NAME` and, if present, `K8S_POD_UID`
+    pub fn from_cni_args(cni_args: &HashMap<String, String>) -> Result<Self> {
+        match (cni_args.get("K8S_POD_NAMESPACE"), cni_args.get("K8S_POD_NAME")) {
+            (Some(namespace), Some(name)) => Ok(Self {
+                namespace: namespace.clone(),
+                name: name.clone(),
+                uid: cni_args.get("K8S_POD_UID").cloned(),
+            }),
+            _ => anyhow::bail!("CNI_ARGS has no K8S_POD_NAMESPACE and K8S_POD_NAME to check the token against"),
+        }
+    }
+}
+
+/// Public key tokens are verified against
+pub struct TokenVerifier {
+    /// JWT `alg` the key verifies
+    alg: &'static str,
+    algorithm: &'static dyn VerificationAlgorithm,
+    key: Vec<u8>,
+}
+
+impl TokenVerifier {
+    /// Load a PEM or DER SubjectPublicKeyInfo from `path`
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read(path)
+            .with_context(|| format!("Failed to read token public key {}", path.display()))?;
+        let der = match std::str::from_utf8(&contents) {
+            Ok(text) if text.trim_start().starts_with("-----BEGIN") => {
+                let body: String = text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.starts_with("-----"))
+                    .collect();
+                encoding::decode(body.trim(), Alphabet::Standard)
+                    .with_context(|| format!("Token public key {} is not valid PEM", path.display()))?
+            }
+            _ => contents,
+        };
+        Self::from_der(&der)
+            .with_context(|| format!("Unsupported token public key {}", path.display()))
+    }
+    
+    /// Key from a DER SubjectPublicKeyInfo
+    pub fn from_der(spki: &[u8]) -> Result<Self> {
+        let (spki, _) = der_element(spki, 0x30)?;
+        let (algorithm_id, rest) = der_element(spki, 0x30)?;
+        let (bits, _) = der_element(rest, 0x03)?;
+        let (oid, parameters) = der_element(algorithm_id, 0x06)?;
+        let key = match bits.split_first() {
+            Some((0, key)) => key.to_vec(),
+            _ => anyhow::bail!("Public key bit string is malformed"),
+        };
+        
+        let (alg, algorithm): (_, &'static dyn VerificationAlgorithm) = match oid {
+            OID_RSA => ("RS256", &signature::RSA_PKCS1_2048_8192_SHA256),
+            OID_EC => match der_element(parameters, 0x06) {
+                Ok((OID_P256, _)) => ("ES256", &signature::ECDSA_P256_SHA256_FIXED),
+                _ => anyhow::bail!("Only P-256 EC keys are supported"),
+            },
+            OID_ED25519 => ("EdDSA", &signature::ED25519),
+            _ => anyhow::bail!("Public key algorithm is not RSA, P-256 or Ed25519"),
+        };
+        Ok(Self { alg, algorithm, key })
+    }
+    
+    /// Verify `token` at `now` (seconds since the epoch) and return its claims
+    ///
+    /// The token must be signed with this key's algorithm, carry an `exp`
+    /// that hasn't passed and be issued to `pod`.
+    pub fn verify(&self, token: &str, now: u64, pod: &TokenSubject) -> Result<Claims> {
+        let mut parts = token.split('.');
+        let (header, payload, sig) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(sig), None) => (header, payload, sig),
+            _ => anyhow::bail!("Token is not a signed JWT"),
+        };
+        let signed = &token[..header.len() + 1 + payload.len()];
+        
+        let header: serde_json::Value = serde_json::from_slice(&encoding::decode(header, Alphabet::UrlSafe)?)
+            .context("Token header is not JSON")?;
+        let alg = header.get("alg").and_then(|a| a.as_str()).unwrap_or("none");
+        if alg != self.alg {
+            anyhow::bail!("Token is signed with {}, but the configured key verifies {}", alg, self.alg);
+        }
+        
+        UnparsedPublicKey::new(self.algorithm, &self.key)
+            .verify(signed.as_bytes(), &encoding::decode(sig, Alphabet::UrlSafe)?)
+            .map_err(|_| anyhow::anyhow!("Token signature is invalid"))?;
+        
+        let claims: Claims = serde_json::from_slice(&encoding::decode(payload, Alphabet::UrlSafe)?)
+            .context("Token claims are not valid")?;
+        match claims.exp {
+            Some(exp) if exp <= now => anyhow::bail!("Token expired at {}", exp),
+            Some(_) => {}
+            None => anyhow::bail!("Token has no expiry"),
+        }
+        if let Some(nbf) = claims.nbf.filter(|&nbf| nbf > now) {
+            anyhow::bail!("Token is not valid before {}", nbf);
+        }
+        
+        if claims.aud.is_empty() {
+            anyhow::bail!("Token has no audience");
+        }
+        if !claims.aud.contains(&pod.namespace) {
+            anyhow::bail!("Token is for namespace {}, not {}", claims.aud.join(", "), pod.namespace);
+        }
+        match claims.sub.as_deref() {
+            None => anyhow::bail!("Token has no subject"),
+            Some(sub) if sub == pod.name || Some(sub) == pod.uid.as_deref() => {}
+            Some(sub) => anyhow::bail!("Token is for pod {}, not {}/{}", sub, pod.namespace, pod.name),
+        }
+        Ok(claims)
+    }
+    
+    /// Tenant attested by `token` for `pod`
+    pub fn tenant(&self, token: &str, now: u64, pod: &TokenSubject) -> Result<String> {
+        self.verify(token, now, pod)?
+            .tenant
+            .filter(|tenant| !tenant.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Token has no tenant claim"))
+    }
+}
+
+/// Contents and remainder of the DER element with `tag` at the start of `input`
+fn der_element(input: &[u8], tag: u8) -> Result<(&[u8], &[u8])> {
+    let (&found, rest) = input.split_first()
+        .ok_or_else(|| anyhow::anyhow!("Public key is truncated"))?;
+    if found != tag {
+        anyhow::bail!("Public key has tag {:#04x} where {:#04x} was expected", found, tag);
+    }
+    let (&first, rest) = rest.split_first()
+        .ok_or_else(|| anyhow::anyhow!("Public key is truncated"))?;
+    let (len, rest) = match first {
+        0..=0x7f => (first as usize, rest),
+        0x81..=0x84 => {
+            let count = (first & 0x7f) as usize;
+            if rest.len() < count {
+                anyhow::bail!("Public key is truncated");
+            }
+            let len = rest[..count].iter().fold(0usize, |len, &b| len << 8 | b as usize);
+            (len, &rest[count..])
+        }
+        _ => anyhow::bail!("Public key has an unsupported length encoding"),
+    };
+    if rest.len() < len {
+        anyhow::bail!("Public key is truncated");
+    }
+    Ok(rest.split_at(len))
+}
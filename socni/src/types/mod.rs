@@ -32,6 +32,10 @@ pub struct Result {
     pub dns: Option<DNS>,
     /// Routes to configure
     pub routes: Option<Vec<Route>>,
+    /// Fields beyond the CNI spec; socni's own are kept under
+    /// [`SOCNI_EXTENSION`]
+    #[serde(flatten, default, skip_serializing_if = "serde_json::Map::is_empty")]
+    pub extensions: serde_json::Map<String, serde_json::Value>,
 }
 
 /// Interface information
@@ -43,6 +47,9 @@ pub struct Interface {
     pub mac: Option<String>,
     /// Sandbox path (network namespace)
     pub sandbox: Option<String>,
+    /// Interface MTU
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtu: Option<u32>,
 }
 
 /// IP configuration
@@ -74,6 +81,9 @@ pub struct Route {
     pub dst: String,
     /// Gateway for this route
     pub gw: Option<String>,
+    /// Route metric
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u32>,
 }
 
 impl Result {
@@ -85,6 +95,7 @@ impl Result {
             ips: None,
             dns: None,
             routes: None,
+            extensions: serde_json::Map::new(),
         }
     }
     
@@ -126,10 +137,151 @@ impl Result {
         self.dns = Some(dns);
     }
     
+    /// Set `key` in socni's extension block
+    pub fn set_socni(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        if let Some(socni) = self.socni_mut() {
+            socni.insert(key.to_string(), value.into());
+        }
+    }
+    
+    /// Field `key` of socni's extension block
+    pub fn socni(&self, key: &str) -> Option<&serde_json::Value> {
+        self.extensions.get(SOCNI_EXTENSION).and_then(|socni| socni.get(key))
+    }
+    
+    /// Record a non-fatal issue under `socni.warnings`
+    pub fn add_warning(&mut self, warning: impl Into<String>) {
+        if let Some(socni) = self.socni_mut() {
+            let warnings = socni.entry("warnings").or_insert_with(|| serde_json::json!([]));
+            if let Some(warnings) = warnings.as_array_mut() {
+                warnings.push(warning.into().into());
+            }
+        }
+    }
+    
+    fn socni_mut(&mut self) -> Option<&mut serde_json::Map<String, serde_json::Value>> {
+        self.extensions.entry(SOCNI_EXTENSION)
+            .or_insert_with(|| serde_json::json!({}))
+            .as_object_mut()
+    }
+    
+    /// MTU the pod's interface was given
+    ///
+    /// Taken from `socni.mtu`, which every version carries, falling back to
+    /// the first sandbox interface for results recorded without it.
+    pub fn mtu(&self) -> Option<u32> {
+        self.socni("mtu")
+            .and_then(|m| m.as_u64())
+            .map(|m| m as u32)
+            .or_else(|| self.interfaces.iter().flatten().find(|i| i.sandbox.is_some()).and_then(|i| i.mtu))
+    }
+    
+    /// Non-fatal issues recorded with [`add_warning`](Self::add_warning)
+    pub fn warnings(&self) -> Vec<String> {
+        self.socni("warnings")
+            .and_then(|w| w.as_array())
+            .map(|w| w.iter().filter_map(|w| w.as_str().map(String::from)).collect())
+            .unwrap_or_default()
+    }
+    
+    /// The result in the shape its `cniVersion` defines
+    ///
+    /// Before 1.0.0 every IP also names its family in `version` (`"4"` or
+    /// `"6"`), which 0.3.x and 0.4.0 consumers require. An interface's `mtu`
+    /// only exists from 1.1.0 and is left out before it.
+    pub fn to_json(&self) -> anyhow::Result<serde_json::Value> {
+        let mut json = serde_json::to_value(self)?;
+        if !has_interface_mtu(&self.cni_version) {
+            for interface in json.get_mut("interfaces").and_then(|i| i.as_array_mut()).into_iter().flatten() {
+                if let Some(interface) = interface.as_object_mut() {
+                    interface.remove("mtu");
+                }
+            }
+        }
+        if is_legacy_version(&self.cni_version) {
+            for ip in json.get_mut("ips").and_then(|ips| ips.as_array_mut()).into_iter().flatten() {
+                let family = ip.get("address")
+                    .and_then(|a| a.as_str())
+                    .map_or("4", |a| if a.contains(':') { "6" } else { "4" });
+                if let Some(ip) = ip.as_object_mut() {
+                    ip.insert("version".to_string(), family.into());
+                }
+            }
+        }
+        Ok(json)
+    }
+    
     /// Print result as JSON
     pub fn print(&self) -> anyhow::Result<()> {
-        let json = serde_json::to_string_pretty(self)?;
+        let json = serde_json::to_string_pretty(&self.to_json()?)?;
         println!("{}", json);
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Whether `cni_version` predates 1.0.0 (0.3.x and 0.4.0)
+pub fn is_legacy_version(cni_version: &str) -> bool {
+    cni_version.starts_with("0.")
+}
+
+/// Whether `cni_version` results carry an interface's `mtu` (1.1.0 and later)
+pub fn has_interface_mtu(cni_version: &str) -> bool {
+    let mut parts = cni_version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+    (major, minor) >= (1, 1)
+}
+
+/// Result extension key socni's own fields are reported under
+pub const SOCNI_EXTENSION: &str = "socni";
+
+/// CNI error code asking the runtime to retry the command later
+pub const CNI_ERR_TRY_AGAIN_LATER: u32 = 11;
+
+/// CNI error code for failures without a more specific code
+pub const CNI_ERR_GENERIC: u32 = 100;
+
+/// Error reported to the runtime with a specific CNI error code
+#[derive(Debug)]
+pub struct CniError {
+    pub code: u32,
+    pub msg: String,
+}
+
+impl CniError {
+    /// A transient failure the runtime should back off and retry
+    pub fn try_again_later(msg: impl Into<String>) -> Self {
+        Self { code: CNI_ERR_TRY_AGAIN_LATER, msg: msg.into() }
+    }
+    
+    /// CNI error code for `err`, taken from the first [`CniError`] in its chain
+    pub fn code_of(err: &anyhow::Error) -> u32 {
+        err.chain()
+            .find_map(|e| e.downcast_ref::<CniError>())
+            .map_or(CNI_ERR_GENERIC, |e| e.code)
+    }
+    
+    /// Error result reporting `err` in the shape `cni_version` defines
+    ///
+    /// 0.3.x has no code for a transient failure, so it is reported as generic.
+    pub fn to_json(err: &anyhow::Error, cni_version: &str) -> serde_json::Value {
+        let mut code = Self::code_of(err);
+        if code == CNI_ERR_TRY_AGAIN_LATER && cni_version.starts_with("0.3.") {
+            code = CNI_ERR_GENERIC;
+        }
+        serde_json::json!({
+            "cniVersion": cni_version,
+            "code": code,
+            "msg": err.to_string(),
+            "details": format!("{:#}", err),
+        })
+    }
+}
+
+impl std::fmt::Display for CniError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl std::error::Error for CniError {}
@@ -0,0 +1,218 @@
+//! Comparison of a live interface against the result recorded by ADD
+//!
+//! Inputs are the JSON documents printed by `ip -d -j addr show dev <if>`
+//! and `ip -j route show dev <if>` inside the container namespace.
+
+use ipnetwork::IpNetwork;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::config::Neighbor;
+use crate::types::Result as CniResult;
+
+/// Outcome of one CHECK verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckItem {
+    /// What was verified (`interface`, `vlan`, `addresses`, ...)
+    pub name: &'static str,
+    /// Why the verification failed, or `None` if it passed
+    pub error: Option<String>,
+}
+
+impl CheckItem {
+    pub fn new(name: &'static str, error: Option<String>) -> Self {
+        Self { name, error }
+    }
+    
+    pub fn pass(name: &'static str) -> Self {
+        Self::new(name, None)
+    }
+    
+    pub fn fail(name: &'static str, error: String) -> Self {
+        Self::new(name, Some(error))
+    }
+}
+
+impl fmt::Display for CheckItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            None => write!(f, "{:<10} ok", self.name),
+            Some(error) => write!(f, "{:<10} FAIL: {}", self.name, error),
+        }
+    }
+}
+
+/// The first failed item as an error, which is what CHECK reports
+pub fn first_failure(items: &[CheckItem]) -> anyhow::Result<()> {
+    match items.iter().find_map(|item| item.error.as_ref()) {
+        Some(error) => Err(anyhow::anyhow!("{}", error)),
+        None => Ok(()),
+    }
+}
+
+/// List every difference between the expected result and the live interface
+pub fn diff_result(
+    expected: &CniResult,
+    ifname: &str,
+    link: &serde_json::Value,
+    routes: &[serde_json::Value],
+) -> Vec<String> {
+    let mut issues = Vec::new();
+    
+    // Interface identity
+    let index = expected.interfaces.iter()
+        .flatten()
+        .position(|i| i.name == ifname && i.sandbox.is_some());
+    let iface = index.and_then(|i| expected.interfaces.as_ref().map(|ifaces| &ifaces[i]));
+    
+    if let Some(iface) = iface {
+        if let Some(mac) = &iface.mac {
+            let live = link.get("address").and_then(|a| a.as_str()).unwrap_or("");
+            if !live.eq_ignore_ascii_case(mac) {
+                issues.push(format!("mac: expected {}, found {}", mac, live));
+            }
+        }
+        
+        if let Some(mtu) = iface.mtu {
+            let live = link.get("mtu").and_then(|m| m.as_u64());
+            if live != Some(mtu as u64) {
+                issues.push(format!("mtu: expected {}, found {}", 
+                                   mtu, live.map(|m| m.to_string()).unwrap_or_else(|| "none".into())));
+            }
+        }
+    } else {
+        issues.push(format!("result has no sandbox interface named {}", ifname));
+    }
+    
+    // Addresses must match exactly; ips of other interfaces in the result are ignored
+    let expected_addrs: BTreeSet<String> = expected.ips.iter()
+        .flatten()
+        .filter(|ip| ip.interface.is_none() || ip.interface == index)
+        .map(|ip| ip.address.clone())
+        .collect();
+    let live_addrs: BTreeSet<String> = global_addresses(link).into_iter().collect();
+    
+    for missing in expected_addrs.difference(&live_addrs) {
+        issues.push(format!("address {} is missing", missing));
+    }
+    for extra in live_addrs.difference(&expected_addrs) {
+        issues.push(format!("unexpected address {}", extra));
+    }
+    
+    // Every recorded route must be installed
+    let live_routes: Vec<(Option<IpNetwork>, Option<String>, Option<u32>)> = routes.iter()
+        .map(|r| {
+            let gw = r.get("gateway").and_then(|g| g.as_str()).map(String::from);
+            let metric = r.get("metric").and_then(|m| m.as_u64()).map(|m| m as u32);
+            let dst = r.get("dst").and_then(|d| d.as_str()).and_then(|d| match d {
+                // `ip -j route` prints default routes without a family
+                "default" if gw.as_deref().map(|g| g.contains(':')).unwrap_or(false) => normalize_dst("::/0"),
+                "default" => normalize_dst("0.0.0.0/0"),
+                _ => normalize_dst(d),
+            });
+            (dst, gw, metric)
+        })
+        .collect();
+    
+    for route in expected.routes.iter().flatten() {
+        let dst = normalize_dst(&route.dst);
+        let found = live_routes.iter().any(|(live_dst, live_gw, live_metric)| {
+            *live_dst == dst
+                && (route.gw.is_none() || *live_gw == route.gw)
+                && (route.priority.is_none() || *live_metric == route.priority)
+        });
+        
+        if !found {
+            match route.priority {
+                Some(metric) => issues.push(format!("route {} via {} metric {} is missing", 
+                                                    route.dst, route.gw.as_deref().unwrap_or("-"), metric)),
+                None => issues.push(format!("route {} via {} is missing", 
+                                            route.dst, route.gw.as_deref().unwrap_or("-"))),
+            }
+        }
+    }
+    
+    issues
+}
+
+/// Global-scope addresses (`addr/prefix`) from an `ip -j addr show` entry
+pub fn global_addresses(link: &serde_json::Value) -> Vec<String> {
+    link.get("addr_info")
+        .and_then(|a| a.as_array())
+        .map(|addrs| {
+            addrs.iter()
+                .filter(|a| a.get("scope").and_then(|s| s.as_str()) == Some("global"))
+                .filter_map(|a| {
+                    let local = a.get("local")?.as_str()?;
+                    let prefix = a.get("prefixlen")?.as_u64()?;
+                    Some(format!("{}/{}", local, prefix))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse a route destination; host routes are printed without a prefix
+fn normalize_dst(dst: &str) -> Option<IpNetwork> {
+    dst.parse().ok()
+}
+
+/// Resolved link-layer address of `ip` in `ip -j neigh show` output
+///
+/// Entries still resolving or that failed to resolve carry no usable address.
+pub fn neighbor_lladdr(entries: &[serde_json::Value], ip: &str) -> Option<String> {
+    entries.iter()
+        .filter(|e| e.get("dst").and_then(|d| d.as_str()) == Some(ip))
+        .filter(|e| !e.get("state")
+            .and_then(|s| s.as_array())
+            .map_or(false, |states| states.iter().any(|s| matches!(s.as_str(), Some("FAILED" | "INCOMPLETE")))))
+        .find_map(|e| e.get("lladdr").and_then(|l| l.as_str()).map(String::from))
+}
+
+/// List addresses (`addr/prefix`) without a rule in `ip -j rule show`
+/// output sending their traffic to `table`
+pub fn source_rule_issues(addresses: &[String], table: u32, rules: &[serde_json::Value]) -> Vec<String> {
+    let table = table.to_string();
+    addresses.iter()
+        .map(|address| address.split('/').next().unwrap_or(address))
+        .filter(|source| !rules.iter().any(|rule| {
+            // Unnamed tables are listed by number, which may come as a string
+            let rule_table = rule.get("table").map(|t| t.as_str().map_or_else(|| t.to_string(), String::from));
+            rule.get("src").and_then(|s| s.as_str()) == Some(*source) && rule_table.as_deref() == Some(table.as_str())
+        }))
+        .map(|source| format!("no rule from {} to table {}", source, table))
+        .collect()
+}
+
+/// List configured neighbors missing from `ip -j neigh show dev <if>` output
+///
+/// Entries must be permanent and carry the configured link-layer address.
+pub fn neighbor_issues(expected: &[Neighbor], entries: &[serde_json::Value]) -> Vec<String> {
+    let mut issues = Vec::new();
+    
+    for neighbor in expected {
+        let entry = entries.iter()
+            .find(|e| e.get("dst").and_then(|d| d.as_str()) == Some(neighbor.ip.as_str()));
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                issues.push(format!("neighbor {} missing", neighbor.ip));
+                continue;
+            }
+        };
+        
+        let lladdr = entry.get("lladdr").and_then(|l| l.as_str()).unwrap_or("");
+        if !lladdr.eq_ignore_ascii_case(&neighbor.mac) {
+            issues.push(format!("neighbor {} has lladdr {}, expected {}", neighbor.ip, lladdr, neighbor.mac));
+        }
+        
+        let permanent = entry.get("state")
+            .and_then(|s| s.as_array())
+            .map_or(false, |states| states.iter().any(|s| s.as_str() == Some("PERMANENT")));
+        if !permanent {
+            issues.push(format!("neighbor {} is not permanent", neighbor.ip));
+        }
+    }
+    
+    issues
+}
@@ -0,0 +1,72 @@
+// File: socni/tests/aranya_test.rs
+
+use std::os::unix::fs::PermissionsExt;
+
+use socni::integrations::aranya::{load_macsec_secret, parse_vlan_label, vlan_label, MacsecSecret};
+
+const SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+const POD: &str = "02:00:00:00:00:01";
+
+#[test]
+fn test_macsec_key_derivation() -> anyhow::Result<()> {
+    let secret = MacsecSecret::new(SECRET, "team-a", "label-100", 100);
+    let key = secret.key_for(POD, 16)?;
+    assert_eq!(key.key.len(), 16);
+    assert_eq!(key.id_hex().len(), 32);
+    assert_eq!(key.key_hex().len(), 32);
+    assert_eq!(key.salt_hex().len(), 24);
+
+    // Every node derives a sender's key from the same secret, label and MAC
+    let again = MacsecSecret::new(SECRET, "team-a", "label-100", 100).key_for(&POD.to_uppercase(), 16)?;
+    assert_eq!((again.key, again.id, again.salt), (key.key.clone(), key.id, key.salt));
+
+    // Another sender, VLAN, label, team or secret gets an unrelated key
+    for other in [
+        secret.key_for("02:00:00:00:00:02", 16)?,
+        MacsecSecret::new(SECRET, "team-a", "label-100", 101).key_for(POD, 16)?,
+        MacsecSecret::new(SECRET, "team-a", "label-200", 100).key_for(POD, 16)?,
+        MacsecSecret::new(SECRET, "team-b", "label-100", 100).key_for(POD, 16)?,
+        MacsecSecret::new(b"another secret of thirty-two bytes", "team-a", "label-100", 100).key_for(POD, 16)?,
+    ] {
+        assert_ne!(other.key, key.key);
+        assert_ne!(other.id, key.id);
+    }
+
+    assert_eq!(secret.key_for(POD, 32)?.key.len(), 32);
+    Ok(())
+}
+
+#[test]
+fn test_macsec_secret_file() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("socni-macsec-secret-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("macsec.key");
+
+    std::fs::write(&path, SECRET)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    assert_eq!(load_macsec_secret(&path)?, SECRET);
+
+    // Readable by others, too short or missing
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644))?;
+    assert!(load_macsec_secret(&path).unwrap_err().to_string().contains("chmod 600"));
+    std::fs::write(&path, b"short")?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    assert!(load_macsec_secret(&path).is_err());
+    assert!(load_macsec_secret(&dir.join("missing")).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[test]
+fn test_vlan_labels_are_scoped_by_team() {
+    assert_eq!(vlan_label("team-a", 100), "team-a/vlan-100");
+    assert_ne!(vlan_label("team-a", 100), vlan_label("team-b", 100));
+    assert_eq!(parse_vlan_label("team-a/vlan-100"), Some(("team-a".to_string(), 100)));
+
+    // Only the exact form maps back, so no label names two (team, VLAN) pairs
+    for label in ["vlan-100", "/vlan-100", "team-a/vlan-0100", "team-a/vlan-+100", "team-a/vlan-",
+                  "a/b/vlan-100", "team-a/vlan-100/x", "team-a/vlan-65536", "team-a/VLAN-100"] {
+        assert_eq!(parse_vlan_label(label), None, "{}", label);
+    }
+}
@@ -0,0 +1,127 @@
+// File: socni/tests/backend_test.rs
+
+use std::sync::Arc;
+
+use socni::backend::{errno_of, AddressLifetimes, BackendKind, IpCommand, IpError, IpFamily, LinkKind, NetBackend, NewLink, NewRoute};
+use socni::runner::MockRunner;
+
+#[test]
+fn test_backend_kind_parsing() {
+    assert_eq!("netlink".parse::<BackendKind>().unwrap(), BackendKind::Netlink);
+    assert_eq!("iproute2".parse::<BackendKind>().unwrap(), BackendKind::Iproute2);
+    assert!("ifconfig".parse::<BackendKind>().is_err());
+    assert_eq!(BackendKind::Iproute2.to_string(), "iproute2");
+}
+
+#[test]
+fn test_ip_command_backend() {
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip link delete gone", 1, ""));
+    let backend = IpCommand::new(runner.clone());
+
+    backend.set_link_up("eth1").unwrap();
+    backend.set_link_mtu("eth1", 9000).unwrap();
+    backend.add_address("eth1", "10.0.0.2/24", AddressLifetimes::default()).unwrap();
+    backend.add_address("eth1", "fd00::2/64", AddressLifetimes { valid: Some(3600), preferred: None }).unwrap();
+    let err = backend.delete_link("gone").unwrap_err();
+
+    assert_eq!(backend.kind(), BackendKind::Iproute2);
+    assert_eq!(err.to_string(), "mock failure");
+    assert_eq!(runner.calls(), [
+        "ip link set dev eth1 up",
+        "ip link set dev eth1 mtu 9000",
+        "ip addr add 10.0.0.2/24 dev eth1",
+        "ip addr add fd00::2/64 dev eth1 valid_lft 3600 preferred_lft 3600",
+        "ip link delete gone",
+    ]);
+}
+
+#[test]
+fn test_ip_command_creates_links() {
+    let runner = Arc::new(MockRunner::new());
+    let backend = IpCommand::new(runner.clone());
+
+    backend.add_link(&NewLink {
+        name: "eth0.100",
+        parent: "eth0",
+        kind: LinkKind::Vlan { id: 100, protocol: Some("802.1ad".to_string()), flags: vec![("reorder_hdr", false)] },
+        mtu: None,
+        netns: None,
+    }).unwrap();
+    backend.add_link(&NewLink {
+        name: "net1",
+        parent: "eth0",
+        kind: LinkKind::Macvlan,
+        mtu: Some(1400),
+        netns: Some("pod"),
+    }).unwrap();
+
+    assert_eq!(runner.calls(), [
+        "ip link add link eth0 name eth0.100 type vlan id 100 reorder_hdr off protocol 802.1ad",
+        "ip link add link eth0 name net1 netns pod mtu 1400 type macvlan mode bridge",
+    ]);
+}
+
+#[test]
+fn test_ip_errors_carry_errno() {
+    assert_eq!(IpError::new("Cannot find device \"eth9\"\n").errno, Some(libc::ENODEV));
+    assert_eq!(IpError::new("Device \"eth9\" does not exist.").errno, Some(libc::ENODEV));
+    assert_eq!(IpError::new("RTNETLINK answers: File exists").errno, Some(libc::EEXIST));
+    assert_eq!(IpError::new("RTNETLINK answers: Operation not permitted").errno, Some(libc::EPERM));
+    assert_eq!(IpError::new("Error: argument is wrong").errno, None);
+    assert_eq!(IpError::new("RTNETLINK answers: File exists\n").to_string(), "RTNETLINK answers: File exists");
+
+    let missing = anyhow::Error::new(IpError::new("Cannot find device \"eth9\"")).context("Failed to delete link");
+    assert_eq!(errno_of(&missing), Some(libc::ENODEV));
+    let exists = anyhow::Error::new(std::io::Error::from_raw_os_error(libc::EEXIST)).context("Failed to add link");
+    assert_eq!(errno_of(&exists), Some(libc::EEXIST));
+    assert_eq!(errno_of(&anyhow::anyhow!("Cannot find device \"eth9\"")), None);
+
+    // A missing link reads as none, any other failure as an error
+    let runner = Arc::new(MockRunner::new()
+        .fail("ip -d -j link show dev gone", 1, "Device \"gone\" does not exist.")
+        .respond("ip -d -j link show dev broken", 1, ""));
+    let backend = IpCommand::new(runner);
+    assert!(backend.link("gone").unwrap().is_none());
+    assert!(backend.link("broken").is_err());
+}
+
+#[test]
+fn test_ip_command_routes_rules_and_neighbors() {
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -j neigh show 10.0.0.1 dev eth1", 0, r#"[{"dst": "10.0.0.1", "lladdr": "02:00:00:00:00:01", "state": ["REACHABLE"]}]"#));
+    let backend = IpCommand::new(runner.clone());
+
+    backend.add_route(&NewRoute { gateway: Some("10.0.0.1"), dev: "eth1", ..Default::default() }).unwrap();
+    backend.add_route(&NewRoute { gateway: Some("fd00::1"), dev: "eth1", onlink: true, ..Default::default() }).unwrap();
+    backend.add_route(&NewRoute { dst: Some("10.0.0.1"), dev: "eth1", ..Default::default() }).unwrap();
+    backend.add_route(&NewRoute {
+        dst: Some("172.30.0.0/16"),
+        gateway: Some("10.0.0.1"),
+        dev: "eth1",
+        metric: Some(100),
+        table: Some(190),
+        onlink: false,
+    }).unwrap();
+    backend.add_source_rule("10.0.0.2", 190).unwrap();
+    backend.delete_source_rule("10.0.0.2", 190).unwrap();
+    backend.set_neighbor("eth1", "10.0.0.9", "02:00:00:00:00:09").unwrap();
+    backend.probe_neighbor("eth1", "10.0.0.1").unwrap();
+    backend.delete_neighbor("eth1", "10.0.0.9").unwrap();
+    let neighbors = backend.neighbors("eth1", Some("10.0.0.1")).unwrap();
+
+    assert_eq!(neighbors[0]["lladdr"], "02:00:00:00:00:01");
+    assert_eq!(NewRoute { gateway: Some("fd00::1"), ..Default::default() }.family(), IpFamily::V6);
+    assert_eq!(runner.calls(), [
+        "ip route add default via 10.0.0.1 dev eth1",
+        "ip -6 route add default via fd00::1 dev eth1 onlink",
+        "ip route add 10.0.0.1 dev eth1",
+        "ip route add 172.30.0.0/16 via 10.0.0.1 dev eth1 metric 100 table 190",
+        "ip rule add from 10.0.0.2 lookup 190",
+        "ip rule del from 10.0.0.2 lookup 190",
+        "ip neigh replace 10.0.0.9 lladdr 02:00:00:00:00:09 dev eth1 nud permanent",
+        "ip neigh replace 10.0.0.1 dev eth1 use",
+        "ip neigh del 10.0.0.9 dev eth1",
+        "ip -j neigh show 10.0.0.1 dev eth1",
+    ]);
+}
@@ -0,0 +1,43 @@
+// File: socni/tests/caps_test.rs
+
+use socni::backend::IpError;
+use socni::caps::{is_initial_uid_map, is_permission_error, missing_caps, parse_cap_eff, CAP_NET_ADMIN, CAP_SYS_ADMIN};
+
+#[test]
+fn test_parse_cap_eff() {
+    let status = "Name:\tsocni\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+    let caps = parse_cap_eff(status).expect("CapEff present");
+    assert!(caps & (1 << CAP_NET_ADMIN) != 0);
+
+    let unprivileged = "CapEff:\t0000000000000000\n";
+    assert_eq!(parse_cap_eff(unprivileged), Some(0));
+    assert_eq!(parse_cap_eff("Name:\tsocni\n"), None);
+}
+
+#[test]
+fn test_initial_uid_map_detection() {
+    assert!(is_initial_uid_map("         0          0 4294967295\n"));
+    assert!(!is_initial_uid_map("         0       1000          1\n"));
+}
+
+#[test]
+fn test_missing_caps() {
+    let required = [CAP_NET_ADMIN, CAP_SYS_ADMIN];
+    assert!(missing_caps(0x1ff_ffff_ffff, &required).is_empty());
+    assert_eq!(missing_caps(1 << CAP_NET_ADMIN, &required), ["CAP_SYS_ADMIN"]);
+    assert_eq!(missing_caps(0, &required), ["CAP_NET_ADMIN", "CAP_SYS_ADMIN"]);
+}
+
+#[test]
+fn test_permission_error_detection() {
+    let denied = anyhow::Error::new(std::io::Error::from_raw_os_error(libc::EPERM)).context("Failed to add link");
+    assert!(is_permission_error(&denied));
+    let exists = anyhow::Error::new(std::io::Error::from_raw_os_error(libc::EEXIST)).context("Failed to add link");
+    assert!(!is_permission_error(&exists));
+    assert!(!is_permission_error(&anyhow::anyhow!("Operation not permitted")));
+
+    // ip reports the errno only as text
+    let denied = anyhow::Error::new(IpError::new("RTNETLINK answers: Operation not permitted\n")).context("Failed to add link");
+    assert!(is_permission_error(&denied));
+    assert!(!is_permission_error(&anyhow::Error::new(IpError::new("RTNETLINK answers: File exists"))));
+}
@@ -3,7 +3,7 @@ use std::path::PathBuf;
 
 use socni::config::NetConf;
 use socni::plugin::VlanPlugin;
-use socni::types::{CmdArgs, Result as CniResult, Interface, IPConfig, Route, DNS};
+use socni::types::{has_interface_mtu, CmdArgs, CniError, Result as CniResult, Interface, IPConfig, Route, DNS, CNI_ERR_GENERIC, CNI_ERR_TRY_AGAIN_LATER};
 
 #[test]
 fn test_net_conf_creation() -> Result<(), Box<dyn std::error::Error>> {
@@ -15,6 +15,7 @@ fn test_net_conf_creation() -> Result<(), Box<dyn std::error::Error>> {
         vlan: 100,
         mtu: Some(1500),
         ipam: None,
+        ..Default::default()
     };
 
     assert_eq!(conf.cni_version, "1.0.0");
@@ -38,6 +39,7 @@ fn test_cmd_args_creation() -> Result<(), Box<dyn std::error::Error>> {
         vlan: 100,
         mtu: Some(1500),
         ipam: None,
+        ..Default::default()
     };
 
     let args = CmdArgs {
@@ -69,6 +71,7 @@ fn test_vlan_plugin_creation() -> Result<(), Box<dyn std::error::Error>> {
         vlan: 100,
         mtu: Some(1500),
         ipam: None,
+        ..Default::default()
     };
 
     let args = CmdArgs {
@@ -97,6 +100,7 @@ fn test_cni_result_serialization() -> Result<(), Box<dyn std::error::Error>> {
         ips: Some(vec![]),
         routes: Some(vec![]),
         dns: None,
+        extensions: Default::default(),
     };
 
     let serialized = serde_json::to_string(&result)?;
@@ -109,4 +113,105 @@ fn test_cni_result_serialization() -> Result<(), Box<dyn std::error::Error>> {
     assert!(result.dns.is_none() && deserialized.dns.is_none());
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+// The result shape a CNI 0.4.0 consumer expects, with the per-IP `version` it requires
+#[derive(serde::Deserialize)]
+struct Result040 {
+    #[serde(rename = "cniVersion")]
+    cni_version: String,
+    interfaces: Vec<Interface>,
+    ips: Vec<IPConfig040>,
+    routes: Vec<Route>,
+}
+
+#[derive(serde::Deserialize)]
+struct IPConfig040 {
+    version: String,
+    interface: Option<usize>,
+    address: String,
+    gateway: Option<String>,
+}
+
+fn dual_stack_result(cni_version: &str) -> CniResult {
+    let mut result = CniResult::new(cni_version);
+    result.add_interface(Interface {
+        name: "eth1".to_string(),
+        mac: Some("02:00:00:00:00:01".to_string()),
+        sandbox: Some("/var/run/netns/test".to_string()),
+        mtu: None,
+    });
+    for (address, gateway) in [("10.0.0.2/24", "10.0.0.1"), ("fd00::2/64", "fd00::1")] {
+        result.add_ip(IPConfig {
+            interface: Some(0),
+            address: address.to_string(),
+            gateway: Some(gateway.to_string()),
+        });
+    }
+    result.add_route(Route { dst: "0.0.0.0/0".to_string(), gw: Some("10.0.0.1".to_string()), priority: None });
+    result
+}
+
+#[test]
+fn test_cni_040_result_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let output = serde_json::to_string(&dual_stack_result("0.4.0").to_json()?)?;
+    let parsed: Result040 = serde_json::from_str(&output)?;
+    assert_eq!(parsed.cni_version, "0.4.0");
+    assert_eq!(parsed.interfaces[0].name, "eth1");
+    assert_eq!(parsed.ips.iter().map(|ip| ip.version.as_str()).collect::<Vec<_>>(), ["4", "6"]);
+    assert_eq!(parsed.ips[1].address, "fd00::2/64");
+    assert_eq!(parsed.ips[1].interface, Some(0));
+    assert_eq!(parsed.ips[0].gateway.as_deref(), Some("10.0.0.1"));
+    assert_eq!(parsed.routes.len(), 1);
+
+    // Our own prevResult parsing accepts the 0.4.0 shape back
+    let back: CniResult = serde_json::from_str(&output)?;
+    assert_eq!(back.ips.unwrap().len(), 2);
+
+    // 1.0.0 dropped the field
+    let current = dual_stack_result("1.0.0").to_json()?;
+    assert!(current["ips"][0].get("version").is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_interface_mtu_needs_cni_1_1() -> Result<(), Box<dyn std::error::Error>> {
+    for (version, reported) in [("0.4.0", false), ("1.0.0", false), ("1.1.0", true)] {
+        let mut result = dual_stack_result(version);
+        if let Some(interfaces) = result.interfaces.as_mut() {
+            interfaces[0].mtu = Some(9000);
+        }
+        let json = result.to_json()?;
+        assert_eq!(json["interfaces"][0].get("mtu").is_some(), reported, "cniVersion {}", version);
+    }
+    assert!(has_interface_mtu("1.1.0"));
+    assert!(!has_interface_mtu("1.0.0"));
+    assert!(!has_interface_mtu("0.4.0"));
+    Ok(())
+}
+
+#[test]
+fn test_mtu_is_reported_for_every_version() -> Result<(), Box<dyn std::error::Error>> {
+    let mut result = dual_stack_result("1.0.0");
+    assert_eq!(result.mtu(), None);
+    result.set_socni("mtu", 1468);
+    assert_eq!(result.to_json()?["socni"]["mtu"], 1468);
+
+    // Parsed back from disk, as status reads it
+    let recorded: CniResult = serde_json::from_value(result.to_json()?)?;
+    assert_eq!(recorded.mtu(), Some(1468));
+    Ok(())
+}
+
+#[test]
+fn test_cni_error_shape_per_version() {
+    let err = anyhow::Error::new(CniError::try_again_later("state locked"));
+    let json = CniError::to_json(&err, "0.4.0");
+    assert_eq!(json["cniVersion"], "0.4.0");
+    assert_eq!(json["code"], CNI_ERR_TRY_AGAIN_LATER);
+    assert_eq!(json["msg"], "state locked");
+
+    // 0.3.x has no code for a transient failure
+    assert_eq!(CniError::to_json(&err, "0.3.1")["code"], CNI_ERR_GENERIC);
+}
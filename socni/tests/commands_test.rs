@@ -0,0 +1,63 @@
+// File: socni/tests/commands_test.rs
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Stdio};
+
+use socni::commands::find_executable;
+
+#[test]
+fn test_find_executable_in_path() -> std::io::Result<()> {
+    let root = std::env::temp_dir().join(format!("socni-path-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let (empty, bin) = (root.join("empty"), root.join("bin"));
+    std::fs::create_dir_all(&empty)?;
+    std::fs::create_dir_all(&bin)?;
+
+    // Not executable yet
+    let ip = bin.join("ip");
+    std::fs::write(&ip, "#!/bin/sh\n")?;
+    let path: OsString = std::env::join_paths([&empty, &bin]).unwrap();
+    assert_eq!(find_executable("ip", &path), None);
+
+    std::fs::set_permissions(&ip, std::fs::Permissions::from_mode(0o755))?;
+    assert_eq!(find_executable("ip", &path), Some(ip));
+    assert_eq!(find_executable("ip", &OsString::new()), None);
+
+    let _ = std::fs::remove_dir_all(&root);
+    Ok(())
+}
+
+/// Run the plugin binary with `envs` and the config on stdin
+fn run_plugin(envs: &[(&str, &str)]) -> std::io::Result<std::process::Output> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_socni"))
+        .env_clear()
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    child.stdin.take().unwrap().write_all(br#"{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100
+    }"#)?;
+    child.wait_with_output()
+}
+
+#[test]
+fn test_config_check_output() -> std::io::Result<()> {
+    // A runtime's CHECK without a sandbox gets no stdout
+    let output = run_plugin(&[("CNI_COMMAND", "CHECK"), ("CNI_NETNS", "")])?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+
+    let output = run_plugin(&[("CNI_COMMAND", "CHECK"), ("SOCNI_CONFIG_CHECK", "1")])?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout),
+               "Configuration for network test-vlan (VLAN 100 on eth0) is valid\n");
+    Ok(())
+}
@@ -0,0 +1,27 @@
+// File: socni/tests/common/mod.rs
+
+// Each test binary uses only some of these helpers
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use socni::config::SocniConfig;
+
+/// Fresh, per-test state directory under the system temp dir
+pub fn temp_state_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("socni-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+/// Node configuration keeping state in `state_dir`, with Aranya disabled
+///
+/// Plugins take it through `with_socni_config`, so tests running in
+/// parallel never share the process environment.
+pub fn socni_config(state_dir: impl AsRef<Path>) -> SocniConfig {
+    SocniConfig {
+        state_dir: state_dir.as_ref().to_path_buf(),
+        disable_aranya: true,
+        ..SocniConfig::default()
+    }
+}
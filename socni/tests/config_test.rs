@@ -0,0 +1,968 @@
+// File: socni/tests/config_test.rs
+
+use std::collections::HashMap;
+
+use socni::backend::AddressLifetimes;
+use socni::config::{glob_match, netconf_schema, sanitize_ifname, validate_ifname, validate_path_component, DnsConfig, NetConf, SocniConfig, VlanRange};
+
+// Environment with only `pairs` set, for `SocniConfig::from_vars`
+fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = pairs.iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_parse_valid_config() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {"type": "host-local", "subnet": "10.10.0.0/24", "gateway": "10.10.0.1"}
+    }"#)?;
+
+    assert_eq!(conf.vlan, 100);
+    assert!(conf.fail_on_hook_error);
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_rejects_bad_values() {
+    let base = NetConf::new_default("test-vlan", "eth0", 100, None);
+    assert!(base.validate().is_ok());
+
+    let mut conf = base.clone();
+    conf.vlan = 4095;
+    assert!(conf.validate().is_err());
+
+    let mut conf = base.clone();
+    conf.master = String::new();
+    assert!(conf.validate().is_err());
+
+    let mut conf = base.clone();
+    conf.mtu = Some(10);
+    assert!(conf.validate().is_err());
+
+    let mut conf = base.clone();
+    conf.post_hook = Some("relative/hook.sh".to_string());
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_conf_args_precedence() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "args": {
+            "cni": {"mac": "02:00:00:00:00:01"},
+            "labels": [{"key": "tenant", "value": "finance"}]
+        }
+    }"#)?;
+
+    let mut cni_args = HashMap::new();
+    assert_eq!(conf.resolve_mac(&cni_args).as_deref(), Some("02:00:00:00:00:01"));
+    assert_eq!(conf.resolve_tenant(&cni_args).as_deref(), Some("finance"));
+
+    cni_args.insert("MAC".to_string(), "02:00:00:00:00:02".to_string());
+    cni_args.insert("ARANYA_TENANT_ID".to_string(), "hr".to_string());
+    assert_eq!(conf.resolve_mac(&cni_args).as_deref(), Some("02:00:00:00:00:02"));
+    assert_eq!(conf.resolve_tenant(&cni_args).as_deref(), Some("hr"));
+
+    Ok(())
+}
+
+#[test]
+fn test_conf_args_rejects_bad_mac() {
+    let parse = |mac: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "args": {{"mac": {}}}
+    }}"#, mac).as_bytes());
+
+    assert!(parse(r#""02:00:00:00:00:01""#).is_ok());
+    assert!(parse(r#""not-a-mac""#).is_err());
+    assert!(parse("42").is_err());
+    // Held to the same rules as the top-level mac
+    assert!(format!("{:#}", parse(r#""01:00:5e:00:00:01""#).unwrap_err()).contains("multicast"));
+    assert!(format!("{:#}", parse(r#""00:00:00:00:00:00""#).unwrap_err()).contains("all zeros"));
+}
+
+#[test]
+fn test_fixed_mac() -> Result<(), Box<dyn std::error::Error>> {
+    let parse = |mac: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "mac": "{}"
+    }}"#, mac).as_bytes());
+
+    let conf = parse("02:42:AC:11:00:02")?;
+    let mut cni_args = HashMap::new();
+    assert_eq!(conf.resolve_mac(&cni_args).as_deref(), Some("02:42:AC:11:00:02"));
+    cni_args.insert("MAC".to_string(), "02:00:00:00:00:02".to_string());
+    assert_eq!(conf.resolve_mac(&cni_args).as_deref(), Some("02:00:00:00:00:02"));
+
+    assert!(parse("02:42:ac:11:00").unwrap_err().to_string().contains("Invalid MAC address 02:42:ac:11:00"));
+    assert!(parse("02-42-ac-11-00-02").is_err());
+    assert!(parse("01:00:5e:00:00:01").unwrap_err().to_string().contains("multicast"));
+    assert!(parse("00:00:00:00:00:00").unwrap_err().to_string().contains("all zeros"));
+    Ok(())
+}
+
+#[test]
+fn test_ifname_matrix() {
+    for name in ["net1", "eth0", "eth1", "vlan100", "eth0.100", "a"] {
+        assert!(validate_ifname(name).is_ok(), "{} should be accepted", name);
+    }
+
+    for name in ["", ".", "..", "has space", "a/b", "eth0:1", "abcdefghijklmnop"] {
+        assert!(validate_ifname(name).is_err(), "{:?} should be rejected", name);
+    }
+}
+
+#[test]
+fn test_names_stay_inside_the_state_directory() {
+    for name in ["corp", "corp.v2", "a..b", "f3b1c2d4e5"] {
+        assert!(validate_path_component("Network name", name).is_ok(), "{} should be accepted", name);
+    }
+
+    for name in ["", ".", "..", "../etc", "corp/ipv6", "corp\0"] {
+        assert!(validate_path_component("Network name", name).is_err(), "{:?} should be rejected", name);
+    }
+
+    let conf = br#"{"cniVersion": "1.0.0", "name": "../../etc", "type": "socni", "master": "eth0", "vlan": 100}"#;
+    assert!(NetConf::parse(conf).unwrap_err().to_string().contains("Network name"));
+}
+
+#[test]
+fn test_netconf_schema_matches_serde_names() {
+    let schema = netconf_schema();
+    let props = &schema["properties"];
+
+    assert!(props.get("cniVersion").is_some());
+    assert!(props.get("type").is_some());
+    assert!(props.get("postHook").is_some());
+    assert_eq!(props["vlan"]["minimum"], 0.0);
+    assert_eq!(props["vlan"]["maximum"], 4094.0);
+    assert!(schema["definitions"].get("IPAMConfig").is_some());
+    assert!(schema["definitions"].get("Route").is_some());
+}
+
+#[test]
+fn test_runtime_config_bandwidth_preferred() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "bandwidth": {"egressRate": 1000000, "egressBurst": 8000000},
+        "runtimeConfig": {
+            "bandwidth": {
+                "ingressRate": 2000000, "ingressBurst": 16000000,
+                "egressRate": 5000000, "egressBurst": 40000000
+            }
+        }
+    }"#)?;
+
+    let bandwidth = conf.effective_bandwidth().expect("bandwidth configured");
+    assert_eq!(bandwidth.egress_rate, Some(5000000));
+    assert_eq!(bandwidth.ingress_burst, Some(16000000));
+
+    let missing_burst = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "runtimeConfig": {"bandwidth": {"ingressRate": 2000000}}
+    }"#);
+    assert!(missing_burst.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_native_vlan_validation() {
+    let mut conf = NetConf::new_default("native", "eth0", 0, None);
+    assert!(conf.validate().is_err());
+
+    conf.native = true;
+    assert!(conf.validate().is_ok());
+    assert!(conf.is_native());
+
+    conf.vlan = 100;
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_dns_mixed_family_ordering() {
+    let dns = DnsConfig {
+        nameservers: vec![
+            "10.0.0.53".to_string(),
+            "fd00::53".to_string(),
+            "10.0.1.53".to_string(),
+            "fd00::54".to_string(),
+        ],
+        ..Default::default()
+    };
+
+    assert_eq!(dns.ordered_nameservers(false), vec!["10.0.0.53", "10.0.1.53", "fd00::53", "fd00::54"]);
+    assert_eq!(dns.ordered_nameservers(true), vec!["fd00::53", "fd00::54", "10.0.0.53", "10.0.1.53"]);
+
+    // Limited to three, keeping one resolver of the other family
+    assert_eq!(dns.resolv_conf_nameservers(false), vec!["10.0.0.53", "10.0.1.53", "fd00::53"]);
+    assert_eq!(dns.resolv_conf_nameservers(true), vec!["fd00::53", "fd00::54", "10.0.0.53"]);
+}
+
+#[test]
+fn test_dns_single_family_limit() {
+    let dns = DnsConfig {
+        nameservers: vec!["10.0.0.1".into(), "10.0.0.2".into(), "10.0.0.3".into(), "10.0.0.4".into()],
+        search: vec!["svc.cluster.local".into()],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        dns.resolv_conf(false),
+        "nameserver 10.0.0.1\nnameserver 10.0.0.2\nnameserver 10.0.0.3\nsearch svc.cluster.local\n"
+    );
+}
+
+#[test]
+fn test_dns_invalid_nameserver() {
+    let mut conf = NetConf::new_default("dns", "eth0", 100, None);
+    conf.dns = Some(DnsConfig {
+        nameservers: vec!["not-an-ip".into()],
+        ..Default::default()
+    });
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_effective_mtu_inheritance() {
+    let mut conf = NetConf::new_default("mtu", "eth0", 100, None);
+    assert!(conf.inherit_mtu);
+    assert_eq!(conf.effective_mtu(Some(9000)), Some(9000));
+    assert_eq!(conf.effective_mtu(None), None);
+
+    conf.mtu = Some(1500);
+    assert_eq!(conf.effective_mtu(Some(9000)), Some(1500));
+
+    conf.mtu = None;
+    conf.inherit_mtu = false;
+    assert_eq!(conf.effective_mtu(Some(9000)), None);
+}
+
+#[test]
+fn test_ipam_pool_selection() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "pools",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {
+            "type": "host-local",
+            "pools": {
+                "blue": {"subnet": "10.10.0.0/24"},
+                "green": {"subnet": "10.20.0.0/24", "rangeStart": "10.20.0.50", "gateway": "10.20.0.254"}
+            }
+        }
+    }"#)?;
+    let ipam = conf.ipam.as_ref().unwrap();
+
+    let mut args = HashMap::new();
+    assert!(ipam.resolve_pool(&args)?.is_none());
+
+    args.insert("SOCNI_POOL".to_string(), "blue".to_string());
+    let (name, pool) = ipam.resolve_pool(&args)?.unwrap();
+    assert_eq!(name, "blue");
+    assert_eq!(pool.address()?, ("10.10.0.2/24".to_string(), "10.10.0.1".to_string()));
+
+    args.insert("SOCNI_POOL".to_string(), "green".to_string());
+    let (_, pool) = ipam.resolve_pool(&args)?.unwrap();
+    assert_eq!(pool.address()?, ("10.20.0.50/24".to_string(), "10.20.0.254".to_string()));
+
+    args.insert("SOCNI_POOL".to_string(), "red".to_string());
+    assert!(ipam.resolve_pool(&args).is_err());
+
+    let mut with_default = ipam.clone();
+    with_default.default_pool = Some("blue".to_string());
+    let (name, _) = with_default.resolve_pool(&args)?.unwrap();
+    assert_eq!(name, "blue");
+
+    Ok(())
+}
+
+#[test]
+fn test_ipam_undefined_default_pool() {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "pools",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {"type": "host-local", "pools": {"blue": {"subnet": "10.10.0.0/24"}}, "defaultPool": "red"}
+    }"#);
+    assert!(conf.is_err());
+}
+
+#[test]
+fn test_ipam_pools_need_leases() {
+    // A fixed address per pool would be handed to every pod selecting it
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "pools",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {"type": "http", "url": "http://ipam", "pools": {"blue": {"subnet": "10.10.0.0/24"}}}
+    }"#);
+    let err = conf.unwrap_err();
+    assert!(format!("{:#}", err).contains("pools need host-local"), "{:#}", err);
+}
+
+#[test]
+fn test_neighbor_validation() {
+    let mut conf = NetConf::new_default("neigh", "eth0", 100, None);
+    conf.neighbors = Some(vec![socni::config::Neighbor {
+        ip: "10.0.0.1".to_string(),
+        mac: "02:00:00:00:00:01".to_string(),
+    }]);
+    assert!(conf.validate().is_ok());
+
+    conf.neighbors.as_mut().unwrap()[0].mac = "02:00:00".to_string();
+    assert!(conf.validate().is_err());
+
+    conf.neighbors.as_mut().unwrap()[0] = socni::config::Neighbor {
+        ip: "gateway".to_string(),
+        mac: "02:00:00:00:00:01".to_string(),
+    };
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_unsupported_cni_version() {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.1.0",
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100
+    }"#);
+    let err = conf.unwrap_err().to_string();
+    assert!(err.contains("unsupported cniVersion 1.1.0"));
+    assert!(err.contains("1.0.0"));
+
+    for version in socni::config::SUPPORTED_CNI_VERSIONS {
+        let mut conf = NetConf::new_default("versions", "eth0", 100, None);
+        conf.cni_version = version.to_string();
+        assert!(conf.validate().is_ok());
+    }
+}
+
+#[test]
+fn test_ipam_type_must_be_known() {
+    let parse = |ipam: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {}
+    }}"#, ipam).as_bytes());
+
+    assert!(parse(r#"{"type": "host-local", "subnet": "10.0.0.0/24"}"#).is_ok());
+    assert!(parse(r#"{"type": "none"}"#).is_ok());
+    for ipam in [r#"{"subnet": "10.0.0.0/24"}"#, r#"{"type": "static"}"#, r#"{"type": "dhcp"}"#, r#"{"type": "host-locl"}"#] {
+        let err = parse(ipam).unwrap_err();
+        assert!(format!("{:#}", err).contains("IPAM type"), "{}: {:#}", ipam, err);
+    }
+}
+
+#[test]
+fn test_http_ipam_requires_url() {
+    let parse = |ipam: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {}
+    }}"#, ipam).as_bytes());
+
+    assert!(parse(r#"{"type": "http", "url": "https://ipam.example.com/v1"}"#).is_ok());
+    assert!(parse(r#"{"type": "http"}"#).is_err());
+    assert!(parse(r#"{"type": "http", "url": "ipam.example.com"}"#).is_err());
+    assert!(parse(r#"{"type": "http", "url": "http://ipam", "timeout": 0}"#).is_err());
+}
+
+#[test]
+fn test_ipam_ranges() -> anyhow::Result<()> {
+    let parse = |ipam: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {}
+    }}"#, ipam).as_bytes());
+
+    let conf = parse(r#"{"type": "host-local", "ranges": [
+        {"subnet": "10.0.0.0/24", "rangeStart": "10.0.0.100", "rangeEnd": "10.0.0.199"},
+        {"subnet": "10.0.1.0/24", "gateway": "10.0.1.254"}
+    ]}"#)?;
+    let ranges = conf.ipam.as_ref().unwrap().address_ranges()?;
+    assert_eq!(ranges.len(), 2);
+    assert_eq!(ranges[0].start, Some("10.0.0.100".parse()?));
+    assert_eq!(ranges[0].end, Some("10.0.0.199".parse()?));
+    assert_eq!(ranges[0].gateway, Some("10.0.0.1".parse()?));
+    assert_eq!(ranges[1].gateway, Some("10.0.1.254".parse()?));
+
+    // The flat subnet and gateway are one implicit range
+    let conf = parse(r#"{"type": "host-local", "subnet": "10.0.0.0/24", "gateway": "10.0.0.254"}"#)?;
+    let ranges = conf.ipam.as_ref().unwrap().address_ranges()?;
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].subnet, "10.0.0.0/24".parse()?);
+    assert_eq!(ranges[0].gateway, Some("10.0.0.254".parse()?));
+
+    assert!(parse(r#"{"type": "host-local", "subnet": "10.0.0.0/24", "ranges": [{"subnet": "10.0.1.0/24"}]}"#).is_err());
+    assert!(parse(r#"{"type": "host-local", "ranges": []}"#).unwrap_err().to_string().contains("at least one range"));
+    assert!(parse(r#"{"type": "host-local", "ranges": [{"subnet": "10.0.0.0/24", "rangeEnd": "10.0.1.5"}]}"#).is_err());
+    assert!(parse(r#"{"type": "host-local", "ranges": [
+        {"subnet": "10.0.0.0/24", "rangeStart": "10.0.0.50", "rangeEnd": "10.0.0.40"}
+    ]}"#).is_err());
+
+    // One range set per family is dual-stack; the second family is leased under its own key
+    let conf = parse(r#"{"type": "host-local", "ranges": [
+        {"subnet": "fd00::/64"}, {"subnet": "10.0.0.0/24"}, {"subnet": "fd00:1::/64"}
+    ]}"#)?;
+    let families = conf.ipam.as_ref().unwrap().address_families()?;
+    let subnets: Vec<Vec<String>> = families.iter()
+        .map(|family| family.iter().map(|r| r.subnet.to_string()).collect())
+        .collect();
+    assert_eq!(subnets, [vec!["fd00::/64", "fd00:1::/64"], vec!["10.0.0.0/24"]]);
+    assert_eq!(conf.dual_stack_network().as_deref(), Some("corp/ipv4"));
+    assert_eq!(parse(r#"{"type": "host-local", "subnet": "fd00::/64"}"#)?.dual_stack_network(), None);
+    Ok(())
+}
+
+#[test]
+fn test_ipamd_ipam_validation() -> anyhow::Result<()> {
+    let parse = |ipam: &str, extra: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        {}
+        "ipam": {}
+    }}"#, extra, ipam).as_bytes());
+
+    let conf = parse(r#"{"type": "socni-ipamd", "subnet": "10.0.0.0/24"}"#, "")?;
+    let ipam = conf.ipam.as_ref().unwrap();
+    assert!(ipam.leases_host_local());
+    assert_eq!(ipam.ipamd_socket(), std::path::Path::new("/run/socni/ipamd.sock"));
+
+    assert!(parse(r#"{"type": "socni-ipamd", "socket": "/var/run/ipamd.sock"}"#, "").is_ok());
+    assert!(parse(r#"{"type": "socni-ipamd", "socket": "ipamd.sock"}"#, "").is_err());
+    assert!(parse(r#"{"type": "socni-ipamd", "timeout": 0}"#, "").is_err());
+    assert!(parse(r#"{"type": "socni-ipamd"}"#, r#""dataDir": "/var/lib/socni","#).is_err());
+    assert!(!parse(r#"{"type": "http", "url": "http://ipam"}"#, "")?.ipam.unwrap().leases_host_local());
+    Ok(())
+}
+
+#[test]
+fn test_sanitize_ifname() {
+    // Already valid names pass through untouched
+    assert_eq!(sanitize_ifname("vlan-100"), "vlan-100");
+
+    let pathological = [
+        "lo",
+        "all",
+        "",
+        "///",
+        "default/web-0",
+        "ns.with.dots",
+        "Pod_Name",
+        "ünïcødé-pöd-名前",
+        "a-very-long-pod-name-that-exceeds-ifnamsiz",
+    ];
+    for raw in pathological {
+        let name = sanitize_ifname(raw);
+        assert!(validate_ifname(&name).is_ok(), "{:?} -> {:?}", raw, name);
+        assert!(name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+                "{:?} -> {:?}", raw, name);
+        assert!(!["lo", "all"].contains(&name.as_str()));
+    }
+
+    // Inputs that map to the same characters stay distinct
+    assert_ne!(sanitize_ifname("web.0"), sanitize_ifname("web_0"));
+    assert_ne!(
+        sanitize_ifname("a-very-long-pod-name-one"),
+        sanitize_ifname("a-very-long-pod-name-two"),
+    );
+}
+
+#[test]
+fn test_host_link_name_for_long_master() {
+    let conf = NetConf::new_default("long", "enp129s0f1np1", 4094, None);
+    let name = conf.host_link_name();
+    assert!(validate_ifname(&name).is_ok(), "{}", name);
+
+    let conf = NetConf::new_default("short", "eth0", 100, None);
+    assert_eq!(conf.host_link_name(), "eth0.100");
+}
+
+#[test]
+fn test_cni_versions_plural() -> Result<(), Box<dyn std::error::Error>> {
+    let parse = |versions: &str| NetConf::parse(format!(r#"{{
+        {}
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100
+    }}"#, versions).as_bytes());
+
+    // The highest supported version wins, and takes precedence over cniVersion
+    let conf = parse(r#""cniVersion": "0.3.1", "cniVersions": ["0.4.0", "1.0.0", "1.1.0"],"#)?;
+    assert_eq!(conf.cni_version, "1.0.0");
+
+    let conf = parse(r#""cniVersions": ["0.3.0", "0.4.0"],"#)?;
+    assert_eq!(conf.cni_version, "0.4.0");
+
+    let err = parse(r#""cniVersions": ["1.1.0"],"#).unwrap_err().to_string();
+    assert!(err.contains("none of cniVersions"));
+
+    let err = parse("").unwrap_err().to_string();
+    assert!(err.contains("neither cniVersion nor cniVersions"));
+
+    Ok(())
+}
+
+#[test]
+fn test_ipam_none_is_link_local_only() -> Result<(), Box<dyn std::error::Error>> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "mesh",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {"type": "none"}
+    }"#)?;
+    assert!(conf.link_local_only());
+    assert!(!NetConf::new_default("plain", "eth0", 100, None).link_local_only());
+
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "mesh",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "disableIpv6": true,
+        "ipam": {"type": "none"}
+    }"#);
+    assert!(conf.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_vlan_flags() {
+    let mut conf = NetConf::new_default("flags", "eth0", 100, None);
+    assert!(conf.vlan_flag_args().is_empty());
+
+    conf.reorder_hdr = Some(false);
+    conf.mvrp = Some(true);
+    assert_eq!(conf.vlan_flag_args(), ["reorder_hdr", "off", "mvrp", "on"]);
+    assert!(conf.validate().is_ok());
+
+    conf.gvrp = Some(true);
+    assert!(conf.validate().is_err());
+
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "native",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 0,
+        "native": true,
+        "looseBinding": true
+    }"#);
+    assert!(conf.is_err());
+}
+
+#[test]
+fn test_static_ipv6() {
+    let mut conf = NetConf::new_default("v6", "eth0", 100, None);
+    assert!(conf.static_ipv6_sysctls().is_empty());
+
+    conf.static_ipv6 = true;
+    assert_eq!(conf.static_ipv6_sysctls(), [("accept_ra", "0"), ("use_tempaddr", "0")]);
+    assert!(conf.validate().is_ok());
+
+    conf.disable_ipv6 = true;
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_route_metric_range() {
+    let conf = |metric: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "multi",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {{
+            "type": "host-local",
+            "subnet": "10.0.0.0/24",
+            "routes": [{{"dst": "10.30.0.0/16", "metric": {}}}]
+        }}
+    }}"#, metric).as_bytes());
+
+    let parsed = conf("4294967295").unwrap();
+    assert_eq!(parsed.ipam.unwrap().routes.unwrap()[0].metric, Some(u32::MAX));
+    assert!(conf("4294967296").is_err());
+    assert!(conf("-1").is_err());
+}
+
+#[test]
+fn test_master_netns() {
+    let mut conf = NetConf::new_default("infra", "eth0", 100, None);
+    conf.master_netns = Some("infra".to_string());
+    assert!(conf.validate().is_ok());
+
+    conf.master_netns = Some("../infra".to_string());
+    assert!(conf.validate().is_err());
+
+    conf.master_netns = Some("infra".to_string());
+    conf.numa_hint = true;
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_dscp_range() {
+    let mut conf = NetConf::new_default("qos", "eth0", 100, None);
+    conf.dscp = Some(63);
+    assert!(conf.validate().is_ok());
+
+    conf.dscp = Some(64);
+    assert!(conf.validate().is_err());
+}
+
+#[test]
+fn test_bridge_companion() -> anyhow::Result<()> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "uplink",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "bridgeCompanion": {"bridge": "cni-int", "ipam": {"subnet": "172.16.0.0/24"}}
+    }"#)?;
+    let companion = conf.bridge_companion.unwrap();
+    assert_eq!(companion.ifname, "br1");
+
+    let host = companion.host_ifname("abc123");
+    assert!(host.starts_with("socni"));
+    assert_eq!(host.len(), 15);
+    assert_eq!(host, companion.host_ifname("abc123"));
+    assert_ne!(host, companion.host_ifname("def456"));
+
+    let invalid = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "uplink",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "bridgeCompanion": {"bridge": "cni-int", "ipam": {"subnet": "172.16.0.5"}}
+    }"#);
+    assert!(invalid.is_err());
+
+    // One static address would be shared by every pod on the bridge
+    let shared = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "uplink",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "bridgeCompanion": {"bridge": "cni-int", "address": "172.16.0.5/24"}
+    }"#);
+    assert!(shared.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_ipam_secondary_validation() {
+    let conf = |ipam: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "oam",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {}
+    }}"#, ipam).as_bytes());
+
+    assert!(conf(r#"{"type": "host-local", "subnet": "10.0.0.0/24", "secondary": {"subnet": "172.20.0.0/24"}}"#).is_ok());
+    assert!(conf(r#"{"type": "host-local", "subnet": "10.0.0.0/24", "secondary": {"subnet": "bogus"}}"#).is_err());
+    assert!(conf(r#"{"type": "http", "url": "http://ipam", "secondary": {"subnet": "172.20.0.0/24"}}"#).is_err());
+}
+
+#[test]
+fn test_ipam_address_lifetimes() -> anyhow::Result<()> {
+    let conf = |ipam: &str| NetConf::parse(format!(r#"{{
+        "cniVersion": "1.0.0",
+        "name": "renumber",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {}
+    }}"#, ipam).as_bytes());
+
+    let parsed = conf(r#"{"type": "host-local", "subnet": "fd00::/64", "validLifetime": 3600, "preferredLifetime": 600}"#)?;
+    let ipam = parsed.ipam.unwrap();
+    assert_eq!(ipam.address_lifetimes("fd00::2/64"), AddressLifetimes { valid: Some(3600), preferred: Some(600) });
+    assert!(ipam.address_lifetimes("10.0.0.2/24").is_forever());
+
+    assert!(conf(r#"{"type": "host-local", "subnet": "fd00::/64", "validLifetime": 600, "preferredLifetime": 3600}"#).is_err());
+    assert!(conf(r#"{"type": "host-local", "subnet": "fd00::/64", "validLifetime": 0}"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_disable_aranya_from_env() {
+    assert!(!SocniConfig::from_vars(vars(&[])).0.disable_aranya);
+    assert!(SocniConfig::from_vars(vars(&[("SOCNI_DISABLE_ARANYA", "1")])).0.disable_aranya);
+}
+
+#[test]
+fn test_macsec_config() -> anyhow::Result<()> {
+    let mut conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "sealed",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "macsec": {"peers": ["02:00:00:00:00:01"]}
+    }"#)?;
+    let macsec = conf.macsec.as_ref().unwrap();
+    assert_eq!(macsec.cipher.as_str(), "gcm-aes-xpn-128");
+    assert_eq!(macsec.cipher.key_len(), 16);
+    assert!(macsec.encrypt);
+
+    conf.macsec.as_mut().unwrap().peers.push("not-a-mac".to_string());
+    assert!(conf.validate().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_allowed_self_assign() -> anyhow::Result<()> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "ipam": {"type": "host-local", "subnet": "10.0.0.0/24", "allowedSelfAssign": ["10.0.0.128/25"]}
+    }"#)?;
+    let ipam = conf.ipam.unwrap();
+    let args = |ip: &str| HashMap::from([("IP".to_string(), ip.to_string())]);
+
+    assert_eq!(ipam.requested_ip(&HashMap::new())?, None);
+    assert_eq!(ipam.requested_ip(&args("10.0.0.200"))?, Some("10.0.0.200".parse()?));
+    // Infrastructure addresses in the low half can't be requested
+    assert!(ipam.requested_ip(&args("10.0.0.1")).is_err());
+    assert!(ipam.requested_ip(&args("not-an-ip")).is_err());
+
+    // Without allowedSelfAssign a pod can't pick its address at all
+    let mut closed = ipam.clone();
+    closed.allowed_self_assign = None;
+    assert_eq!(closed.requested_ip(&HashMap::new())?, None);
+    let err = closed.requested_ip(&args("10.0.0.200")).unwrap_err();
+    assert!(err.to_string().contains("sets no allowedSelfAssign"));
+
+    assert!(NetConf::parse(br#"{
+        "cniVersion": "1.0.0", "name": "corp", "type": "vlan", "master": "eth0", "vlan": 100,
+        "ipam": {"type": "host-local", "subnet": "10.0.0.0/24", "allowedSelfAssign": ["10.0.0.0/33"]}
+    }"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_allowed_vlan_ranges() -> anyhow::Result<()> {
+    let ranges = VlanRange::parse_list("100-199, 250")?;
+    assert_eq!(ranges, [VlanRange { start: 100, end: 199 }, VlanRange { start: 250, end: 250 }]);
+    assert_eq!(ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>(), ["100-199", "250"]);
+    assert!(VlanRange::parse_list("199-100").is_err());
+    assert!(VlanRange::parse_list("100-5000").is_err());
+    assert!(VlanRange::parse_list("trunk").is_err());
+
+    let mut config = SocniConfig::default();
+    assert!(config.vlan_allowed(4000));
+    config.allowed_vlan_ranges = Some(ranges);
+    assert!(config.vlan_allowed(150));
+    assert!(config.vlan_allowed(250));
+    assert!(!config.vlan_allowed(200));
+
+    // A malformed list allows nothing rather than everything
+    let (config, _) = SocniConfig::from_vars(vars(&[("SOCNI_ALLOWED_VLANS", "100-")]));
+    assert_eq!(config.allowed_vlan_ranges, Some(Vec::new()));
+    Ok(())
+}
+
+#[test]
+fn test_config_sources() {
+    let (config, sources) = SocniConfig::from_vars(vars(&[
+        ("SOCNI_WARN_INTERVAL", "60"),
+        ("SOCNI_NETNS_RESTORE_ATTEMPTS", "5"),
+        ("SOCNI_ALLOWED_MASTERS", "bond*, ens?f1"),
+        ("SOCNI_TOKEN_PUBLIC_KEY", "/etc/socni/token.pub"),
+    ]));
+
+    assert_eq!(config.warn_interval_secs, 60);
+    assert_eq!(sources["warn_interval_secs"], "env SOCNI_WARN_INTERVAL");
+    assert_eq!(config.netns_restore_attempts, 5);
+    assert_eq!(sources["netns_restore_attempts"], "env SOCNI_NETNS_RESTORE_ATTEMPTS");
+    assert_eq!(config.allowed_masters, Some(vec!["bond*".to_string(), "ens?f1".to_string()]));
+    assert!(config.master_allowed("bond0") && config.master_allowed("ens3f1"));
+    assert!(!config.master_allowed("eno1"));
+    assert_eq!(sources["token_public_key"], "env SOCNI_TOKEN_PUBLIC_KEY");
+    assert_eq!(sources["cni_bin_dir"], "default");
+    // Every field has a source
+    let fields = serde_json::to_value(&config).unwrap();
+    assert_eq!(sources.len(), fields.as_object().unwrap().len());
+}
+
+#[test]
+fn test_registry_config() -> anyhow::Result<()> {
+    let mut conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100,
+        "registry": {"type": "etcd", "endpoint": "http://127.0.0.1:2379"}
+    }"#)?;
+    let registry = conf.registry.as_ref().unwrap();
+    assert_eq!(registry.key, "/socni/{network}/{namespace}/{name}");
+    assert_eq!(registry.timeout, 2);
+
+    for (key, endpoint) in [("/pods/{pod}", "http://127.0.0.1:2379"), ("/pods/{name", "http://127.0.0.1:2379"),
+                            ("/pods/{name}", "127.0.0.1:2379")] {
+        let registry = conf.registry.as_mut().unwrap();
+        registry.key = key.to_string();
+        registry.endpoint = endpoint.to_string();
+        assert!(conf.validate().is_err(), "{} {}", key, endpoint);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_disable_check() -> anyhow::Result<()> {
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "disableCheck": true,
+        "name": "test-vlan",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 100
+    }"#)?;
+    assert!(conf.disable_check);
+    assert!(!NetConf::new_default("test-vlan", "eth0", 100, None).disable_check);
+
+    Ok(())
+}
+
+#[test]
+fn test_source_routing_validation() -> anyhow::Result<()> {
+    let mut conf = NetConf::new_default("test-vlan", "eth0", 100, None);
+    conf.ipam = Some(serde_json::from_value(serde_json::json!({"type": "host-local", "subnet": "10.0.0.0/24"}))?);
+    conf.source_routing = true;
+    assert!(conf.validate().unwrap_err().to_string().contains("requires a routeTable"));
+
+    conf.route_table = Some(254);
+    assert!(conf.validate().unwrap_err().to_string().contains("reserved"));
+
+    conf.route_table = Some(100);
+    conf.validate()?;
+
+    conf.ipam = None;
+    assert!(conf.validate().unwrap_err().to_string().contains("requires IPAM"));
+
+    Ok(())
+}
+
+#[test]
+fn test_connectivity_target() {
+    let mut conf = NetConf::new_default("test-vlan", "eth0", 100, None);
+    conf.check_connectivity = true;
+    conf.connectivity_target = Some("10.0.0.10".to_string());
+    assert!(conf.validate().is_ok());
+
+    conf.connectivity_target = Some("router.example".to_string());
+    assert!(conf.validate().unwrap_err().to_string().contains("Invalid connectivityTarget"));
+}
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("eth0", "eth0"));
+    assert!(!glob_match("eth0", "eth01"));
+    assert!(glob_match("bond*", "bond0"));
+    assert!(glob_match("bond*", "bond"));
+    assert!(glob_match("*.100", "eth0.100"));
+    assert!(glob_match("en*f*", "ens3f1"));
+    assert!(!glob_match("en*f*", "eth0"));
+    assert!(glob_match("ens?", "ens3"));
+    assert!(!glob_match("ens?", "ens"));
+    assert!(glob_match("*", ""));
+    assert!(SocniConfig::default().master_allowed("mgmt0"));
+}
+
+#[test]
+fn test_qinq_validation() -> anyhow::Result<()> {
+    let mut conf = NetConf::new_default("test-vlan", "eth0", 100, None);
+    conf.inner_vlan = Some(20);
+    conf.validate()?;
+    assert_eq!(conf.effective_vlan_protocol(), Some("802.1ad"));
+    assert_eq!(conf.link_vlan(), 20);
+    assert_eq!(conf.outer_link_name().as_deref(), Some("eth0.100"));
+    assert_eq!(conf.host_link_name(), "eth0.100.20");
+
+    // The protocol belongs to the outer tag; the inner one stays 802.1Q
+    assert!(conf.vlan_flag_args().is_empty());
+
+    conf.inner_vlan = Some(4095);
+    assert!(conf.validate().unwrap_err().to_string().contains("Invalid innerVlan"));
+
+    conf.inner_vlan = Some(20);
+    conf.vlan_protocol = Some("802.1Q".to_string());
+    assert!(conf.validate().unwrap_err().to_string().contains("802.1ad"));
+
+    conf.vlan_protocol = Some("802.1x".to_string());
+    assert!(conf.validate().unwrap_err().to_string().contains("Invalid vlanProtocol"));
+
+    // A single tag carries its protocol itself
+    conf.inner_vlan = None;
+    conf.vlan_protocol = Some("802.1ad".to_string());
+    conf.validate()?;
+    assert_eq!(conf.vlan_flag_args(), ["protocol", "802.1ad"]);
+    assert_eq!(conf.outer_link_name(), None);
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// File: socni/tests/doctor_test.rs
+
+use socni::doctor::{likely_causes, Evidence, Priority};
+use socni::ipam::VlanUsage;
+use socni::logging::OpRecord;
+use socni::verify::CheckItem;
+
+fn record(command: &str, error: Option<&str>) -> OpRecord {
+    OpRecord {
+        timestamp: 1_700_000_000,
+        command: command.to_string(),
+        container_id: "abc123".to_string(),
+        network: "corp".to_string(),
+        vlan: 100,
+        ifname: "eth1".to_string(),
+        result: if error.is_some() { "error" } else { "ok" }.to_string(),
+        error: error.map(String::from),
+    }
+}
+
+#[test]
+fn test_unknown_container() {
+    let causes = likely_causes(&Evidence::default());
+    assert_eq!(causes.len(), 1);
+    assert_eq!(causes[0].priority, Priority::High);
+    assert!(causes[0].summary.contains("no record of this container"));
+}
+
+#[test]
+fn test_causes_are_ranked() {
+    let evidence = Evidence {
+        records: vec![record("ADD", Some("no free address")), record("DEL", None)],
+        checks: vec![CheckItem::pass("interface"), CheckItem::fail("up", "Interface eth1 is not up".to_string())],
+        access: Some(Ok(false)),
+        usage: Some(VlanUsage { vlan: 100, allocated: 254, capacity: 254, networks: vec!["corp".to_string()] }),
+        ..Default::default()
+    };
+    assert_eq!(evidence.vlan(), Some(100));
+
+    let causes = likely_causes(&evidence);
+    let summaries: Vec<&str> = causes.iter().map(|c| c.summary.as_str()).collect();
+    assert_eq!(causes.len(), 4, "{:?}", summaries);
+    assert_eq!(summaries[0], "last ADD failed: no free address");
+    assert!(summaries[1].contains("no access to VLAN 100"));
+    assert!(summaries[2].contains("exhausted (254 of 254)"));
+    assert_eq!(causes[3].priority, Priority::Medium);
+    assert_eq!(causes[3].to_string(), "[medium] live interface up: Interface eth1 is not up");
+}
+
+#[test]
+fn test_healthy_container_has_no_causes() {
+    let evidence = Evidence {
+        records: vec![record("ADD", None)],
+        checks: vec![CheckItem::pass("interface")],
+        access: Some(Ok(true)),
+        usage: Some(VlanUsage { vlan: 100, allocated: 10, capacity: 254, networks: vec!["corp".to_string()] }),
+        ..Default::default()
+    };
+    assert!(likely_causes(&evidence).is_empty());
+}
@@ -0,0 +1,33 @@
+// File: socni/tests/encoding_test.rs
+
+use socni::encoding::{decode, encode, Alphabet};
+
+#[test]
+fn test_base64_roundtrip() -> anyhow::Result<()> {
+    for input in [&b""[..], b"f", b"fo", b"foo", b"foob", b"\xfb\xff\xbf"] {
+        for alphabet in [Alphabet::Standard, Alphabet::UrlSafe] {
+            assert_eq!(decode(&encode(input, alphabet), alphabet)?, input);
+        }
+    }
+    assert_eq!(encode(b"fo", Alphabet::Standard), "Zm8=");
+    assert_eq!(encode(b"\xfb\xff\xbf", Alphabet::Standard), "+/+/");
+    assert_eq!(encode(b"\xfb\xff\xbf", Alphabet::UrlSafe), "-_-_");
+    assert_eq!(encode(b"fo", Alphabet::UrlSafe), "Zm8");
+    Ok(())
+}
+
+#[test]
+fn test_base64_decoding_is_strict() {
+    // Each alphabet only takes its own characters and padding rule
+    assert!(decode("-_-_", Alphabet::Standard).is_err());
+    assert!(decode("+/+/", Alphabet::UrlSafe).is_err());
+    assert!(decode("Zm8", Alphabet::Standard).is_err());
+    assert!(decode("Zm8=", Alphabet::UrlSafe).is_err());
+    assert!(decode("Zm===", Alphabet::Standard).is_err());
+
+    // No whitespace, no dangling symbol and no stray trailing bits
+    assert!(decode("Zm 8=", Alphabet::Standard).is_err());
+    assert!(decode("Zm8=\n", Alphabet::Standard).is_err());
+    assert!(decode("Zm8xZ", Alphabet::UrlSafe).is_err());
+    assert!(decode("Zm9=", Alphabet::Standard).is_err());
+}
@@ -0,0 +1,40 @@
+// File: socni/tests/events_test.rs
+
+use socni::events::{auth_config, error_category, failure_event};
+
+#[test]
+fn test_error_category() {
+    let denied = anyhow::anyhow!("Access denied by Aranya policy engine: No permission to use VLAN 100");
+    assert_eq!(error_category(&denied), "AccessDenied");
+
+    let master = anyhow::anyhow!("Master interface eth9 does not exist");
+    assert_eq!(error_category(&master), "MasterInterface");
+
+    let other = anyhow::anyhow!("something unexpected");
+    assert_eq!(error_category(&other), "Internal");
+}
+
+#[test]
+fn test_failure_event_fields() {
+    let err = anyhow::anyhow!("Master interface eth9 does not exist");
+    let event = failure_event("prod", "web-0", "vlan100", 100, &err);
+
+    assert_eq!(event["metadata"]["namespace"], "prod");
+    assert_eq!(event["involvedObject"]["name"], "web-0");
+    assert_eq!(event["type"], "Warning");
+    assert_eq!(event["reason"], "SocniAddMasterInterface");
+
+    let message = event["message"].as_str().unwrap();
+    assert!(message.contains("VLAN 100"));
+    assert!(message.contains("MasterInterface"));
+
+    let ts = event["firstTimestamp"].as_str().unwrap();
+    assert_eq!(ts.len(), 20);
+    assert!(ts.ends_with('Z'));
+}
+
+#[test]
+fn test_auth_config_quotes_the_token() {
+    assert_eq!(auth_config("eyJhbGciOi.x.y"), "header = \"Authorization: Bearer eyJhbGciOi.x.y\"\n");
+    assert_eq!(auth_config(r#"a"b\c"#), "header = \"Authorization: Bearer a\\\"b\\\\c\"\n");
+}
@@ -0,0 +1,78 @@
+// File: socni/tests/firewall_test.rs
+
+use serde_json::json;
+
+use socni::config::{FirewallConfig, FirewallRule};
+use socni::firewall::{chain_name, dscp_marks, dscp_ruleset, rule_handles, ruleset};
+
+#[test]
+fn test_ruleset_rendering() -> anyhow::Result<()> {
+    let firewall = FirewallConfig {
+        allow: vec![
+            FirewallRule { cidr: "10.0.0.0/8".to_string(), protocol: Some("tcp".to_string()), ports: vec![80, 443] },
+            FirewallRule { cidr: "fd00::/64".to_string(), protocol: None, ports: vec![53] },
+            FirewallRule { cidr: "192.168.1.10/32".to_string(), protocol: None, ports: vec![] },
+        ],
+        default_action: "drop".to_string(),
+    };
+
+    let script = ruleset("net1", &firewall)?;
+    let lines: Vec<&str> = script.lines().collect();
+    let prefix = "add rule inet socni in-net1 iifname \"net1\"";
+
+    assert_eq!(chain_name("net1"), "in-net1");
+    assert_eq!(lines[0], "add table inet socni");
+    assert!(lines[1].contains("hook input"));
+    assert_eq!(lines[2], format!("{} ct state established,related accept", prefix));
+    assert_eq!(lines[3], format!("{} ip saddr 10.0.0.0/8 tcp dport {{ 80, 443 }} accept", prefix));
+    assert_eq!(lines[4], format!("{} ip6 saddr fd00::/64 meta l4proto {{ tcp, udp }} th dport {{ 53 }} accept", prefix));
+    assert_eq!(lines[5], format!("{} ip saddr 192.168.1.10/32 accept", prefix));
+    assert_eq!(lines[6], format!("{} drop", prefix));
+
+    Ok(())
+}
+
+#[test]
+fn test_rule_handles() {
+    let listing = json!({"nftables": [
+        {"metainfo": {"version": "1.0.6"}},
+        {"chain": {"family": "inet", "table": "socni", "name": "in-net1", "handle": 1}},
+        {"rule": {"family": "inet", "table": "socni", "chain": "in-net1", "handle": 2}},
+        {"rule": {"family": "inet", "table": "socni", "chain": "in-net1", "handle": 3}}
+    ]});
+
+    assert_eq!(rule_handles(&listing), vec![2, 3]);
+}
+
+#[test]
+fn test_dscp_ruleset_rendering() {
+    let addresses = ["10.0.0.2".parse().unwrap(), "fd00::2".parse().unwrap()];
+    let script = dscp_ruleset("net1", &addresses, 46);
+    let lines: Vec<&str> = script.lines().collect();
+    let prefix = "add rule inet socni dscp-net1 oifname \"net1\"";
+
+    assert!(lines[1].contains("hook postrouting"));
+    assert_eq!(lines[2], "flush chain inet socni dscp-net1");
+    assert_eq!(lines[3], format!("{} ip saddr 10.0.0.2 ip dscp set 46", prefix));
+    assert_eq!(lines[4], format!("{} ip6 saddr fd00::2 ip6 dscp set 46", prefix));
+
+    // Link-local only pods have no address to match on
+    let script = dscp_ruleset("net1", &[], 10);
+    assert!(script.ends_with(&format!("{} ip dscp set 10\n{} ip6 dscp set 10\n", prefix, prefix)));
+}
+
+#[test]
+fn test_dscp_marks() {
+    let listing = json!({"nftables": [
+        {"chain": {"family": "inet", "table": "socni", "name": "dscp-net1", "handle": 1}},
+        {"rule": {"family": "inet", "table": "socni", "chain": "dscp-net1", "handle": 2, "expr": [
+            {"match": {"op": "==", "left": {"meta": {"key": "oifname"}}, "right": "net1"}},
+            {"mangle": {"key": {"payload": {"protocol": "ip", "field": "dscp"}}, "value": 46}}
+        ]}},
+        {"rule": {"family": "inet", "table": "socni", "chain": "dscp-net1", "handle": 3, "expr": [
+            {"mangle": {"key": {"meta": {"key": "mark"}}, "value": 1}}
+        ]}}
+    ]});
+
+    assert_eq!(dscp_marks(&listing), vec![46]);
+}
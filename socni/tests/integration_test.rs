@@ -125,6 +125,7 @@ mod tests {
             vlan: vlan_id,
             mtu: Some(1500),
             ipam: None,
+            ..Default::default()
         };
         
         // Create CNI args
@@ -203,6 +204,7 @@ mod tests {
             vlan: vlan_id,
             mtu: Some(1500),
             ipam: None,
+            ..Default::default()
         };
         
         // Create CNI args
@@ -0,0 +1,449 @@
+// File: socni/tests/ipam_test.rs
+
+use std::net::IpAddr;
+use std::slice;
+use std::time::Duration;
+
+use socni::ipam::daemon::{self, IpamdClient, LeaseRequest};
+use socni::ipam::file;
+use socni::ipam::http::{parse_allocation, AllocationRequest, HttpIpam};
+use socni::ipam::{vlan_usage, AddressRange, AllocationAction, AllocationStrategy, HostLocal, Lease, STATE_VERSION};
+use socni::runner::MockRunner;
+use socni::types::{CniError, CNI_ERR_TRY_AGAIN_LATER};
+
+mod common;
+use common::temp_state_dir;
+
+fn range(subnet: &str) -> AddressRange {
+    let subnet = subnet.parse().unwrap();
+    AddressRange { subnet, start: None, end: None, gateway: Some("10.0.0.1".parse().unwrap()), vlan: None }
+}
+
+fn owner(container_id: &str, identity: Option<&str>) -> Lease {
+    Lease {
+        container_id: container_id.to_string(),
+        ifname: "eth1".to_string(),
+        identity: identity.map(str::to_string),
+        allocated: 0,
+        refreshed: 0,
+        netns: None,
+        uid: None,
+    }
+}
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn test_lowest_strategy() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("lowest"));
+    let range = range("10.0.0.0/24");
+
+    let a = ipam.allocate("net", slice::from_ref(&range), &owner("a", None), AllocationStrategy::Lowest)?;
+    let b = ipam.allocate("net", slice::from_ref(&range), &owner("b", None), AllocationStrategy::Lowest)?;
+    assert_eq!(a, ip("10.0.0.2"));
+    assert_eq!(b, ip("10.0.0.3"));
+
+    // Repeated ADD for the same interface keeps its lease
+    assert_eq!(ipam.allocate("net", slice::from_ref(&range), &owner("a", None), AllocationStrategy::Lowest)?, a);
+
+    // A freed address is the first to be reused
+    assert_eq!(ipam.release("net", "a", "eth1")?, Some(a));
+    assert_eq!(ipam.allocate("net", slice::from_ref(&range), &owner("c", None), AllocationStrategy::Lowest)?, a);
+
+    Ok(())
+}
+
+#[test]
+fn test_random_strategy() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("random"));
+    let range = range("10.0.0.0/29");
+
+    let mut seen = Vec::new();
+    for id in ["a", "b", "c", "d", "e"] {
+        let addr = ipam.allocate("net", slice::from_ref(&range), &owner(id, None), AllocationStrategy::Random)?;
+        assert!(range.subnet.contains(addr));
+        assert_ne!(addr, ip("10.0.0.0"));
+        assert_ne!(addr, ip("10.0.0.1"));
+        assert_ne!(addr, ip("10.0.0.7"));
+        assert!(!seen.contains(&addr));
+        seen.push(addr);
+    }
+
+    // .2 through .6 are all taken now
+    assert!(ipam.allocate("net", slice::from_ref(&range), &owner("f", None), AllocationStrategy::Random).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_sticky_strategy_survives_reload() -> anyhow::Result<()> {
+    let dir = temp_state_dir("sticky");
+    let range = range("10.0.0.0/24");
+
+    let first = {
+        let ipam = HostLocal::new(&dir);
+        ipam.allocate("net", slice::from_ref(&range), &owner("other", None), AllocationStrategy::Sticky)?;
+        let addr = ipam.allocate("net", slice::from_ref(&range), &owner("pod-1", Some("prod/web-0")), AllocationStrategy::Sticky)?;
+        ipam.release("net", "other", "eth1")?;
+        ipam.release("net", "pod-1", "eth1")?;
+        addr
+    };
+    assert_eq!(first, ip("10.0.0.3"));
+
+    // A new allocator over the same state directory still remembers the pod
+    let ipam = HostLocal::new(&dir);
+    let again = ipam.allocate("net", slice::from_ref(&range), &owner("pod-2", Some("prod/web-0")), AllocationStrategy::Sticky)?;
+    assert_eq!(again, first);
+
+    // Without an identity sticky behaves like lowest
+    let anon = ipam.allocate("net", slice::from_ref(&range), &owner("pod-3", None), AllocationStrategy::Sticky)?;
+    assert_eq!(anon, ip("10.0.0.2"));
+
+    Ok(())
+}
+
+#[test]
+fn test_ranges_are_walked_in_order() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("ranges"));
+    let mut first = range("10.0.0.0/24");
+    first.start = Some(ip("10.0.0.10"));
+    first.end = Some(ip("10.0.0.11"));
+    let mut second = range("10.0.1.0/29");
+    second.gateway = Some(ip("10.0.1.1"));
+    let ranges = [first, second];
+
+    let leased: Vec<IpAddr> = ["a", "b", "c"].iter()
+        .map(|id| ipam.allocate("net", &ranges, &owner(id, None), AllocationStrategy::Lowest))
+        .collect::<anyhow::Result<_>>()?;
+    assert_eq!(leased, [ip("10.0.0.10"), ip("10.0.0.11"), ip("10.0.1.2")]);
+
+    // Freed addresses in the first range are handed out before the second's
+    ipam.release("net", "a", "eth1")?;
+    assert_eq!(ipam.allocate("net", &ranges, &owner("d", None), AllocationStrategy::Lowest)?, ip("10.0.0.10"));
+
+    // A requested address may come from any range, but not from between them
+    assert_eq!(ipam.allocate_requested("net", &ranges, &owner("e", None), ip("10.0.1.6"))?, ip("10.0.1.6"));
+    assert!(ipam.allocate_requested("net", &ranges, &owner("f", None), ip("10.0.0.12")).is_err());
+
+    // Usage counts the capacity of every range
+    assert_eq!(ipam.state("net")?.capacity, Some(2 + 5));
+    Ok(())
+}
+
+#[test]
+fn test_http_allocation_response() -> anyhow::Result<()> {
+    let allocation = parse_allocation(br#"{
+        "address": "10.20.0.5/24",
+        "gateway": "10.20.0.1",
+        "routes": [{"dst": "10.30.0.0/16"}]
+    }"#)?;
+    assert_eq!(allocation.address, "10.20.0.5/24");
+    assert_eq!(allocation.gateway, "10.20.0.1");
+    assert_eq!(allocation.routes.len(), 1);
+
+    // Address without a prefix, and a gateway from the other family
+    assert!(parse_allocation(br#"{"address": "10.20.0.5", "gateway": "10.20.0.1"}"#).is_err());
+    assert!(parse_allocation(br#"{"address": "10.20.0.5/24", "gateway": "fd00::1"}"#).is_err());
+
+    Ok(())
+}
+
+fn allocation_request() -> AllocationRequest {
+    AllocationRequest {
+        network: "corp".to_string(),
+        vlan: 100,
+        container_id: "pod-1".to_string(),
+        ifname: "eth1".to_string(),
+        tenant: None,
+    }
+}
+
+#[test]
+fn test_http_requests_go_through_the_runner() -> anyhow::Result<()> {
+    let runner = MockRunner::new()
+        .respond("curl", 0, "{\"address\": \"10.20.0.5/24\", \"gateway\": \"10.20.0.1\"}\n200");
+    let client = HttpIpam::new("http://ipam.local/", Duration::from_secs(3), &runner);
+
+    let allocation = client.allocate(&allocation_request())?;
+    assert_eq!(allocation.address, "10.20.0.5/24");
+    client.release(&allocation_request())?;
+
+    // The request is written to curl's stdin, not its arguments
+    let post = |endpoint: &str| format!("curl -sS -X POST -H Content-Type: application/json --data-binary @- \
+                                   -w \n%{{http_code}} --max-time 3 http://ipam.local/{}", endpoint);
+    assert_eq!(runner.calls(), [post("allocate"), post("release")]);
+    let body: serde_json::Value = serde_json::from_str(&runner.inputs()[1])?;
+    assert_eq!(body, serde_json::json!({
+        "network": "corp", "vlan": 100, "container_id": "pod-1", "ifname": "eth1", "tenant": null
+    }));
+
+    Ok(())
+}
+
+#[test]
+fn test_http_failures_are_retryable() {
+    // A status outside 2xx fails even though curl itself succeeded
+    let runner = MockRunner::new().respond("curl", 0, "pool exhausted\n503");
+    let client = HttpIpam::new("http://ipam.local", Duration::from_secs(1), &runner);
+    let err = client.allocate(&allocation_request()).unwrap_err();
+    assert_eq!(CniError::code_of(&err), CNI_ERR_TRY_AGAIN_LATER);
+    assert!(format!("{:#}", err).contains("returned HTTP 503: pool exhausted"), "{:#}", err);
+    assert_eq!(CniError::code_of(&client.release(&allocation_request()).unwrap_err()), CNI_ERR_TRY_AGAIN_LATER);
+
+    let runner = MockRunner::new().fail("curl", 7, "Failed to connect");
+    let client = HttpIpam::new("http://ipam.local", Duration::from_secs(1), &runner);
+    let err = client.allocate(&allocation_request()).unwrap_err();
+    assert_eq!(CniError::code_of(&err), CNI_ERR_TRY_AGAIN_LATER);
+    assert!(format!("{:#}", err).contains("Failed to connect"), "{:#}", err);
+    assert_eq!(CniError::code_of(&client.release(&allocation_request()).unwrap_err()), CNI_ERR_TRY_AGAIN_LATER);
+    assert_eq!(CniError::code_of(&anyhow::anyhow!("other failure")), 100);
+}
+
+#[test]
+fn test_gc_frees_only_expired_leases_without_netns() -> anyhow::Result<()> {
+    let dir = temp_state_dir("gc");
+    let ipam = HostLocal::new(&dir);
+    let range = range("10.0.0.0/24");
+
+    let with_netns = |id: &str, netns: Option<&str>| Lease { netns: netns.map(str::to_string), ..owner(id, None) };
+    ipam.allocate("net", slice::from_ref(&range), &with_netns("running", Some("/run/netns/running")), AllocationStrategy::Lowest)?;
+    ipam.allocate("net", slice::from_ref(&range), &with_netns("crashed", Some("/run/netns/crashed")), AllocationStrategy::Lowest)?;
+    ipam.allocate("net", slice::from_ref(&range), &with_netns("legacy", None), AllocationStrategy::Lowest)?;
+    let exists = |netns: &str| netns.ends_with("running");
+
+    // Nothing has been idle for an hour
+    assert!(ipam.gc("net", Duration::from_secs(3600), &exists)?.is_empty());
+
+    let freed = ipam.gc("net", Duration::ZERO, &exists)?;
+    let freed: Vec<&str> = freed.iter().map(|(_, lease)| lease.container_id.as_str()).collect();
+    assert_eq!(freed, ["crashed"]);
+
+    // The running pod's lease survives and can still be refreshed
+    assert_eq!(ipam.refresh("net", "running", "eth1")?, Some(ip("10.0.0.2")));
+    assert_eq!(ipam.refresh("net", "crashed", "eth1")?, None);
+    assert_eq!(ipam.state("net")?.leases.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_file_assignments() -> anyhow::Result<()> {
+    let dir = temp_state_dir("file");
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("assignments.json");
+    std::fs::write(&path, br#"{
+        "prod/web-0": {"address": "10.0.0.5/24", "gateway": "10.0.0.1"},
+        "prod/web-1": {"address": "10.0.0.6/24", "gateway": "10.0.0.1", "routes": [{"dst": "10.30.0.0/16"}]}
+    }"#)?;
+
+    let allocation = file::lookup(&path, "prod/web-1")?;
+    assert_eq!(allocation.address, "10.0.0.6/24");
+    assert_eq!(allocation.routes.len(), 1);
+
+    let err = file::lookup(&path, "prod/web-2").unwrap_err();
+    assert!(err.to_string().contains("No address assigned to pod prod/web-2"));
+
+    // One bad entry rejects the whole file
+    assert!(file::parse_assignments(br#"{"prod/web-0": {"address": "10.0.0.5", "gateway": "10.0.0.1"}}"#).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_usage_per_vlan() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("usage"));
+    let mut corp = range("10.0.0.0/29");
+    corp.vlan = Some(100);
+    let mut oam = range("10.0.1.0/30");
+    oam.gateway = None;
+    oam.vlan = Some(100);
+
+    ipam.allocate("corp", slice::from_ref(&corp), &owner("c1", None), AllocationStrategy::Lowest)?;
+    ipam.allocate("corp", slice::from_ref(&corp), &owner("c2", None), AllocationStrategy::Lowest)?;
+    ipam.allocate("oam", slice::from_ref(&oam), &owner("c1", None), AllocationStrategy::Lowest)?;
+
+    // /29 has 6 hosts less the gateway, /30 has 2
+    let networks = ipam.usage()?;
+    assert_eq!(networks.iter().map(|n| n.capacity).collect::<Vec<_>>(), [Some(5), Some(2)]);
+
+    let usage = vlan_usage(&networks);
+    assert_eq!(usage.len(), 1);
+    assert_eq!((usage[0].vlan, usage[0].allocated, usage[0].capacity), (100, 3, 7));
+    assert_eq!(usage[0].networks, ["corp", "oam"]);
+    assert!((usage[0].utilization() - 300.0 / 7.0).abs() < 1e-9);
+
+    Ok(())
+}
+
+#[test]
+fn test_allocate_requested_address() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("requested"));
+    let range = range("10.0.0.0/24");
+
+    assert_eq!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c1", None), ip("10.0.0.50"))?, ip("10.0.0.50"));
+    // Repeating the request is idempotent, a different one for the same interface is not
+    assert_eq!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c1", None), ip("10.0.0.50"))?, ip("10.0.0.50"));
+    assert!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c1", None), ip("10.0.0.51")).is_err());
+
+    // Taken, gateway and out-of-subnet addresses are refused
+    assert!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c2", None), ip("10.0.0.50")).is_err());
+    assert!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c2", None), ip("10.0.0.1")).is_err());
+    assert!(ipam.allocate_requested("net", slice::from_ref(&range), &owner("c2", None), ip("10.0.1.5")).is_err());
+
+    // The lowest-free walk skips the requested address
+    ipam.allocate("net", slice::from_ref(&range), &owner("c3", None), AllocationStrategy::Lowest)?;
+    assert_eq!(ipam.lookup("net", "c1", "eth1")?, Some(ip("10.0.0.50")));
+    assert_eq!(ipam.lookup("net", "c3", "eth1")?, Some(ip("10.0.0.2")));
+
+    Ok(())
+}
+
+#[test]
+fn test_allocation_history() -> anyhow::Result<()> {
+    let ipam = HostLocal::new(temp_state_dir("history"));
+    let mut range = range("10.0.0.0/24");
+    range.vlan = Some(100);
+    let pod = |id: &str, name: &str| Lease {
+        uid: Some(format!("uid-{}", id)),
+        ..owner(id, Some(name))
+    };
+
+    // The address passes from one pod to the next; other addresses stay out of its timeline
+    let a = ipam.allocate("net", slice::from_ref(&range), &pod("a", "finance/ledger-0"), AllocationStrategy::Lowest)?;
+    ipam.allocate("net", slice::from_ref(&range), &pod("b", "finance/ledger-1"), AllocationStrategy::Lowest)?;
+    ipam.release("net", "a", "eth1")?;
+    ipam.allocate("net", slice::from_ref(&range), &pod("c", "hr/payroll-0"), AllocationStrategy::Lowest)?;
+
+    let history = ipam.history(a)?;
+    let timeline: Vec<(AllocationAction, &str)> = history.iter()
+        .map(|(_, e)| (e.action, e.container_id.as_str()))
+        .collect();
+    assert_eq!(timeline, [
+        (AllocationAction::Allocated, "a"),
+        (AllocationAction::Released, "a"),
+        (AllocationAction::Allocated, "c"),
+    ]);
+
+    let (network, first) = &history[0];
+    assert_eq!(network, "net");
+    assert_eq!(first.identity.as_deref(), Some("finance/ledger-0"));
+    assert_eq!(first.uid.as_deref(), Some("uid-a"));
+    assert_eq!(first.vlan, Some(100));
+    assert!(first.timestamp > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_v1_state_is_migrated() -> anyhow::Result<()> {
+    let dir = temp_state_dir("migrate");
+    std::fs::create_dir_all(dir.join("ipam"))?;
+    // Written before the state was versioned and before refreshes were tracked
+    std::fs::write(dir.join("ipam/corp.json"), r#"{
+        "leases": {"10.0.0.2": {"container_id": "old", "ifname": "eth1", "identity": null, "allocated": 1700000000}},
+        "sticky": {}
+    }"#)?;
+
+    let ipam = HostLocal::new(&dir);
+    assert_eq!(ipam.migrate()?, ["corp"]);
+    let raw: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("ipam/corp.json"))?)?;
+    assert_eq!(raw["version"], STATE_VERSION);
+    assert_eq!(raw["leases"]["10.0.0.2"]["refreshed"], 1_700_000_000);
+    assert!(ipam.migrate()?.is_empty());
+
+    // The live lease survives and keeps its address
+    let state = ipam.state("corp")?;
+    assert_eq!(state.version, 2);
+    assert_eq!(ipam.lookup("corp", "old", "eth1")?, Some(ip("10.0.0.2")));
+    assert_eq!(ipam.allocate("corp", &[range("10.0.0.0/24")], &owner("new", None), AllocationStrategy::Lowest)?, ip("10.0.0.3"));
+
+    // State from a newer socni is refused rather than rewritten
+    std::fs::write(dir.join("ipam/next.json"), r#"{"version": 99, "leases": {}}"#)?;
+    assert!(ipam.state("next").unwrap_err().to_string().contains("Failed to read IPAM state of network next"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ipamd_serves_host_local_leases() -> anyhow::Result<()> {
+    let dir = temp_state_dir("ipamd");
+    std::fs::create_dir_all(&dir)?;
+    let socket = dir.join("ipamd.sock");
+    let listener = tokio::net::UnixListener::bind(&socket)?;
+    let allocator = tokio::sync::Mutex::new(HostLocal::new(&dir));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            daemon::serve_connection(stream, &allocator, Duration::from_secs(5)).await.unwrap();
+        }
+    });
+
+    let client = IpamdClient::new(&socket, Duration::from_secs(5));
+    let allocate = |container_id: &str, requested: Option<IpAddr>| LeaseRequest::Allocate {
+        network: "corp".to_string(),
+        ranges: vec![range("10.0.0.0/24")],
+        owner: owner(container_id, None),
+        strategy: AllocationStrategy::Lowest,
+        requested,
+        ttl: Some(60),
+    };
+    let lease_of = |container_id: &str, release: bool| {
+        let (network, container_id, ifname) = ("corp".to_string(), container_id.to_string(), "eth1".to_string());
+        match release {
+            true => LeaseRequest::Release { network, container_id, ifname },
+            false => LeaseRequest::Refresh { network, container_id, ifname },
+        }
+    };
+
+    let leased = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let first = client.request(&allocate("pod-1", None))?;
+        let second = client.request(&allocate("pod-2", None))?;
+        let refreshed = client.request(&lease_of("pod-1", false))?;
+        let released = client.request(&lease_of("pod-1", true))?;
+        let unknown = client.request(&lease_of("pod-1", false))?;
+
+        // Errors from the allocator come back over the socket
+        let taken = client.request(&allocate("pod-3", Some(ip("10.0.0.3"))));
+        Ok((first, second, refreshed, released, unknown, taken.is_err()))
+    }).await??;
+    assert_eq!(leased, (Some(ip("10.0.0.2")), Some(ip("10.0.0.3")), Some(ip("10.0.0.2")), Some(ip("10.0.0.2")), None, true));
+
+    // The daemon's leases are the host-local state, visible to in-process allocation too
+    assert_eq!(HostLocal::new(&dir).lookup("corp", "pod-2", "eth1")?, Some(ip("10.0.0.3")));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ipamd_bounds_request_reads() -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let allocator = tokio::sync::Mutex::new(HostLocal::new(temp_state_dir("ipamd-bounds")));
+
+    // A client that connects and sends nothing gives up its connection...
+    let (_client, server) = tokio::io::duplex(1024);
+    let stalled = daemon::serve_connection(server, &allocator, Duration::from_millis(50)).await;
+    assert!(stalled.unwrap_err().to_string().contains("No request within"));
+
+    // ...and so does one that never ends its line
+    let (mut client, server) = tokio::io::duplex(2 * daemon::MAX_REQUEST_BYTES as usize);
+    client.write_all(&vec![b'x'; daemon::MAX_REQUEST_BYTES as usize + 1]).await?;
+    let oversized = daemon::serve_connection(server, &allocator, Duration::from_secs(5)).await;
+    assert!(oversized.unwrap_err().to_string().contains("exceeds"));
+    Ok(())
+}
+
+#[test]
+fn test_ipamd_unavailable_is_retryable() {
+    let client = IpamdClient::new(&temp_state_dir("ipamd-down").join("ipamd.sock"), Duration::from_secs(1));
+    let request = LeaseRequest::Release {
+        network: "corp".to_string(),
+        container_id: "pod-1".to_string(),
+        ifname: "eth1".to_string(),
+    };
+    assert_eq!(CniError::code_of(&client.request(&request).unwrap_err()), CNI_ERR_TRY_AGAIN_LATER);
+}
@@ -0,0 +1,85 @@
+// File: socni/tests/metrics_test.rs
+
+use socni::config::NetConf;
+use socni::ipam::VlanUsage;
+use socni::logging::OpRecord;
+use socni::metrics::{respond, Snapshot, CONTENT_TYPE};
+use socni::state::Attachment;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn attachment(container_id: &str) -> Attachment {
+    Attachment {
+        config: NetConf::new_default("corp", "eth0", 100, None),
+        container_id: container_id.to_string(),
+        netns: "/var/run/netns/test".to_string(),
+        ifname: "eth1".to_string(),
+        tenant: "default".to_string(),
+        added: 1_700_000_000,
+    }
+}
+
+fn record(command: &str, result: &str) -> OpRecord {
+    OpRecord {
+        timestamp: 1_700_000_000,
+        command: command.to_string(),
+        container_id: "abc123".to_string(),
+        network: "corp".to_string(),
+        vlan: 100,
+        ifname: "eth1".to_string(),
+        result: result.to_string(),
+        error: None,
+    }
+}
+
+fn snapshot() -> Snapshot {
+    Snapshot {
+        attachments: vec![attachment("a"), attachment("b")],
+        usage: vec![VlanUsage { vlan: 100, allocated: 2, capacity: 254, networks: vec!["corp".to_string()] }],
+        records: vec![record("ADD", "ok"), record("ADD", "ok"), record("ADD", "error")],
+    }
+}
+
+#[test]
+fn test_render() {
+    let text = snapshot().render();
+    assert!(text.contains("socni_attachments{network=\"corp\",vlan=\"100\"} 2\n"));
+    assert!(text.contains("socni_ipam_allocated{vlan=\"100\"} 2\n"));
+    assert!(text.contains("socni_ipam_capacity{vlan=\"100\"} 254\n"));
+    assert!(text.contains("# TYPE socni_operations gauge\n"));
+    assert!(text.contains("socni_operations{command=\"ADD\",result=\"ok\"} 2\n"));
+    assert!(text.contains("socni_operations{command=\"ADD\",result=\"error\"} 1\n"));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn test_respond() -> anyhow::Result<()> {
+    let body = snapshot().render();
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    client.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+    respond(server, &body, Duration::from_secs(5)).await?;
+
+    let mut response = String::new();
+    client.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.contains(&format!("Content-Type: {}\r\n", CONTENT_TYPE)));
+    assert!(response.ends_with(&body));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_respond_bounds_the_request() -> anyhow::Result<()> {
+    // A scraper that connects and sends nothing is dropped...
+    let (_client, server) = tokio::io::duplex(1024);
+    let stalled = respond(server, "", Duration::from_millis(50)).await;
+    assert!(stalled.unwrap_err().to_string().contains("No request within"));
+
+    // ...and so is one whose headers never end
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    client.write_all(&b"X-Padding: 0\r\n".repeat(1024)).await?;
+    let oversized = respond(server, "", Duration::from_secs(5)).await;
+    assert!(oversized.unwrap_err().to_string().contains("exceeds"));
+
+    Ok(())
+}
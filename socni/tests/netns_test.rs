@@ -0,0 +1,131 @@
+// File: socni/tests/netns_test.rs
+
+use std::panic::{self, AssertUnwindSafe};
+
+use socni::netns::in_netns;
+
+// Namespace inode of the calling thread
+fn current_netns() -> std::io::Result<std::path::PathBuf> {
+    std::fs::read_link("/proc/thread-self/ns/net")
+}
+
+#[test]
+fn test_panicking_closure_restores_netns() -> Result<(), Box<dyn std::error::Error>> {
+    // Skip if not running as root
+    if !nix::unistd::geteuid().is_root() {
+        println!("Skipping test_panicking_closure_restores_netns: not running as root");
+        return Ok(());
+    }
+
+    let netns_name = "test_netns_guard";
+    let _ = std::process::Command::new("ip").args(&["netns", "delete", netns_name]).output();
+    let output = std::process::Command::new("ip").args(&["netns", "add", netns_name]).output()?;
+    if !output.status.success() {
+        return Err(format!("Failed to create netns: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    let before = current_netns()?;
+
+    // A current-thread runtime keeps the closure on this thread, where setns applies
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        runtime.block_on(in_netns(netns_name, || async {
+            panic!("closure panicked inside the namespace");
+            #[allow(unreachable_code)]
+            Ok(())
+        }))
+    }));
+
+    assert!(outcome.is_err());
+    assert_eq!(current_netns()?, before);
+
+    let _ = std::process::Command::new("ip").args(&["netns", "delete", netns_name]).output();
+    Ok(())
+}
+
+#[test]
+fn test_enter_fresh_netns() -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::unix::fs::MetadataExt;
+
+    // Skip if not running as root
+    if !nix::unistd::geteuid().is_root() {
+        println!("Skipping test_enter_fresh_netns: not running as root");
+        return Ok(());
+    }
+
+    let netns_name = "test_netns_enter";
+    let _ = std::process::Command::new("ip").args(["netns", "delete", netns_name]).output();
+    let output = std::process::Command::new("ip").args(["netns", "add", netns_name]).output()?;
+    if !output.status.success() {
+        return Err(format!("Failed to create netns: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    // The namespace file and the thread's namespace share an inode once setns succeeds
+    let before = current_netns()?;
+    let target = std::fs::metadata(std::path::Path::new(socni::netns::NETNS_DIR).join(netns_name))?.ino();
+    let entered = socni::netns::NetnsGuard::enter(netns_name).map(|guard| {
+        let inside = std::fs::metadata("/proc/thread-self/ns/net").map(|m| m.ino());
+        guard.restore();
+        inside
+    });
+
+    let _ = std::process::Command::new("ip").args(["netns", "delete", netns_name]).output();
+    assert_eq!(entered??, target);
+    assert_eq!(current_netns()?, before);
+    Ok(())
+}
+
+#[test]
+fn test_symlinked_netns_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+    // Skip if not running as root
+    if !nix::unistd::geteuid().is_root() {
+        println!("Skipping test_symlinked_netns_is_refused: not running as root");
+        return Ok(());
+    }
+
+    let netns_name = "test_netns_target";
+    let link_name = "test_netns_symlink";
+    let _ = std::process::Command::new("ip").args(&["netns", "delete", netns_name]).output();
+    let output = std::process::Command::new("ip").args(&["netns", "add", netns_name]).output()?;
+    if !output.status.success() {
+        return Err(format!("Failed to create netns: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    let link = std::path::Path::new(socni::netns::NETNS_DIR).join(link_name);
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(std::path::Path::new(socni::netns::NETNS_DIR).join(netns_name), &link)?;
+
+    let direct = socni::netns::open_netns(netns_name);
+    let via_link = socni::netns::open_netns(link_name);
+    let escaping = socni::netns::open_netns("../netns/test_netns_target");
+
+    let _ = std::fs::remove_file(&link);
+    let _ = std::process::Command::new("ip").args(&["netns", "delete", netns_name]).output();
+
+    unsafe { libc::close(direct?) };
+    assert!(via_link.is_err());
+    assert!(escaping.is_err());
+    Ok(())
+}
+
+#[test]
+fn test_absolute_netns_paths() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("socni-netns-path-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+    let file = dir.join("not-a-netns");
+    std::fs::write(&file, b"")?;
+    let link = dir.join("link");
+    std::os::unix::fs::symlink("/proc/self/ns/net", &link)?;
+
+    // Runtimes pass the namespace link of the sandbox process
+    let proc_link = socni::netns::open_netns("/proc/self/ns/net");
+    let regular = socni::netns::open_netns(file.to_str().unwrap());
+    let via_link = socni::netns::open_netns(link.to_str().unwrap());
+    let _ = std::fs::remove_dir_all(&dir);
+
+    unsafe { libc::close(proc_link?) };
+    assert!(regular.unwrap_err().to_string().contains("not a network namespace"));
+    assert!(via_link.is_err());
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// File: socni/tests/numa_test.rs
+
+use std::fs;
+use std::path::PathBuf;
+
+use socni::numa::{apply_numa_affinity, master_numa_node};
+use socni::runner::MockRunner;
+
+// Minimal sysfs tree with a NUMA-local master and a two-queue VLAN link
+fn fake_sysfs(name: &str, numa_node: &str) -> std::io::Result<PathBuf> {
+    let root = std::env::temp_dir().join(format!("socni-sysfs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    
+    fs::create_dir_all(root.join("class/net/eth0/device"))?;
+    fs::write(root.join("class/net/eth0/device/numa_node"), numa_node)?;
+    fs::create_dir_all(root.join("devices/system/node/node1"))?;
+    fs::write(root.join("devices/system/node/node1/cpumap"), "00000000,0000ff00\n")?;
+    for queue in ["rx-0", "tx-0"] {
+        fs::create_dir_all(root.join("class/net/eth0.100/queues").join(queue))?;
+    }
+    
+    Ok(root)
+}
+
+#[test]
+fn test_numa_affinity_written_to_queues() -> anyhow::Result<()> {
+    let sysfs = fake_sysfs("local", "1\n")?;
+    let runner = MockRunner::new();
+    
+    assert_eq!(apply_numa_affinity(&runner, &sysfs, "eth0", "eth0.100")?, Some(1));
+    let queues = sysfs.join("class/net/eth0.100/queues");
+    let mut calls = runner.calls();
+    calls.sort();
+    assert_eq!(calls, [
+        format!("tee {}", queues.join("rx-0/rps_cpus").display()),
+        format!("tee {}", queues.join("tx-0/xps_cpus").display()),
+    ]);
+    assert_eq!(runner.inputs(), ["00000000,0000ff00", "00000000,0000ff00"]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_no_numa_locality() -> anyhow::Result<()> {
+    let sysfs = fake_sysfs("none", "-1\n")?;
+    
+    assert_eq!(master_numa_node(&sysfs, "eth0")?, None);
+    assert_eq!(master_numa_node(&sysfs, "veth0")?, None);
+    let runner = MockRunner::new();
+    assert_eq!(apply_numa_affinity(&runner, &sysfs, "eth0", "eth0.100")?, None);
+    assert!(runner.calls().is_empty());
+    
+    Ok(())
+}
@@ -0,0 +1,55 @@
+// File: socni/tests/oplog_test.rs
+
+use socni::logging::{OpLog, OpRecord, OPLOG_FILE, OPLOG_KEEP};
+
+mod common;
+use common::temp_state_dir;
+
+fn record(command: &str, container_id: &str, error: Option<&str>) -> OpRecord {
+    OpRecord {
+        timestamp: 1_700_000_000,
+        command: command.to_string(),
+        container_id: container_id.to_string(),
+        network: "vlan-network".to_string(),
+        vlan: 100,
+        ifname: "eth1".to_string(),
+        result: if error.is_some() { "error" } else { "ok" }.to_string(),
+        error: error.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_records_read_back_in_order() -> anyhow::Result<()> {
+    let dir = temp_state_dir("order");
+    let log = OpLog::new(&dir);
+    
+    log.append(&record("ADD", "abc", None))?;
+    log.append(&record("CHECK", "abc", Some("address missing")))?;
+    log.append(&record("DEL", "abc", None))?;
+    
+    let records = log.read()?;
+    let commands: Vec<&str> = records.iter().map(|r| r.command.as_str()).collect();
+    assert_eq!(commands, ["ADD", "CHECK", "DEL"]);
+    assert_eq!(records[1].error.as_deref(), Some("address missing"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_log_rotates_and_drops_oldest() -> anyhow::Result<()> {
+    let dir = temp_state_dir("rotate");
+    let line_len = serde_json::to_string(&record("ADD", "c00", None))?.len() as u64 + 1;
+    let log = OpLog::new(&dir).max_bytes(line_len * 2);
+    
+    for i in 0..10 {
+        log.append(&record("ADD", &format!("c{:02}", i), None))?;
+    }
+    
+    // Two records per file and OPLOG_KEEP rotated files behind the live one
+    assert!(dir.join(format!("{}.{}", OPLOG_FILE, OPLOG_KEEP)).exists());
+    assert!(!dir.join(format!("{}.{}", OPLOG_FILE, OPLOG_KEEP + 1)).exists());
+    let ids: Vec<String> = log.read()?.into_iter().map(|r| r.container_id).collect();
+    assert_eq!(ids, ["c02", "c03", "c04", "c05", "c06", "c07", "c08", "c09"]);
+    
+    Ok(())
+}
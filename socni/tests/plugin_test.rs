@@ -0,0 +1,84 @@
+// File: socni/tests/plugin_test.rs
+
+use serde_json::json;
+
+use socni::plugin::{existing_link_mismatch, gateway_needs_onlink, MasterKind};
+
+#[test]
+fn test_master_kind_detection() {
+    let physical = json!({"ifname": "eth0", "flags": ["UP"]});
+    let bond = json!({"ifname": "bond0", "linkinfo": {"info_kind": "bond"}});
+    let bridge = json!({"ifname": "br0", "linkinfo": {"info_kind": "bridge"}});
+    let vxlan = json!({"ifname": "vx0", "linkinfo": {"info_kind": "vxlan"}});
+
+    assert_eq!(MasterKind::from_link(&physical), MasterKind::Physical);
+    assert_eq!(MasterKind::from_link(&bond), MasterKind::Bond);
+    assert_eq!(MasterKind::from_link(&bridge), MasterKind::Bridge);
+    assert_eq!(MasterKind::from_link(&vxlan), MasterKind::Other("vxlan".to_string()));
+}
+
+#[test]
+fn test_gateway_onlink_detection() {
+    assert!(!gateway_needs_onlink("10.0.0.5/24", "10.0.0.1"));
+    assert!(gateway_needs_onlink("10.0.0.5/32", "10.0.0.1"));
+    assert!(gateway_needs_onlink("100.64.10.7/30", "100.64.0.1"));
+    assert!(!gateway_needs_onlink("2001:db8::5/64", "2001:db8::1"));
+    assert!(!gateway_needs_onlink("garbage", "10.0.0.1"));
+}
+
+#[test]
+fn test_existing_link_mismatch() {
+    let ours = json!({"ifname": "eth0.100", "link": "eth0",
+        "linkinfo": {"info_kind": "vlan", "info_data": {"protocol": "802.1Q", "id": 100}}});
+    assert_eq!(existing_link_mismatch(&ours, "eth0", 100, "802.1Q", false), None);
+
+    let other_id = json!({"ifname": "eth0.100", "link": "eth0",
+        "linkinfo": {"info_kind": "vlan", "info_data": {"id": 200}}});
+    assert!(existing_link_mismatch(&other_id, "eth0", 100, "802.1Q", false).unwrap().contains("VLAN id 200"));
+
+    let other_master = json!({"ifname": "eth0.100", "link": "eth1",
+        "linkinfo": {"info_kind": "vlan", "info_data": {"id": 100}}});
+    assert!(existing_link_mismatch(&other_master, "eth0", 100, "802.1Q", false).unwrap().contains("eth1"));
+
+    // Same id, but the tag another conflist asked for
+    assert!(existing_link_mismatch(&ours, "eth0", 100, "802.1ad", false).unwrap().contains("protocol 802.1Q, not 802.1ad"));
+
+    let dummy = json!({"ifname": "eth0.100", "linkinfo": {"info_kind": "dummy"}});
+    assert!(existing_link_mismatch(&dummy, "eth0", 100, "802.1Q", false).unwrap().contains("dummy"));
+
+    let native = json!({"ifname": "eth0.0", "link": "eth0", "linkinfo": {"info_kind": "macvlan"}});
+    assert_eq!(existing_link_mismatch(&native, "eth0", 0, "802.1Q", true), None);
+}
+
+#[test]
+fn test_master_mtu_change_guard() {
+    use socni::config::NetConf;
+    use socni::plugin::check_master_mtu;
+    use socni::state::Attachment;
+    use std::collections::HashMap;
+
+    let attachment = |container: &str, master: &str, mtu: Option<u32>| Attachment {
+        config: NetConf::new_default("corp", master, 100, mtu),
+        container_id: container.to_string(),
+        netns: format!("/var/run/netns/{}", container),
+        ifname: "eth1".to_string(),
+        tenant: "finance".to_string(),
+        added: 0,
+    };
+    let attachments: HashMap<String, Attachment> = [
+        ("corp/pod-a/eth1".to_string(), attachment("pod-a", "eth0", Some(9000))),
+        ("corp/pod-b/eth1".to_string(), attachment("pod-b", "eth0", None)),
+        ("corp/pod-c/eth1".to_string(), attachment("pod-c", "eth2", Some(9000))),
+    ].into_iter().collect();
+
+    // Raising never conflicts
+    assert!(check_master_mtu(&attachments, "eth0", Some(1500), 9000).is_ok());
+    // pod-a uses 9000, pod-b inherited the current 9000
+    let err = check_master_mtu(&attachments, "eth0", Some(9000), 1500).unwrap_err().to_string();
+    assert!(err.contains("container pod-a"), "{}", err);
+    assert!(err.contains("container pod-b"), "{}", err);
+    assert!(!err.contains("pod-c"), "{}", err);
+    // Other masters are not affected
+    assert!(check_master_mtu(&attachments, "eth1", Some(9000), 1500).is_ok());
+    assert!(check_master_mtu(&HashMap::new(), "eth0", Some(9000), 1500).is_ok());
+}
@@ -0,0 +1,80 @@
+// File: socni/tests/reconcile_test.rs
+
+use serde_json::json;
+use std::collections::BTreeMap;
+
+use socni::ipam::{IpamState, Lease};
+use socni::reconcile::{diff, load_networks, Drift};
+
+#[test]
+fn test_load_networks_from_conflists() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("socni-reconcile-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)?;
+
+    std::fs::write(dir.join("10-vlan.conflist"), r#"{
+        "cniVersion": "1.0.0",
+        "name": "finance",
+        "plugins": [
+            {"type": "vlan", "master": "eth0", "vlan": 100, "mtu": 1500},
+            {"type": "portmap"}
+        ]
+    }"#)?;
+    std::fs::write(dir.join("20-bridge.conf"), r#"{"cniVersion": "1.0.0", "name": "br", "type": "bridge"}"#)?;
+    std::fs::write(dir.join("notes.txt"), "not a config")?;
+
+    let networks = load_networks(&dir)?;
+    assert_eq!(networks.len(), 1);
+    assert_eq!(networks[0].name, "finance");
+    assert_eq!(networks[0].vlan, 100);
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_reports_drift() -> anyhow::Result<()> {
+    let mut finance = socni::NetConf::new_default("finance", "eth0", 100, Some(1500));
+    finance.ipam = serde_json::from_value(json!({"type": "host-local", "subnet": "10.100.0.0/24"}))?;
+    let missing = socni::NetConf::new_default("hr", "eth9", 200, None);
+
+    let links = vec![
+        json!({"ifname": "eth0", "mtu": 9000}),
+        json!({"ifname": "eth0.100", "link": "eth0", "mtu": 9000,
+               "linkinfo": {"info_kind": "vlan", "info_data": {"id": 100}}}),
+        json!({"ifname": "eth0.300", "link": "eth0", "mtu": 1500,
+               "linkinfo": {"info_kind": "vlan", "info_data": {"id": 300}}}),
+    ];
+
+    let lease = |id: &str| Lease {
+        container_id: id.to_string(),
+        ifname: "eth1".to_string(),
+        identity: None,
+        allocated: 0,
+        refreshed: 0,
+        netns: None,
+        uid: None,
+    };
+    let mut finance_state = IpamState::default();
+    finance_state.leases.insert("10.100.0.2".to_string(), lease("ok"));
+    finance_state.leases.insert("10.200.0.2".to_string(), lease("moved"));
+    let mut gone_state = IpamState::default();
+    gone_state.leases.insert("10.9.0.2".to_string(), lease("gone"));
+
+    let mut leases = BTreeMap::new();
+    leases.insert("finance".to_string(), finance_state);
+    leases.insert("retired".to_string(), gone_state);
+
+    let drift = diff(&[finance, missing], &links, &leases);
+
+    assert!(drift.contains(&Drift::MissingMaster { network: "hr".into(), master: "eth9".into() }));
+    assert!(drift.contains(&Drift::OrphanedLink { name: "eth0.300".into() }));
+    assert!(drift.contains(&Drift::MtuMismatch { name: "eth0.100".into(), expected: 1500, actual: 9000 }));
+
+    let stale: Vec<&str> = drift.iter().filter_map(|d| match d {
+        Drift::StaleLease { container_id, .. } => Some(container_id.as_str()),
+        _ => None,
+    }).collect();
+    assert_eq!(stale, vec!["moved", "gone"]);
+
+    Ok(())
+}
@@ -0,0 +1,1134 @@
+// File: socni/tests/runner_test.rs
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use socni::backend::IpCommand;
+use socni::config::{IPAMConfig, MacsecConfig, NetConf};
+use socni::integrations::aranya::MacsecSecret;
+use socni::plugin::{add_macsec, VlanPlugin};
+use socni::runner::MockRunner;
+use socni::types::{CmdArgs, CniError, CNI_ERR_TRY_AGAIN_LATER};
+
+mod common;
+use common::{socni_config, temp_state_dir};
+
+fn cmd_args(container_id: &str) -> CmdArgs {
+    CmdArgs {
+        container_id: container_id.to_string(),
+        netns: "/var/run/netns/test".to_string(),
+        ifname: "eth1".to_string(),
+        args: HashMap::new(),
+        path: String::new(),
+        stdin_data: Vec::new(),
+    }
+}
+
+const MASTER: &str = r#"[{"ifname": "eth0", "mtu": 1500, "flags": ["UP"]}]"#;
+
+#[tokio::test]
+async fn test_add_network_command_sequence() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("add"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 100, Some(9000));
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+        "type": "host-local",
+        "subnet": "10.0.0.0/24",
+        "gateway": "10.0.0.1"
+    }))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let mut plugin = VlanPlugin::new(conf, cmd_args("pod-1")).with_socni_config(socni).with_runner(runner.clone());
+    let result = plugin.add_network().await?;
+
+    assert_eq!(runner.calls(), [
+        "ip -d -j link show dev eth0",
+        "ip link add link eth0 name eth0.100 netns /var/run/netns/test mtu 9000 type vlan id 100",
+        "netns /var/run/netns/test",
+        "ip link set dev eth0.100 name eth1",
+        "ip link set dev eth1 up",
+        "ip -s -j link show dev eth1",
+        "ip addr add 10.0.0.2/24 dev eth1",
+        "ip route add default via 10.0.0.1 dev eth1",
+    ]);
+
+    assert_eq!(result.socni("master"), Some(&serde_json::json!("eth0")));
+    assert_eq!(result.socni("mtu"), Some(&serde_json::json!(9000)));
+
+    // A link created in the pod's namespace has no separate move
+    let phases: Vec<_> = plugin.phase_timings().iter().map(|(phase, _)| *phase).collect();
+    assert_eq!(phases, ["aranya", "link-create", "ipam"]);
+
+    // host-local leases the first free address of the subnet
+    let ips = result.ips.unwrap();
+    assert_eq!(ips[0].address, "10.0.0.2/24");
+    assert_eq!(ips[0].interface, Some(0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_falls_back_to_create_and_move() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("fallback"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 200, None);
+    conf.setup_loopback = false;
+
+    // Kernels that can't create the link in another namespace reject the first add
+    let runner = Arc::new(MockRunner::new()
+        .fail("ip -d -j link show dev eth0.200", 1, "Device \"eth0.200\" does not exist.")
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip link add link eth0 name eth0.200 netns", 2, ""));
+    let mut plugin = VlanPlugin::new(conf, cmd_args("pod-2")).with_socni_config(socni).with_runner(runner.clone());
+    plugin.add_network().await?;
+
+    let calls = runner.calls();
+    assert_eq!(calls[1..7], [
+        "ip link add link eth0 name eth0.200 netns /var/run/netns/test mtu 1500 type vlan id 200",
+        "ip -d -j link show dev eth0.200",
+        "ip link add link eth0 name eth0.200 type vlan id 200",
+        "ip link set dev eth0.200 up",
+        "ip link set dev eth0.200 mtu 1500",
+        "ip link set dev eth0.200 netns /var/run/netns/test",
+    ]);
+    assert_eq!(calls[7], "netns /var/run/netns/test");
+
+    // The move is timed apart from creating the link
+    let phases: Vec<_> = plugin.phase_timings().iter().map(|(phase, _)| *phase).collect();
+    assert_eq!(phases, ["aranya", "link-create", "netns-move", "ipam"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_assigns_secondary_address() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("secondary"));
+
+    let mut conf = NetConf::new_default("bearer", "eth0", 300, None);
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+        "type": "host-local",
+        "pools": {"data": {"subnet": "10.10.0.0/24"}},
+        "defaultPool": "data",
+        "secondary": {
+            "subnet": "172.20.0.0/24",
+            "rangeStart": "172.20.0.9",
+            "routes": [{"dst": "172.30.0.0/16"}]
+        }
+    }))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let result = VlanPlugin::new(conf, cmd_args("pod-3"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    let calls = runner.calls();
+    assert_eq!(calls[calls.len() - 4..], [
+        "ip addr add 10.10.0.2/24 dev eth1",
+        "ip route add default via 10.10.0.1 dev eth1",
+        "ip addr add 172.20.0.9/24 dev eth1",
+        "ip route add 172.30.0.0/16 via 172.20.0.1 dev eth1",
+    ]);
+
+    // Both addresses belong to the VLAN interface, each with its own gateway
+    let ips = result.ips.unwrap();
+    assert_eq!(ips.len(), 2);
+    assert_eq!(ips[1].address, "172.20.0.9/24");
+    assert_eq!(ips[1].interface, Some(0));
+    assert_eq!(ips[1].gateway.as_deref(), Some("172.20.0.1"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_reports_gateway_mac() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("gateway-mac"));
+
+    let mut conf = NetConf::new_default("mesh", "eth0", 150, None);
+    conf.setup_loopback = false;
+    conf.resolve_gateway_mac = true;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.150.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -j neigh show 192.168.150.1 dev eth1", 0,
+                 r#"[{"dst": "192.168.150.1", "lladdr": "02:00:00:00:01:01", "state": ["REACHABLE"]}]"#));
+    let result = VlanPlugin::new(conf, cmd_args("pod-4"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    assert_eq!(result.socni("gateway"), Some(&serde_json::json!({"ip": "192.168.150.1", "mac": "02:00:00:00:01:01"})));
+    let output = serde_json::to_value(&result)?;
+    assert_eq!(output["socni"]["gateway"]["mac"], "02:00:00:00:01:01");
+    assert!(output.get("gateway").is_none());
+    assert!(runner.calls().contains(&"ip neigh replace 192.168.150.1 dev eth1 use".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verify_gateway_reports_reachability() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("verify-gateway"));
+
+    let mut conf = NetConf::new_default("mesh", "eth0", 160, None);
+    conf.setup_loopback = false;
+    conf.verify_gateway = true;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.160.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -j neigh show 192.168.160.1 dev eth1", 0,
+                 r#"[{"dst": "192.168.160.1", "lladdr": "02:00:00:00:01:01", "state": ["REACHABLE"]}]"#));
+    let result = VlanPlugin::new(conf.clone(), cmd_args("pod-13"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+    assert_eq!(result.socni("gateway"), Some(&serde_json::json!({"ip": "192.168.160.1", "reachable": true})));
+
+    // A silent gateway only warns...
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let result = VlanPlugin::new(conf.clone(), cmd_args("pod-14"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+    assert_eq!(result.socni("gateway").map(|g| &g["reachable"]), Some(&serde_json::json!(false)));
+    assert!(result.warnings().iter().any(|w| w.contains("Gateway 192.168.160.1 did not answer")));
+
+    // ...unless it is required
+    conf.require_gateway = true;
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let err = VlanPlugin::new(conf, cmd_args("pod-15"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("did not answer"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check_accepts_down_link_without_admin_up() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("admin-up"));
+
+    let link = r#"[{"ifname": "eth1", "flags": ["BROADCAST", "MULTICAST"],
+                    "linkinfo": {"info_kind": "vlan", "info_data": {"id": 100}}, "addr_info": []}]"#;
+    let check = |admin_up: bool| {
+        let mut conf = NetConf::new_default("late", "eth0", 100, None);
+        conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "none"})).unwrap());
+        conf.admin_up = admin_up;
+        let runner = Arc::new(MockRunner::new().respond("ip -d -j addr show dev eth1", 0, link));
+        VlanPlugin::new(conf, cmd_args("pod-5")).with_socni_config(socni.clone()).with_runner(runner)
+    };
+
+    let items = check(false).check_report().await?;
+    assert!(items.iter().all(|item| item.error.is_none()), "{:?}", items);
+    assert!(!items.iter().any(|item| item.name == "up"));
+
+    let items = check(true).check_report().await?;
+    let up = items.iter().find(|item| item.name == "up").unwrap();
+    assert_eq!(up.error.as_deref(), Some("Interface eth1 is not up"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check_without_lease_fails() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("check-no-lease"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 100, None);
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "10.0.0.0/24"}))?);
+    let link = r#"[{"ifname": "eth1", "flags": ["UP"],
+                    "linkinfo": {"info_kind": "vlan", "info_data": {"id": 100}},
+                    "addr_info": [{"family": "inet", "local": "192.168.100.2", "prefixlen": 24, "scope": "global"}]}]"#;
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j addr show dev eth1", 0, link));
+    let items = VlanPlugin::new(conf, cmd_args("pod-6"))
+        .with_socni_config(socni)
+        .with_runner(runner)
+        .check_report()
+        .await?;
+
+    // No address is expected without a lease, whatever the interface holds
+    let addresses = items.iter().find(|item| item.name == "addresses").unwrap();
+    assert_eq!(addresses.error.as_deref(), Some("Interface eth1 holds no host-local IPAM address"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_without_admin_up_defers_routes() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("admin-up-routes"));
+
+    let mut conf = NetConf::new_default("late", "eth0", 180, None);
+    conf.setup_loopback = false;
+    conf.admin_up = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.180.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let result = VlanPlugin::new(conf, cmd_args("pod-31"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    // Setting the link down flushed the routes, so the result doesn't claim them
+    assert!(runner.calls().contains(&"ip link set dev eth1 down".to_string()));
+    assert!(result.routes.is_none());
+    let deferred = result.socni("deferredRoutes").and_then(|r| r.as_array()).unwrap();
+    assert_eq!(deferred[0]["gw"], "192.168.180.1");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_macsec_without_aranya_creates_nothing() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("macsec"));
+
+    let mut conf = NetConf::new_default("sealed", "eth0", 100, None);
+    conf.macsec = Some(serde_json::from_value(serde_json::json!({"peers": ["02:00:00:00:00:01"]}))?);
+
+    // No key means no plaintext fallback, and nothing to clean up
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let err = VlanPlugin::new(conf, cmd_args("pod-6"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await
+        .unwrap_err();
+
+    assert!(format!("{:#}", err).contains("needs its key from Aranya"), "{:#}", err);
+    assert!(runner.calls().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_macsec_keys_stay_off_the_command_line() -> anyhow::Result<()> {
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -s -j link show dev eth1", 0, r#"[{"ifname": "eth1", "address": "02:00:00:00:00:0a"}]"#));
+    let backend = IpCommand::new(runner.clone());
+    let macsec: MacsecConfig = serde_json::from_value(serde_json::json!({"peers": ["02:00:00:00:00:01"]}))?;
+    let secret = MacsecSecret::new(b"0123456789abcdef0123456789abcdef", "team-a", "label-100", 100);
+
+    add_macsec(&backend, "eth0.100", "eth1", Some("02:00:00:00:00:0a"), &macsec, &secret)?;
+
+    // The pinned MAC is on the lower link before the device inherits it
+    let tx = secret.key_for("02:00:00:00:00:0a", 16)?;
+    let rx = secret.key_for("02:00:00:00:00:01", 16)?;
+    let calls = runner.calls();
+    assert_eq!(calls[..3], [
+        "ip link set dev eth0.100 address 02:00:00:00:00:0a",
+        "ip link set dev eth0.100 up",
+        "ip link add link eth0.100 name eth1 type macsec port 1 encrypt on cipher gcm-aes-xpn-128",
+    ]);
+    assert_eq!(calls.last().unwrap(), "ip -batch -");
+    assert!(calls.iter().all(|call| !call.contains(&tx.key_hex()) && !call.contains(&rx.key_hex())));
+
+    // Each sender has its own key; ours starts at a clock-based packet number
+    let batch = runner.inputs().concat();
+    let lines: Vec<&str> = batch.lines().collect();
+    assert_eq!(lines.len(), 3);
+    let first_pn: u64 = lines[0].split(' ').nth(7).unwrap().parse()?;
+    assert!(first_pn > 1_600_000_000 * 1_000_000_000);
+    assert_eq!(lines[0], format!("macsec add eth1 tx sa 0 xpn {} on ssci 1 salt {} key {} {}",
+                                 first_pn, tx.salt_hex(), tx.id_hex(), tx.key_hex()));
+    assert_eq!(lines[1], "macsec add eth1 rx port 1 address 02:00:00:00:00:01");
+    assert_eq!(lines[2], format!("macsec add eth1 rx port 1 address 02:00:00:00:00:01 sa 0 xpn 1 on ssci 1 salt {} key {} {}",
+                                 rx.salt_hex(), rx.id_hex(), rx.key_hex()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_reports_warnings() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("warnings"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 160, None);
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.160.0/24"}))?);
+
+    // The pod is still usable through its address, so ADD succeeds
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip route add default via 192.168.160.1 dev eth1", 2, ""));
+    let result = VlanPlugin::new(conf, cmd_args("pod-7"))
+        .with_socni_config(socni)
+        .with_runner(runner)
+        .add_network()
+        .await?;
+
+    let warnings = result.warnings();
+    assert_eq!(warnings, ["Failed to add default route via 192.168.160.1: mock failure"]);
+    assert_eq!(serde_json::to_value(&result)?["socni"]["warnings"].as_array().unwrap().len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_records_stats_baseline() -> anyhow::Result<()> {
+    let dir = temp_state_dir("baseline");
+    let socni = socni_config(&dir);
+
+    let mut conf = NetConf::new_default("corp", "eth0", 170, None);
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.170.0/24"}))?);
+
+    let link = r#"[{"ifname": "eth1", "address": "02:00:00:00:00:aa",
+                    "stats64": {"rx": {"bytes": 1200, "packets": 10, "dropped": 1},
+                                "tx": {"bytes": 800, "packets": 6, "dropped": 0}}}]"#;
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -s -j link show dev eth1", 0, link));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-8"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+
+    let baseline = socni::state::StateStore::new(&dir).load_baseline("corp", "pod-8", "eth1")?.unwrap();
+    assert_eq!(baseline.stats.rx_bytes, 1200);
+    assert_eq!(baseline.stats.tx_packets, 6);
+
+    // Traffic is reported from the baseline on
+    let later = r#"[{"ifname": "eth1", "stats64": {"rx": {"bytes": 5200, "packets": 40, "dropped": 1},
+                                                   "tx": {"bytes": 900, "packets": 7, "dropped": 0}}}]"#;
+    let runner = Arc::new(MockRunner::new().respond("ip -s -j link show dev eth1", 0, later));
+    let current = VlanPlugin::new(conf, cmd_args("pod-8")).with_socni_config(socni.clone()).with_runner(runner).link_stats().await?;
+    let delta = current.since(&baseline.stats);
+    assert_eq!((delta.rx_bytes, delta.rx_packets, delta.rx_dropped), (4000, 30, 0));
+    assert_eq!((delta.tx_bytes, delta.tx_packets), (100, 1));
+
+    // Lower counters mean the link was recreated since the baseline
+    assert_eq!(baseline.stats.since(&current), baseline.stats);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_status_reads_mtu_inside_the_pod() -> anyhow::Result<()> {
+    let dir = temp_state_dir("status-mtu");
+    let socni = socni_config(&dir);
+
+    let mut conf = NetConf::new_default("corp", "eth0", 171, None);
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.171.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-9"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+
+    // The inherited MTU is recorded for status to fall back on
+    let recorded = socni::state::StateStore::new(&dir).load_result("corp", "pod-9", "eth1")?.unwrap();
+    assert_eq!(recorded.mtu(), Some(1500));
+
+    let link = r#"[{"ifname": "eth1", "mtu": 1500, "operstate": "UP"}]"#;
+    let runner = Arc::new(MockRunner::new().respond("ip -s -j link show dev eth1", 0, link));
+    let live = VlanPlugin::new(conf, cmd_args("pod-9")).with_socni_config(socni).with_runner(runner.clone()).link().await?;
+    assert_eq!(runner.calls(), ["netns /var/run/netns/test", "ip -s -j link show dev eth1"]);
+    assert_eq!(live["mtu"], 1500);
+    assert_eq!(live["operstate"], "UP");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_reuses_only_matching_host_link() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("reuse"));
+
+    let add = |existing: &'static str| {
+        let mut conf = NetConf::new_default("corp", "eth0", 210, None);
+        conf.setup_loopback = false;
+        let runner = Arc::new(MockRunner::new()
+            .respond("ip -d -j link show dev eth0.210", 0, existing)
+            .respond("ip -d -j link show dev eth0", 0, MASTER)
+            .respond("ip link add link eth0 name eth0.210 netns", 2, ""));
+        (VlanPlugin::new(conf, cmd_args("pod-9")).with_socni_config(socni.clone()).with_runner(runner.clone()), runner)
+    };
+
+    // A link left by an earlier ADD of this network is reused without creating another
+    let (mut plugin, runner) = add(r#"[{"ifname": "eth0.210", "link": "eth0",
+                                        "linkinfo": {"info_kind": "vlan", "info_data": {"protocol": "802.1Q", "id": 210}}}]"#);
+    plugin.add_network().await?;
+    assert!(!runner.calls().contains(&"ip link add link eth0 name eth0.210 type vlan id 210".to_string()));
+    assert!(runner.calls().contains(&"ip link set dev eth0.210 netns /var/run/netns/test".to_string()));
+
+    // Nor is a link with the same id but an 802.1ad tag
+    let (mut plugin, runner) = add(r#"[{"ifname": "eth0.210", "link": "eth0",
+                                        "linkinfo": {"info_kind": "vlan", "info_data": {"protocol": "802.1ad", "id": 210}}}]"#);
+    let err = plugin.add_network().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("uses protocol 802.1ad, not 802.1Q"));
+    assert!(!runner.calls().iter().any(|call| call.starts_with("ip link set dev eth0.210")));
+
+    // Someone else's link of the same name is never taken over
+    let (mut plugin, runner) = add(r#"[{"ifname": "eth0.210", "linkinfo": {"info_kind": "dummy"}}]"#);
+    let err = plugin.add_network().await.unwrap_err();
+    assert!(format!("{:#}", err).contains("refusing to reuse it"), "{:#}", err);
+    assert!(!runner.calls().iter().any(|call| call.starts_with("ip link set dev eth0.210")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_data_dir_keeps_network_allocations_apart() -> anyhow::Result<()> {
+    let state = temp_state_dir("data-dir");
+    let socni = socni_config(&state);
+
+    // Two segments that reuse a name and an overlapping subnet on one node
+    let mut allocated = Vec::new();
+    for (segment, container) in [("a", "pod-10"), ("b", "pod-11")] {
+        let mut conf = NetConf::new_default("corp", "eth0", 220, None);
+        conf.setup_loopback = false;
+        conf.data_dir = Some(state.join(segment));
+        conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+            "type": "host-local",
+            "subnet": "10.0.0.0/24",
+            "gateway": "10.0.0.1"
+        }))?);
+
+        let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+        let result = VlanPlugin::new(conf, cmd_args(container))
+            .with_socni_config(socni.clone())
+            .with_runner(runner)
+            .add_network()
+            .await?;
+        allocated.push(result.ips.unwrap()[0].address.clone());
+    }
+
+    // Neither sees the other's lease, so both start from the bottom of the range
+    assert_eq!(allocated, ["10.0.0.2/24", "10.0.0.2/24"]);
+    let leases = socni::ipam::HostLocal::new(state.join("a")).state("corp")?.leases;
+    assert_eq!(leases.values().map(|l| l.container_id.as_str()).collect::<Vec<_>>(), ["pod-10"]);
+    assert!(socni::ipam::HostLocal::new(&state).networks()?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_registry_is_best_effort() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("registry"));
+
+    let plugin = |registry: serde_json::Value, runner: Arc<MockRunner>| -> anyhow::Result<VlanPlugin> {
+        let mut conf = NetConf::new_default("corp", "eth0", 180, None);
+        conf.setup_loopback = false;
+        conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.180.0/24"}))?);
+        conf.registry = Some(serde_json::from_value(registry)?);
+        let mut args = cmd_args("pod-12");
+        args.args.insert("K8S_POD_NAMESPACE".to_string(), "shop".to_string());
+        args.args.insert("K8S_POD_NAME".to_string(), "cart-0".to_string());
+        Ok(VlanPlugin::new(conf, args).with_socni_config(socni.clone()).with_runner(runner))
+    };
+
+    // Consul stores the address under the rendered key, and DEL removes it
+    let consul = serde_json::json!({"type": "consul", "endpoint": "http://127.0.0.1:8500/", "key": "pods/{namespace}/{name}"});
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let result = plugin(consul.clone(), runner.clone())?.add_network().await?;
+    assert!(result.warnings().is_empty());
+    assert!(runner.calls().contains(&"curl -sS -f --max-time 2 -X PUT --data-binary 192.168.180.2 \
+                                      http://127.0.0.1:8500/v1/kv/pods/shop/cart-0".to_string()));
+
+    let entry = |value: &str| format!(r#"[{{"Key": "pods/shop/cart-0", "ModifyIndex": 42, "Value": "{}"}}]"#, value);
+    let runner = Arc::new(MockRunner::new()
+        .respond("curl -sS --max-time 2 -X GET", 0, &entry("MTkyLjE2OC4xODAuMg==")));
+    plugin(consul.clone(), runner.clone())?.del_network().await?;
+    assert!(runner.calls().contains(&"curl -sS -f --max-time 2 -X DELETE \
+                                      http://127.0.0.1:8500/v1/kv/pods/shop/cart-0?cas=42".to_string()));
+
+    // A key a newer pod of the same name has taken over stays
+    let runner = Arc::new(MockRunner::new()
+        .respond("curl -sS --max-time 2 -X GET", 0, &entry("MTkyLjE2OC4xODAuMw==")));
+    plugin(consul, runner.clone())?.del_network().await?;
+    assert!(!runner.calls().iter().any(|call| call.contains("-X DELETE")));
+
+    // An etcd outage leaves the pod networked and is reported as a warning
+    let etcd = serde_json::json!({"type": "etcd", "endpoint": "http://127.0.0.1:2379", "key": "/{network}/{name}"});
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("curl", 7, ""));
+    let result = plugin(etcd, runner.clone())?.add_network().await?;
+    let put = runner.calls().into_iter().find(|c| c.starts_with("curl")).unwrap();
+    assert_eq!(put, r#"curl -sS -f --max-time 2 -X POST --data-binary {"key":"L2NvcnAvY2FydC0w","value":"MTkyLjE2OC4xODAuMg=="} http://127.0.0.1:2379/v3/kv/put"#);
+    let warnings = result.warnings();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].starts_with("Failed to register 192.168.180.2 in the Etcd registry"), "{}", warnings[0]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_source_routing() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("source-routing"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 190, None);
+    conf.setup_loopback = false;
+    conf.route_table = Some(190);
+    conf.source_routing = true;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.190.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-16"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+    let calls = runner.calls();
+    let start = calls.iter().position(|c| c == "ip route add default via 192.168.190.1 dev eth1").unwrap();
+    assert_eq!(calls[start + 1..start + 4], [
+        "ip route add 192.168.190.0/24 dev eth1 table 190",
+        "ip route add default via 192.168.190.1 dev eth1 table 190",
+        "ip rule add from 192.168.190.2 lookup 190",
+    ]);
+
+    // The rule outlives the link, so DEL removes it
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf, cmd_args("pod-16"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .del_network()
+        .await?;
+    assert!(runner.calls().contains(&"ip rule del from 192.168.190.2 lookup 190".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_check_connectivity() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("connectivity"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 200, None);
+    conf.setup_loopback = false;
+    conf.check_connectivity = true;
+    conf.connectivity_target = Some("10.9.9.9".to_string());
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({"type": "host-local", "subnet": "192.168.200.0/24"}))?);
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-17"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+
+    // The gateway answers, the target behind it doesn't
+    let link = r#"[{"ifname": "eth1", "flags": ["BROADCAST", "UP"],
+                    "linkinfo": {"info_kind": "vlan", "info_data": {"id": 200}}, "addr_info": []}]"#;
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j addr show dev eth1", 0, link)
+        .respond("ping -c 1 -W 1 -I eth1 10.9.9.9", 1, ""));
+    let items = VlanPlugin::new(conf, cmd_args("pod-17"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .check_report()
+        .await?;
+
+    assert!(runner.calls().contains(&"ping -c 1 -W 1 -I eth1 192.168.200.1".to_string()));
+    let connectivity = items.iter().find(|item| item.name == "connectivity").unwrap();
+    assert_eq!(connectivity.error.as_deref(), Some("Interface eth1 has no connectivity: 10.9.9.9 did not answer within 1s"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_add_rolls_back_link_and_lease() -> anyhow::Result<()> {
+    let state = temp_state_dir("rollback");
+    let socni = socni_config(&state);
+
+    let mut conf = NetConf::new_default("corp", "eth0", 230, None);
+    conf.setup_loopback = false;
+    conf.data_dir = Some(state.clone());
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+        "type": "host-local",
+        "subnet": "10.0.0.0/24",
+        "gateway": "10.0.0.1"
+    }))?);
+
+    // The link is created and renamed before its address is refused
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip addr add", 2, ""));
+    let added = VlanPlugin::new(conf, cmd_args("pod-18"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await;
+    assert!(added.is_err());
+
+    let calls = runner.calls();
+    assert_eq!(calls[calls.len() - 2..], ["netns /var/run/netns/test", "ip link delete eth1"]);
+    assert!(socni::ipam::HostLocal::new(&state).state("corp")?.leases.is_empty());
+
+    Ok(())
+}
+
+fn companion_conf(vlan: u16) -> anyhow::Result<NetConf> {
+    let mut conf = NetConf::new_default("corp", "eth0", vlan, None);
+    conf.setup_loopback = false;
+    conf.bridge_companion = Some(serde_json::from_value(serde_json::json!({
+        "bridge": "cni-int",
+        "ipam": {"subnet": "172.16.0.0/24"}
+    }))?);
+    Ok(conf)
+}
+
+#[tokio::test]
+async fn test_bridge_companion_leases_per_pod() -> anyhow::Result<()> {
+    let state = temp_state_dir("companion");
+    let socni = socni_config(&state);
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -d -j link show dev socni", 0, r#"[{"ifname": "socni", "address": "02:00:00:00:0b:01"}]"#)
+        .respond("ip -d -j link show dev br1", 0, r#"[{"ifname": "br1", "address": "02:00:00:00:0b:02"}]"#));
+    let mut addresses = Vec::new();
+    for pod in ["pod-40", "pod-41"] {
+        let result = VlanPlugin::new(companion_conf(250)?, cmd_args(pod))
+            .with_socni_config(socni.clone())
+            .with_runner(runner.clone())
+            .add_network()
+            .await?;
+
+        // Both ends of the pair are reported with their MACs
+        let interfaces = result.interfaces.unwrap();
+        assert_eq!(interfaces[1].mac.as_deref(), Some("02:00:00:00:0b:01"));
+        assert_eq!(interfaces[2].mac.as_deref(), Some("02:00:00:00:0b:02"));
+
+        let ips = result.ips.unwrap();
+        assert_eq!(ips.last().unwrap().interface, Some(2));
+        addresses.push(ips.last().unwrap().address.clone());
+    }
+
+    // Pods sharing the bridge never share an address
+    assert_eq!(addresses, ["172.16.0.2/24", "172.16.0.3/24"]);
+
+    VlanPlugin::new(companion_conf(250)?, cmd_args("pod-40"))
+        .with_socni_config(socni)
+        .with_runner(runner)
+        .del_network()
+        .await?;
+    let leases = socni::ipam::HostLocal::new(&state).state("corp/companion")?.leases;
+    assert_eq!(leases.len(), 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_companion_removes_host_veth() -> anyhow::Result<()> {
+    let state = temp_state_dir("companion-rollback");
+    let socni = socni_config(&state);
+
+    // The pair exists by the time the pod end refuses its address
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip addr add 172.16.0.2/24 dev br1", 2, ""));
+    let added = VlanPlugin::new(companion_conf(251)?, cmd_args("pod-42"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await;
+    assert!(added.is_err());
+
+    let host_ifname = companion_conf(251)?.bridge_companion.unwrap().host_ifname("pod-42");
+    assert!(runner.calls().contains(&format!("ip link delete {}", host_ifname)));
+    assert!(socni::ipam::HostLocal::new(&state).state("corp/companion")?.leases.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interrupted_add_creates_nothing() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("interrupt"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 240, None);
+    conf.setup_loopback = false;
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let err = VlanPlugin::new(conf, cmd_args("pod-19"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .with_interrupt(Arc::new(AtomicBool::new(true)))
+        .add_network()
+        .await
+        .unwrap_err();
+
+    assert!(err.to_string().contains("SIGTERM"));
+    assert!(!runner.calls().iter().any(|call| call.starts_with("ip link add")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_qinq_stacks_on_shared_outer_link() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("qinq"));
+
+    let conf = |inner: u16| {
+        let mut conf = NetConf::new_default("corp", "eth0", 300, None);
+        conf.setup_loopback = false;
+        conf.inner_vlan = Some(inner);
+        conf
+    };
+
+    // The first pod creates the outer link, the second finds it
+    let runner = Arc::new(MockRunner::new()
+        .fail("ip -d -j link show dev eth0.300", 1, "Device \"eth0.300\" does not exist.")
+        .respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf(20), cmd_args("pod-20"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+    assert_eq!(runner.calls()[1..5], [
+        "ip -d -j link show dev eth0.300",
+        "ip link add link eth0 name eth0.300 type vlan id 300 protocol 802.1ad",
+        "ip link set dev eth0.300 up",
+        "ip link add link eth0.300 name eth0.300.20 netns /var/run/netns/test mtu 1500 type vlan id 20",
+    ]);
+
+    let outer = r#"[{"ifname": "eth0.300", "link": "eth0",
+                     "linkinfo": {"info_kind": "vlan", "info_data": {"protocol": "802.1ad", "id": 300}}}]"#;
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0.300", 0, outer)
+        .respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf(21), cmd_args("pod-21"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+    assert!(!runner.calls().iter().any(|call| call.starts_with("ip link add link eth0 ")));
+
+    // Only the last pod's DEL removes the outer link
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf(20), cmd_args("pod-20")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert!(runner.calls().contains(&"ip link delete eth1".to_string()));
+    assert!(!runner.calls().contains(&"ip link delete eth0.300".to_string()));
+
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf(21), cmd_args("pod-21")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert_eq!(runner.calls().last().map(String::as_str), Some("ip link delete eth0.300"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_link_owned_by_another_network_is_refused() -> anyhow::Result<()> {
+    let state = temp_state_dir("host-link-owner");
+    let socni = socni_config(&state);
+
+    // The shared outer link of a QinQ network stays on the host
+    let mut conf = NetConf::new_default("corp", "eth0", 310, None);
+    conf.setup_loopback = false;
+    conf.inner_vlan = Some(20);
+    let runner = Arc::new(MockRunner::new()
+        .fail("ip -d -j link show dev eth0.310", 1, "Device \"eth0.310\" does not exist.")
+        .respond("ip -d -j link show dev eth0", 0, MASTER));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-22"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner)
+        .add_network()
+        .await?;
+    let claims = socni::state::StateStore::new(&state).host_links()?;
+    assert_eq!(claims["eth0.310"].network, "corp");
+    assert_eq!(claims["eth0.310"].container_id, None);
+
+    // A different conflist that picked the same master and VLAN
+    conf.name = "lab".to_string();
+    conf.inner_vlan = Some(30);
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let err = VlanPlugin::new(conf, cmd_args("pod-23"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("belongs to network corp"));
+    assert!(!runner.calls().iter().any(|call| call.starts_with("ip link add")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_del_removes_leaked_host_link() -> anyhow::Result<()> {
+    let state = temp_state_dir("leaked-host-link");
+    let socni = socni_config(&state);
+
+    let mut conf = NetConf::new_default("corp", "eth0", 320, None);
+    conf.setup_loopback = false;
+
+    // An ADD killed after creating eth0.320 on the host, before moving it
+    let store = socni::state::StateStore::new(&state);
+    store.claim_host_link("eth0.320", "corp", Some("pod-24"))?;
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf.clone(), cmd_args("pod-24")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert!(runner.calls().contains(&"ip link delete eth0.320".to_string()));
+    assert!(store.host_links()?.is_empty());
+
+    // The link another pod of the network is being added through stays
+    store.claim_host_link("eth0.320", "corp", Some("pod-25"))?;
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf.clone(), cmd_args("pod-24")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert!(!runner.calls().contains(&"ip link delete eth0.320".to_string()));
+    assert_eq!(store.host_links()?["eth0.320"].container_id.as_deref(), Some("pod-25"));
+    store.release_host_link("eth0.320", "corp")?;
+
+    // Another network's link of the same name is not ours to delete
+    store.claim_host_link("eth0.320", "lab", Some("pod-24"))?;
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf.clone(), cmd_args("pod-24")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert!(!runner.calls().contains(&"ip link delete eth0.320".to_string()));
+    assert_eq!(store.host_links()?["eth0.320"].network, "lab");
+    store.release_host_link("eth0.320", "lab")?;
+
+    // Nor is a link of that name no ADD of ours recorded
+    let runner = Arc::new(MockRunner::new());
+    VlanPlugin::new(conf, cmd_args("pod-24")).with_socni_config(socni.clone()).with_runner(runner.clone()).del_network().await?;
+    assert!(!runner.calls().contains(&"ip link delete eth0.320".to_string()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_local_adds_get_distinct_addresses() -> anyhow::Result<()> {
+    let state = temp_state_dir("host-local-distinct");
+    let socni = socni_config(&state);
+
+    let mut conf = NetConf::new_default("corp", "eth0", 330, None);
+    conf.setup_loopback = false;
+    conf.data_dir = Some(state.clone());
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+        "type": "host-local",
+        "subnet": "10.0.0.0/24",
+        "gateway": "10.0.0.1"
+    }))?);
+
+    // Two pods on the same VLAN, added at once from their own threads like
+    // two plugin processes would be
+    let add = |container: &str| {
+        let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+        let mut plugin = VlanPlugin::new(conf.clone(), cmd_args(container)).with_socni_config(socni.clone()).with_runner(runner);
+        std::thread::spawn(move || -> anyhow::Result<_> {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            runtime.block_on(plugin.add_network())
+        })
+    };
+    let (first, second) = (add("pod-25"), add("pod-26"));
+    let (first, second) = (first.join().unwrap()?, second.join().unwrap()?);
+    let mut addresses = vec![first.ips.unwrap()[0].address.clone(), second.ips.unwrap()[0].address.clone()];
+    addresses.sort();
+    assert_eq!(addresses, ["10.0.0.2/24", "10.0.0.3/24"]);
+
+    // DEL frees only that pod's address
+    VlanPlugin::new(conf.clone(), cmd_args("pod-25"))
+        .with_socni_config(socni.clone())
+        .with_runner(Arc::new(MockRunner::new()))
+        .del_network()
+        .await?;
+    let leases = socni::ipam::HostLocal::new(&state).state("corp")?.leases;
+    assert_eq!(leases.values().map(|l| l.container_id.as_str()).collect::<Vec<_>>(), ["pod-26"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dual_stack_add_reports_both_families() -> anyhow::Result<()> {
+    let state = temp_state_dir("dual-stack");
+    let socni = socni_config(&state);
+
+    let conf = NetConf::parse(br#"{
+        "cniVersion": "1.0.0",
+        "name": "corp",
+        "type": "vlan",
+        "master": "eth0",
+        "vlan": 340,
+        "setupLoopback": false,
+        "ipam": {
+            "type": "host-local",
+            "ranges": [{"subnet": "10.0.0.0/24"}, {"subnet": "fd00::/64", "gateway": "fd00::1"}]
+        }
+    }"#)?;
+    let conf = NetConf { data_dir: Some(state.clone()), ..conf };
+
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let result = VlanPlugin::new(conf.clone(), cmd_args("pod-27"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    let ips: Vec<(String, Option<String>)> = result.ips.unwrap().into_iter().map(|ip| (ip.address, ip.gateway)).collect();
+    assert_eq!(ips, [
+        ("10.0.0.2/24".to_string(), Some("10.0.0.1".to_string())),
+        ("fd00::2/64".to_string(), Some("fd00::1".to_string())),
+    ]);
+    let defaults: Vec<String> = result.routes.unwrap().into_iter().map(|r| r.dst).collect();
+    assert_eq!(defaults, ["0.0.0.0/0", "::/0"]);
+
+    let calls = runner.calls();
+    assert!(calls.contains(&"ip route add default via 10.0.0.1 dev eth1".to_string()));
+    assert!(calls.contains(&"ip addr add fd00::2/64 dev eth1".to_string()));
+    assert!(calls.contains(&"ip -6 route add default via fd00::1 dev eth1".to_string()));
+
+    // DEL frees the lease of each family
+    VlanPlugin::new(conf, cmd_args("pod-27"))
+        .with_socni_config(socni.clone())
+        .with_runner(Arc::new(MockRunner::new()))
+        .del_network()
+        .await?;
+    let ipam = socni::ipam::HostLocal::new(&state);
+    assert!(ipam.state("corp")?.leases.is_empty());
+    assert!(ipam.networks()?.contains(&"corp/ipv6".to_string()));
+    assert!(ipam.state("corp/ipv6")?.leases.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_add_network_reports_interface_mac() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("interface-mac"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 350, None);
+    conf.setup_loopback = false;
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -s -j link show dev eth1", 0, r#"[{"ifname": "eth1", "address": "02:42:ac:11:00:1e"}]"#));
+    let result = VlanPlugin::new(conf, cmd_args("pod-28"))
+        .with_socni_config(socni)
+        .with_runner(runner)
+        .add_network()
+        .await?;
+
+    // Read back from the link once it is up in the pod's namespace
+    let interfaces = result.interfaces.unwrap();
+    let mac = interfaces[0].mac.as_deref().unwrap();
+    assert_eq!(mac, "02:42:ac:11:00:1e");
+    assert!(socni::config::is_valid_mac(mac));
+    assert_eq!(interfaces[0].sandbox.as_deref(), Some("/var/run/netns/test"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fixed_mac_is_set_before_link_up() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("fixed-mac"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 360, None);
+    conf.setup_loopback = false;
+    conf.mac = Some("02:42:ac:11:00:02".to_string());
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("ip -s -j link show dev eth1", 0, r#"[{"ifname": "eth1", "address": "02:42:ac:11:00:02"}]"#));
+    VlanPlugin::new(conf.clone(), cmd_args("pod-29"))
+        .with_socni_config(socni.clone())
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    let calls = runner.calls();
+    let position = |call: &str| calls.iter().position(|c| c == call);
+    let address = position("ip link set dev eth1 address 02:42:ac:11:00:02").expect("MAC was not applied");
+    assert!(address < position("ip link set dev eth1 up").unwrap());
+
+    // A second pod can't take the MAC while the first is attached
+    let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+    let err = VlanPlugin::new(conf, cmd_args("pod-32"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("already used by pod-29"), "{:#}", err);
+    assert_eq!(runner.calls(), ["ip -d -j link show dev eth0"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cni_args_mac_must_be_unicast() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("cni-args-mac"));
+
+    for (mac, reason) in [("01:00:5e:00:00:01", "multicast"), ("00:00:00:00:00:00", "all zeros")] {
+        let mut conf = NetConf::new_default("corp", "eth0", 360, None);
+        conf.setup_loopback = false;
+        let mut args = cmd_args("pod-33");
+        args.args.insert("MAC".to_string(), mac.to_string());
+
+        let runner = Arc::new(MockRunner::new().respond("ip -d -j link show dev eth0", 0, MASTER));
+        let err = VlanPlugin::new(conf, args)
+            .with_socni_config(socni.clone())
+            .with_runner(runner.clone())
+            .add_network()
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains(reason), "{:#}", err);
+        // Rejected before a link exists to roll back
+        assert_eq!(runner.calls(), ["ip -d -j link show dev eth0"]);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sysctls_and_firewall_go_through_the_runner() -> anyhow::Result<()> {
+    let socni = socni_config(temp_state_dir("sysctl-firewall"));
+
+    let mut conf = NetConf::new_default("corp", "eth0", 370, None);
+    conf.setup_loopback = false;
+    conf.disable_ipv6 = true;
+    conf.firewall = Some(serde_json::from_value(serde_json::json!({"allow": [{"cidr": "10.0.0.0/8"}]}))?);
+
+    let runner = Arc::new(MockRunner::new()
+        .respond("ip -d -j link show dev eth0", 0, MASTER)
+        .respond("nft -a -j list chain inet socni", 0, r#"{"nftables": [{"rule": {"handle": 4}}]}"#));
+    VlanPlugin::new(conf, cmd_args("pod-30"))
+        .with_socni_config(socni)
+        .with_runner(runner.clone())
+        .add_network()
+        .await?;
+
+    // IPv6 is off before the link comes up, and the ruleset goes to nft on stdin
+    let calls = runner.calls();
+    let position = |call: &str| calls.iter().position(|c| c == call);
+    let disabled = position("sysctl -q -w net/ipv6/conf/eth1/disable_ipv6=1").expect("IPv6 was not disabled");
+    assert!(disabled < position("ip link set dev eth1 up").unwrap());
+    assert!(calls.contains(&"nft -f -".to_string()));
+    assert!(runner.inputs().iter().any(|script| script.contains("ip saddr 10.0.0.0/8 accept")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_del_with_ipamd_down_is_retried() -> anyhow::Result<()> {
+    let dir = temp_state_dir("del-ipamd-down");
+    let socket = dir.join("ipamd.sock");
+
+    let mut conf = NetConf::new_default("corp", "eth0", 100, None);
+    conf.setup_loopback = false;
+    conf.ipam = Some(serde_json::from_value::<IPAMConfig>(serde_json::json!({
+        "type": "socni-ipamd",
+        "subnet": "10.0.0.0/24",
+        "socket": socket
+    }))?);
+
+    // The lease would leak if DEL went on, so it fails before the link goes
+    let runner = Arc::new(MockRunner::new());
+    let err = VlanPlugin::new(conf, cmd_args("pod-1"))
+        .with_socni_config(socni_config(&dir))
+        .with_runner(runner.clone())
+        .del_network()
+        .await
+        .unwrap_err();
+    assert_eq!(CniError::code_of(&err), CNI_ERR_TRY_AGAIN_LATER);
+    assert!(runner.calls().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+// File: socni/tests/state_test.rs
+
+use std::collections::HashMap;
+
+use socni::config::{NetConf, OnRevoke};
+use socni::state::{pod_identity, Attachment, StateStore};
+
+mod common;
+use common::temp_state_dir;
+
+#[test]
+fn test_pod_identity_from_cni_args() {
+    let mut args = HashMap::new();
+    assert_eq!(pod_identity(&args), None);
+
+    args.insert("K8S_POD_NAMESPACE".to_string(), "finance".to_string());
+    args.insert("K8S_POD_NAME".to_string(), "ledger-0".to_string());
+    assert_eq!(pod_identity(&args).as_deref(), Some("finance/ledger-0"));
+}
+
+#[test]
+fn test_mac_reservation_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = temp_state_dir("macs");
+    let store = StateStore::new(&dir);
+
+    store.reserve_mac("net-a", "finance/ledger-0", "net1", "02:00:00:00:00:01")?;
+    store.reserve_mac("net-a", "hr/payroll-0", "net1", "02:00:00:00:00:02")?;
+
+    // A new store instance models the next CNI invocation
+    let store = StateStore::new(&dir);
+    assert_eq!(
+        store.reserved_mac("net-a", "finance/ledger-0", "net1")?.as_deref(),
+        Some("02:00:00:00:00:01")
+    );
+    assert_eq!(store.reserved_mac("net-b", "finance/ledger-0", "net1")?, None);
+
+    assert_eq!(store.clear_macs(Some("finance/ledger-0"))?, 1);
+    assert_eq!(store.mac_reservations()?.len(), 1);
+    assert_eq!(store.clear_macs(None)?, 1);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_attachments_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+    let store = StateStore::new(temp_state_dir("attachments"));
+    let mut config = NetConf::new_default("corp", "eth0", 100, None);
+    config.on_revoke = OnRevoke::Teardown;
+
+    for container_id in ["pod-a", "pod-b"] {
+        store.record_attachment(Attachment {
+            config: config.clone(),
+            container_id: container_id.to_string(),
+            netns: format!("/var/run/netns/{}", container_id),
+            ifname: "eth1".to_string(),
+            tenant: "finance".to_string(),
+            added: 0,
+        })?;
+    }
+
+    let attachments = store.attachments()?;
+    assert_eq!(attachments.len(), 2);
+    let a = &attachments["corp/pod-a/eth1"];
+    assert_eq!(a.config.on_revoke, OnRevoke::Teardown);
+    assert_eq!(a.netns, "/var/run/netns/pod-a");
+
+    store.remove_attachment("corp", "pod-a", "eth1")?;
+    store.remove_attachment("corp", "pod-a", "eth1")?;
+    assert_eq!(store.attachments()?.keys().collect::<Vec<_>>(), ["corp/pod-b/eth1"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_vlan_references() -> Result<(), Box<dyn std::error::Error>> {
+    let store = StateStore::new(temp_state_dir("references"));
+    for (network, vlan, container_id) in [("corp", 100, "pod-b"), ("corp", 100, "pod-a"), ("oam", 200, "pod-a")] {
+        store.record_attachment(Attachment {
+            config: NetConf::new_default(network, "eth0", vlan, None),
+            container_id: container_id.to_string(),
+            netns: format!("/var/run/netns/{}", container_id),
+            ifname: "eth1".to_string(),
+            tenant: "finance".to_string(),
+            added: 0,
+        })?;
+    }
+
+    let references = store.vlan_references(100)?;
+    assert_eq!(references.iter().map(|a| a.container_id.as_str()).collect::<Vec<_>>(), ["pod-a", "pod-b"]);
+    assert!(store.vlan_references(300)?.is_empty());
+
+    store.remove_attachment("oam", "pod-a", "eth1")?;
+    assert!(store.vlan_references(200)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_host_link_ownership() -> Result<(), Box<dyn std::error::Error>> {
+    let store = StateStore::new(temp_state_dir("host-links"));
+    store.claim_host_link("eth0.100", "corp", Some("pod-a"))?;
+    store.claim_host_link("eth0.100", "corp", Some("pod-a"))?;
+
+    // Another conflist on the same master and VLAN is refused
+    let err = store.claim_host_link("eth0.100", "lab", Some("pod-b")).unwrap_err();
+    assert!(err.to_string().contains("belongs to network corp"));
+
+    // A later ADD on the same network takes the claim over
+    store.claim_host_link("eth0.100", "corp", Some("pod-b"))?;
+    assert_eq!(store.host_links()?["eth0.100"].container_id.as_deref(), Some("pod-b"));
+
+    // Only the owner can give it up
+    store.release_host_link("eth0.100", "lab")?;
+    assert_eq!(store.host_links()?["eth0.100"].network, "corp");
+    store.release_host_link("eth0.100", "corp")?;
+    store.claim_host_link("eth0.100", "lab", None)?;
+    assert_eq!(store.host_links()?["eth0.100"].container_id, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_recorded_host_links() -> Result<(), Box<dyn std::error::Error>> {
+    let store = StateStore::new(temp_state_dir("recorded-links"));
+    store.claim_host_link("eth0.100", "corp", Some("pod-a"))?;
+    store.claim_host_link("/var/run/netns/infra/eth0.200", "infra", None)?;
+
+    let mut qinq = NetConf::new_default("lab", "eth1", 300, None);
+    qinq.inner_vlan = Some(20);
+    store.record_attachment(Attachment {
+        config: qinq,
+        container_id: "pod-b".to_string(),
+        netns: "/var/run/netns/pod-b".to_string(),
+        ifname: "eth1".to_string(),
+        tenant: "finance".to_string(),
+        added: 0,
+    })?;
+
+    // Links in another namespace are never candidates on the host
+    let recorded: Vec<String> = store.recorded_host_links()?.into_iter().collect();
+    assert_eq!(recorded, ["eth0.100", "eth1.300", "eth1.300.20"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_outer_link_users() -> Result<(), Box<dyn std::error::Error>> {
+    let store = StateStore::new(temp_state_dir("outer-links"));
+    let _lock = store.lock_outer_link("eth0.300")?;
+    store.add_outer_link_user("eth0.300", "corp", "pod-a", "eth1")?;
+    store.add_outer_link_user("eth0.300", "corp", "pod-a", "eth1")?;
+    store.add_outer_link_user("eth0.300", "corp", "pod-b", "eth1")?;
+    assert_eq!(store.outer_link_users("eth0.300")?.len(), 2);
+
+    // The link becomes unused only when its last user goes
+    assert_eq!(store.remove_outer_link_user("eth0.300", "corp", "pod-a", "eth1")?, 1);
+    assert_eq!(store.remove_outer_link_user("eth0.300", "corp", "pod-b", "eth1")?, 0);
+    assert_eq!(store.remove_outer_link_user("eth0.300", "corp", "pod-b", "eth1")?, 0);
+    assert!(store.outer_link_users("eth0.300")?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+// File: socni/tests/token_test.rs
+
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+
+use socni::encoding::{encode, Alphabet};
+use socni::token::{TokenSubject, TokenVerifier};
+
+const NOW: u64 = 1_700_000_000;
+
+fn pod() -> TokenSubject {
+    TokenSubject {
+        namespace: "finance".to_string(),
+        name: "ledger-0".to_string(),
+        uid: Some("5f0c2a".to_string()),
+    }
+}
+
+const ED25519_SPKI_PREFIX: &[u8] = &[0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+const P256_SPKI_PREFIX: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+    0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+
+// Claims of a token issued to pod(), with `changes` applied; null removes a claim
+fn claims(changes: serde_json::Value) -> serde_json::Value {
+    let mut claims = serde_json::json!({"tenant": "finance", "exp": NOW + 60, "aud": "finance", "sub": "ledger-0"});
+    for (name, value) in changes.as_object().unwrap() {
+        match value {
+            serde_json::Value::Null => claims.as_object_mut().unwrap().remove(name),
+            value => claims.as_object_mut().unwrap().insert(name.clone(), value.clone()),
+        };
+    }
+    claims
+}
+
+fn jwt(alg: &str, claims: serde_json::Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+    let header = encode(serde_json::json!({"alg": alg, "typ": "JWT"}).to_string().as_bytes(), Alphabet::UrlSafe);
+    let payload = encode(claims.to_string().as_bytes(), Alphabet::UrlSafe);
+    let signed = format!("{}.{}", header, payload);
+    let sig = encode(&sign(signed.as_bytes()), Alphabet::UrlSafe);
+    format!("{}.{}", signed, sig)
+}
+
+fn ed25519_key() -> Ed25519KeyPair {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+}
+
+#[test]
+fn test_ed25519_token_attests_tenant() -> anyhow::Result<()> {
+    let key = ed25519_key();
+    let verifier = TokenVerifier::from_der(&[ED25519_SPKI_PREFIX, key.public_key().as_ref()].concat())?;
+    let sign = |data: &[u8]| key.sign(data).as_ref().to_vec();
+
+    let token = jwt("EdDSA", claims(serde_json::json!({})), sign);
+    assert_eq!(verifier.tenant(&token, NOW, &pod())?, "finance");
+
+    let expired = jwt("EdDSA", claims(serde_json::json!({"exp": NOW})), sign);
+    assert!(verifier.tenant(&expired, NOW, &pod()).unwrap_err().to_string().contains("expired"));
+
+    let early = jwt("EdDSA", claims(serde_json::json!({"nbf": NOW + 30})), sign);
+    assert!(verifier.tenant(&early, NOW, &pod()).unwrap_err().to_string().contains("not valid before"));
+
+    let unbounded = jwt("EdDSA", claims(serde_json::json!({"exp": null})), sign);
+    assert!(verifier.tenant(&unbounded, NOW, &pod()).unwrap_err().to_string().contains("no expiry"));
+
+    let anonymous = jwt("EdDSA", claims(serde_json::json!({"tenant": null})), sign);
+    assert!(verifier.tenant(&anonymous, NOW, &pod()).unwrap_err().to_string().contains("no tenant"));
+
+    Ok(())
+}
+
+#[test]
+fn test_forged_tokens_are_rejected() -> anyhow::Result<()> {
+    let key = ed25519_key();
+    let verifier = TokenVerifier::from_der(&[ED25519_SPKI_PREFIX, key.public_key().as_ref()].concat())?;
+    let token = jwt("EdDSA", claims(serde_json::json!({})),
+                    |data| key.sign(data).as_ref().to_vec());
+
+    // Claims swapped under the original signature
+    let mut parts: Vec<&str> = token.split('.').collect();
+    let forged = encode(claims(serde_json::json!({"tenant": "payroll"})).to_string().as_bytes(), Alphabet::UrlSafe);
+    parts[1] = &forged;
+    assert!(verifier.tenant(&parts.join("."), NOW, &pod()).unwrap_err().to_string().contains("signature is invalid"));
+
+    // Signed by another key
+    let other = ed25519_key();
+    let token = jwt("EdDSA", claims(serde_json::json!({})),
+                    |data| other.sign(data).as_ref().to_vec());
+    assert!(verifier.tenant(&token, NOW, &pod()).is_err());
+
+    // Unsigned tokens never verify
+    let token = jwt("none", claims(serde_json::json!({})), |_| Vec::new());
+    assert!(verifier.tenant(&token, NOW, &pod()).unwrap_err().to_string().contains("signed with none"));
+
+    assert!(verifier.tenant("not-a-token", NOW, &pod()).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_token_must_be_issued_to_the_pod() -> anyhow::Result<()> {
+    let key = ed25519_key();
+    let verifier = TokenVerifier::from_der(&[ED25519_SPKI_PREFIX, key.public_key().as_ref()].concat())?;
+    let sign = |data: &[u8]| key.sign(data).as_ref().to_vec();
+    let tenant = |changes: serde_json::Value| verifier.tenant(&jwt("EdDSA", claims(changes), sign), NOW, &pod());
+
+    // The pod's UID works as subject, and aud may be a list
+    assert_eq!(tenant(serde_json::json!({"sub": "5f0c2a"}))?, "finance");
+    assert_eq!(tenant(serde_json::json!({"aud": ["payroll", "finance"]}))?, "finance");
+
+    // Another pod's token doesn't carry over
+    let err = tenant(serde_json::json!({"sub": "ledger-1"})).unwrap_err();
+    assert!(err.to_string().contains("not finance/ledger-0"));
+    let err = tenant(serde_json::json!({"aud": "payroll"})).unwrap_err();
+    assert!(err.to_string().contains("namespace payroll, not finance"));
+
+    // Both claims are required
+    assert!(tenant(serde_json::json!({"sub": null})).unwrap_err().to_string().contains("no subject"));
+    assert!(tenant(serde_json::json!({"aud": null})).unwrap_err().to_string().contains("no audience"));
+    assert!(tenant(serde_json::json!({"aud": []})).unwrap_err().to_string().contains("no audience"));
+
+    Ok(())
+}
+
+#[test]
+fn test_es256_key_loads_from_pem() -> anyhow::Result<()> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+
+    let der = [P256_SPKI_PREFIX, key.public_key().as_ref()].concat();
+    let encoded = encode(&der, Alphabet::Standard);
+    let lines: Vec<&str> = encoded.as_bytes().chunks(64).map(|l| std::str::from_utf8(l).unwrap()).collect();
+    let pem = format!("-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n", lines.join("\n"));
+    let path = std::env::temp_dir().join(format!("socni-token-{}.pub", std::process::id()));
+    std::fs::write(&path, pem)?;
+    let verifier = TokenVerifier::load(&path)?;
+    std::fs::remove_file(&path)?;
+
+    let token = jwt("ES256", claims(serde_json::json!({})),
+                    |data| key.sign(&rng, data).unwrap().as_ref().to_vec());
+    assert_eq!(verifier.tenant(&token, NOW, &pod())?, "finance");
+
+    // The key decides the algorithm, not the token
+    let token = jwt("EdDSA", claims(serde_json::json!({})), |_| vec![0; 64]);
+    assert!(verifier.tenant(&token, NOW, &pod()).unwrap_err().to_string().contains("verifies ES256"));
+
+    Ok(())
+}
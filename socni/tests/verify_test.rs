@@ -0,0 +1,180 @@
+// File: socni/tests/verify_test.rs
+
+use serde_json::json;
+
+use socni::types::{IPConfig, Interface, Result as CniResult, Route};
+use socni::config::Neighbor;
+use socni::verify::{diff_result, first_failure, neighbor_issues, neighbor_lladdr, source_rule_issues, CheckItem};
+
+fn expected_result() -> CniResult {
+    let mut result = CniResult::new("1.0.0");
+    result.add_interface(Interface {
+        name: "net1".to_string(),
+        mac: Some("02:00:00:00:00:01".to_string()),
+        sandbox: Some("/var/run/netns/test".to_string()),
+        mtu: Some(1500),
+    });
+    result.add_ip(IPConfig {
+        interface: Some(0),
+        address: "10.0.0.2/24".to_string(),
+        gateway: Some("10.0.0.1".to_string()),
+    });
+    result.add_route(Route {
+        dst: "0.0.0.0/0".to_string(),
+        gw: Some("10.0.0.1".to_string()),
+        priority: None,
+    });
+    result
+}
+
+#[test]
+fn test_diff_result_matches_live_state() {
+    let link = json!({
+        "ifname": "net1",
+        "address": "02:00:00:00:00:01",
+        "mtu": 1500,
+        "addr_info": [
+            {"family": "inet", "local": "10.0.0.2", "prefixlen": 24, "scope": "global"},
+            {"family": "inet6", "local": "fe80::1", "prefixlen": 64, "scope": "link"}
+        ]
+    });
+    let routes = vec![
+        json!({"dst": "default", "gateway": "10.0.0.1"}),
+        json!({"dst": "10.0.0.0/24", "prefsrc": "10.0.0.2"}),
+    ];
+
+    assert!(diff_result(&expected_result(), "net1", &link, &routes).is_empty());
+}
+
+#[test]
+fn test_diff_result_reports_every_discrepancy() {
+    let link = json!({
+        "ifname": "net1",
+        "address": "02:00:00:00:00:99",
+        "mtu": 9000,
+        "addr_info": [
+            {"family": "inet", "local": "10.0.0.3", "prefixlen": 24, "scope": "global"}
+        ]
+    });
+
+    let issues = diff_result(&expected_result(), "net1", &link, &[]);
+
+    // mac, mtu, missing address, unexpected address, missing route
+    assert_eq!(issues.len(), 5, "{:?}", issues);
+}
+
+#[test]
+fn test_diff_result_compares_route_metric() {
+    let link = json!({
+        "ifname": "net1",
+        "address": "02:00:00:00:00:01",
+        "mtu": 1500,
+        "addr_info": [
+            {"family": "inet", "local": "10.0.0.2", "prefixlen": 24, "scope": "global"}
+        ]
+    });
+    let mut expected = expected_result();
+    expected.add_route(Route {
+        dst: "10.30.0.0/16".to_string(),
+        gw: Some("10.0.0.1".to_string()),
+        priority: Some(50),
+    });
+
+    let mut routes = vec![
+        json!({"dst": "default", "gateway": "10.0.0.1"}),
+        json!({"dst": "10.30.0.0/16", "gateway": "10.0.0.1", "metric": 50}),
+    ];
+    assert!(diff_result(&expected, "net1", &link, &routes).is_empty());
+
+    routes[1] = json!({"dst": "10.30.0.0/16", "gateway": "10.0.0.1", "metric": 100});
+    let issues = diff_result(&expected, "net1", &link, &routes);
+    assert_eq!(issues, ["route 10.30.0.0/16 via 10.0.0.1 metric 50 is missing"]);
+}
+
+#[test]
+fn test_diff_result_ignores_other_interfaces() {
+    let link = json!({
+        "ifname": "net1",
+        "address": "02:00:00:00:00:01",
+        "mtu": 1500,
+        "addr_info": [
+            {"family": "inet", "local": "10.0.0.2", "prefixlen": 24, "scope": "global"}
+        ]
+    });
+    let routes = vec![json!({"dst": "default", "gateway": "10.0.0.1"})];
+
+    // A bridge companion adds the host veth at 1 and the pod end at 2
+    let mut expected = expected_result();
+    expected.add_interface(Interface { name: "socni0011223344".to_string(), mac: None, sandbox: None, mtu: None });
+    expected.add_interface(Interface {
+        name: "br1".to_string(),
+        mac: None,
+        sandbox: Some("/var/run/netns/test".to_string()),
+        mtu: None,
+    });
+    expected.add_ip(IPConfig { interface: Some(2), address: "172.16.0.5/24".to_string(), gateway: None });
+
+    assert!(diff_result(&expected, "net1", &link, &routes).is_empty());
+}
+
+#[test]
+fn test_neighbor_issues() {
+    let expected = vec![
+        Neighbor { ip: "10.0.0.1".to_string(), mac: "02:00:00:00:00:01".to_string() },
+        Neighbor { ip: "fd00::1".to_string(), mac: "02:00:00:00:00:02".to_string() },
+    ];
+
+    let entries = vec![
+        json!({"dst": "10.0.0.1", "lladdr": "02:00:00:00:00:01", "state": ["PERMANENT"]}),
+        json!({"dst": "fd00::1", "lladdr": "02:00:00:00:00:02", "state": ["PERMANENT"]}),
+    ];
+    assert!(neighbor_issues(&expected, &entries).is_empty());
+
+    let drifted = vec![
+        json!({"dst": "10.0.0.1", "lladdr": "02:00:00:00:00:99", "state": ["REACHABLE"]}),
+    ];
+    let issues = neighbor_issues(&expected, &drifted);
+    assert_eq!(issues.len(), 3);
+    assert!(issues.iter().any(|i| i.contains("fd00::1 missing")));
+}
+
+#[test]
+fn test_source_rule_issues() {
+    let addresses = vec!["10.0.0.2/24".to_string()];
+    let rules = vec![
+        json!({"priority": 0, "src": "all", "table": "local"}),
+        json!({"priority": 32765, "src": "10.0.0.2", "table": "100"}),
+    ];
+    assert!(source_rule_issues(&addresses, 100, &rules).is_empty());
+    assert_eq!(source_rule_issues(&addresses, 101, &rules), ["no rule from 10.0.0.2 to table 101"]);
+    assert_eq!(source_rule_issues(&addresses, 100, &rules[..1]).len(), 1);
+}
+
+#[test]
+fn test_neighbor_lladdr() {
+    let entries = vec![
+        json!({"dst": "10.0.0.1", "lladdr": "02:00:00:00:00:01", "state": ["STALE"]}),
+        json!({"dst": "10.0.0.2", "state": ["INCOMPLETE"]}),
+        json!({"dst": "10.0.0.3", "lladdr": "02:00:00:00:00:03", "state": ["FAILED"]}),
+    ];
+
+    assert_eq!(neighbor_lladdr(&entries, "10.0.0.1").as_deref(), Some("02:00:00:00:00:01"));
+    assert_eq!(neighbor_lladdr(&entries, "10.0.0.2"), None);
+    assert_eq!(neighbor_lladdr(&entries, "10.0.0.3"), None);
+    assert_eq!(neighbor_lladdr(&entries, "10.0.0.4"), None);
+}
+
+#[test]
+fn test_first_failure_is_reported() {
+    let items = vec![
+        CheckItem::pass("interface"),
+        CheckItem::fail("vlan", "Interface eth1 is not VLAN 100".to_string()),
+        CheckItem::fail("addresses", "wrong addresses".to_string()),
+    ];
+    assert!(first_failure(&items[..1]).is_ok());
+
+    let err = first_failure(&items).unwrap_err();
+    assert_eq!(err.to_string(), "Interface eth1 is not VLAN 100");
+    assert!(items[1].to_string().starts_with("vlan"));
+    assert!(items[1].to_string().contains("FAIL"));
+}